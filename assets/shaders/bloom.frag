@@ -0,0 +1,18 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D tex;
+uniform sampler2D bloom;
+uniform float u_intensity;
+
+varying vec2 uv;
+
+void main() {
+    vec4 color = texture2D(tex, uv);
+
+    // Add the blurred glowing paths, so they spill light onto their surroundings
+    vec3 glow = texture2D(bloom, uv).rgb * u_intensity;
+
+    gl_FragColor = vec4(color.rgb + glow, color.a);
+}
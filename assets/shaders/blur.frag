@@ -0,0 +1,23 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D tex;
+uniform vec2 u_resolution;
+uniform vec2 u_direction;
+
+varying vec2 uv;
+
+void main() {
+    // A gaussian blur of 9 pixels along the direction in 5 samples, the filtering blends the
+    // pixels in between the offsets
+    vec2 step = u_direction / u_resolution;
+
+    vec3 color = texture2D(tex, uv).rgb * 0.2270270270;
+    color += texture2D(tex, uv + step * 1.3846153846).rgb * 0.3162162162;
+    color += texture2D(tex, uv - step * 1.3846153846).rgb * 0.3162162162;
+    color += texture2D(tex, uv + step * 3.2307692308).rgb * 0.0702702703;
+    color += texture2D(tex, uv - step * 3.2307692308).rgb * 0.0702702703;
+
+    gl_FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D tex;
+uniform vec2 u_resolution;
+uniform float u_intensity;
+
+varying vec2 uv;
+
+void main() {
+    // Shift the red & blue channels away from the center, by the intensity in pixels at the edges
+    vec2 shift = (uv - 0.5) * 2.0 * u_intensity / u_resolution;
+
+    vec4 color = texture2D(tex, uv);
+    float red = texture2D(tex, uv + shift).r;
+    float blue = texture2D(tex, uv - shift).b;
+
+    gl_FragColor = vec4(red, color.g, blue, color.a);
+}
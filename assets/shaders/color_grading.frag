@@ -0,0 +1,27 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D tex;
+uniform sampler2D lut;
+
+varying vec2 uv;
+
+// The amount of colors on every axis of the lookup table
+const float SIZE = 16.0;
+
+void main() {
+    vec4 color = texture2D(tex, uv);
+
+    // The table is a row of slices for the blue channel, the two nearest ones are blended
+    float slice = color.b * (SIZE - 1.0);
+    float low = floor(slice);
+    float high = min(low + 1.0, SIZE - 1.0);
+
+    // Sample the centers of the texels so the filtering blends red & green
+    vec2 pos = (color.rg * (SIZE - 1.0) + 0.5) / vec2(SIZE * SIZE, SIZE);
+    vec3 low_color = texture2D(lut, pos + vec2(low / SIZE, 0.0)).rgb;
+    vec3 high_color = texture2D(lut, pos + vec2(high / SIZE, 0.0)).rgb;
+
+    gl_FragColor = vec4(mix(low_color, high_color, slice - low), color.a);
+}
@@ -0,0 +1,53 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D tex;
+uniform vec2 u_resolution;
+
+varying vec2 uv;
+
+// Furthest distance in pixels that's blurred along an edge
+const float SPAN_MAX = 8.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = 1.0 / u_resolution;
+
+    // The brightness of the corners around the pixel
+    float nw = luma(texture2D(tex, uv + vec2(-1.0, -1.0) * texel).rgb);
+    float ne = luma(texture2D(tex, uv + vec2(1.0, -1.0) * texel).rgb);
+    float sw = luma(texture2D(tex, uv + vec2(-1.0, 1.0) * texel).rgb);
+    float se = luma(texture2D(tex, uv + vec2(1.0, 1.0) * texel).rgb);
+    vec4 center = texture2D(tex, uv);
+    float m = luma(center.rgb);
+
+    float min_luma = min(m, min(min(nw, ne), min(sw, se)));
+    float max_luma = max(m, max(max(nw, ne), max(sw, se)));
+
+    // Blur along the edge, perpendicular to the direction the brightness changes in
+    vec2 dir = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    float reduce = max((nw + ne + sw + se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, -SPAN_MAX, SPAN_MAX) * texel;
+
+    vec3 near = 0.5 * (
+        texture2D(tex, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture2D(tex, uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 far = near * 0.5 + 0.25 * (
+        texture2D(tex, uv - dir * 0.5).rgb +
+        texture2D(tex, uv + dir * 0.5).rgb);
+
+    // Sampling further away crossed another edge when the brightness is out of range
+    float far_luma = luma(far);
+    if (far_luma < min_luma || far_luma > max_luma) {
+        gl_FragColor = vec4(near, center.a);
+    } else {
+        gl_FragColor = vec4(far, center.a);
+    }
+}
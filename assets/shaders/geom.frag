@@ -0,0 +1,7 @@
+#version 100
+
+varying lowp vec4 color;
+
+void main() {
+    gl_FragColor = color;
+}
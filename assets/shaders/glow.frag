@@ -0,0 +1,9 @@
+#version 100
+
+varying lowp vec4 color;
+varying lowp float glow;
+
+void main() {
+    // The paths that don't glow are drawn black, so they still cover the glow behind them
+    gl_FragColor = vec4(color.rgb * color.a * glow, 1.0);
+}
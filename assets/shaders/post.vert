@@ -0,0 +1,12 @@
+#version 100
+
+attribute vec2 a_pos;
+
+varying mediump vec2 uv;
+
+void main() {
+    // The quad covers the whole clip space, the texture coordinates go from 0.0 to 1.0
+    uv = a_pos * 0.5 + 0.5;
+
+    gl_Position = vec4(a_pos, 0.0, 1.0);
+}
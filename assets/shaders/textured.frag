@@ -0,0 +1,11 @@
+#version 100
+
+uniform sampler2D tex;
+
+varying lowp vec4 color;
+varying mediump vec2 uv;
+
+void main() {
+    // The instance color tints the bitmap
+    gl_FragColor = texture2D(tex, uv) * color;
+}
@@ -0,0 +1,35 @@
+#version 100
+
+uniform mat4 u_transform;
+
+attribute vec2 a_pos;
+attribute vec4 a_color;
+attribute vec2 a_uv;
+attribute vec3 a_inst_pos;
+attribute float a_inst_rot;
+attribute vec2 a_inst_scale;
+attribute vec4 a_inst_color;
+
+varying lowp vec4 color;
+varying mediump vec2 uv;
+
+void main() {
+    // Scale the vertices along the axes of the mesh, a negative scale flips them
+    vec2 scaled_pos = a_pos * a_inst_scale;
+
+    // Rotate the scaled vertices around the zero center
+    float s = sin(a_inst_rot);
+    float c = cos(a_inst_rot);
+    mat2 rotation_mat = mat2(c, -s, s, c);
+    vec2 rotated_pos = scaled_pos * rotation_mat;
+
+    // Offset rotated position with instance position
+    vec2 pos = rotated_pos + a_inst_pos.xy;
+
+    // Project with the camera, the Z position is only used for the depth
+    vec4 projected = u_transform * vec4(pos, 0.0, 1.0);
+    gl_Position = vec4(projected.xy, a_inst_pos.z, 1.0);
+
+    color = a_color * a_inst_color;
+    uv = a_uv;
+}
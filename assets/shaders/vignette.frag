@@ -0,0 +1,18 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D tex;
+uniform float u_intensity;
+
+varying vec2 uv;
+
+void main() {
+    vec4 color = texture2D(tex, uv);
+
+    // The distance to the center, 1.0 in the corners
+    float distance = length(uv - 0.5) * 1.414;
+    float darkening = smoothstep(0.4, 1.0, distance) * u_intensity;
+
+    gl_FragColor = vec4(color.rgb * (1.0 - darkening), color.a);
+}
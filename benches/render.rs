@@ -0,0 +1,101 @@
+//! Benchmarks of the renderer, without a graphics context.
+//!
+//! The meshes & instances are stored like in the game, only the GPU buffers are never filled. Run
+//! with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use lyon::{math::Point, path::Path};
+use replace_me::{
+    render::{Instance, Mesh, Render},
+    svg::Svg,
+};
+use usvg::Color;
+
+const SIEGE_TOWER: &str = include_str!("../assets/siege-tower.svg");
+
+/// Amount of meshes the instances are spread over, like the stress scene.
+const MESHES: usize = 16;
+
+/// Upload meshes with a different amount of sides each, identical meshes would share a draw call.
+fn meshes(render: &mut Render) -> Vec<Mesh> {
+    (0..MESHES)
+        .map(|index| {
+            let sides = 3 + index * 8;
+            let mut builder = Path::builder();
+            for side in 0..sides {
+                let angle = side as f32 / sides as f32 * std::f32::consts::PI * 2.0;
+                let point = Point::new(angle.cos() * 10.0, angle.sin() * 10.0);
+                if side == 0 {
+                    builder.move_to(point);
+                } else {
+                    builder.line_to(point);
+                }
+            }
+            builder.close();
+
+            render.upload_path(&builder.build(), Color::new(0x80, 0x80, 0x80), 1.0)
+        })
+        .collect()
+}
+
+/// Rotated instances spread over the meshes, like a frame of the stress scene.
+fn instances(amount: usize, rotation: f32) -> Vec<Vec<Instance>> {
+    let mut instances = vec![vec![]; MESHES];
+    for index in 0..amount {
+        let mut instance = Instance::new((index % 100) as f32 * 10.0, (index / 100) as f32 * 10.0);
+        instance.set_rotation(index as f32 * 0.01 + rotation);
+
+        instances[index % MESHES].push(instance);
+    }
+
+    instances
+}
+
+fn upload_svg(c: &mut Criterion) {
+    let svg = SIEGE_TOWER.parse::<Svg>().unwrap();
+
+    c.bench_function("upload siege tower svg", |b| {
+        b.iter_batched_ref(
+            Render::offscreen,
+            |render| black_box(&svg).upload(render).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn sync_instances(c: &mut Criterion) {
+    let mut render = Render::offscreen();
+    let meshes = meshes(&mut render);
+
+    for &amount in &[1000, 10000, 50000] {
+        // Alternate between two frames, so every sync changes all instances
+        let frames = [instances(amount, 0.0), instances(amount, 0.1)];
+        let mut frame = 0;
+        c.bench_function(&format!("sync {} moving instances", amount), |b| {
+            b.iter(|| {
+                frame = (frame + 1) % frames.len();
+                for (mesh, instances) in meshes.iter().zip(frames[frame].iter()) {
+                    render.set_instances(*mesh, black_box(instances));
+                }
+            })
+        });
+    }
+}
+
+fn sync_static_instances(c: &mut Criterion) {
+    let mut render = Render::offscreen();
+    let meshes = meshes(&mut render);
+
+    // Only the first sync changes anything, like the objects of a level that don't move
+    let instances = instances(100_000, 0.0);
+    c.bench_function("sync 100000 static instances", |b| {
+        b.iter(|| {
+            for (mesh, instances) in meshes.iter().zip(instances.iter()) {
+                render.set_instances(*mesh, black_box(instances));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, upload_svg, sync_instances, sync_static_instances);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+//! Benchmarks of the work done when uploading assets, without a graphics context.
+//!
+//! The same functions the game calls before uploading the vertices are measured. Run with
+//! `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lyon::{math::Point, path::Path};
+use replace_me::{render, svg::Svg, text::Font, FONT};
+use usvg::Color;
+
+const SIEGE_TOWER: &str = include_str!("../assets/siege-tower.svg");
+const GROUND: &str = include_str!("../assets/ground.svg");
+
+/// Build a polygon approximating a circle with an amount of sides.
+fn polygon(sides: usize) -> Path {
+    let mut builder = Path::builder();
+    for side in 0..sides {
+        let angle = side as f32 / sides as f32 * std::f32::consts::PI * 2.0;
+        let point = Point::new(angle.cos() * 100.0, angle.sin() * 100.0);
+        if side == 0 {
+            builder.move_to(point);
+        } else {
+            builder.line_to(point);
+        }
+    }
+    builder.close();
+
+    builder.build()
+}
+
+fn svg(c: &mut Criterion) {
+    c.bench_function("parse & tessellate siege tower svg", |b| {
+        b.iter(|| black_box(SIEGE_TOWER).parse::<Svg>().unwrap())
+    });
+    c.bench_function("parse & tessellate ground svg", |b| {
+        b.iter(|| black_box(GROUND).parse::<Svg>().unwrap())
+    });
+}
+
+fn fill(c: &mut Criterion) {
+    let color = Color::new(0x80, 0x80, 0x80);
+    for &sides in &[8, 64, 1024] {
+        let path = polygon(sides);
+        c.bench_function(&format!("tessellate polygon with {} sides", sides), |b| {
+            b.iter(|| render::tessellate_path(black_box(&path), color, 1.0).unwrap())
+        });
+    }
+}
+
+fn font(c: &mut Criterion) {
+    let font = Font::from_bytes(FONT).unwrap();
+    let chars = font.outlined_chars();
+
+    c.bench_function("tessellate all glyphs", |b| {
+        b.iter(|| font.tessellate(black_box(&chars).chars()).unwrap())
+    });
+}
+
+criterion_group!(benches, svg, fill, font);
+criterion_main!(benches);
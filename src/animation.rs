@@ -0,0 +1,221 @@
+use crate::render::{Instance, Mesh};
+use std::collections::HashMap;
+
+/// A movable part of a unit, drawn with the mesh of a named group in the SVG.
+#[derive(Debug, Clone)]
+pub struct Part {
+    /// The mesh of the named group.
+    pub mesh: Mesh,
+    /// Index of the part it's attached to, always earlier in the list of parts.
+    pub parent: Option<usize>,
+    /// The point it rotates around, in SVG coordinates.
+    pub pivot: (f32, f32),
+}
+
+/// The pose of a single part at a moment in an animation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe {
+    /// Seconds since the start of the animation.
+    pub time: f32,
+    /// Rotation around the pivot in radians.
+    pub rotation: f32,
+    /// Translation relative to the parent.
+    pub offset: (f32, f32),
+}
+
+/// The keyframes of a single part, sorted by time.
+#[derive(Debug, Clone)]
+pub struct Track {
+    /// Index of the part that's moved.
+    pub part: usize,
+    /// The poses that are interpolated between.
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// A named movement of the parts, like walking.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// Length in seconds.
+    pub duration: f32,
+    /// Whether it starts over at the end, otherwise the last pose is held.
+    pub looping: bool,
+    /// The moved parts, the other parts keep their rest pose.
+    pub tracks: Vec<Track>,
+}
+
+/// The hierarchy of parts of a unit with the animations that move them.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    /// The parts with the parents before their children, drawn in this order.
+    pub parts: Vec<Part>,
+    /// The animations by name.
+    pub animations: HashMap<String, Animation>,
+}
+
+/// A rigid transformation, applied to a point as a rotation followed by a translation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Transform {
+    rotation: f32,
+    translation: (f32, f32),
+}
+
+impl Transform {
+    /// The transformation that doesn't move anything.
+    const IDENTITY: Transform = Transform {
+        rotation: 0.0,
+        translation: (0.0, 0.0),
+    };
+
+    /// Rotate around a pivot point and translate afterwards.
+    fn around(pivot: (f32, f32), rotation: f32, offset: (f32, f32)) -> Self {
+        let (x, y) = rotate(pivot, rotation);
+
+        Self {
+            rotation,
+            translation: (pivot.0 + offset.0 - x, pivot.1 + offset.1 - y),
+        }
+    }
+
+    /// The transformation of applying the other one first and this one after.
+    fn then(self, other: Transform) -> Self {
+        let (x, y) = rotate(other.translation, self.rotation);
+
+        Self {
+            rotation: self.rotation + other.rotation,
+            translation: (self.translation.0 + x, self.translation.1 + y),
+        }
+    }
+}
+
+/// Plays the animations of a single unit.
+#[derive(Debug, Clone, Default)]
+pub struct Animator {
+    /// The name of the animation that's playing.
+    playing: Option<String>,
+    /// Seconds since it started playing.
+    time: f32,
+}
+
+impl Animator {
+    /// Start playing an animation from the beginning.
+    ///
+    /// Nothing happens when it's already playing.
+    pub fn play(&mut self, name: &str) {
+        if self.playing.as_deref() != Some(name) {
+            self.playing = Some(name.to_string());
+            self.time = 0.0;
+        }
+    }
+
+    /// Advance the animation, should be called every frame.
+    pub fn update(&mut self, delta: f32) {
+        if self.playing.is_some() {
+            self.time += delta;
+        }
+    }
+
+    /// The instances of the parts of the skeleton in the same order, posed relative to the
+    /// instance of the unit.
+    ///
+    /// The parts are drawn on top of their parents.
+    pub fn pose(&self, skeleton: &Skeleton, instance: &Instance) -> Vec<Instance> {
+        let animation = self
+            .playing
+            .as_ref()
+            .and_then(|name| skeleton.animations.get(name));
+
+        // The transformation of every part relative to the unit, parents are calculated first
+        let mut transforms: Vec<Transform> = Vec::with_capacity(skeleton.parts.len());
+        for (index, part) in skeleton.parts.iter().enumerate() {
+            let (rotation, offset) = animation
+                .and_then(|animation| {
+                    let time = if animation.looping && animation.duration > 0.0 {
+                        self.time % animation.duration
+                    } else {
+                        self.time.min(animation.duration)
+                    };
+
+                    animation
+                        .tracks
+                        .iter()
+                        .find(|track| track.part == index)
+                        .map(|track| sample(&track.keyframes, time))
+                })
+                .unwrap_or((0.0, (0.0, 0.0)));
+
+            let local = Transform::around(part.pivot, rotation, offset);
+            let parent = part
+                .parent
+                .map_or(Transform::IDENTITY, |parent| transforms[parent]);
+            transforms.push(parent.then(local));
+        }
+
+        let unit = Transform {
+            rotation: instance.rotation(),
+            translation: (instance.x(), instance.y()),
+        };
+        let (scale_x, scale_y) = instance.scale();
+        // A flipped unit turns its parts the other way around
+        let mirror = (scale_x * scale_y).signum();
+        transforms
+            .into_iter()
+            .enumerate()
+            .map(|(index, transform)| {
+                // The scale of the unit applies to the translation of the part as well
+                let scaled = Transform {
+                    rotation: transform.rotation * mirror,
+                    translation: (
+                        transform.translation.0 * scale_x,
+                        transform.translation.1 * scale_y,
+                    ),
+                };
+                let transform = unit.then(scaled);
+
+                let mut part_instance = *instance;
+                part_instance.set_x(transform.translation.0);
+                part_instance.set_y(transform.translation.1);
+                part_instance.set_rotation(transform.rotation);
+                part_instance.set_z(instance.z().saturating_add(index as u8));
+
+                part_instance
+            })
+            .collect()
+    }
+}
+
+/// Interpolate the rotation & offset between the keyframes around a time.
+fn sample(keyframes: &[Keyframe], time: f32) -> (f32, (f32, f32)) {
+    let next = match keyframes.iter().position(|keyframe| keyframe.time > time) {
+        Some(next) => next,
+        // Hold the last pose
+        None => {
+            return keyframes
+                .last()
+                .map_or((0.0, (0.0, 0.0)), |last| (last.rotation, last.offset))
+        }
+    };
+    let to = keyframes[next];
+    let from = match next.checked_sub(1) {
+        Some(previous) => keyframes[previous],
+        // Hold the first pose
+        None => return (to.rotation, to.offset),
+    };
+
+    let t = (time - from.time) / (to.time - from.time);
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+    (
+        lerp(from.rotation, to.rotation),
+        (
+            lerp(from.offset.0, to.offset.0),
+            lerp(from.offset.1, to.offset.1),
+        ),
+    )
+}
+
+/// Rotate a point around the origin.
+fn rotate((x, y): (f32, f32), rotation: f32) -> (f32, f32) {
+    let (sin, cos) = rotation.sin_cos();
+
+    (x * cos - y * sin, x * sin + y * cos)
+}
@@ -0,0 +1,195 @@
+use crate::{config::Config, headless, stress::StressOptions};
+use anyhow::{anyhow, Result};
+
+/// Where the configuration is stored without `--config`.
+const CONFIG_PATH: &str = "config.toml";
+
+/// The help text printed with `--help`.
+pub const USAGE: &str = "\
+Usage: replace_me [FLAGS] [section.key=value]...
+
+Flags:
+    --config <path>       Read the configuration from another file
+    --windowed <W>x<H>    Start in a window with the size in pixels
+    --scene <name>        Load a scene as soon as the assets are loaded
+    --debug-physics       Draw the shapes of the physics bodies
+    --mute                Don't play any sounds
+    --record <file>       Write the replay of the session to a file when quitting
+    --replay <file>       Play back a replay file instead of taking input
+    --headless            Simulate without a window
+    --steps=<amount>      Amount of fixed steps simulated without a window
+    --test <path>         Evaluate a test script after the main script without a window
+    --check-determinism   Simulate twice without a window and compare the results
+    --stress              Fill the window with instances to measure the renderer
+    --instances=<amount>  Amount of instances in the stress scene
+    --meshes=<amount>     Amount of meshes in the stress scene
+    --static              Don't move the instances of the stress scene
+    --help                Print this text
+
+All other arguments override an option of the configuration file.";
+
+/// The arguments passed on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    /// Path of the configuration file.
+    pub config: String,
+    /// Window size in pixels, starts windowed instead of fullscreen.
+    pub windowed: Option<(i32, i32)>,
+    /// The scene loaded when the assets are loaded.
+    pub scene: Option<String>,
+    /// Whether to draw the shapes of the physics bodies.
+    pub debug_physics: bool,
+    /// Whether to silence all sounds.
+    pub mute: bool,
+    /// File the replay is written to when quitting.
+    pub record: Option<String>,
+    /// Replay file played back.
+    pub replay: Option<String>,
+    /// Whether to simulate without a window.
+    pub headless: bool,
+    /// Amount of fixed steps simulated without a window.
+    pub steps: u64,
+    /// Script evaluated after the main script without a window, to check the simulation.
+    pub test: Option<String>,
+    /// Whether to simulate twice without a window and fail when the results differ.
+    pub check_determinism: bool,
+    /// Options for the stress scene, `None` when it's not enabled.
+    pub stress: Option<StressOptions>,
+    /// Whether the help text is requested.
+    pub help: bool,
+    /// Overrides for the configuration in the form `section.key=value`.
+    pub overrides: Vec<String>,
+}
+
+impl Args {
+    /// Parse the arguments, without the name of the executable.
+    ///
+    /// Flags with a value can be passed as `--flag value` and as `--flag=value`.
+    pub fn parse<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Self {
+            config: CONFIG_PATH.to_string(),
+            windowed: None,
+            scene: None,
+            debug_physics: false,
+            mute: false,
+            record: None,
+            replay: None,
+            headless: false,
+            steps: headless::DEFAULT_STEPS,
+            test: None,
+            check_determinism: false,
+            stress: None,
+            help: false,
+            overrides: vec![],
+        };
+        let mut stress = StressOptions::default();
+        let mut stress_enabled = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.overrides.push(arg);
+
+                continue;
+            }
+
+            // Split the inline value from the flag
+            let (flag, inline) = match arg.find('=') {
+                Some(index) => (&arg[..index], Some(arg[index + 1..].to_string())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| anyhow!("Flag \"{}\" requires a value", flag))
+            };
+
+            match flag {
+                "--config" => parsed.config = value()?,
+                "--windowed" => parsed.windowed = Some(parse_size(&value()?)?),
+                "--scene" => parsed.scene = Some(value()?),
+                "--debug-physics" => parsed.debug_physics = true,
+                "--mute" => parsed.mute = true,
+                "--record" => parsed.record = Some(value()?),
+                "--replay" => parsed.replay = Some(value()?),
+                "--headless" => parsed.headless = true,
+                "--steps" => parsed.steps = parse_amount(flag, &value()?)?,
+                // A test only runs without a window
+                "--test" => {
+                    parsed.test = Some(value()?);
+                    parsed.headless = true;
+                }
+                "--check-determinism" => {
+                    parsed.check_determinism = true;
+                    parsed.headless = true;
+                }
+                "--stress" => stress_enabled = true,
+                "--instances" => stress.instances = parse_amount(flag, &value()?)?,
+                "--meshes" => stress.meshes = parse_amount(flag, &value()?)?,
+                "--static" => stress.moving = false,
+                "--help" => parsed.help = true,
+                _ => return Err(anyhow!("Unknown flag \"{}\"", flag)),
+            }
+        }
+
+        if stress_enabled {
+            parsed.stress = Some(stress);
+        }
+
+        Ok(parsed)
+    }
+
+    /// Override the options of the configuration with the flags.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some((width, height)) = self.windowed {
+            config.window.width = width;
+            config.window.height = height;
+            config.window.fullscreen = false;
+        }
+        if self.scene.is_some() {
+            config.debug.scene = self.scene.clone();
+        }
+        if self.debug_physics {
+            config.debug.physics = true;
+        }
+        if self.mute {
+            config.audio.master_volume = 0.0;
+        }
+        if self.record.is_some() {
+            config.debug.record = self.record.clone();
+        }
+        if self.replay.is_some() {
+            config.debug.replay = self.replay.clone();
+        }
+    }
+}
+
+/// Parse a window size in the form `800x600`.
+fn parse_size(size: &str) -> Result<(i32, i32)> {
+    let invalid = || {
+        anyhow!(
+            "Window size \"{}\" is not in the form <width>x<height>",
+            size
+        )
+    };
+
+    let index = size.find('x').ok_or_else(invalid)?;
+    let width: i32 = size[..index].parse().map_err(|_| invalid())?;
+    let height: i32 = size[index + 1..].parse().map_err(|_| invalid())?;
+    if width <= 0 || height <= 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+/// Parse the value of a flag as a number.
+fn parse_amount<T: std::str::FromStr>(flag: &str, amount: &str) -> Result<T> {
+    amount
+        .parse()
+        .map_err(|_| anyhow!("Value \"{}\" of flag \"{}\" is not a number", amount, flag))
+}
@@ -0,0 +1,269 @@
+use crate::svg::Svg;
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// Whether the assets are read from the assets directory, only in debug builds on desktop so
+/// release builds & the browser don't depend on the working directory.
+const FROM_FILES: bool = cfg!(all(debug_assertions, not(target_arch = "wasm32")));
+/// Seconds between checking the SVG files for changes.
+const POLL_INTERVAL: f64 = 0.5;
+
+/// Every asset embedded in the executable by its path in the assets directory.
+const EMBEDDED: &[(&str, &[u8])] = &[
+    (
+        "single-character.svg",
+        include_bytes!("../assets/single-character.svg"),
+    ),
+    ("ground.svg", include_bytes!("../assets/ground.svg")),
+    ("arrow.svg", include_bytes!("../assets/arrow.svg")),
+    ("panel.svg", include_bytes!("../assets/panel.svg")),
+    (
+        "cursor-default.svg",
+        include_bytes!("../assets/cursor-default.svg"),
+    ),
+    (
+        "cursor-attack.svg",
+        include_bytes!("../assets/cursor-attack.svg"),
+    ),
+    (
+        "cursor-forbidden.svg",
+        include_bytes!("../assets/cursor-forbidden.svg"),
+    ),
+    ("objects.toml", include_bytes!("../assets/objects.toml")),
+    ("FetteNationalFraktur.ttf", crate::FONT),
+    ("sounds/hit.wav", include_bytes!("../assets/sounds/hit.wav")),
+    (
+        "sounds/shoot.wav",
+        include_bytes!("../assets/sounds/shoot.wav"),
+    ),
+    (
+        "sounds/theme.wav",
+        include_bytes!("../assets/sounds/theme.wav"),
+    ),
+];
+
+/// An SVG by its path in the assets directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SvgHandle(&'static str);
+
+impl SvgHandle {
+    /// The stretchable background of UI panels.
+    pub const PANEL: Self = Self("panel.svg");
+    /// The mouse cursor when it's not above anything.
+    pub const CURSOR_DEFAULT: Self = Self("cursor-default.svg");
+    /// The mouse cursor above an enemy.
+    pub const CURSOR_ATTACK: Self = Self("cursor-attack.svg");
+    /// The mouse cursor where nothing can be done.
+    pub const CURSOR_FORBIDDEN: Self = Self("cursor-forbidden.svg");
+
+    /// The path of the file in the assets directory.
+    pub fn path(self) -> &'static str {
+        self.0
+    }
+
+    /// Find the SVG with a path in the assets directory, it has to be embedded.
+    pub fn from_path(path: &str) -> Result<Self> {
+        EMBEDDED
+            .iter()
+            .find(|(name, _)| *name == path && name.ends_with(".svg"))
+            .map(|(name, _)| Self(name))
+            .ok_or_else(|| anyhow!("SVG \"{}\" is not embedded", path))
+    }
+}
+
+/// A TOML data file by its path in the assets directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DataHandle(&'static str);
+
+impl DataHandle {
+    /// The definitions of the objects that can be spawned.
+    pub const OBJECTS: Self = Self("objects.toml");
+}
+
+/// A TrueType font by its path in the assets directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FontHandle(&'static str);
+
+impl FontHandle {
+    /// The font used for all text.
+    pub const FRAKTUR: Self = Self("FetteNationalFraktur.ttf");
+}
+
+/// A WAV sound by its path in the assets directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SoundHandle(&'static str);
+
+impl SoundHandle {
+    /// Played when an arrow hits a unit.
+    pub const HIT: Self = Self("sounds/hit.wav");
+    /// Played when an arrow is shot.
+    pub const SHOOT: Self = Self("sounds/shoot.wav");
+    /// The background music.
+    pub const THEME: Self = Self("sounds/theme.wav");
+}
+
+/// Reads the assets from the assets directory or from the executable.
+///
+/// The parsed SVGs are cached, so loading the same SVG twice only parses it once and uploading
+/// it again returns the same mesh. Clones share the cache, so it can be used from the jobs.
+#[derive(Clone)]
+pub struct AssetManager {
+    /// The directory the files are read from when they aren't embedded.
+    root: PathBuf,
+    /// The SVGs parsed so far.
+    svgs: Arc<Mutex<HashMap<SvgHandle, Svg>>>,
+}
+
+impl AssetManager {
+    /// Read the assets from a directory in debug builds, the embedded ones are used otherwise.
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            svgs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Parse an SVG, or get it from the cache when it's parsed before.
+    pub fn svg(&self, handle: SvgHandle) -> Result<Svg> {
+        if let Some(svg) = self.svgs.lock().unwrap().get(&handle) {
+            return Ok(svg.clone());
+        }
+
+        let bytes = self.read(handle.0)?;
+        let source = std::str::from_utf8(&bytes)
+            .map_err(|err| anyhow!("SVG \"{}\" is not valid UTF-8: {}", handle.0, err))?;
+        let svg = source
+            .parse::<Svg>()
+            .map_err(|err| anyhow!("Parsing SVG \"{}\" failed: {}", handle.0, err))?;
+
+        self.svgs.lock().unwrap().insert(handle, svg.clone());
+
+        Ok(svg)
+    }
+
+    /// Parse a changed SVG file again, replacing the cached version.
+    fn reload_svg(&self, handle: SvgHandle) -> Result<Svg> {
+        self.svgs.lock().unwrap().remove(&handle);
+
+        self.svg(handle)
+    }
+
+    /// The SVGs that are loaded.
+    fn loaded_svgs(&self) -> Vec<SvgHandle> {
+        self.svgs.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Get the bytes of a font.
+    ///
+    /// The glyphs borrow the bytes for the rest of the game, so a font read from a file is never
+    /// freed.
+    pub fn font(&self, handle: FontHandle) -> Result<&'static [u8]> {
+        Ok(match self.read(handle.0)? {
+            Cow::Borrowed(bytes) => bytes,
+            Cow::Owned(bytes) => Box::leak(bytes.into_boxed_slice()),
+        })
+    }
+
+    /// Get the text of a data file.
+    pub fn data(&self, handle: DataHandle) -> Result<String> {
+        String::from_utf8(self.read(handle.0)?.into_owned())
+            .map_err(|err| anyhow!("Data file \"{}\" is not valid UTF-8: {}", handle.0, err))
+    }
+
+    /// Get the bytes of a WAV sound.
+    pub fn sound(&self, handle: SoundHandle) -> Result<Cow<'static, [u8]>> {
+        self.read(handle.0)
+    }
+
+    /// Read the file from the assets directory, falling back to the embedded version when it
+    /// doesn't exist.
+    fn read(&self, path: &'static str) -> Result<Cow<'static, [u8]>> {
+        if FROM_FILES {
+            let file = self.root.join(path);
+            match std::fs::read(&file) {
+                Ok(bytes) => return Ok(Cow::Owned(bytes)),
+                Err(err) => debug!(
+                    "reading \"{}\" failed, using the embedded version: {}",
+                    file.display(),
+                    err
+                ),
+            }
+        }
+
+        EMBEDDED
+            .iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, bytes)| Cow::Borrowed(*bytes))
+            .ok_or_else(|| anyhow!("Asset \"{}\" is not embedded", path))
+    }
+}
+
+/// Watches the files of the loaded SVGs, so they can be edited while the game runs.
+///
+/// Only the files read from the assets directory are watched, so nothing changes in release
+/// builds & the browser.
+pub struct SvgWatcher {
+    /// The last modification time of the files of the loaded SVGs.
+    modified: HashMap<SvgHandle, SystemTime>,
+    /// The time the files were last checked in seconds.
+    last_poll: f64,
+}
+
+impl Default for SvgWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SvgWatcher {
+    /// Watch nothing until SVGs are loaded.
+    pub fn new() -> Self {
+        Self {
+            modified: HashMap::new(),
+            last_poll: 0.0,
+        }
+    }
+
+    /// Parse the loaded SVGs of which the file changed since the last call again.
+    ///
+    /// The files are only checked twice a second, the first time a file is seen it's only
+    /// remembered because it was just loaded.
+    pub fn poll(&mut self, assets: &AssetManager) -> Vec<(SvgHandle, Result<Svg>)> {
+        let now = miniquad::date::now();
+        if !FROM_FILES || now - self.last_poll < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = now;
+
+        let mut changed = vec![];
+        for handle in assets.loaded_svgs() {
+            let modified = match last_modified(&assets.root.join(handle.0)) {
+                Some(modified) => modified,
+                None => continue,
+            };
+
+            match self.modified.insert(handle, modified) {
+                Some(previous) if previous != modified => {
+                    changed.push((handle, assets.reload_svg(handle)))
+                }
+                _ => (),
+            }
+        }
+
+        changed
+    }
+}
+
+/// The time a file was last modified, `None` when it can't be read.
+fn last_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
@@ -0,0 +1,174 @@
+use crate::{
+    assets::{AssetManager, SoundHandle},
+    config::AudioConfig,
+};
+use anyhow::{anyhow, Result};
+use glsp::{bail, lib, rfn, GResult, GSend, Runtime};
+use quad_snd::{
+    decoder,
+    mixer::{PlaybackStyle, Sound, SoundId, SoundMixer, Volume},
+};
+use std::collections::HashMap;
+
+/// The sound effects, played once by name.
+const SOUNDS: &[(&str, SoundHandle)] = &[("hit", SoundHandle::HIT), ("shoot", SoundHandle::SHOOT)];
+/// The music, looped by name.
+const MUSIC: &[(&str, SoundHandle)] = &[("theme", SoundHandle::THEME)];
+
+lib! {
+/// Plays the sound effects & music through the audio device.
+pub struct Audio {
+    /// Mixes all playing sounds.
+    mixer: SoundMixer,
+    /// The decoded sound effects by name.
+    sounds: HashMap<&'static str, Sound>,
+    /// The decoded music by name.
+    music: HashMap<&'static str, Sound>,
+    /// The music that's playing with its name.
+    playing_music: Option<(&'static str, SoundId)>,
+    /// Volume of the sound effects, multiplied with the master volume.
+    effects_volume: f32,
+    /// Volume of the music, multiplied with the master volume.
+    music_volume: f32,
+}
+}
+
+// The mixer holds no script values, it's moved into the script runtime
+impl GSend for Audio {}
+
+impl Audio {
+    /// Decode the sounds and open the audio device.
+    pub fn new(config: &AudioConfig, assets: &AssetManager) -> Result<Self> {
+        let decode = |sounds: &[(&'static str, SoundHandle)], style: PlaybackStyle| {
+            sounds
+                .iter()
+                .map(|(name, handle)| {
+                    decoder::read_wav_ext(&assets.sound(*handle)?, style.clone())
+                        .map(|sound| (*name, sound))
+                        .map_err(|err| anyhow!("Decoding sound \"{}\" failed: {}", name, err))
+                })
+                .collect::<Result<HashMap<_, _>>>()
+        };
+
+        let master = config.master_volume.max(0.0).min(1.0);
+
+        Ok(Self {
+            mixer: SoundMixer::new(),
+            sounds: decode(SOUNDS, PlaybackStyle::Once)?,
+            music: decode(MUSIC, PlaybackStyle::Looped)?,
+            playing_music: None,
+            effects_volume: master * config.effects_volume.max(0.0).min(1.0),
+            music_volume: master * config.music_volume.max(0.0).min(1.0),
+        })
+    }
+
+    /// Mix the playing sounds, should be called every frame.
+    pub fn update(&mut self) {
+        self.mixer.frame();
+    }
+
+    /// Play a sound effect once.
+    pub fn play_sfx(&mut self, name: &str) -> Result<()> {
+        let sound = self
+            .sounds
+            .get(name)
+            .ok_or_else(|| anyhow!("Sound \"{}\" doesn't exist", name))?
+            .clone();
+
+        let id = self.mixer.play(sound);
+        self.mixer.set_volume(id, Volume(self.effects_volume));
+
+        Ok(())
+    }
+
+    /// Loop a music track, replacing the one that's playing.
+    ///
+    /// Nothing happens when the track is already playing.
+    pub fn play_music(&mut self, name: &str) -> Result<()> {
+        let (name, sound) = self
+            .music
+            .get_key_value(name)
+            .map(|(name, sound)| (*name, sound.clone()))
+            .ok_or_else(|| anyhow!("Music \"{}\" doesn't exist", name))?;
+        if matches!(self.playing_music, Some((playing, _)) if playing == name) {
+            return Ok(());
+        }
+
+        self.stop_music();
+
+        let id = self.mixer.play(sound);
+        self.mixer.set_volume(id, Volume(self.music_volume));
+        self.playing_music = Some((name, id));
+
+        Ok(())
+    }
+
+    /// Stop the music that's playing.
+    pub fn stop_music(&mut self) {
+        if let Some((_, id)) = self.playing_music.take() {
+            self.mixer.stop(id);
+        }
+    }
+
+    /// Play a sound effect from the scripts.
+    fn play_sound_script(&mut self, name: &str) -> GResult<()> {
+        match self.play_sfx(name) {
+            Ok(()) => Ok(()),
+            Err(err) => bail!("{}", err),
+        }
+    }
+
+    /// Play a music track from the scripts.
+    fn play_music_script(&mut self, name: &str) -> GResult<()> {
+        match self.play_music(name) {
+            Ok(()) => Ok(()),
+            Err(err) => bail!("{}", err),
+        }
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("play-sound", rfn!(Self::play_sound_script))?;
+            glsp::bind_rfn("play-music", rfn!(Self::play_music_script))?;
+            glsp::bind_rfn("stop-music", rfn!(Self::stop_music))?;
+
+            Ok(())
+        });
+    }
+
+    /// Bind the GameLisp functions without an audio device, nothing is played.
+    ///
+    /// The names are still checked, so a script playing a sound that doesn't exist fails like it
+    /// does in the game.
+    pub fn bind_silent_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("play-sound", rfn!(check_sound))?;
+            glsp::bind_rfn("play-music", rfn!(check_music))?;
+            glsp::bind_rfn("stop-music", rfn!(stop_silent_music))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Fail when a sound effect doesn't exist, without playing it.
+fn check_sound(name: &str) -> GResult<()> {
+    if !SOUNDS.iter().any(|(sound, _)| *sound == name) {
+        bail!("Sound \"{}\" doesn't exist", name);
+    }
+
+    Ok(())
+}
+
+/// Fail when a music track doesn't exist, without playing it.
+fn check_music(name: &str) -> GResult<()> {
+    if !MUSIC.iter().any(|(music, _)| *music == name) {
+        bail!("Music \"{}\" doesn't exist", name);
+    }
+
+    Ok(())
+}
+
+/// There's no music playing without an audio device.
+fn stop_silent_music() {}
@@ -0,0 +1,433 @@
+use crate::{
+    replication::EntityId,
+    tween::{Property, Value},
+};
+use glsp::{lib, rfn, Runtime};
+
+/// Mouse wheel steps between the default zoom and the closest or farthest zoom.
+const ZOOM_STEPS: f32 = 20.0;
+/// Mouse wheel steps to double the zoom.
+const ZOOM_STEPS_PER_DOUBLING: f32 = 20.0;
+/// How fast a followed entity is caught up with, higher is faster.
+const FOLLOW_SPEED: f32 = 5.0;
+/// Trauma removed every second, so the strongest shake lasts a second.
+const TRAUMA_DECAY: f32 = 1.0;
+/// Farthest the view is moved by the strongest shake in screen pixels.
+const MAX_SHAKE_OFFSET: f32 = 24.0;
+/// Largest rotation of the view by the strongest shake in radians.
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+/// How fast the view shakes back & forth, in radians of the noise per second.
+const SHAKE_FREQUENCY: f32 = 30.0;
+/// How fast a zoom punch returns to the normal zoom, higher is faster.
+const PUNCH_SPEED: f32 = 8.0;
+
+/// A smooth movement of the view towards a world position.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Pan {
+    /// The world position at the start.
+    from: (f32, f32),
+    /// The world position at the end.
+    to: (f32, f32),
+    /// The duration of the movement in seconds.
+    duration: f32,
+    /// The time since the start in seconds.
+    elapsed: f32,
+}
+
+/// The part of the camera scripts can read, saved for seeking in replays.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraState {
+    /// The world position in the center of the screen.
+    pub position: (f32, f32),
+    /// Screen pixels per world unit.
+    pub zoom: f32,
+    /// Mouse wheel steps from the default zoom.
+    pub zoom_steps: f32,
+    /// Rotation of the view in radians.
+    pub rotation: f32,
+    /// The entity that's kept in the center of the screen.
+    pub target: Option<EntityId>,
+    /// The screen position where the mouse was while dragging the view.
+    pub drag: Option<(f32, f32)>,
+}
+
+lib! {
+/// The view of the world, converts world coordinates to the screen and back.
+pub struct Camera {
+    /// The world position in the center of the screen.
+    position: (f32, f32),
+    /// Screen pixels per world unit.
+    zoom: f32,
+    /// Mouse wheel steps from the default zoom.
+    zoom_steps: f32,
+    /// Rotation of the view in radians.
+    rotation: f32,
+    /// The size of the screen in logical pixels.
+    viewport: (f32, f32),
+    /// Physical pixels of the framebuffer per logical pixel, above 1.0 on HiDPI displays.
+    dpi_scale: f32,
+    /// The entity that's kept in the center of the screen.
+    target: Option<EntityId>,
+    /// The screen position where the mouse was while dragging the view.
+    drag: Option<(f32, f32)>,
+    /// How much the view shakes, from 0.0 to 1.0.
+    trauma: f32,
+    /// The time the view has been shaking in seconds, the position in the noise.
+    shake_time: f32,
+    /// The factor the zoom is temporarily multiplied with, returns to 0.0.
+    punch: f32,
+    /// The movement towards a world position that's playing.
+    pan: Option<Pan>,
+}
+}
+
+impl Camera {
+    /// Look at the origin without zooming.
+    pub fn new() -> Self {
+        Self {
+            position: (0.0, 0.0),
+            zoom: 1.0,
+            zoom_steps: 0.0,
+            rotation: 0.0,
+            viewport: (1.0, 1.0),
+            dpi_scale: 1.0,
+            target: None,
+            drag: None,
+            trauma: 0.0,
+            shake_time: 0.0,
+            punch: 0.0,
+            pan: None,
+        }
+    }
+
+    /// Get the view, without the size of the screen.
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            zoom: self.zoom,
+            zoom_steps: self.zoom_steps,
+            rotation: self.rotation,
+            target: self.target,
+            drag: self.drag,
+        }
+    }
+
+    /// Continue from a previously saved view.
+    pub fn restore(&mut self, state: CameraState) {
+        self.position = state.position;
+        self.zoom = state.zoom;
+        self.zoom_steps = state.zoom_steps;
+        self.rotation = state.rotation;
+        self.target = state.target;
+        self.drag = state.drag;
+    }
+
+    /// Set the size of the screen in logical pixels, should be called before converting
+    /// coordinates.
+    pub fn set_viewport(&mut self, width: f32, height: f32) {
+        self.viewport = (width.max(1.0), height.max(1.0));
+    }
+
+    /// Set the physical pixels per logical pixel of the display.
+    pub fn set_dpi_scale(&mut self, scale: f32) {
+        self.dpi_scale = scale.max(f32::EPSILON);
+    }
+
+    /// Physical pixels per logical pixel of the display.
+    pub fn dpi_scale(&self) -> f32 {
+        self.dpi_scale
+    }
+
+    /// Convert a position in physical pixels, like the mouse events, to logical pixels.
+    pub fn to_logical(&self, x: f32, y: f32) -> (f32, f32) {
+        (x / self.dpi_scale, y / self.dpi_scale)
+    }
+
+    /// Look at a world position, stops following.
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.position = (x, y);
+        self.target = None;
+        self.pan = None;
+    }
+
+    /// The world position in the center of the screen.
+    pub fn position(&self) -> (f32, f32) {
+        self.position
+    }
+
+    /// Set the amount of screen pixels per world unit.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(std::f32::EPSILON);
+        self.zoom_steps = self.zoom.log2() * ZOOM_STEPS_PER_DOUBLING;
+    }
+
+    /// Zoom in or out with mouse wheel steps, limited to the closest & farthest zoom.
+    pub fn zoom_by(&mut self, steps: f32) {
+        self.zoom_steps = (self.zoom_steps + steps).max(-ZOOM_STEPS).min(ZOOM_STEPS);
+        self.zoom = (self.zoom_steps / ZOOM_STEPS_PER_DOUBLING).exp2();
+    }
+
+    /// Set the rotation of the view in radians.
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// The value of a property a tween animates, `None` when the camera doesn't have it.
+    pub fn tween_value(&self, property: Property) -> Option<Value> {
+        match property {
+            Property::Position => Some(Value::Point(self.position.0, self.position.1)),
+            Property::Rotation => Some(Value::Scalar(self.rotation)),
+            Property::Zoom => Some(Value::Scalar(self.zoom)),
+            Property::Scale => None,
+        }
+    }
+
+    /// Set a property to the value of a tween, moving the view stops following.
+    pub fn apply_tween(&mut self, property: Property, value: Value) {
+        match (property, value) {
+            (Property::Position, Value::Point(x, y)) => self.set_position(x, y),
+            (Property::Rotation, Value::Scalar(rotation)) => self.set_rotation(rotation),
+            (Property::Zoom, Value::Scalar(zoom)) => self.set_zoom(zoom),
+            _ => (),
+        }
+    }
+
+    /// Keep an entity in the center of the screen, smoothly moving towards it.
+    pub fn follow(&mut self, entity: EntityId) {
+        self.target = Some(entity);
+        self.pan = None;
+    }
+
+    /// Follow an entity by the index & generation the scripts know it by.
+    fn follow_script(&mut self, entity: (i32, i32)) {
+        self.follow(EntityId::from_script(entity));
+    }
+
+    /// The entity that's followed.
+    pub fn target(&self) -> Option<EntityId> {
+        self.target
+    }
+
+    /// Shake the view, the shake is the square of the trauma so small amounts add up.
+    ///
+    /// The trauma is limited to 1.0 and decays over time.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).max(0.0).min(1.0);
+    }
+
+    /// Zoom in by a factor of the zoom, like 0.1 for 10% closer, quickly returning to the zoom.
+    pub fn punch_zoom(&mut self, amount: f32) {
+        self.punch += amount;
+    }
+
+    /// Smoothly move the view to a world position in a duration in seconds, stops following.
+    pub fn pan_to(&mut self, x: f32, y: f32, duration: f32) {
+        self.target = None;
+        self.pan = Some(Pan {
+            from: self.position,
+            to: (x, y),
+            duration: duration.max(std::f32::EPSILON),
+            elapsed: 0.0,
+        });
+    }
+
+    /// Advance the effects and move towards the position of the followed entity, should be
+    /// called every frame.
+    ///
+    /// The target stops being followed when its position is `None` because it doesn't exist.
+    pub fn update(&mut self, delta: f32, target_position: Option<(f32, f32)>) {
+        self.trauma = (self.trauma - TRAUMA_DECAY * delta).max(0.0);
+        self.shake_time = if self.trauma > 0.0 {
+            self.shake_time + delta
+        } else {
+            0.0
+        };
+        self.punch *= (-PUNCH_SPEED * delta).exp();
+
+        if let Some(mut pan) = self.pan {
+            pan.elapsed += delta;
+
+            // Ease in & out
+            let t = (pan.elapsed / pan.duration).min(1.0);
+            let t = t * t * (3.0 - 2.0 * t);
+            self.position = (
+                pan.from.0 + (pan.to.0 - pan.from.0) * t,
+                pan.from.1 + (pan.to.1 - pan.from.1) * t,
+            );
+
+            self.pan = if pan.elapsed < pan.duration {
+                Some(pan)
+            } else {
+                None
+            };
+        }
+
+        if self.target.is_none() {
+            return;
+        }
+
+        match target_position {
+            Some((x, y)) => {
+                // Exponential smoothing, independent of the frame rate
+                let factor = 1.0 - (-FOLLOW_SPEED * delta).exp();
+                self.position.0 += (x - self.position.0) * factor;
+                self.position.1 += (y - self.position.1) * factor;
+            }
+            None => self.target = None,
+        }
+    }
+
+    /// Start moving the view with the mouse at a screen position.
+    pub fn start_drag(&mut self, x: f32, y: f32) {
+        self.drag = Some((x, y));
+        self.target = None;
+        self.pan = None;
+    }
+
+    /// Move the view so the world position under the mouse stays the same while dragging.
+    pub fn drag(&mut self, x: f32, y: f32) {
+        if let Some((prev_x, prev_y)) = self.drag {
+            let (from_x, from_y) = self.screen_to_world(prev_x, prev_y);
+            let (to_x, to_y) = self.screen_to_world(x, y);
+            self.position.0 -= to_x - from_x;
+            self.position.1 -= to_y - from_y;
+
+            self.drag = Some((x, y));
+        }
+    }
+
+    /// Stop moving the view with the mouse.
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// The position, zoom & rotation of what's shown, with the shake & the zoom punch.
+    fn view(&self) -> ((f32, f32), f32, f32) {
+        let zoom = self.zoom * (1.0 + self.punch);
+        if self.trauma <= 0.0 {
+            return (self.position, zoom, self.rotation);
+        }
+
+        // Smooth noise from sines with unrelated frequencies, differently for every direction
+        let noise = |seed: f32| {
+            let t = self.shake_time * SHAKE_FREQUENCY + seed;
+
+            (t.sin() + (t * 2.3).sin() * 0.5 + (t * 4.7).sin() * 0.25) / 1.75
+        };
+        let shake = self.trauma * self.trauma;
+
+        (
+            (
+                self.position.0 + noise(0.0) * shake * MAX_SHAKE_OFFSET / zoom,
+                self.position.1 + noise(10.0) * shake * MAX_SHAKE_OFFSET / zoom,
+            ),
+            zoom,
+            self.rotation + noise(20.0) * shake * MAX_SHAKE_ANGLE,
+        )
+    }
+
+    /// Convert a screen position in logical pixels to a world position.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (position, zoom, rotation) = self.view();
+
+        // Relative to the center of the screen, the Y axis points down in both
+        let x = (x - self.viewport.0 / 2.0) / zoom;
+        let y = (y - self.viewport.1 / 2.0) / zoom;
+
+        let (sin, cos) = rotation.sin_cos();
+
+        (
+            x * cos - y * sin + position.0,
+            x * sin + y * cos + position.1,
+        )
+    }
+
+    /// The smallest axis aligned rectangle in world positions containing the whole screen, as the
+    /// top left & bottom right corners.
+    pub fn visible_rect(&self) -> ((f32, f32), (f32, f32)) {
+        let (width, height) = self.viewport;
+        let corners = [
+            self.screen_to_world(0.0, 0.0),
+            self.screen_to_world(width, 0.0),
+            self.screen_to_world(0.0, height),
+            self.screen_to_world(width, height),
+        ];
+
+        corners.iter().fold(
+            (corners[0], corners[0]),
+            |((min_x, min_y), (max_x, max_y)), &(x, y)| {
+                ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+            },
+        )
+    }
+
+    /// Convert a world position to a screen position in pixels.
+    pub fn world_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        let (position, zoom, rotation) = self.view();
+        let (x, y) = (x - position.0, y - position.1);
+
+        // Rotate the other way around
+        let (sin, cos) = rotation.sin_cos();
+        let (x, y) = (x * cos + y * sin, -x * sin + y * cos);
+
+        (
+            x * zoom + self.viewport.0 / 2.0,
+            y * zoom + self.viewport.1 / 2.0,
+        )
+    }
+
+    /// The column-major matrix converting world positions to clip space.
+    pub fn transform(&self) -> [f32; 16] {
+        let (position, zoom, rotation) = self.view();
+        let (sin, cos) = rotation.sin_cos();
+        let scale_x = 2.0 * zoom / self.viewport.0;
+        let scale_y = 2.0 * zoom / self.viewport.1;
+
+        // Flip the Y axis * scale * rotate(-rotation) * translate(-position)
+        let (a, b, c, d) = (cos * scale_x, sin * scale_y, sin * scale_x, -cos * scale_y);
+        let (px, py) = position;
+
+        [
+            a,
+            b,
+            0.0,
+            0.0,
+            c,
+            d,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+            0.0,
+            -(a * px + c * py),
+            -(b * px + d * py),
+            0.0,
+            1.0,
+        ]
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("set-camera-pos", rfn!(Self::set_position))?;
+            glsp::bind_rfn("camera-pos", rfn!(Self::position))?;
+            glsp::bind_rfn("set-camera-zoom", rfn!(Self::set_zoom))?;
+            glsp::bind_rfn("set-camera-rotation", rfn!(Self::set_rotation))?;
+            glsp::bind_rfn("camera-follow", rfn!(Self::follow_script))?;
+            glsp::bind_rfn("add-camera-trauma", rfn!(Self::add_trauma))?;
+            glsp::bind_rfn("camera-zoom-punch", rfn!(Self::punch_zoom))?;
+            glsp::bind_rfn("camera-pan-to", rfn!(Self::pan_to))?;
+            glsp::bind_rfn("screen->world", rfn!(Self::screen_to_world))?;
+            glsp::bind_rfn("world->screen", rfn!(Self::world_to_screen))?;
+
+            Ok(())
+        });
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
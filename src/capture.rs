@@ -0,0 +1,156 @@
+use crate::render::Readback;
+use std::collections::VecDeque;
+
+/// Amount of seconds of gameplay kept.
+const DURATION: f64 = 5.0;
+/// Amount of frames captured every second.
+const FPS: f64 = 10.0;
+/// Only every n-th pixel in both directions is kept, to limit the memory & file size.
+const DOWNSCALE: usize = 2;
+/// Quality of the color quantization, between 1 (slow & best) and 30 (fast & worst).
+#[cfg(not(target_arch = "wasm32"))]
+const QUANTIZE_SPEED: i32 = 10;
+
+/// A downscaled frame in the ring buffer.
+#[derive(Debug, Clone)]
+struct CapturedFrame {
+    /// Width in pixels.
+    width: u16,
+    /// Height in pixels.
+    height: u16,
+    /// RGBA values of the pixels, starting with the top row.
+    pixels: Vec<u8>,
+}
+
+/// Keeps the last few seconds of rendered frames, so they can be exported as a GIF.
+pub struct Capture {
+    /// The captured frames, the newest at the back.
+    frames: VecDeque<CapturedFrame>,
+    /// The time the last frame was captured in seconds.
+    last_capture: f64,
+}
+
+impl Capture {
+    /// Create an empty ring buffer.
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::with_capacity(Self::capacity()),
+            last_capture: 0.0,
+        }
+    }
+
+    /// Whether enough time has passed since the last frame to capture the next one.
+    ///
+    /// Should be called every frame, if it returns true a readback should be requested.
+    pub fn wants_frame(&mut self) -> bool {
+        let now = miniquad::date::now();
+        if now - self.last_capture < 1.0 / FPS {
+            return false;
+        }
+
+        self.last_capture = now;
+
+        true
+    }
+
+    /// Add a frame that was read back, dropping the oldest when the buffer is full.
+    pub fn push(&mut self, readback: &Readback) {
+        let Readback {
+            width,
+            height,
+            pixels,
+        } = readback.downscaled(DOWNSCALE);
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        // Resizing the window makes the older frames useless for the same animation
+        let resized = self.frames.back().map_or(false, |frame| {
+            frame.width as usize != width || frame.height as usize != height
+        });
+        if resized {
+            self.frames.clear();
+        }
+
+        self.frames.push_back(CapturedFrame {
+            // GIF dimensions are limited to 16 bits
+            width: width.min(u16::MAX as usize) as u16,
+            height: height.min(u16::MAX as usize) as u16,
+            pixels,
+        });
+        while self.frames.len() > Self::capacity() {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Write the captured frames to a GIF file in the working directory.
+    ///
+    /// The encoding happens on a background thread, the result is logged.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export(&self) {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        if self.frames.is_empty() {
+            log::warn!("No frames captured yet");
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let path = format!("capture-{}.gif", timestamp);
+
+        log::info!("Exporting {} frames to \"{}\"", self.frames.len(), path);
+        let frames = self.frames.iter().cloned().collect::<Vec<_>>();
+
+        std::thread::spawn(move || match encode_gif(&path, frames) {
+            Ok(()) => log::info!("Capture written to \"{}\"", path),
+            Err(err) => log::error!("Writing capture \"{}\" failed: {}", path, err),
+        });
+    }
+
+    /// There's no filesystem in the browser, so nothing can be exported.
+    #[cfg(target_arch = "wasm32")]
+    pub fn export(&self) {
+        log::warn!("Exporting captures is not supported in the browser");
+    }
+
+    /// Maximum amount of frames in the ring buffer.
+    fn capacity() -> usize {
+        (DURATION * FPS) as usize
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encode the frames as a looping GIF file.
+#[cfg(not(target_arch = "wasm32"))]
+fn encode_gif(path: &str, frames: Vec<CapturedFrame>) -> anyhow::Result<()> {
+    use gif::{Encoder, Frame, Repeat};
+    use std::{fs::File, io::BufWriter};
+
+    let (width, height) = (frames[0].width, frames[0].height);
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = Encoder::new(file, width, height, &[])?;
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    // The delay is in hundredths of a second
+    let delay = (100.0 / FPS) as u16;
+    for mut captured in frames {
+        let mut frame = Frame::from_rgba_speed(
+            captured.width,
+            captured.height,
+            &mut captured.pixels,
+            QUANTIZE_SPEED,
+        );
+        frame.delay = delay;
+        encoder.write_frame(&frame)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use crate::{
+    camera::{Camera, CameraState},
+    clock::{Clock, ClockState},
+    random::Random,
+    tween::{Tweens, TweensState},
+    world::{World, WorldCheckpoint},
+};
+use anyhow::{anyhow, Result};
+use glsp::{lib, DequeAccess, DequeOps, GResult, Lib, Sym, Val};
+use std::collections::BTreeMap;
+
+/// Amount of frames between checkpoints, a lower amount makes seeking faster.
+pub const CHECKPOINT_INTERVAL: usize = 300;
+
+/// Everything simulated before a frame, the starting point when seeking through a replay.
+struct Checkpoint {
+    /// The units, objects & projectiles with their bodies & joints, the inventory & dragged stack.
+    world: WorldCheckpoint,
+    /// The state of the clock.
+    clock: ClockState,
+    /// The state of the random number generator, so seeking gets the same numbers.
+    random: u64,
+    /// The view, the scripts convert the mouse position with it.
+    camera: CameraState,
+    /// The running tweens of the camera & the units.
+    tweens: TweensState,
+    /// The values the main script defines at the top level.
+    values: Vec<(Sym, Val)>,
+}
+
+lib! {
+/// The checkpoints of the replay by the frame they're saved before.
+///
+/// The state of the scripts holds script values, so they're kept in the runtime.
+pub struct Checkpoints {
+    /// The saved checkpoints.
+    saved: BTreeMap<usize, Checkpoint>,
+}
+}
+
+impl Default for Checkpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Checkpoints {
+    /// Create without any checkpoints.
+    pub fn new() -> Self {
+        Self {
+            saved: BTreeMap::new(),
+        }
+    }
+
+    /// Remove all checkpoints, should be called inside the runtime.
+    pub fn clear(&mut self) {
+        self.saved.clear();
+    }
+
+    /// Save the state before a frame, should be called inside the runtime.
+    ///
+    /// A frame that already has a checkpoint keeps it, playing back after seeking passes the same
+    /// frames again.
+    pub fn save(&mut self, frame: usize, world: &World, script_source: &str) -> Result<()> {
+        if self.saved.contains_key(&frame) {
+            return Ok(());
+        }
+
+        let checkpoint = Checkpoint {
+            world: world.save_checkpoint(),
+            clock: Clock::borrow().state(),
+            random: Random::borrow().state(),
+            camera: Camera::borrow().state(),
+            tweens: Tweens::borrow().state(),
+            values: save_values(script_source).map_err(|err| anyhow!("{}", err))?,
+        };
+        self.saved.insert(frame, checkpoint);
+
+        Ok(())
+    }
+
+    /// The closest frame with a checkpoint at or before a frame.
+    pub fn closest(&self, frame: usize) -> Option<usize> {
+        self.saved
+            .range(..=frame)
+            .next_back()
+            .map(|(frame, _)| *frame)
+    }
+
+    /// Continue from the state before a frame, should be called inside the runtime.
+    pub fn restore(&self, frame: usize, world: &mut World) -> Result<()> {
+        let checkpoint = self
+            .saved
+            .get(&frame)
+            .ok_or_else(|| anyhow!("Checkpoint of frame {} doesn't exist", frame))?;
+
+        world.restore_checkpoint(&checkpoint.world)?;
+        Clock::borrow_mut().restore(checkpoint.clock);
+        Random::borrow_mut().restore(checkpoint.random);
+        Camera::borrow_mut().restore(checkpoint.camera);
+        Tweens::borrow_mut().restore(&checkpoint.tweens);
+        restore_values(&checkpoint.values).map_err(|err| anyhow!("{}", err))?;
+
+        Ok(())
+    }
+}
+
+/// Copy the values the main script defines with `(def name ...)`, should be called inside the
+/// runtime.
+///
+/// They're copied deeply, so changing them afterwards doesn't change the copies. The functions
+/// aren't copied, they don't change while the game runs.
+fn save_values(source: &str) -> GResult<Vec<(Sym, Val)>> {
+    let mut values = vec![];
+    for form in glsp::parse_all(source, Some("main.glsp"))? {
+        if let Some(name) = defined_value(&form) {
+            if glsp::has_global(name)? {
+                values.push((name, glsp::global::<_, Val>(name)?.deep_clone()?));
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Bind copies of saved values of the main script again, should be called inside the runtime.
+fn restore_values(values: &[(Sym, Val)]) -> GResult<()> {
+    for (name, value) in values {
+        glsp::set_global(*name, value.deep_clone()?)?;
+    }
+
+    Ok(())
+}
+
+/// The name of the global a top level `(def name ...)` form binds.
+fn defined_value(form: &Val) -> Option<Sym> {
+    let form = match form {
+        Val::Arr(form) if form.len() >= 2 => form,
+        _ => return None,
+    };
+
+    match (form.get::<Val>(0).ok()?, form.get::<Val>(1).ok()?) {
+        (Val::Sym(kind), Val::Sym(name)) if &*kind.name() == "def" => Some(name),
+        _ => None,
+    }
+}
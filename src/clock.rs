@@ -0,0 +1,222 @@
+use glsp::{lib, rfn, Runtime};
+use serde::{Deserialize, Serialize};
+
+/// Longest duration a single frame can take in seconds.
+///
+/// Prevents a spiral where catching up with the fixed steps takes longer than the frame itself.
+pub const MAX_DELTA: f64 = 0.25;
+/// Fastest the simulation can run compared to the real time.
+pub const MAX_TIME_SCALE: f64 = 4.0;
+
+/// The part of the clock that influences the simulation, saved for seeking in replays.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClockState {
+    /// The sum of all deltas in seconds.
+    pub elapsed: f64,
+    /// The time not yet consumed by fixed steps in seconds.
+    pub accumulator: f64,
+    /// The amount of ticks, missing in older saves & replays.
+    #[serde(default)]
+    pub frame: u64,
+}
+
+lib! {
+/// Measures the time between frames and accumulates it for the fixed steps.
+pub struct Clock {
+    /// The time of the last tick in seconds.
+    last_tick: f64,
+    /// The clamped time between the last two ticks in seconds.
+    delta: f64,
+    /// The sum of all deltas in seconds.
+    elapsed: f64,
+    /// The time not yet consumed by fixed steps in seconds.
+    accumulator: f64,
+    /// The amount of ticks since the clock started.
+    frame: u64,
+    /// The duration of a single fixed step in seconds.
+    fixed_step: f64,
+    /// Whether the time stands still.
+    paused: bool,
+    /// How fast the time passes compared to the real time.
+    time_scale: f64,
+    /// Whether the next tick advances a single fixed step while paused.
+    step_once: bool,
+    /// Whether every tick advances a fixed step instead of the measured time.
+    fixed_only: bool,
+}
+}
+
+impl Clock {
+    /// Start measuring from now.
+    pub fn new(fixed_step: f64) -> Self {
+        Self {
+            last_tick: miniquad::date::now(),
+            delta: 0.0,
+            elapsed: 0.0,
+            accumulator: 0.0,
+            frame: 0,
+            fixed_step,
+            paused: false,
+            time_scale: 1.0,
+            step_once: false,
+            fixed_only: false,
+        }
+    }
+
+    /// Measure the time since the previous tick, should be called once every frame.
+    ///
+    /// The measured time is scaled, so the recorded delta of a replay is already scaled.
+    pub fn tick(&mut self) {
+        let delta = if self.fixed_only {
+            self.fixed_step
+        } else {
+            (miniquad::date::now() - self.last_tick)
+                .max(0.0)
+                .min(MAX_DELTA)
+        };
+
+        let delta = if self.step_once {
+            self.step_once = false;
+
+            self.fixed_step
+        } else if self.paused {
+            0.0
+        } else {
+            delta * self.time_scale
+        };
+
+        self.tick_with(delta);
+    }
+
+    /// Tick with a recorded delta instead of the measured time, used when playing back replays.
+    pub fn tick_with(&mut self, delta: f64) {
+        self.delta = delta;
+        self.last_tick = miniquad::date::now();
+
+        self.elapsed += self.delta;
+        self.accumulator += self.delta;
+        self.frame += 1;
+    }
+
+    /// Skip the time since the previous tick without simulating it, so the simulation doesn't
+    /// jump ahead after it wasn't advanced for a while.
+    ///
+    /// Returns the skipped time in seconds.
+    pub fn skip(&mut self) -> f64 {
+        let now = miniquad::date::now();
+        let skipped = (now - self.last_tick).max(0.0);
+        self.last_tick = now;
+
+        skipped
+    }
+
+    /// Get the state that influences the simulation.
+    pub fn state(&self) -> ClockState {
+        ClockState {
+            elapsed: self.elapsed,
+            accumulator: self.accumulator,
+            frame: self.frame,
+        }
+    }
+
+    /// Continue from a previously saved state.
+    pub fn restore(&mut self, state: ClockState) {
+        self.elapsed = state.elapsed;
+        self.accumulator = state.accumulator;
+        self.frame = state.frame;
+    }
+
+    /// Consume a fixed step from the accumulated time.
+    ///
+    /// Returns whether a fixed step should be executed, call it in a loop.
+    pub fn fixed_update(&mut self) -> bool {
+        if self.accumulator >= self.fixed_step {
+            self.accumulator -= self.fixed_step;
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How far the accumulated time is between the last & the next fixed step, from 0.0 to 1.0.
+    ///
+    /// Used to interpolate the positions of the bodies between the last two steps.
+    pub fn alpha(&self) -> f64 {
+        (self.accumulator / self.fixed_step).max(0.0).min(1.0)
+    }
+
+    /// The time between the last two frames in seconds.
+    pub fn delta(&self) -> f64 {
+        self.delta
+    }
+
+    /// The time since the clock started in seconds, without the clamped time.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// The amount of ticks since the clock started, also counted while paused.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// The duration of a single fixed step in seconds.
+    pub fn fixed_step(&self) -> f64 {
+        self.fixed_step
+    }
+
+    /// Whether the time stands still.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop or continue the time.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// How fast the time passes compared to the real time.
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Slow down or speed up the time, between 0.0 & `MAX_TIME_SCALE`.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.max(0.0).min(MAX_TIME_SCALE);
+    }
+
+    /// Advance a fixed step every tick instead of the measured time, so the simulation doesn't
+    /// depend on the frame rate of the machine.
+    pub fn set_fixed_only(&mut self, fixed_only: bool) {
+        self.fixed_only = fixed_only;
+    }
+
+    /// Pause and advance a single fixed step at the next tick.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step_once = true;
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("delta-time", rfn!(Self::delta))?;
+            glsp::bind_rfn("elapsed-time", rfn!(Self::elapsed))?;
+            // Replaces the wall clock of the standard library, which isn't bound on the web
+            if glsp::has_global("time")? {
+                glsp::del_global("time")?;
+            }
+            glsp::bind_rfn("time", rfn!(Self::elapsed))?;
+            glsp::bind_rfn("frame", rfn!(Self::frame))?;
+            glsp::bind_rfn("fixed-step", rfn!(Self::fixed_step))?;
+            glsp::bind_rfn("paused?", rfn!(Self::paused))?;
+            glsp::bind_rfn("set-paused", rfn!(Self::set_paused))?;
+            glsp::bind_rfn("time-scale", rfn!(Self::time_scale))?;
+            glsp::bind_rfn("set-time-scale", rfn!(Self::set_time_scale))?;
+            glsp::bind_rfn("step-once", rfn!(Self::step))?;
+
+            Ok(())
+        });
+    }
+}
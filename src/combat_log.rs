@@ -0,0 +1,186 @@
+use crate::{
+    event::Event,
+    i18n::I18n,
+    render::{ClipRect, Instance, Mesh, Render},
+    text::FontInstance,
+};
+use std::collections::VecDeque;
+
+/// Height of a single line in pixels.
+const LINE_HEIGHT: f32 = 20.0;
+/// Space between the border of the panel and the text in pixels.
+const PADDING: f32 = 8.0;
+/// Scale of the glyphs.
+const TEXT_SCALE: f32 = 0.15;
+
+/// The z index of the background.
+const BACKGROUND_Z: u8 = 0;
+/// The z index of the text.
+const TEXT_Z: u8 = 1;
+
+/// A single timestamped message.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// Seconds since the start of the game.
+    pub time: f64,
+    /// The message to show.
+    pub message: String,
+}
+
+/// Screen space panel showing the history of gameplay messages.
+pub struct CombatLog {
+    /// The messages, the newest at the back.
+    entries: VecDeque<LogEntry>,
+    /// Maximum amount of messages kept in the history.
+    max_entries: usize,
+    /// How far the panel is scrolled up in pixels, zero shows the newest messages.
+    scroll: f32,
+    /// Position of the top left corner in pixels.
+    pos: (f32, f32),
+    /// Size of the panel in pixels.
+    size: (f32, f32),
+    /// Nine-slice mesh for the background of the panel, stretched to the size.
+    background_mesh: Mesh,
+    /// Last known mouse position.
+    mouse: (f32, f32),
+}
+
+impl CombatLog {
+    /// Create the panel with a nine-slice background mesh.
+    pub fn new(
+        background_mesh: Mesh,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        max_entries: usize,
+    ) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries,
+            scroll: 0.0,
+            pos: (x, y),
+            size: (width, height),
+            background_mesh,
+            mouse: (0.0, 0.0),
+        }
+    }
+
+    /// Add a message to the log.
+    pub fn push(&mut self, time: f64, message: String) {
+        self.entries.push_back(LogEntry { time, message });
+
+        // Drop the oldest messages when the history is full
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+
+        // Keep the same messages in view when scrolled up
+        if self.scroll > 0.0 {
+            self.scroll = (self.scroll + LINE_HEIGHT).min(self.max_scroll());
+        }
+    }
+
+    /// Add a message for a gameplay event.
+    pub fn handle_event(&mut self, time: f64, event: &Event, i18n: &I18n) {
+        let message = match event {
+            Event::DamageDealt { amount } => {
+                i18n.t("log-damage-dealt", &[("amount", &amount.to_string())])
+            }
+            Event::WaveStarted { wave } => {
+                i18n.t("log-wave-started", &[("wave", &wave.to_string())])
+            }
+        };
+
+        self.push(time, message);
+    }
+
+    /// Move the top left corner of the panel.
+    pub fn set_pos(&mut self, x: f32, y: f32) {
+        self.pos = (x, y);
+    }
+
+    /// Whether a screen position is inside the panel.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.pos.0
+            && y >= self.pos.1
+            && x < self.pos.0 + self.size.0
+            && y < self.pos.1 + self.size.1
+    }
+
+    /// Handle miniquad mouse motion events.
+    pub fn mouse_motion(&mut self, x: f32, y: f32) {
+        self.mouse = (x, y);
+    }
+
+    /// Handle miniquad mouse wheel events.
+    ///
+    /// Returns whether the event is consumed by the panel.
+    pub fn mouse_wheel(&mut self, y: f32) -> bool {
+        if !self.contains(self.mouse.0, self.mouse.1) {
+            return false;
+        }
+
+        self.scroll = (self.scroll + y * LINE_HEIGHT)
+            .max(0.0)
+            .min(self.max_scroll());
+
+        true
+    }
+
+    /// Queue the screen space instances of the panel for the next frame.
+    pub fn draw(&self, font: &mut FontInstance, render: &mut Render) {
+        let mut background = Instance::new(self.pos.0, self.pos.1);
+        background.set_z(BACKGROUND_Z);
+        background.set_size(self.size.0, self.size.1);
+        render.draw_screen(self.background_mesh, background);
+
+        // Only show the text inside the padding of the panel
+        let clip = ClipRect {
+            x: (self.pos.0 + PADDING) as i32,
+            y: (self.pos.1 + PADDING) as i32,
+            width: (self.size.0 - PADDING * 2.0) as i32,
+            height: (self.size.1 - PADDING * 2.0) as i32,
+        };
+
+        // Draw the newest message at the bottom
+        let top = self.pos.1 + PADDING;
+        let bottom = self.pos.1 + self.size.1 - PADDING + self.scroll;
+        for (index, entry) in self.entries.iter().rev().enumerate() {
+            let line_bottom = bottom - index as f32 * LINE_HEIGHT;
+
+            // Skip the lines scrolled below the panel
+            if line_bottom - LINE_HEIGHT > self.pos.1 + self.size.1 {
+                continue;
+            }
+            // All the following lines are above the panel
+            if line_bottom < top {
+                break;
+            }
+
+            let line = format!("{} {}", format_time(entry.time), entry.message);
+            for (mut instance, mesh) in font.text_scaled(
+                render,
+                &line,
+                self.pos.0 + PADDING,
+                line_bottom - 4.0,
+                TEXT_SCALE,
+            ) {
+                instance.set_z(TEXT_Z);
+                render.draw_screen_clipped(mesh, instance, clip);
+            }
+        }
+    }
+
+    /// How far the panel can be scrolled up in pixels.
+    fn max_scroll(&self) -> f32 {
+        (self.entries.len() as f32 * LINE_HEIGHT - (self.size.1 - PADDING * 2.0)).max(0.0)
+    }
+}
+
+/// Format seconds as minutes and seconds.
+fn format_time(time: f64) -> String {
+    let seconds = time.max(0.0) as u64;
+
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,433 @@
+use crate::{
+    input::{AxisBinding, Binding, GamepadAxis, GamepadButton},
+    key::Key,
+    post::PostConfig,
+    render::{MAX_UI_SCALE, MIN_UI_SCALE},
+};
+use anyhow::{anyhow, Result};
+use miniquad::KeyCode;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io::ErrorKind};
+use toml::{value::Table, Value};
+
+/// Amount the UI scale changes with every step.
+pub const UI_SCALE_STEP: f32 = 0.25;
+/// Lowest & highest amount of fixed steps per second.
+const TICK_RATE_RANGE: (u32, u32) = (10, 480);
+
+/// Options loaded at startup from a TOML file, every missing option uses the default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The language code of the translations.
+    pub language: String,
+    /// The window the game is shown in.
+    pub window: WindowConfig,
+    /// How the game is rendered.
+    pub display: DisplayConfig,
+    /// The loudness of the sounds.
+    pub audio: AudioConfig,
+    /// How the gameplay is simulated.
+    pub simulation: SimulationConfig,
+    /// Which debugging tools are enabled.
+    pub debug: DebugConfig,
+    /// Where the assets are located.
+    pub assets: AssetsConfig,
+    /// Which messages are logged where.
+    pub log: LogConfig,
+    /// How to connect to the other player.
+    pub network: NetworkConfig,
+    /// The keys & gamepad buttons of the actions the scripts check.
+    pub input: InputConfig,
+}
+
+/// Options for the window, only applied at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// Title shown in the title bar.
+    pub title: String,
+    /// The preferred width in pixels.
+    pub width: i32,
+    /// The preferred height in pixels.
+    pub height: i32,
+    /// Whether to start in fullscreen.
+    pub fullscreen: bool,
+    /// Amount of samples used for anti-aliasing.
+    pub sample_count: i32,
+    /// Whether to render in the full resolution of HiDPI displays instead of being upscaled.
+    pub high_dpi: bool,
+}
+
+/// Options for rendering the frames.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Scale of the screen space layer.
+    pub ui_scale: f32,
+    /// Whether to wait for the vertical blank, only applied at startup where it's supported.
+    pub vsync: bool,
+    /// Maximum frames per second, zero doesn't limit it.
+    pub fps_cap: u32,
+    /// Maximum frames per second when the window is in the background.
+    pub background_fps: u32,
+    /// The full screen effects applied to every frame.
+    pub post: PostConfig,
+}
+
+/// Volumes between 0.0 and 1.0.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Multiplier for all sounds.
+    pub master_volume: f32,
+    /// Volume of the music.
+    pub music_volume: f32,
+    /// Volume of the sound effects.
+    pub effects_volume: f32,
+}
+
+/// Options for the simulation, only applied at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+    /// Amount of fixed physics & gameplay steps per second.
+    pub tick_rate: u32,
+    /// Seed of the random numbers the scripts get.
+    pub seed: u64,
+    /// Whether every frame advances exactly one fixed step instead of the measured time.
+    pub deterministic: bool,
+}
+
+/// Flags for the debugging tools.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Draw the shapes of the physics bodies.
+    pub physics: bool,
+    /// Show the frame timings.
+    pub profiler: bool,
+    /// Show the most recent log messages.
+    pub log_view: bool,
+    /// Replay file played back instead of taking input.
+    pub replay: Option<String>,
+    /// File the replay of the session is written to when quitting.
+    pub record: Option<String>,
+    /// Scene loaded by the main script as soon as the assets are loaded.
+    pub scene: Option<String>,
+    /// GPU memory in megabytes the meshes may use before a warning is logged, 0 for no limit.
+    pub memory_budget: u32,
+}
+
+/// Options for the asset files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssetsConfig {
+    /// Directory the assets are read from when they aren't embedded.
+    pub root: String,
+}
+
+/// Options for the logger, only applied at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Level for all modules without a filter, like "info".
+    pub level: String,
+    /// File all messages are also written to.
+    pub file: Option<String>,
+    /// Levels for specific modules, like "replace_me::render" = "warn".
+    pub filters: BTreeMap<String, String>,
+}
+
+/// Whether & how a multiplayer session is started.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkMode {
+    /// Play alone.
+    Offline,
+    /// Wait for another player to join.
+    Host,
+    /// Join a player that's hosting.
+    Join,
+    /// Simulate the world for the clients that connect.
+    Server,
+    /// Connect to a server and show the world it simulates.
+    Client,
+}
+
+/// The actions & axes the scripts check, with the keys & gamepad buttons triggering them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// Gamepad stick positions closer to the center are ignored, between 0.0 and 1.0.
+    pub deadzone: f32,
+    /// The keys & buttons of every action, like "jump".
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    /// The keys, buttons & stick of every axis, like "move-x".
+    pub axes: BTreeMap<String, AxisBinding>,
+}
+
+/// Options for multiplayer, only applied at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Whether to host or join a session.
+    pub mode: NetworkMode,
+    /// The address of the host or server when joining.
+    pub host: String,
+    /// The UDP port the host or server listens on.
+    pub port: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            language: "en".to_string(),
+            window: WindowConfig::default(),
+            display: DisplayConfig::default(),
+            audio: AudioConfig::default(),
+            simulation: SimulationConfig::default(),
+            debug: DebugConfig::default(),
+            assets: AssetsConfig::default(),
+            log: LogConfig::default(),
+            network: NetworkConfig::default(),
+            input: InputConfig::default(),
+        }
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: concat!("replace_me - ", env!("CARGO_PKG_VERSION")).to_string(),
+            width: 800,
+            height: 600,
+            fullscreen: false,
+            sample_count: 8,
+            high_dpi: true,
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            ui_scale: 1.0,
+            vsync: true,
+            fps_cap: 0,
+            background_fps: 15,
+            post: PostConfig::default(),
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            music_volume: 0.8,
+            effects_volume: 1.0,
+        }
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60,
+            seed: 0,
+            deterministic: false,
+        }
+    }
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        Self {
+            root: "assets".to_string(),
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            file: None,
+            filters: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            mode: NetworkMode::Offline,
+            host: "127.0.0.1".to_string(),
+            port: 7777,
+        }
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        let key = |code| Binding::Key(Key::from(code));
+
+        Self {
+            deadzone: 0.2,
+            actions: vec![
+                (
+                    "jump".to_string(),
+                    vec![key(KeyCode::Up), Binding::Gamepad(GamepadButton::South)],
+                ),
+                (
+                    "fire".to_string(),
+                    vec![
+                        key(KeyCode::Space),
+                        Binding::Gamepad(GamepadButton::RightTrigger),
+                    ],
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            axes: vec![(
+                "move-x".to_string(),
+                AxisBinding {
+                    negative: vec![key(KeyCode::Left), key(KeyCode::A)],
+                    positive: vec![key(KeyCode::Right), key(KeyCode::D)],
+                    gamepad: Some(GamepadAxis::LeftStickX),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration from a TOML file and apply the overrides on top.
+    ///
+    /// The file is created with the defaults when it doesn't exist yet. The overrides are in the
+    /// form `display.vsync=false`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load<P: AsRef<Path>>(path: P, overrides: &[String]) -> Result<Self> {
+        let path = path.as_ref();
+
+        let value = match fs::read_to_string(path) {
+            Ok(source) => source.parse::<Value>()?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                let config = Self::default();
+                config.save(path)?;
+
+                Value::try_from(config)?
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Self::with_overrides(value, overrides)
+    }
+
+    /// There's no filesystem in the browser, so the overrides are applied on the defaults.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load<P: AsRef<Path>>(_path: P, overrides: &[String]) -> Result<Self> {
+        Self::load_defaults(overrides)
+    }
+
+    /// The defaults with the overrides applied on top, for when the file can't be used.
+    pub fn load_defaults(overrides: &[String]) -> Result<Self> {
+        Self::with_overrides(Value::try_from(Self::default())?, overrides)
+    }
+
+    /// Write the configuration to a TOML file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// There's no filesystem in the browser, so the configuration only lasts for the session.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save<P: AsRef<Path>>(&self, _path: P) -> Result<()> {
+        Ok(())
+    }
+
+    /// Change an option in the TOML file, without storing the overrides.
+    pub fn modify_file<P, F>(path: P, modify: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Self),
+    {
+        let mut config = Self::load(&path, &[])?;
+        modify(&mut config);
+
+        config.save(path)
+    }
+
+    /// Apply the overrides on the parsed configuration and read it.
+    fn with_overrides(mut value: Value, overrides: &[String]) -> Result<Self> {
+        for arg in overrides {
+            apply_override(&mut value, arg)?;
+        }
+
+        // The UI positions are divided by the scale, so it must stay in range
+        let mut config: Self = value.try_into()?;
+        config.display.set_ui_scale(config.display.ui_scale);
+
+        Ok(config)
+    }
+}
+
+impl DisplayConfig {
+    /// Set the UI scale, clamped to the allowed range.
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale.max(MIN_UI_SCALE).min(MAX_UI_SCALE);
+    }
+}
+
+impl SimulationConfig {
+    /// The duration of a single fixed step in seconds, the tick rate is clamped to a sane range.
+    pub fn fixed_step(&self) -> f64 {
+        let tick_rate = self.tick_rate.max(TICK_RATE_RANGE.0).min(TICK_RATE_RANGE.1);
+
+        1.0 / f64::from(tick_rate)
+    }
+}
+
+/// Set an option in the form `section.key=value` in the parsed configuration.
+///
+/// Values that aren't valid TOML are used as strings.
+fn apply_override(config: &mut Value, arg: &str) -> Result<()> {
+    let (key, raw) = match arg.find('=') {
+        Some(index) => (&arg[..index], &arg[index + 1..]),
+        None => return Err(anyhow!("Override \"{}\" is not in the form key=value", arg)),
+    };
+
+    let value = format!("value = {}", raw)
+        .parse::<Value>()
+        .ok()
+        .and_then(|table| table.get("value").cloned())
+        .unwrap_or_else(|| Value::String(raw.to_string()));
+
+    // Walk the sections, creating them when missing
+    let mut sections = key.split('.').collect::<Vec<_>>();
+    let name = sections.pop().unwrap_or_default();
+    let mut table = config
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("Configuration is not a table"))?;
+    for section in sections {
+        if !table.contains_key(section) {
+            table.insert(section.to_string(), Value::Table(Table::new()));
+        }
+
+        table = table
+            .get_mut(section)
+            .and_then(|value| value.as_table_mut())
+            .ok_or_else(|| anyhow!("Override \"{}\": \"{}\" is not a section", arg, section))?;
+    }
+    table.insert(name.to_string(), value);
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::{
+    physics::{Physics, RigidBody},
+    Float,
+};
+
+/// Fastest horizontal speed in world units per second.
+const MAX_SPEED: Float = 300.0;
+/// How fast the speed changes towards the input on the ground, in world units per second squared.
+const ACCELERATION: Float = 2400.0;
+/// How fast a unit on the ground stops without input, in world units per second squared.
+const FRICTION: Float = 3000.0;
+/// The part of the acceleration that's left in the air.
+const AIR_CONTROL: Float = 0.5;
+/// Upwards speed at the start of a jump in world units per second.
+const JUMP_SPEED: Float = 500.0;
+/// How long after walking off a ledge a jump is still allowed, in seconds.
+const COYOTE_TIME: Float = 0.1;
+/// How long a jump pressed just before landing is remembered, in seconds.
+const JUMP_BUFFER: Float = 0.1;
+/// Steepest slope a unit can stand on in degrees, it slides down anything steeper.
+const MAX_SLOPE: Float = 50.0;
+/// Speed away from the ground above which a unit is leaving it, like when jumping, in world units
+/// per second.
+const SEPARATION_SPEED: Float = 10.0;
+
+/// Moves a unit like the character of a platformer, walking along slopes and only jumping from
+/// the ground.
+///
+/// The world Y axis points down, so the ground is below the unit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CharacterController {
+    /// The horizontal input, from -1.0 for left to 1.0 for right.
+    direction: Float,
+    /// The time since the unit last stood on the ground in seconds.
+    airborne: Float,
+    /// The time the last jump request is remembered for in seconds.
+    jump_buffer: Float,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CharacterController {
+    /// Start standing still.
+    pub fn new() -> Self {
+        Self {
+            direction: 0.0,
+            // Not on the ground until the first contact is found
+            airborne: COYOTE_TIME,
+            jump_buffer: 0.0,
+        }
+    }
+
+    /// Walk in a direction, from -1.0 for left to 1.0 for right, 0.0 stops.
+    pub fn set_direction(&mut self, direction: Float) {
+        self.direction = direction.max(-1.0).min(1.0);
+    }
+
+    /// Jump as soon as the unit is on the ground.
+    pub fn jump(&mut self) {
+        self.jump_buffer = JUMP_BUFFER;
+    }
+
+    /// Change the velocity of the body for the next fixed step of a duration in seconds, should be
+    /// called before every step.
+    pub fn update(&mut self, physics: &mut Physics<Float>, rigid_body: &RigidBody, dt: Float) {
+        let (mut vx, mut vy) = match physics.linear_velocity(rigid_body) {
+            Some(velocity) => velocity,
+            None => return,
+        };
+
+        // Only ground that isn't too steep and isn't being left counts, the normal points up on
+        // flat ground
+        let min_up = MAX_SLOPE.to_radians().cos();
+        let ground = physics
+            .ground_normal(rigid_body)
+            .filter(|&(nx, ny)| -ny >= min_up && vx * nx + vy * ny <= SEPARATION_SPEED);
+        self.airborne = if ground.is_some() {
+            0.0
+        } else {
+            self.airborne + dt
+        };
+
+        let target = self.direction * MAX_SPEED;
+        let rate = if self.direction.abs() > Float::EPSILON {
+            ACCELERATION
+        } else {
+            FRICTION
+        };
+        match ground {
+            Some((nx, ny)) => {
+                // Move along the slope, so walking doesn't launch the unit off it
+                let (tx, ty) = (-ny, nx);
+                let speed = approach(vx * tx + vy * ty, target, rate * dt);
+                vx = tx * speed;
+                vy = ty * speed;
+            }
+            None => vx = approach(vx, target, rate * AIR_CONTROL * dt),
+        }
+
+        self.jump_buffer = (self.jump_buffer - dt).max(0.0);
+        if self.jump_buffer > 0.0 && self.airborne <= COYOTE_TIME {
+            vy = -JUMP_SPEED;
+            self.jump_buffer = 0.0;
+            // Don't allow a second jump while the unit is still leaving the ground
+            self.airborne = COYOTE_TIME + dt;
+        }
+
+        physics.set_linear_velocity(rigid_body, (vx, vy));
+        // Keep the unit upright
+        physics.set_rotation(rigid_body, 0.0);
+    }
+}
+
+/// Move a value towards a target without passing it.
+fn approach(value: Float, target: Float, amount: Float) -> Float {
+    if value < target {
+        (value + amount).min(target)
+    } else {
+        (value - amount).max(target)
+    }
+}
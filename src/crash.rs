@@ -0,0 +1,212 @@
+use crate::{config::Config, logger::LogHistory};
+use backtrace::Backtrace;
+use std::{
+    any::Any,
+    fmt::Write,
+    panic::{self, PanicInfo},
+    sync::{Arc, Mutex},
+};
+
+/// Amount of the most recent log lines added to a report.
+const REPORT_LOG_LINES: usize = 50;
+
+/// The OpenGL enum for the renderer name, missing from the miniquad bindings.
+#[cfg(not(target_arch = "wasm32"))]
+const GL_RENDERER: u32 = 0x1F01;
+
+/// Information only known after the window is created, added to the crash reports.
+#[derive(Debug, Clone, Default)]
+pub struct CrashInfo {
+    /// Description of the graphics card & driver.
+    gpu: Arc<Mutex<Option<String>>>,
+}
+
+impl CrashInfo {
+    /// Query the graphics card & driver, should be called after the OpenGL context is created.
+    pub fn detect_gpu(&self) {
+        if let Ok(mut gpu) = self.gpu.lock() {
+            *gpu = Some(gpu_info());
+        }
+    }
+}
+
+/// Replace the panic hook with one that writes a crash report and tells the player where it is.
+///
+/// The original hook is still called afterwards.
+pub fn install(config: &Config, log_history: LogHistory) -> CrashInfo {
+    let info = CrashInfo::default();
+
+    let config = config.clone();
+    let hook_info = info.clone();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        let report = report(panic_info, &config, &log_history, &hook_info);
+
+        match write_report(&report) {
+            Ok(path) => show_message(&format!(
+                "Sorry, the game crashed.\n\nA crash report is written to \"{}\", please attach it when reporting the problem.",
+                path
+            )),
+            Err(err) => show_message(&format!(
+                "Sorry, the game crashed.\n\nWriting the crash report failed: {}\n\n{}",
+                err, report
+            )),
+        }
+
+        default_hook(panic_info);
+    }));
+
+    info
+}
+
+/// Build the text of the crash report.
+fn report(
+    panic_info: &PanicInfo,
+    config: &Config,
+    log_history: &LogHistory,
+    info: &CrashInfo,
+) -> String {
+    let mut report = String::new();
+
+    // Writing to a string can't fail
+    let _ = writeln!(
+        report,
+        "{} {} crashed",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let _ = writeln!(
+        report,
+        "\n# Panic\n\n{}",
+        payload_message(panic_info.payload())
+    );
+    if let Some(location) = panic_info.location() {
+        let _ = writeln!(
+            report,
+            "at {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        );
+    }
+
+    let _ = writeln!(report, "\n# Backtrace\n\n{:?}", Backtrace::new());
+
+    // Don't block when the panic happened while logging
+    let _ = writeln!(report, "# Log\n");
+    match log_history.try_lock() {
+        Ok(history) => {
+            let skip = history.len().saturating_sub(REPORT_LOG_LINES);
+            for line in history.iter().skip(skip) {
+                let _ = writeln!(report, "{}", line);
+            }
+        }
+        Err(_) => {
+            let _ = writeln!(report, "unavailable");
+        }
+    }
+
+    let _ = writeln!(
+        report,
+        "\n# Configuration\n\n{}",
+        toml::to_string_pretty(config).unwrap_or_else(|err| err.to_string())
+    );
+
+    let gpu = info
+        .gpu
+        .try_lock()
+        .ok()
+        .and_then(|gpu| gpu.clone())
+        .unwrap_or_else(|| "unknown".to_string());
+    let _ = writeln!(report, "# GPU\n\n{}", gpu);
+
+    report
+}
+
+/// Get the message passed to `panic!` or `expect`.
+fn payload_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Write the report to a new file in the working directory, returning the path.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_report(report: &str) -> std::io::Result<String> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let path = format!("crash-{}.txt", timestamp);
+    std::fs::write(&path, report)?;
+
+    Ok(path)
+}
+
+/// There's no filesystem in the browser, so the report is shown in the console instead.
+#[cfg(target_arch = "wasm32")]
+fn write_report(report: &str) -> std::io::Result<String> {
+    crate::web::console(log::Level::Error, report);
+
+    Ok("the browser console".to_string())
+}
+
+/// Show a message box, because the window is gone after the panic.
+#[cfg(not(target_arch = "wasm32"))]
+fn show_message(message: &str) {
+    eprintln!("{}", message);
+
+    tinyfiledialogs::message_box_ok(
+        env!("CARGO_PKG_NAME"),
+        // The dialogs can't show quotes on every platform
+        &message.replace('"', "'"),
+        tinyfiledialogs::MessageBoxIcon::Error,
+    );
+}
+
+/// The browser has no message boxes without JavaScript bindings, so use the console.
+#[cfg(target_arch = "wasm32")]
+fn show_message(message: &str) {
+    crate::web::console(log::Level::Error, message);
+}
+
+/// Describe the graphics card & driver of the current OpenGL context.
+#[cfg(not(target_arch = "wasm32"))]
+fn gpu_info() -> String {
+    use miniquad::gl::{glGetString, GL_VENDOR, GL_VERSION};
+    use std::ffi::CStr;
+
+    let get = |name| {
+        // Safe because the strings returned by OpenGL are static & null terminated
+        unsafe {
+            let string = glGetString(name);
+            if string.is_null() {
+                "unknown".to_string()
+            } else {
+                CStr::from_ptr(string as *const _)
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        }
+    };
+
+    format!(
+        "vendor: {}\nrenderer: {}\nversion: {}",
+        get(GL_VENDOR),
+        get(GL_RENDERER),
+        get(GL_VERSION)
+    )
+}
+
+/// The browser hides the graphics card behind WebGL.
+#[cfg(target_arch = "wasm32")]
+fn gpu_info() -> String {
+    "WebGL".to_string()
+}
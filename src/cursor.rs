@@ -0,0 +1,176 @@
+use crate::{
+    render::{Instance, Mesh, Render},
+    svg::Svg,
+};
+use anyhow::Result;
+use glsp::{bail, lib, rfn, GResult, Runtime};
+use std::collections::HashMap;
+
+/// The z index of the cursor, always on top of everything else.
+const CURSOR_Z: u8 = u8::MAX;
+
+/// The different cursor variants for the context the mouse is in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CursorKind {
+    Default,
+    Attack,
+    Forbidden,
+}
+
+impl CursorKind {
+    /// Get the cursor kind from the name used in scripts.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::Default),
+            "attack" => Some(Self::Attack),
+            "forbidden" => Some(Self::Forbidden),
+            _ => None,
+        }
+    }
+}
+
+/// A mesh with the point that's placed at the mouse position.
+#[derive(Debug, Copy, Clone)]
+struct CursorVariant {
+    /// Mesh reference to render the cursor.
+    mesh: Mesh,
+    /// Offset in the mesh of the point that clicks.
+    hotspot: (f32, f32),
+}
+
+lib! {
+/// Mouse cursor rendered as a mesh in screen space, replacing the OS cursor.
+pub struct Cursor {
+    /// The meshes for every kind of cursor.
+    variants: HashMap<CursorKind, CursorVariant>,
+    /// The currently shown cursor.
+    active: CursorKind,
+    /// Last known mouse position.
+    pos: (f32, f32),
+}
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cursor {
+    /// Create a cursor without any variants.
+    pub fn new() -> Self {
+        Self {
+            variants: HashMap::new(),
+            active: CursorKind::Default,
+            pos: (0.0, 0.0),
+        }
+    }
+
+    /// Add or replace the mesh of a cursor kind.
+    pub fn set_variant(&mut self, kind: CursorKind, mesh: Mesh, hotspot: (f32, f32)) {
+        self.variants.insert(kind, CursorVariant { mesh, hotspot });
+    }
+
+    /// Upload a parsed SVG with an optional hotspot in the metadata and use it for a cursor kind.
+    pub fn load_variant(&mut self, render: &mut Render, kind: CursorKind, svg: Svg) -> Result<()> {
+        let hotspot = svg.metadata_point("hotspot")?.unwrap_or((0.0, 0.0));
+
+        let mesh = svg.upload(render)?;
+        render.set_label(mesh, "cursors");
+        self.set_variant(kind, mesh, hotspot);
+
+        Ok(())
+    }
+
+    /// Draw a cursor kind with a changed version of its SVG, keeping its mesh.
+    pub fn reload_variant(
+        &mut self,
+        render: &mut Render,
+        kind: CursorKind,
+        svg: Svg,
+    ) -> Result<()> {
+        let variant = match self.variants.get_mut(&kind) {
+            Some(variant) => variant,
+            None => return self.load_variant(render, kind, svg),
+        };
+
+        variant.hotspot = svg.metadata_point("hotspot")?.unwrap_or((0.0, 0.0));
+        svg.replace_meshes(render, variant.mesh, &HashMap::new())
+    }
+
+    /// Change the offset of the point that clicks of a cursor kind.
+    pub fn set_hotspot(&mut self, kind: CursorKind, x: f32, y: f32) {
+        if let Some(variant) = self.variants.get_mut(&kind) {
+            variant.hotspot = (x, y);
+        }
+    }
+
+    /// Show a different kind of cursor.
+    pub fn set(&mut self, kind: CursorKind) {
+        self.active = kind;
+    }
+
+    /// Get the kind of cursor that's shown.
+    pub fn kind(&self) -> CursorKind {
+        self.active
+    }
+
+    /// Handle miniquad mouse motion events.
+    pub fn mouse_motion(&mut self, x: f32, y: f32) {
+        self.pos = (x, y);
+    }
+
+    /// Queue the screen space instance of the cursor for the next frame.
+    pub fn draw(&self, render: &mut Render) {
+        // Fall back to the default cursor when the variant is missing
+        let variant = match self
+            .variants
+            .get(&self.active)
+            .or_else(|| self.variants.get(&CursorKind::Default))
+        {
+            Some(variant) => variant,
+            None => return,
+        };
+
+        let mut instance = Instance::new(
+            self.pos.0 - variant.hotspot.0,
+            self.pos.1 - variant.hotspot.1,
+        );
+        instance.set_z(CURSOR_Z);
+        render.draw_screen(variant.mesh, instance);
+    }
+
+    /// Show a different kind of cursor by name.
+    fn set_by_name(&mut self, name: &str) -> GResult<()> {
+        match CursorKind::from_name(name) {
+            Some(kind) => {
+                self.set(kind);
+
+                Ok(())
+            }
+            None => bail!("unknown cursor kind \"{}\"", name),
+        }
+    }
+
+    /// Change the offset of the point that clicks by name.
+    fn set_hotspot_by_name(&mut self, name: &str, x: f32, y: f32) -> GResult<()> {
+        match CursorKind::from_name(name) {
+            Some(kind) => {
+                self.set_hotspot(kind, x, y);
+
+                Ok(())
+            }
+            None => bail!("unknown cursor kind \"{}\"", name),
+        }
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("set-cursor", rfn!(Self::set_by_name))?;
+            glsp::bind_rfn("set-cursor-hotspot", rfn!(Self::set_hotspot_by_name))?;
+
+            Ok(())
+        });
+    }
+}
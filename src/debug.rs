@@ -0,0 +1,292 @@
+use crate::{
+    physics::Physics,
+    render::{Instance, Layer, Mesh, Render},
+    Float,
+};
+use lyon::{math::Point, path::Path};
+use nalgebra::{Isometry2, Point2};
+use ncollide2d::shape::{Ball, Capsule, Compound, ConvexPolygon, Cuboid, Polyline, Shape};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+use usvg::Color;
+
+/// Amount of sides of the polygon the circles are drawn with.
+const CIRCLE_SIDES: usize = 24;
+/// Opacity of the collider shapes, so what's behind them stays visible.
+const OPACITY: f32 = 0.35;
+/// Thickness of the open polylines & the velocities in world units.
+const LINE_WIDTH: f32 = 2.0;
+/// Seconds of movement the velocity lines are long.
+const VELOCITY_TIME: f32 = 0.1;
+/// The color of the velocity lines.
+const VELOCITY_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 0.8];
+
+/// Draws the colliders of the physics bodies on top of the world.
+pub struct DebugPhysics {
+    /// Whether the colliders are drawn.
+    enabled: bool,
+    /// A circle with a radius of one world unit, scaled to the radius of every ball.
+    ball: Mesh,
+    /// The meshes of the other shapes by the hash of their dimensions, uploaded the first time a
+    /// shape like it is drawn and removed when no shape like it is drawn.
+    meshes: HashMap<u64, Mesh>,
+}
+
+impl DebugPhysics {
+    /// Upload the circle, the colliders are drawn when it's enabled.
+    pub fn new(render: &mut Render, enabled: bool) -> Self {
+        let ball = render.upload_path(&polygon(CIRCLE_SIDES, 1.0), color(), OPACITY);
+        render.set_label(ball, "debug");
+
+        Self {
+            enabled,
+            ball,
+            meshes: HashMap::new(),
+        }
+    }
+
+    /// Start or stop drawing the colliders.
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Pass the colliders to the renderer, should be called every frame.
+    pub fn draw(&mut self, physics: &Physics<Float>, render: &mut Render) {
+        let mut balls = vec![];
+        let mut instances = HashMap::new();
+
+        if self.enabled {
+            let mut shapes = DrawnShapes {
+                balls: &mut balls,
+                instances: &mut instances,
+                meshes: &mut self.meshes,
+                render: &mut *render,
+            };
+
+            for (ball, position) in physics.debug_shapes::<Ball<Float>>() {
+                shapes.add(&ball, &position);
+            }
+            for (cuboid, position) in physics.debug_shapes::<Cuboid<Float>>() {
+                shapes.add(&cuboid, &position);
+            }
+            for (capsule, position) in physics.debug_shapes::<Capsule<Float>>() {
+                shapes.add(&capsule, &position);
+            }
+            for (polygon, position) in physics.debug_shapes::<ConvexPolygon<Float>>() {
+                shapes.add(&polygon, &position);
+            }
+            for (compound, position) in physics.debug_shapes::<Compound<Float>>() {
+                shapes.add(&compound, &position);
+            }
+            for (polyline, position) in physics.debug_shapes::<Polyline<Float>>() {
+                shapes.add(&polyline, &position);
+            }
+
+            // Point to where the moving bodies will be in a moment
+            for ((x, y), (velocity_x, velocity_y)) in physics.debug_velocities() {
+                let from = (x as f32, y as f32);
+                let to = (
+                    from.0 + velocity_x as f32 * VELOCITY_TIME,
+                    from.1 + velocity_y as f32 * VELOCITY_TIME,
+                );
+                render.draw_line(from, to, VELOCITY_COLOR, LINE_WIDTH);
+            }
+        }
+
+        // Also clear the circle without colliders, so nothing lingers after disabling
+        render.set_instances(self.ball, &balls);
+
+        // Remove the meshes of shapes that aren't drawn anymore, like the colliders of a previous
+        // level, they're uploaded again when a shape like it shows up
+        self.meshes.retain(|key, mesh| {
+            let drawn = instances.contains_key(key);
+            if !drawn {
+                render.remove_mesh(*mesh);
+            }
+
+            drawn
+        });
+        for (key, mesh) in self.meshes.iter() {
+            render.set_instances(*mesh, &instances[key]);
+        }
+    }
+}
+
+/// The instances of the colliders drawn this frame, uploading the meshes of new shapes.
+struct DrawnShapes<'a> {
+    /// The instances of the circle.
+    balls: &'a mut Vec<Instance>,
+    /// The instances of the other meshes by their key.
+    instances: &'a mut HashMap<u64, Vec<Instance>>,
+    /// The uploaded meshes by their key.
+    meshes: &'a mut HashMap<u64, Mesh>,
+    /// Uploads the meshes.
+    render: &'a mut Render,
+}
+
+impl<'a> DrawnShapes<'a> {
+    /// Add a shape at a world position, the parts of compound shapes are added one by one.
+    ///
+    /// Shapes that can't be drawn, like heightfields, are skipped.
+    fn add(&mut self, shape: &dyn Shape<Float>, position: &Isometry2<Float>) {
+        if let Some(ball) = shape.as_shape::<Ball<Float>>() {
+            let mut instance = instance(position);
+            instance.set_scale(ball.radius() as f32);
+            self.balls.push(instance);
+        } else if let Some(cuboid) = shape.as_shape::<Cuboid<Float>>() {
+            let half_extents = cuboid.half_extents();
+            let (width, height) = (half_extents.x as f32, half_extents.y as f32);
+
+            // Instances can only be scaled uniformly, so every size has its own mesh
+            self.add_mesh(
+                &("cuboid", width.to_bits(), height.to_bits()),
+                position,
+                || rectangle(width, height),
+            );
+        } else if let Some(capsule) = shape.as_shape::<Capsule<Float>>() {
+            let (half_height, radius) = (capsule.half_height() as f32, capsule.radius() as f32);
+
+            self.add_mesh(
+                &("capsule", half_height.to_bits(), radius.to_bits()),
+                position,
+                || capsule_path(half_height, radius),
+            );
+        } else if let Some(polygon) = shape.as_shape::<ConvexPolygon<Float>>() {
+            let points = polygon
+                .points()
+                .iter()
+                .map(|point| Point::new(point.x as f32, point.y as f32))
+                .collect::<Vec<_>>();
+            let key = points
+                .iter()
+                .map(|point| (point.x.to_bits(), point.y.to_bits()))
+                .collect::<Vec<_>>();
+
+            self.add_mesh(&("polygon", key), position, || outline(&points));
+        } else if let Some(polyline) = shape.as_shape::<Polyline<Float>>() {
+            // Every polyline is different, so they're drawn as lines instead of meshes
+            let points = polyline
+                .points()
+                .iter()
+                .map(|point| {
+                    let point: Point2<Float> = position * point;
+
+                    (point.x as f32, point.y as f32)
+                })
+                .collect::<Vec<_>>();
+            let Color { red, green, blue } = color();
+            let line_color = [
+                red as f32 / 255.0,
+                green as f32 / 255.0,
+                blue as f32 / 255.0,
+                OPACITY,
+            ];
+            self.render.draw_polyline(&points, line_color, LINE_WIDTH);
+        } else if let Some(compound) = shape.as_shape::<Compound<Float>>() {
+            // The parts are positioned relative to the body
+            for (part_position, part) in compound.shapes() {
+                self.add(part.as_ref(), &(position * part_position));
+            }
+        }
+    }
+
+    /// Add an instance of the mesh of a shape, uploading it when no shape like it was drawn
+    /// before.
+    fn add_mesh<K, F>(&mut self, key: &K, position: &Isometry2<Float>, path: F)
+    where
+        K: Hash,
+        F: FnOnce() -> Path,
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let render = &mut self.render;
+        self.meshes.entry(key).or_insert_with(|| {
+            // Not shared, so removing it can't remove the mesh of something else
+            let mesh = render.upload_path_unique(&path(), color(), OPACITY);
+            render.set_label(mesh, "debug");
+
+            mesh
+        });
+
+        self.instances
+            .entry(key)
+            .or_default()
+            .push(instance(position));
+    }
+}
+
+/// The color of the collider shapes.
+fn color() -> Color {
+    Color::new(0x40, 0xff, 0x40)
+}
+
+/// An instance at the position & rotation of a shape, on top of everything in the world.
+fn instance(position: &Isometry2<Float>) -> Instance {
+    let translation = position.translation.vector;
+    let mut instance = Instance::new(translation.x as f32, translation.y as f32);
+    instance.set_rotation(position.rotation.angle() as f32);
+    instance.set_layer(Layer::Text, 0);
+
+    instance
+}
+
+/// A closed outline through points.
+fn outline(points: &[Point]) -> Path {
+    let mut builder = Path::builder();
+    for (index, point) in points.iter().enumerate() {
+        if index == 0 {
+            builder.move_to(*point);
+        } else {
+            builder.line_to(*point);
+        }
+    }
+    builder.close();
+
+    builder.build()
+}
+
+/// A regular polygon around the origin.
+fn polygon(sides: usize, radius: f32) -> Path {
+    outline(
+        &(0..sides)
+            .map(|side| {
+                let angle = side as f32 / sides as f32 * std::f32::consts::PI * 2.0;
+
+                Point::new(angle.cos() * radius, angle.sin() * radius)
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// A rectangle around the origin.
+fn rectangle(half_width: f32, half_height: f32) -> Path {
+    outline(&[
+        Point::new(-half_width, -half_height),
+        Point::new(half_width, -half_height),
+        Point::new(half_width, half_height),
+        Point::new(-half_width, half_height),
+    ])
+}
+
+/// A capsule around the origin along the Y axis, with a half circle at both ends.
+fn capsule_path(half_height: f32, radius: f32) -> Path {
+    let half = CIRCLE_SIDES / 2;
+    let cap = |center_y: f32, start: f32| {
+        (0..=half).map(move |side| {
+            let angle = start + side as f32 / half as f32 * std::f32::consts::PI;
+
+            Point::new(angle.cos() * radius, center_y + angle.sin() * radius)
+        })
+    };
+
+    // The bottom cap from right to left, then the top cap from left to right
+    outline(
+        &cap(half_height, 0.0)
+            .chain(cap(-half_height, std::f32::consts::PI))
+            .collect::<Vec<_>>(),
+    )
+}
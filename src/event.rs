@@ -0,0 +1,62 @@
+use glsp::{lib, rfn, Runtime};
+use std::mem;
+
+/// A gameplay event that can be handled by multiple systems.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Damage has been dealt, independent of the precision of the physics.
+    DamageDealt { amount: f64 },
+    /// A new wave of enemies started.
+    WaveStarted { wave: u32 },
+}
+
+lib! {
+/// Queue of gameplay events emitted during a frame.
+pub struct EventBus {
+    /// The events emitted since the last drain.
+    events: Vec<Event>,
+}
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Create an empty event bus.
+    pub fn new() -> Self {
+        Self { events: vec![] }
+    }
+
+    /// Emit an event, it will be handled by the systems on the next drain.
+    pub fn emit(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Take all events emitted since the last drain.
+    pub fn drain(&mut self) -> Vec<Event> {
+        mem::take(&mut self.events)
+    }
+
+    /// Emit a damage dealt event.
+    pub fn emit_damage_dealt(&mut self, amount: f64) {
+        self.emit(Event::DamageDealt { amount });
+    }
+
+    /// Emit a wave started event.
+    pub fn emit_wave_started(&mut self, wave: u32) {
+        self.emit(Event::WaveStarted { wave });
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("emit-damage-dealt", rfn!(Self::emit_damage_dealt))?;
+            glsp::bind_rfn("emit-wave-started", rfn!(Self::emit_wave_started))?;
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{anyhow, Result};
+use lyon::math::Point;
+use usvg::{BaseGradient, NodeKind, Rect, SpreadMethod, Transform, Tree, Units};
+
+/// A linear or radial gradient, sampled at the vertices of a tessellated path.
+///
+/// The colors are interpolated between the vertices, so gradients with many stops on shapes
+/// with few vertices are approximated.
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    /// The shape of the gradient in gradient space.
+    kind: GradientKind,
+    /// Converts a point on the path to gradient space.
+    to_gradient: Transform,
+    /// What happens outside of the gradient vector.
+    spread: SpreadMethod,
+    /// The offsets between 0.0 and 1.0 with the RGBA colors, sorted by offset.
+    stops: Vec<(f64, [f32; 4])>,
+}
+
+/// The shape of a gradient.
+#[derive(Debug, Clone)]
+enum GradientKind {
+    /// Along the vector from the first to the second point.
+    Linear { start: (f64, f64), end: (f64, f64) },
+    /// From the focal point to the circle.
+    Radial {
+        center: (f64, f64),
+        radius: f64,
+        focal: (f64, f64),
+    },
+}
+
+impl Gradient {
+    /// Find the gradient a paint links to.
+    ///
+    /// The bounding box of the path is needed for gradients in object bounding box units.
+    pub fn from_link(tree: &Tree, id: &str, bbox: Option<Rect>) -> Result<Self> {
+        let node = tree
+            .defs_by_id(id)
+            .ok_or_else(|| anyhow!("Paint server \"{}\" doesn't exist", id))?;
+        let node = node.borrow();
+
+        let (kind, base) = match &*node {
+            NodeKind::LinearGradient(gradient) => (
+                GradientKind::Linear {
+                    start: (gradient.x1, gradient.y1),
+                    end: (gradient.x2, gradient.y2),
+                },
+                &gradient.base,
+            ),
+            NodeKind::RadialGradient(gradient) => (
+                GradientKind::Radial {
+                    center: (gradient.cx, gradient.cy),
+                    radius: gradient.r.value(),
+                    focal: (gradient.fx, gradient.fy),
+                },
+                &gradient.base,
+            ),
+            _ => return Err(anyhow!("Paint server \"{}\" is not a gradient", id)),
+        };
+
+        Ok(Self {
+            kind,
+            to_gradient: gradient_transform(base, bbox)
+                .ok_or_else(|| anyhow!("Gradient \"{}\" can't be inverted", id))?,
+            spread: base.spread_method,
+            stops: base
+                .stops
+                .iter()
+                .map(|stop| {
+                    (
+                        stop.offset.value(),
+                        [
+                            stop.color.red as f32 / 255.0,
+                            stop.color.green as f32 / 255.0,
+                            stop.color.blue as f32 / 255.0,
+                            stop.opacity.value() as f32,
+                        ],
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    /// The RGBA color at a point of the path.
+    pub fn color_at(&self, point: Point) -> [f32; 4] {
+        let (x, y) = self.to_gradient.apply(point.x as f64, point.y as f64);
+
+        let offset = match self.kind {
+            GradientKind::Linear { start, end } => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let length = dx * dx + dy * dy;
+                if length <= std::f64::EPSILON {
+                    // The gradient has no direction so the last stop is used
+                    1.0
+                } else {
+                    ((x - start.0) * dx + (y - start.1) * dy) / length
+                }
+            }
+            GradientKind::Radial {
+                center,
+                radius,
+                focal,
+            } => radial_offset((x - focal.0, y - focal.1), center, radius, focal),
+        };
+
+        self.color_at_offset(self.spread_offset(offset))
+    }
+
+    /// Map an offset outside of the 0.0 to 1.0 range with the spread method.
+    fn spread_offset(&self, offset: f64) -> f64 {
+        match self.spread {
+            SpreadMethod::Pad => offset.max(0.0).min(1.0),
+            SpreadMethod::Repeat => offset - offset.floor(),
+            SpreadMethod::Reflect => {
+                let cycle = offset.abs() % 2.0;
+                if cycle > 1.0 {
+                    2.0 - cycle
+                } else {
+                    cycle
+                }
+            }
+        }
+    }
+
+    /// Interpolate the colors of the stops around an offset.
+    fn color_at_offset(&self, offset: f64) -> [f32; 4] {
+        let next = self.stops.iter().position(|(stop, _)| *stop >= offset);
+
+        match next {
+            // Before the first stop
+            Some(0) => self.stops[0].1,
+            Some(index) => {
+                let (from_offset, from) = self.stops[index - 1];
+                let (to_offset, to) = self.stops[index];
+                let range = to_offset - from_offset;
+                let factor = if range <= std::f64::EPSILON {
+                    1.0
+                } else {
+                    ((offset - from_offset) / range) as f32
+                };
+
+                let mut color = [0.0; 4];
+                for (channel, value) in color.iter_mut().enumerate() {
+                    *value = from[channel] + (to[channel] - from[channel]) * factor;
+                }
+
+                color
+            }
+            // After the last stop
+            None => self
+                .stops
+                .last()
+                .map_or([0.0, 0.0, 0.0, 0.0], |(_, color)| *color),
+        }
+    }
+}
+
+/// The transform from the path to gradient space, `None` when it can't be inverted.
+fn gradient_transform(base: &BaseGradient, bbox: Option<Rect>) -> Option<Transform> {
+    // From gradient space to the space of the path
+    let mut transform = match base.units {
+        Units::UserSpaceOnUse => Transform::default(),
+        Units::ObjectBoundingBox => {
+            let bbox = bbox?;
+            Transform::new(bbox.width(), 0.0, 0.0, bbox.height(), bbox.x(), bbox.y())
+        }
+    };
+    transform.append(&base.transform);
+
+    invert(&transform)
+}
+
+/// The inverse of an affine transform, `None` when it collapses the space.
+fn invert(ts: &Transform) -> Option<Transform> {
+    let det = ts.a * ts.d - ts.b * ts.c;
+    if det.abs() <= std::f64::EPSILON {
+        return None;
+    }
+
+    Some(Transform::new(
+        ts.d / det,
+        -ts.b / det,
+        -ts.c / det,
+        ts.a / det,
+        (ts.c * ts.f - ts.d * ts.e) / det,
+        (ts.b * ts.e - ts.a * ts.f) / det,
+    ))
+}
+
+/// The offset of a point relative to the focal point on the circles growing from the focal point
+/// to the outer circle.
+fn radial_offset(point: (f64, f64), center: (f64, f64), radius: f64, focal: (f64, f64)) -> f64 {
+    // Solve |point - offset * direction| = offset * radius for the offset
+    let direction = (center.0 - focal.0, center.1 - focal.1);
+    let a = direction.0 * direction.0 + direction.1 * direction.1 - radius * radius;
+    let b = point.0 * direction.0 + point.1 * direction.1;
+    let c = point.0 * point.0 + point.1 * point.1;
+
+    if a.abs() <= std::f64::EPSILON {
+        // The focal point lies on the circle
+        if b.abs() <= std::f64::EPSILON {
+            0.0
+        } else {
+            c / (2.0 * b)
+        }
+    } else {
+        let discriminant = (b * b - a * c).max(0.0);
+
+        // The largest root, the focal point is inside the circle so there's one positive root
+        ((b - discriminant.sqrt()) / a).max((b + discriminant.sqrt()) / a)
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{
+    assets::{AssetManager, DataHandle, SvgHandle},
+    audio::Audio,
+    call_script,
+    camera::Camera,
+    clock::Clock,
+    config::Config,
+    event::EventBus,
+    i18n::I18n,
+    input::Input,
+    label::Labels,
+    level::Level,
+    load_i18n, load_scripts, logger,
+    motion::Motion,
+    object::ObjectRegistry,
+    random::Random,
+    render::Render,
+    save::SaveQueue,
+    simulation::{self, Frame},
+    spawner::Spawner,
+    tween::Tweens,
+    ui::Ui,
+    world::{Assets, World},
+    Float,
+};
+use anyhow::{anyhow, Result};
+use glsp::{Lib, Runtime};
+use log::{debug, info};
+
+/// Amount of fixed steps simulated when no amount is given, a minute of game time.
+pub const DEFAULT_STEPS: u64 = 3600;
+
+/// Run the world & the scripts for an amount of fixed steps as fast as possible, without
+/// creating a window or anything else that needs a graphics context.
+///
+/// The render & cursor functions aren't available for the scripts, the audio functions don't play
+/// anything. A test script is
+/// evaluated after the main script, its `test:finish` function is called after the last step.
+///
+/// Returns the hash of the physics state after the last step.
+pub fn run(config: &Config, steps: u64, test: Option<&str>) -> Result<u64> {
+    let i18n = load_i18n(&config.language)?;
+    let fixed_step = config.simulation.fixed_step();
+
+    // The meshes are uploaded into a renderer that never draws them
+    let mut render = Render::offscreen();
+    let assets = load_assets(config, &mut render)?;
+    let mut world = World::new(assets, &mut render, fixed_step as Float)?;
+
+    // Let the scripts tell the kinds of objects apart
+    let mut spawner = Spawner::new();
+    spawner.set_object_tags(world.defs.tags());
+
+    // Setup the script runtime with everything that doesn't need a window
+    let runtime = Runtime::new();
+    runtime.run(|| {
+        glsp::add_lib(EventBus::new());
+        glsp::add_lib(Camera::new());
+        glsp::add_lib(spawner);
+        glsp::add_lib(SaveQueue::new());
+        glsp::add_lib(Motion::new());
+        glsp::add_lib(Tweens::new());
+        glsp::add_lib(Labels::new());
+        glsp::add_lib(Ui::new());
+        glsp::add_lib(Clock::new(fixed_step));
+        glsp::add_lib(Random::new(config.simulation.seed));
+        glsp::add_lib(i18n);
+        glsp::add_lib(Input::new(config.input.clone()));
+
+        Ok(())
+    });
+    EventBus::bind_functions(&runtime);
+    Camera::bind_functions(&runtime);
+    Spawner::bind_functions(&runtime);
+    SaveQueue::bind_functions(&runtime);
+    Motion::bind_functions(&runtime);
+    Tweens::bind_functions(&runtime);
+    Labels::bind_functions(&runtime);
+    Ui::bind_functions(&runtime);
+    Clock::bind_functions(&runtime);
+    Random::bind_functions(&runtime);
+    I18n::bind_functions(&runtime);
+    Input::bind_functions(&runtime);
+    // There's no audio device, the sounds are only checked
+    Audio::bind_silent_functions(&runtime);
+    logger::bind_functions(&runtime);
+    load_scripts(&runtime)?;
+    if let Some(path) = test {
+        load_test(&runtime, path)?;
+    }
+
+    info!("simulating {} steps without a window", steps);
+    let start = miniquad::date::now();
+
+    for step in 0..steps {
+        // Every frame is exactly a single fixed step
+        let frame = runtime
+            .run(|| {
+                let mut clock = Clock::borrow_mut();
+                clock.tick_with(fixed_step);
+                let mut fixed_steps = 0;
+                while clock.fixed_update() {
+                    fixed_steps += 1;
+                }
+
+                Ok(Frame {
+                    delta: clock.delta(),
+                    elapsed: clock.elapsed(),
+                    frame: clock.frame(),
+                    fixed_steps,
+                })
+            })
+            .unwrap_or_default();
+        simulation::step(&runtime, Some(&mut world), frame, true)?;
+
+        // There are no systems to handle the events, the saves & the labels
+        runtime.run(|| {
+            for event in EventBus::borrow_mut().drain() {
+                debug!("event at step {}: {:?}", step, event);
+            }
+            // There's nothing to save
+            for request in SaveQueue::borrow_mut().drain() {
+                debug!("save request at step {}: {:?}", step, request);
+            }
+            for label in Labels::borrow_mut().drain() {
+                debug!("text at step {}: {:?}", step, label);
+            }
+            // There's no mouse to use the widgets with
+            Ui::borrow_mut().end_frame();
+
+            Ok(())
+        });
+    }
+
+    let duration = miniquad::date::now() - start;
+    info!(
+        "simulated {} steps in {:.2} seconds, {:.0} steps per second",
+        steps,
+        duration,
+        steps as f64 / duration.max(std::f64::EPSILON)
+    );
+
+    if test.is_some() && !call_script(&runtime, "test:finish", &(steps,)) {
+        return Err(anyhow!("Calling test:finish failed"));
+    }
+
+    let hash = world.physics.state_hash();
+    info!("physics state hash after {} steps: {:016x}", steps, hash);
+
+    Ok(hash)
+}
+
+/// Run the same simulation twice from scratch and fail when the physics ended up different.
+///
+/// Everything that influences the simulation, like the random numbers of the scripts & the order
+/// the bodies are stepped in, must be the same every run for replays & multiplayer to work.
+pub fn check_determinism(config: &Config, steps: u64, test: Option<&str>) -> Result<()> {
+    let first = run(config, steps, test)?;
+    let second = run(config, steps, test)?;
+
+    if first != second {
+        return Err(anyhow!(
+            "Simulation is not deterministic, the physics state hashes {:016x} & {:016x} differ after {} steps",
+            first,
+            second,
+            steps
+        ));
+    }
+
+    info!("both simulations ended with the same physics state");
+
+    Ok(())
+}
+
+/// Load the object definitions, the levels & the panel the world is built from, synchronously
+/// instead of with a loading screen.
+pub fn load_assets(config: &Config, render: &mut Render) -> Result<Assets> {
+    let asset_manager = AssetManager::new(&config.assets.root);
+    let mut assets = Assets::default();
+
+    let sources = ObjectRegistry::parse_sources(&asset_manager.data(DataHandle::OBJECTS)?)?;
+    for (name, source) in sources {
+        let handle = SvgHandle::from_path(&source.svg)?;
+        let svg = asset_manager.svg(handle)?;
+        if source.level {
+            let level = Level::load(&name, svg, render, &source, &mut assets.defs, handle)?;
+            assets.levels.insert(name, level);
+        } else {
+            let def = svg.into_object_def(render, &source)?;
+            assets.defs.register(&name, handle, def);
+        }
+    }
+    assets.panel = Some(asset_manager.svg(SvgHandle::PANEL)?.into_nine_slice()?);
+
+    Ok(assets)
+}
+
+/// Evaluate a GameLisp file after the main script, it can replace its functions.
+fn load_test(runtime: &Runtime, path: &str) -> Result<()> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Reading test script \"{}\" failed: {}", path, err))?;
+
+    runtime
+        .run(|| {
+            glsp::eval_multi(&glsp::parse_all(&source, None)?, None)?;
+
+            Ok(())
+        })
+        .ok_or_else(|| anyhow!("Evaluating test script \"{}\" failed", path))
+}
@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Result};
+use glsp::{bail, lib, rfn, GResult, Runtime, Val};
+use std::collections::{BTreeSet, HashMap};
+use toml::Value;
+
+/// The translated strings of a single language.
+#[derive(Debug, Default)]
+pub struct StringTable {
+    /// Translations by key.
+    strings: HashMap<String, String>,
+}
+
+impl StringTable {
+    /// Parse a TOML file where every key maps to a translated string.
+    ///
+    /// Keys in nested tables are joined with a dot.
+    pub fn from_toml(source: &str) -> Result<Self> {
+        let value = source.parse::<Value>()?;
+
+        let mut strings = HashMap::new();
+        flatten("", &value, &mut strings)?;
+
+        Ok(Self { strings })
+    }
+
+    /// Get the translation belonging to a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(|string| string.as_str())
+    }
+}
+
+lib! {
+/// Localized strings for all languages, looked up in the selected language.
+pub struct I18n {
+    /// The string tables by language code.
+    languages: HashMap<String, StringTable>,
+    /// The selected language code.
+    language: String,
+    /// Language code used when a key is missing in the selected language.
+    fallback: String,
+    /// Whether the language is switched since the last check.
+    changed: bool,
+}
+}
+
+impl I18n {
+    /// Create without any languages, the language is also used as the fallback.
+    pub fn new(language: &str) -> Self {
+        Self {
+            languages: HashMap::new(),
+            language: language.to_string(),
+            fallback: language.to_string(),
+            changed: false,
+        }
+    }
+
+    /// Parse and add the string table for a language.
+    pub fn add_language(&mut self, language: &str, source: &str) -> Result<()> {
+        let table = StringTable::from_toml(source)
+            .map_err(|err| anyhow!("Could not parse language \"{}\": {}", language, err))?;
+        self.languages.insert(language.to_string(), table);
+
+        Ok(())
+    }
+
+    /// Switch to another language.
+    pub fn set_language(&mut self, language: &str) -> Result<()> {
+        if !self.languages.contains_key(language) {
+            return Err(anyhow!("Language \"{}\" is not loaded", language));
+        }
+
+        if self.language != language {
+            self.language = language.to_string();
+            self.changed = true;
+        }
+
+        Ok(())
+    }
+
+    /// The selected language code.
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Translate a key, replacing `{name}` placeholders with the arguments.
+    ///
+    /// When the key can't be found in any language the key itself is returned.
+    pub fn t(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let mut result = self
+            .lookup(&self.language, key)
+            .or_else(|| self.lookup(&self.fallback, key))
+            .unwrap_or(key)
+            .to_string();
+
+        for (name, value) in args {
+            result = result.replace(&format!("{{{}}}", name), value);
+        }
+
+        result
+    }
+
+    /// All unique characters used in the selected language, without whitespace.
+    ///
+    /// Placeholders are skipped, arguments are usually numbers which need to be uploaded
+    /// separately.
+    pub fn charset(&self) -> String {
+        let mut chars = BTreeSet::new();
+
+        for string in self
+            .languages
+            .get(&self.language)
+            .into_iter()
+            .flat_map(|table| table.strings.values())
+        {
+            let mut in_placeholder = false;
+            for ch in string.chars() {
+                match ch {
+                    '{' => in_placeholder = true,
+                    '}' => in_placeholder = false,
+                    ch if !in_placeholder && !ch.is_whitespace() => {
+                        chars.insert(ch);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        chars.into_iter().collect()
+    }
+
+    /// Whether the language is switched since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+
+        changed
+    }
+
+    /// Get the translation of a key in a specific language.
+    fn lookup(&self, language: &str, key: &str) -> Option<&str> {
+        self.languages
+            .get(language)
+            .and_then(|table| table.get(key))
+    }
+
+    /// GameLisp translate function, the arguments are pairs of names and values.
+    fn translate(&self, key: &str, args: &[Val]) -> GResult<String> {
+        if args.len() % 2 != 0 {
+            bail!("t expects pairs of argument names and values");
+        }
+
+        let args: Vec<(String, String)> = args
+            .chunks(2)
+            .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+            .collect();
+        let args: Vec<(&str, &str)> = args
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+
+        Ok(self.t(key, &args))
+    }
+
+    /// GameLisp function to switch the language.
+    fn switch_language(&mut self, language: &str) -> GResult<()> {
+        if let Err(err) = self.set_language(language) {
+            bail!("{}", err);
+        }
+
+        Ok(())
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("t", rfn!(Self::translate))?;
+            glsp::bind_rfn("set-language", rfn!(Self::switch_language))?;
+            glsp::bind_rfn("language", rfn!(Self::language))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Collect all strings of a TOML value with their full key.
+fn flatten(prefix: &str, value: &Value, strings: &mut HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(string) => {
+            strings.insert(prefix.to_string(), string.clone());
+        }
+        Value::Table(table) => {
+            for (key, value) in table.iter() {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+
+                flatten(&key, value, strings)?;
+            }
+        }
+        _ => return Err(anyhow!("Translation \"{}\" is not a string", prefix)),
+    }
+
+    Ok(())
+}
@@ -1,50 +1,345 @@
-use miniquad::KeyCode;
+use crate::{config::InputConfig, key::Key, replay::InputEvent};
+use glsp::{bail, lib, rfn, GResult, Runtime, Sym};
+#[cfg(not(target_arch = "wasm32"))]
+use log::warn;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Default)]
-pub struct Input {
-    up: bool,
-    down: bool,
-    left: bool,
-    right: bool,
+/// Prefix of the names of gamepad buttons in the configuration, to tell them apart from keys.
+const GAMEPAD_PREFIX: &str = "gamepad-";
+
+/// The name of every gamepad button, the face buttons are named after their position.
+const GAMEPAD_BUTTONS: &[(GamepadButton, &str)] = &[
+    (GamepadButton::South, "south"),
+    (GamepadButton::East, "east"),
+    (GamepadButton::North, "north"),
+    (GamepadButton::West, "west"),
+    (GamepadButton::LeftBumper, "left-bumper"),
+    (GamepadButton::RightBumper, "right-bumper"),
+    (GamepadButton::LeftTrigger, "left-trigger"),
+    (GamepadButton::RightTrigger, "right-trigger"),
+    (GamepadButton::Select, "select"),
+    (GamepadButton::Start, "start"),
+    (GamepadButton::LeftStick, "left-stick"),
+    (GamepadButton::RightStick, "right-stick"),
+    (GamepadButton::DPadUp, "d-pad-up"),
+    (GamepadButton::DPadDown, "d-pad-down"),
+    (GamepadButton::DPadLeft, "d-pad-left"),
+    (GamepadButton::DPadRight, "d-pad-right"),
+];
+
+/// A button of a gamepad, the same for every kind of gamepad.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
 }
 
-impl Input {
-    /// Get whether the up key is pressed or not.
-    pub fn up_pressed(&self) -> bool {
-        self.up
+impl GamepadButton {
+    /// The kebab-case name, like `south` or `left-trigger`.
+    pub fn name(self) -> &'static str {
+        GAMEPAD_BUTTONS
+            .iter()
+            .find(|(button, _)| *button == self)
+            .map_or("unknown", |(_, name)| name)
     }
 
-    /// Get whether the down key is pressed or not.
-    pub fn down_pressed(&self) -> bool {
-        self.down
+    /// Find the button with a name, `None` when it doesn't exist.
+    pub fn from_name(name: &str) -> Option<Self> {
+        GAMEPAD_BUTTONS
+            .iter()
+            .find(|(_, button_name)| *button_name == name)
+            .map(|(button, _)| *button)
     }
+}
+
+/// An analog stick direction of a gamepad, the Y axes point down like the world.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+}
+
+/// A key or gamepad button that triggers an action.
+///
+/// In the configuration it's the name of a key, like `space`, or the name of a gamepad button
+/// prefixed with `gamepad-`, like `gamepad-south`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Binding {
+    Key(Key),
+    Gamepad(GamepadButton),
+}
 
-    /// Get whether the left key is pressed or not.
-    pub fn left_pressed(&self) -> bool {
-        self.left
+impl Serialize for Binding {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Binding::Key(key) => serializer.serialize_str(key.name()),
+            Binding::Gamepad(button) => {
+                serializer.serialize_str(&format!("{}{}", GAMEPAD_PREFIX, button.name()))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Binding {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+
+        let binding = if let Some(button) = name.strip_prefix(GAMEPAD_PREFIX) {
+            GamepadButton::from_name(button).map(Binding::Gamepad)
+        } else {
+            Key::from_name(&name).map(Binding::Key)
+        };
+
+        binding.ok_or_else(|| de::Error::custom(format!("unknown key or button \"{}\"", name)))
     }
+}
 
-    /// Get whether the right key is pressed or not.
-    pub fn right_pressed(&self) -> bool {
-        self.right
+/// The inputs that move an axis between -1.0 & 1.0.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisBinding {
+    /// The keys & buttons moving the axis to -1.0.
+    pub negative: Vec<Binding>,
+    /// The keys & buttons moving the axis to 1.0.
+    pub positive: Vec<Binding>,
+    /// The gamepad stick direction moving the axis.
+    pub gamepad: Option<GamepadAxis>,
+}
+
+lib! {
+/// The state of the keys & gamepads of the local player, mapped to the actions of the
+/// configuration.
+pub struct Input {
+    /// The actions & axes with their bindings.
+    config: InputConfig,
+    /// The keys that are held.
+    keys: HashSet<Key>,
+    /// The gamepad buttons that are held.
+    buttons: HashSet<GamepadButton>,
+    /// The position of the gamepad sticks.
+    axes: HashMap<GamepadAxis, f32>,
+}
+}
+
+impl Input {
+    /// Nothing is held until the first input.
+    pub fn new(config: InputConfig) -> Self {
+        Self {
+            config,
+            keys: HashSet::new(),
+            buttons: HashSet::new(),
+            axes: HashMap::new(),
+        }
     }
 
-    /// Handle miniquad key events.
-    pub fn handle_key(&mut self, code: KeyCode, is_down: bool) {
-        match code {
-            KeyCode::W | KeyCode::Comma => {
-                self.up = is_down;
+    /// Remember the held keys & buttons and the position of the sticks.
+    pub fn handle(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::KeyDown { key } => {
+                self.keys.insert(key);
+            }
+            InputEvent::KeyUp { key } => {
+                self.keys.remove(&key);
             }
-            KeyCode::A => {
-                self.left = is_down;
+            InputEvent::GamepadButtonDown { button } => {
+                self.buttons.insert(button);
             }
-            KeyCode::S | KeyCode::O => {
-                self.down = is_down;
+            InputEvent::GamepadButtonUp { button } => {
+                self.buttons.remove(&button);
             }
-            KeyCode::D | KeyCode::E => {
-                self.right = is_down;
+            InputEvent::GamepadAxis { axis, value } => {
+                self.axes.insert(axis, value as f32);
             }
             _ => (),
         }
     }
+
+    /// Whether a key or button is held.
+    fn is_held(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.keys.contains(key),
+            Binding::Gamepad(button) => self.buttons.contains(button),
+        }
+    }
+
+    /// Whether any key or button of an action is held.
+    fn action_pressed(&self, action: Sym) -> GResult<bool> {
+        match self.config.actions.get(&*action.name()) {
+            Some(bindings) => Ok(bindings.iter().any(|binding| self.is_held(binding))),
+            None => bail!("unknown action {}", action),
+        }
+    }
+
+    /// The position of an axis between -1.0 & 1.0, the keys are added to the gamepad stick.
+    fn axis(&self, axis: Sym) -> GResult<f32> {
+        let binding = match self.config.axes.get(&*axis.name()) {
+            Some(binding) => binding,
+            None => bail!("unknown axis {}", axis),
+        };
+
+        let held = |bindings: &[Binding]| bindings.iter().any(|binding| self.is_held(binding));
+        let keys = match (held(&binding.negative), held(&binding.positive)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        };
+
+        // Sticks don't return to exactly the center
+        let stick = binding
+            .gamepad
+            .and_then(|axis| self.axes.get(&axis))
+            .copied()
+            .filter(|value| value.abs() >= self.config.deadzone)
+            .unwrap_or(0.0);
+
+        Ok((keys + stick).max(-1.0).min(1.0))
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("action-pressed?", rfn!(Self::action_pressed))?;
+            glsp::bind_rfn("axis", rfn!(Self::axis))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Reads the connected gamepads.
+///
+/// There's no gamepad support in the browser yet.
+pub struct Gamepads {
+    /// The gamepad backend, `None` when it couldn't be started.
+    #[cfg(not(target_arch = "wasm32"))]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gamepads {
+    /// Start listening to the gamepads, they can be connected later.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> Self {
+        let gilrs = gilrs::Gilrs::new()
+            .map_err(|err| warn!("gamepads are not available: {}", err))
+            .ok();
+
+        Self { gilrs }
+    }
+
+    /// There are no gamepads in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Take the input of all gamepads since the last call.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        use gilrs::EventType;
+
+        let gilrs = match self.gilrs.as_mut() {
+            Some(gilrs) => gilrs,
+            None => return vec![],
+        };
+
+        let mut events = vec![];
+        while let Some(event) = gilrs.next_event() {
+            let event = match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    convert_button(button).map(|button| InputEvent::GamepadButtonDown { button })
+                }
+                EventType::ButtonReleased(button, _) => {
+                    convert_button(button).map(|button| InputEvent::GamepadButtonUp { button })
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    convert_axis(axis).map(|(axis, direction)| InputEvent::GamepadAxis {
+                        axis,
+                        value: f64::from(value * direction),
+                    })
+                }
+                _ => None,
+            };
+            events.extend(event);
+        }
+
+        events
+    }
+
+    /// There are no gamepads in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        vec![]
+    }
+}
+
+/// The button of a gilrs gamepad event, `None` for buttons most gamepads don't have.
+#[cfg(not(target_arch = "wasm32"))]
+fn convert_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+
+    match button {
+        Button::South => Some(GamepadButton::South),
+        Button::East => Some(GamepadButton::East),
+        Button::North => Some(GamepadButton::North),
+        Button::West => Some(GamepadButton::West),
+        Button::LeftTrigger => Some(GamepadButton::LeftBumper),
+        Button::RightTrigger => Some(GamepadButton::RightBumper),
+        Button::LeftTrigger2 => Some(GamepadButton::LeftTrigger),
+        Button::RightTrigger2 => Some(GamepadButton::RightTrigger),
+        Button::Select => Some(GamepadButton::Select),
+        Button::Start => Some(GamepadButton::Start),
+        Button::LeftThumb => Some(GamepadButton::LeftStick),
+        Button::RightThumb => Some(GamepadButton::RightStick),
+        Button::DPadUp => Some(GamepadButton::DPadUp),
+        Button::DPadDown => Some(GamepadButton::DPadDown),
+        Button::DPadLeft => Some(GamepadButton::DPadLeft),
+        Button::DPadRight => Some(GamepadButton::DPadRight),
+        _ => None,
+    }
+}
+
+/// The stick direction of a gilrs gamepad event with the factor pointing it like the world.
+#[cfg(not(target_arch = "wasm32"))]
+fn convert_axis(axis: gilrs::Axis) -> Option<(GamepadAxis, f32)> {
+    use gilrs::Axis;
+
+    // The Y axes of gilrs point up
+    match axis {
+        Axis::LeftStickX => Some((GamepadAxis::LeftStickX, 1.0)),
+        Axis::LeftStickY => Some((GamepadAxis::LeftStickY, -1.0)),
+        Axis::RightStickX => Some((GamepadAxis::RightStickX, 1.0)),
+        Axis::RightStickY => Some((GamepadAxis::RightStickY, -1.0)),
+        _ => None,
+    }
 }
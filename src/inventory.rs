@@ -0,0 +1,525 @@
+use crate::{
+    i18n::I18n,
+    render::{Instance, Mesh, Render},
+    text::FontInstance,
+};
+use miniquad::MouseButton;
+use serde::{Deserialize, Serialize};
+use usvg::Color;
+
+/// Size of a single slot in pixels.
+const SLOT_SIZE: f32 = 64.0;
+/// Space between the slots in pixels.
+const SLOT_SPACING: f32 = 4.0;
+/// Size of the tooltip background in pixels.
+const TOOLTIP_SIZE: (f32, f32) = (160.0, 32.0);
+/// Scale of the glyphs of the stack count.
+const COUNT_TEXT_SCALE: f32 = 0.15;
+/// Scale of the glyphs of the tooltip.
+const TOOLTIP_TEXT_SCALE: f32 = 0.2;
+
+/// The z index of the slot backgrounds.
+const SLOT_Z: u8 = 0;
+/// The z index of the items in the slots.
+const ITEM_Z: u8 = 1;
+/// The z index of the tooltip.
+const TOOLTIP_Z: u8 = 3;
+/// The z index of the item being dragged.
+const DRAG_Z: u8 = 5;
+
+/// A reference to a registered item definition.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ItemId(usize);
+
+impl ItemId {
+    /// Get the numeric value so it can be passed to scripts.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Definition of a type of item.
+pub struct ItemDef {
+    /// Translation key of the name shown in the tooltip.
+    pub name: String,
+    /// Mesh reference to render the item in a slot.
+    pub mesh: Mesh,
+    /// Scale of the mesh so it fits in a slot.
+    pub scale: f32,
+    /// Maximum amount of items that can be stacked in a single slot.
+    pub max_stack: u32,
+}
+
+/// All item definitions.
+#[derive(Default)]
+pub struct ItemRegistry {
+    defs: Vec<ItemDef>,
+}
+
+impl ItemRegistry {
+    /// Register a new item definition.
+    pub fn register(&mut self, def: ItemDef) -> ItemId {
+        self.defs.push(def);
+
+        ItemId(self.defs.len() - 1)
+    }
+
+    /// Get an item definition.
+    pub fn get(&self, item: ItemId) -> Option<&ItemDef> {
+        self.defs.get(item.0)
+    }
+
+    /// Get the maximum amount of items that can be stacked in a single slot.
+    pub fn max_stack(&self, item: ItemId) -> u32 {
+        self.get(item).map(|def| def.max_stack).unwrap_or(1)
+    }
+}
+
+/// A stack of the same items in a single slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ItemStack {
+    /// The type of item.
+    pub item: ItemId,
+    /// The amount of items.
+    pub count: u32,
+}
+
+/// Inventory component, a list of slots that can contain stacks of items.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(into = "StoredInventory", from = "StoredInventory")]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+}
+
+/// A filled slot of an inventory, so it can be written to a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredStack {
+    slot: usize,
+    item: ItemId,
+    count: u32,
+}
+
+/// The inventory without the empty slots, TOML can't store missing values in arrays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredInventory {
+    size: usize,
+    stacks: Vec<StoredStack>,
+}
+
+impl From<Inventory> for StoredInventory {
+    fn from(inventory: Inventory) -> Self {
+        Self {
+            size: inventory.slots.len(),
+            stacks: inventory
+                .slots
+                .iter()
+                .enumerate()
+                .filter_map(|(slot, stack)| {
+                    stack.map(|stack| StoredStack {
+                        slot,
+                        item: stack.item,
+                        count: stack.count,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<StoredInventory> for Inventory {
+    fn from(stored: StoredInventory) -> Self {
+        let mut inventory = Inventory::new(stored.size);
+        for stack in stored.stacks {
+            if let Some(slot) = inventory.slots.get_mut(stack.slot) {
+                *slot = Some(ItemStack {
+                    item: stack.item,
+                    count: stack.count,
+                });
+            }
+        }
+
+        inventory
+    }
+}
+
+impl Inventory {
+    /// Create an empty inventory with an amount of slots.
+    pub fn new(size: usize) -> Self {
+        Self {
+            slots: vec![None; size],
+        }
+    }
+
+    /// Amount of slots.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Whether there are no slots.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Get the stack in a slot.
+    pub fn slot(&self, slot: usize) -> Option<ItemStack> {
+        self.slots.get(slot).copied().flatten()
+    }
+
+    /// Add items, filling the existing stacks first.
+    ///
+    /// Returns the amount of items that didn't fit.
+    pub fn add(&mut self, item: ItemId, mut count: u32, max_stack: u32) -> u32 {
+        let max_stack = max_stack.max(1);
+
+        // Fill the existing stacks of the same item
+        for stack in self
+            .slots
+            .iter_mut()
+            .flatten()
+            .filter(|stack| stack.item == item)
+        {
+            let added = max_stack.saturating_sub(stack.count).min(count);
+            stack.count += added;
+            count -= added;
+        }
+
+        // Put the rest in empty slots
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if count == 0 {
+                break;
+            }
+
+            let added = max_stack.min(count);
+            *slot = Some(ItemStack { item, count: added });
+            count -= added;
+        }
+
+        count
+    }
+
+    /// Remove items from a slot.
+    ///
+    /// Returns the amount of items removed.
+    pub fn take(&mut self, slot: usize, count: u32) -> u32 {
+        let stack = match self.slots.get_mut(slot) {
+            Some(Some(stack)) => stack,
+            _ => return 0,
+        };
+
+        let taken = stack.count.min(count);
+        stack.count -= taken;
+        if stack.count == 0 {
+            self.slots[slot] = None;
+        }
+
+        taken
+    }
+
+    /// Move the stack of a slot to another slot.
+    ///
+    /// Stacks of the same item are merged, otherwise the slots are swapped.
+    pub fn move_stack(&mut self, from: usize, to: usize, max_stack: u32) {
+        if from == to || from >= self.slots.len() || to >= self.slots.len() {
+            return;
+        }
+
+        match (self.slots[from], self.slots[to]) {
+            (Some(source), Some(target)) if source.item == target.item => {
+                let moved = max_stack.saturating_sub(target.count).min(source.count);
+
+                self.slots[to] = Some(ItemStack {
+                    count: target.count + moved,
+                    ..target
+                });
+                self.slots[from] = if source.count > moved {
+                    Some(ItemStack {
+                        count: source.count - moved,
+                        ..source
+                    })
+                } else {
+                    None
+                };
+            }
+            _ => self.slots.swap(from, to),
+        }
+    }
+}
+
+/// Action that should be handled by the game after interacting with the grid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InventoryAction {
+    /// Use the item in the slot, triggered with the right mouse button.
+    Use { slot: usize, item: ItemId },
+    /// Equip the item in the slot, triggered with the middle mouse button.
+    Equip { slot: usize, item: ItemId },
+}
+
+/// Screen space grid showing the contents of an inventory.
+pub struct InventoryGrid {
+    /// Position of the top left corner in pixels.
+    pos: (f32, f32),
+    /// Amount of slots in a single row.
+    columns: usize,
+    /// Mesh for the background of a slot.
+    slot_mesh: Mesh,
+    /// Mesh for the background of the tooltip.
+    tooltip_mesh: Mesh,
+    /// Last known mouse position.
+    mouse: (f32, f32),
+    /// The slot of the stack that's being dragged.
+    dragging: Option<usize>,
+}
+
+impl InventoryGrid {
+    /// Upload the meshes for the grid.
+    pub fn new(render: &mut Render, x: f32, y: f32, columns: usize) -> Self {
+        let slot_mesh =
+            render.upload_rectangle(SLOT_SIZE, SLOT_SIZE, Color::new(0x40, 0x40, 0x40), 0.8);
+        let tooltip_mesh = render.upload_rectangle(
+            TOOLTIP_SIZE.0,
+            TOOLTIP_SIZE.1,
+            Color::new(0x10, 0x10, 0x10),
+            0.9,
+        );
+
+        Self {
+            pos: (x, y),
+            columns: columns.max(1),
+            slot_mesh,
+            tooltip_mesh,
+            mouse: (0.0, 0.0),
+            dragging: None,
+        }
+    }
+
+    /// Get the position of the top left corner of a slot.
+    pub fn slot_pos(&self, slot: usize) -> (f32, f32) {
+        let column = (slot % self.columns) as f32;
+        let row = (slot / self.columns) as f32;
+
+        (
+            self.pos.0 + column * (SLOT_SIZE + SLOT_SPACING),
+            self.pos.1 + row * (SLOT_SIZE + SLOT_SPACING),
+        )
+    }
+
+    /// Get the slot at a screen position.
+    pub fn slot_at(&self, inventory: &Inventory, x: f32, y: f32) -> Option<usize> {
+        let local_x = x - self.pos.0;
+        let local_y = y - self.pos.1;
+        if local_x < 0.0 || local_y < 0.0 {
+            return None;
+        }
+
+        let stride = SLOT_SIZE + SLOT_SPACING;
+        let column = (local_x / stride) as usize;
+        let row = (local_y / stride) as usize;
+
+        // Ignore the spacing between the slots
+        if column >= self.columns || local_x % stride > SLOT_SIZE || local_y % stride > SLOT_SIZE {
+            return None;
+        }
+
+        let slot = row * self.columns + column;
+        if slot < inventory.len() {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+
+    /// Get the slot of the stack that's being dragged.
+    pub fn dragging(&self) -> Option<usize> {
+        self.dragging
+    }
+
+    /// Continue or stop dragging a stack, used when restoring a checkpoint.
+    pub fn set_dragging(&mut self, slot: Option<usize>) {
+        self.dragging = slot;
+    }
+
+    /// Handle miniquad mouse motion events.
+    pub fn mouse_motion(&mut self, x: f32, y: f32) {
+        self.mouse = (x, y);
+    }
+
+    /// Handle miniquad mouse button down events.
+    ///
+    /// Returns an action when an item should be used or equipped.
+    pub fn mouse_button_down(
+        &mut self,
+        inventory: &Inventory,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Option<InventoryAction> {
+        self.mouse = (x, y);
+
+        let slot = self.slot_at(inventory, x, y)?;
+        let item = inventory.slot(slot)?.item;
+
+        match button {
+            MouseButton::Left => {
+                // Start dragging the stack
+                self.dragging = Some(slot);
+
+                None
+            }
+            MouseButton::Right => Some(InventoryAction::Use { slot, item }),
+            MouseButton::Middle => Some(InventoryAction::Equip { slot, item }),
+            MouseButton::Unknown => None,
+        }
+    }
+
+    /// Handle miniquad mouse button up events.
+    pub fn mouse_button_up(
+        &mut self,
+        inventory: &mut Inventory,
+        items: &ItemRegistry,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) {
+        self.mouse = (x, y);
+
+        if button != MouseButton::Left {
+            return;
+        }
+
+        let from = match self.dragging.take() {
+            Some(from) => from,
+            None => return,
+        };
+
+        // Dropping the stack outside of the grid cancels the drag
+        if let Some(to) = self.slot_at(inventory, x, y) {
+            let max_stack = inventory
+                .slot(from)
+                .map(|stack| items.max_stack(stack.item))
+                .unwrap_or(1);
+
+            inventory.move_stack(from, to, max_stack);
+        }
+    }
+
+    /// Queue the screen space instances of the grid for the next frame.
+    pub fn draw(
+        &self,
+        inventory: &Inventory,
+        items: &ItemRegistry,
+        font: &mut FontInstance,
+        i18n: &I18n,
+        render: &mut Render,
+    ) {
+        let hovered = self.slot_at(inventory, self.mouse.0, self.mouse.1);
+
+        for slot in 0..inventory.len() {
+            let (x, y) = self.slot_pos(slot);
+
+            // Draw the background, highlighted when hovering over it
+            let mut background = Instance::new(x, y);
+            background.set_z(SLOT_Z);
+            if hovered == Some(slot) {
+                background.set_color_multiplier(1.0, 1.0, 0.6);
+            }
+            render.draw_screen(self.slot_mesh, background);
+
+            // Don't draw the stack being dragged in its original slot
+            if self.dragging == Some(slot) {
+                continue;
+            }
+
+            if let Some(stack) = inventory.slot(slot) {
+                self.draw_stack(
+                    stack,
+                    x + SLOT_SIZE / 2.0,
+                    y + SLOT_SIZE / 2.0,
+                    ITEM_Z,
+                    items,
+                    font,
+                    render,
+                );
+            }
+        }
+
+        match self.dragging.and_then(|slot| inventory.slot(slot)) {
+            // Draw the dragged stack under the mouse cursor
+            Some(stack) => self.draw_stack(
+                stack,
+                self.mouse.0,
+                self.mouse.1,
+                DRAG_Z,
+                items,
+                font,
+                render,
+            ),
+            // Draw the tooltip of the hovered stack
+            None => {
+                if let Some(def) = hovered
+                    .and_then(|slot| inventory.slot(slot))
+                    .and_then(|stack| items.get(stack.item))
+                {
+                    self.draw_tooltip(&i18n.t(&def.name, &[]), font, render);
+                }
+            }
+        }
+    }
+
+    /// Queue the instances of a stack centered around a position.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_stack(
+        &self,
+        stack: ItemStack,
+        x: f32,
+        y: f32,
+        z: u8,
+        items: &ItemRegistry,
+        font: &mut FontInstance,
+        render: &mut Render,
+    ) {
+        let def = match items.get(stack.item) {
+            Some(def) => def,
+            None => return,
+        };
+
+        let mut instance = Instance::new(x, y);
+        instance.set_scale(def.scale);
+        instance.set_z(z);
+        render.draw_screen(def.mesh, instance);
+
+        // Show the amount in the bottom right corner when there's more than one item
+        if stack.count > 1 {
+            for (mut instance, mesh) in font.text_scaled(
+                render,
+                &stack.count.to_string(),
+                x + SLOT_SIZE / 2.0 - 24.0,
+                y + SLOT_SIZE / 2.0 - 4.0,
+                COUNT_TEXT_SCALE,
+            ) {
+                instance.set_z(z + 1);
+                render.draw_screen(mesh, instance);
+            }
+        }
+    }
+
+    /// Queue the instances of a tooltip next to the mouse cursor.
+    fn draw_tooltip(&self, text: &str, font: &mut FontInstance, render: &mut Render) {
+        let x = self.mouse.0 + 16.0;
+        let y = self.mouse.1 + 16.0;
+
+        let mut background = Instance::new(x, y);
+        background.set_z(TOOLTIP_Z);
+        render.draw_screen(self.tooltip_mesh, background);
+
+        for (mut instance, mesh) in font.text_scaled(
+            render,
+            text,
+            x + 8.0,
+            y + TOOLTIP_SIZE.1 - 8.0,
+            TOOLTIP_TEXT_SCALE,
+        ) {
+            instance.set_z(TOOLTIP_Z + 1);
+            render.draw_screen(mesh, instance);
+        }
+    }
+}
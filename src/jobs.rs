@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use log::error;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// A boxed job that sends its result to a handle.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A pool of worker threads for parsing & tessellating assets, so the main thread only has to
+/// upload the buffers.
+///
+/// There are no threads in the browser, the jobs run on the main thread when their result is
+/// requested instead.
+#[derive(Clone)]
+pub struct JobPool {
+    /// Queue of the jobs shared by all workers, `None` without threads.
+    sender: Option<Sender<Job>>,
+}
+
+impl Default for JobPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobPool {
+    /// Start a worker thread for every core.
+    pub fn new() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            return Self { sender: None };
+        }
+
+        let workers = num_cpus::get();
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..workers {
+            let receiver = receiver.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("job-worker-{}", index))
+                .spawn(move || {
+                    // The lock is released as soon as a job is taken from the queue, the worker
+                    // stops when the pool is dropped
+                    while let Ok(Ok(job)) = receiver.lock().map(|receiver| receiver.recv()) {
+                        // A panicking job only disconnects its handle, the worker keeps running
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                });
+
+            if let Err(err) = spawned {
+                error!("starting worker thread failed: {}", err);
+            }
+        }
+
+        Self {
+            sender: Some(sender),
+        }
+    }
+
+    /// Queue a job, the result can be taken from the returned handle when it's done.
+    pub fn spawn<V, F>(&self, job: F) -> JobHandle<V>
+    where
+        V: Send + 'static,
+        F: FnOnce() -> Result<V> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            // The handle might be dropped already
+            let _ = sender.send(job());
+        });
+
+        // Run the job on the main thread when there are no workers
+        let deferred = match &self.sender {
+            Some(jobs) => jobs.send(job).err().map(|err| err.0),
+            None => Some(job),
+        };
+
+        JobHandle { receiver, deferred }
+    }
+}
+
+/// The result of a job that's queued in the pool.
+pub struct JobHandle<V> {
+    /// Receives the result when the job is done.
+    receiver: Receiver<Result<V>>,
+    /// The job when it couldn't be queued, executed when the result is requested.
+    deferred: Option<Job>,
+}
+
+impl<V> JobHandle<V> {
+    /// Take the result if the job is done, `None` when it's still running.
+    pub fn try_take(&mut self) -> Option<Result<V>> {
+        self.run_deferred();
+
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("Job panicked"))),
+        }
+    }
+
+    /// Block until the job is done and take the result.
+    pub fn wait(mut self) -> Result<V> {
+        self.run_deferred();
+
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(anyhow!("Job panicked")))
+    }
+
+    /// Run the job on this thread if it isn't queued.
+    fn run_deferred(&mut self) {
+        if let Some(job) = self.deferred.take() {
+            job();
+        }
+    }
+}
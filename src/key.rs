@@ -0,0 +1,180 @@
+use glsp::{GResult, ToVal, Val};
+use miniquad::KeyCode;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The name of every key as the scripts know it, in kebab-case.
+const KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Space, "space"),
+    (KeyCode::Apostrophe, "apostrophe"),
+    (KeyCode::Comma, "comma"),
+    (KeyCode::Minus, "minus"),
+    (KeyCode::Period, "period"),
+    (KeyCode::Slash, "slash"),
+    (KeyCode::Key0, "key-0"),
+    (KeyCode::Key1, "key-1"),
+    (KeyCode::Key2, "key-2"),
+    (KeyCode::Key3, "key-3"),
+    (KeyCode::Key4, "key-4"),
+    (KeyCode::Key5, "key-5"),
+    (KeyCode::Key6, "key-6"),
+    (KeyCode::Key7, "key-7"),
+    (KeyCode::Key8, "key-8"),
+    (KeyCode::Key9, "key-9"),
+    (KeyCode::Semicolon, "semicolon"),
+    (KeyCode::Equal, "equal"),
+    (KeyCode::A, "a"),
+    (KeyCode::B, "b"),
+    (KeyCode::C, "c"),
+    (KeyCode::D, "d"),
+    (KeyCode::E, "e"),
+    (KeyCode::F, "f"),
+    (KeyCode::G, "g"),
+    (KeyCode::H, "h"),
+    (KeyCode::I, "i"),
+    (KeyCode::J, "j"),
+    (KeyCode::K, "k"),
+    (KeyCode::L, "l"),
+    (KeyCode::M, "m"),
+    (KeyCode::N, "n"),
+    (KeyCode::O, "o"),
+    (KeyCode::P, "p"),
+    (KeyCode::Q, "q"),
+    (KeyCode::R, "r"),
+    (KeyCode::S, "s"),
+    (KeyCode::T, "t"),
+    (KeyCode::U, "u"),
+    (KeyCode::V, "v"),
+    (KeyCode::W, "w"),
+    (KeyCode::X, "x"),
+    (KeyCode::Y, "y"),
+    (KeyCode::Z, "z"),
+    (KeyCode::LeftBracket, "left-bracket"),
+    (KeyCode::Backslash, "backslash"),
+    (KeyCode::RightBracket, "right-bracket"),
+    (KeyCode::GraveAccent, "grave-accent"),
+    (KeyCode::World1, "world-1"),
+    (KeyCode::World2, "world-2"),
+    (KeyCode::Escape, "escape"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Up, "up"),
+    (KeyCode::PageUp, "page-up"),
+    (KeyCode::PageDown, "page-down"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+    (KeyCode::CapsLock, "caps-lock"),
+    (KeyCode::ScrollLock, "scroll-lock"),
+    (KeyCode::NumLock, "num-lock"),
+    (KeyCode::PrintScreen, "print-screen"),
+    (KeyCode::Pause, "pause"),
+    (KeyCode::F1, "f1"),
+    (KeyCode::F2, "f2"),
+    (KeyCode::F3, "f3"),
+    (KeyCode::F4, "f4"),
+    (KeyCode::F5, "f5"),
+    (KeyCode::F6, "f6"),
+    (KeyCode::F7, "f7"),
+    (KeyCode::F8, "f8"),
+    (KeyCode::F9, "f9"),
+    (KeyCode::F10, "f10"),
+    (KeyCode::F11, "f11"),
+    (KeyCode::F12, "f12"),
+    (KeyCode::F13, "f13"),
+    (KeyCode::F14, "f14"),
+    (KeyCode::F15, "f15"),
+    (KeyCode::F16, "f16"),
+    (KeyCode::F17, "f17"),
+    (KeyCode::F18, "f18"),
+    (KeyCode::F19, "f19"),
+    (KeyCode::F20, "f20"),
+    (KeyCode::F21, "f21"),
+    (KeyCode::F22, "f22"),
+    (KeyCode::F23, "f23"),
+    (KeyCode::F24, "f24"),
+    (KeyCode::F25, "f25"),
+    (KeyCode::Kp0, "kp-0"),
+    (KeyCode::Kp1, "kp-1"),
+    (KeyCode::Kp2, "kp-2"),
+    (KeyCode::Kp3, "kp-3"),
+    (KeyCode::Kp4, "kp-4"),
+    (KeyCode::Kp5, "kp-5"),
+    (KeyCode::Kp6, "kp-6"),
+    (KeyCode::Kp7, "kp-7"),
+    (KeyCode::Kp8, "kp-8"),
+    (KeyCode::Kp9, "kp-9"),
+    (KeyCode::KpDecimal, "kp-decimal"),
+    (KeyCode::KpDivide, "kp-divide"),
+    (KeyCode::KpMultiply, "kp-multiply"),
+    (KeyCode::KpSubtract, "kp-subtract"),
+    (KeyCode::KpAdd, "kp-add"),
+    (KeyCode::KpEnter, "kp-enter"),
+    (KeyCode::KpEqual, "kp-equal"),
+    (KeyCode::LeftShift, "left-shift"),
+    (KeyCode::LeftControl, "left-control"),
+    (KeyCode::LeftAlt, "left-alt"),
+    (KeyCode::LeftSuper, "left-super"),
+    (KeyCode::RightShift, "right-shift"),
+    (KeyCode::RightControl, "right-control"),
+    (KeyCode::RightAlt, "right-alt"),
+    (KeyCode::RightSuper, "right-super"),
+    (KeyCode::Menu, "menu"),
+    (KeyCode::Unknown, "unknown"),
+];
+
+/// A keyboard key that can be stored in a replay and passed to the scripts as a symbol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Key(KeyCode);
+
+impl Key {
+    /// The kebab-case name, like `space`, `a`, `f1` or `left-shift`.
+    pub fn name(self) -> &'static str {
+        KEYS.iter()
+            .find(|(code, _)| *code == self.0)
+            .map_or("unknown", |(_, name)| name)
+    }
+
+    /// Find the key with a name, `None` when it doesn't exist.
+    pub fn from_name(name: &str) -> Option<Self> {
+        KEYS.iter()
+            .find(|(_, key_name)| *key_name == name)
+            .map(|(code, _)| Key(*code))
+    }
+}
+
+impl From<KeyCode> for Key {
+    fn from(code: KeyCode) -> Self {
+        Key(code)
+    }
+}
+
+impl ToVal for Key {
+    fn to_val(&self) -> GResult<Val> {
+        Ok(Val::Sym(glsp::sym(self.name())?))
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+
+        Key::from_name(&name).ok_or_else(|| de::Error::custom(format!("unknown key \"{}\"", name)))
+    }
+}
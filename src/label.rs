@@ -0,0 +1,199 @@
+use crate::{
+    render::{Instance, Layer, Mesh, Render},
+    text::{Anchor, FontInstance, Space, Text},
+};
+use glsp::{bail, lib, rfn, GResult, Runtime, Sym};
+use std::mem;
+
+/// Height of the lowercase letters when the scripts don't pass a size.
+const DEFAULT_SIZE: f32 = 16.0;
+
+/// The z index of screen space text, above the combat log & below the log view.
+const SCREEN_Z: u8 = 100;
+
+/// A text the scripts want drawn this frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelRequest {
+    /// The characters shown.
+    pub string: String,
+    /// The position of the anchor.
+    pub pos: (f32, f32),
+    /// The height of the lowercase letters.
+    pub size: f32,
+    /// The point of the text at the position.
+    pub anchor: Anchor,
+    /// The coordinates of the position.
+    pub space: Space,
+}
+
+lib! {
+/// Queue of the texts the scripts draw, they have to be drawn again every frame.
+pub struct Labels {
+    /// The texts since the last drain, in the order they were drawn.
+    requests: Vec<LabelRequest>,
+}
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Labels {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self { requests: vec![] }
+    }
+
+    /// Take all texts since the last drain.
+    pub fn drain(&mut self) -> Vec<LabelRequest> {
+        mem::take(&mut self.requests)
+    }
+
+    /// Draw a text for a single frame.
+    ///
+    /// The anchor is a symbol like `'top-left` or `'center`, the space is `'screen` or `'world`.
+    fn draw_text(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: Option<f32>,
+        anchor: Option<Sym>,
+        space: Option<Sym>,
+    ) -> GResult<()> {
+        let anchor = anchor
+            .map(|anchor| parse_anchor(&anchor))
+            .transpose()?
+            .unwrap_or(Anchor::TopLeft);
+        let space = space
+            .map(|space| parse_space(&space))
+            .transpose()?
+            .unwrap_or(Space::Screen);
+
+        self.requests.push(LabelRequest {
+            string: string.to_string(),
+            pos: (x, y),
+            size: size.unwrap_or(DEFAULT_SIZE),
+            anchor,
+            space,
+        });
+
+        Ok(())
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("draw-text", rfn!(Self::draw_text))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Keeps the texts drawn by the scripts between frames, so their glyphs are only formed again
+/// when the string changes.
+#[derive(Debug, Default)]
+pub struct LabelSystem {
+    /// The texts of the last frame, in the order they were drawn.
+    texts: Vec<Text>,
+    /// The glyph meshes that had world space instances in the last frame.
+    world_meshes: Vec<Mesh>,
+}
+
+impl LabelSystem {
+    /// Replace the texts with the ones drawn this frame, the text drawn at the same index in the
+    /// last frame is reused.
+    pub fn update(
+        &mut self,
+        requests: Vec<LabelRequest>,
+        font: &mut FontInstance,
+        render: &mut Render,
+    ) {
+        self.texts.truncate(requests.len());
+
+        for (index, request) in requests.into_iter().enumerate() {
+            match self.texts.get_mut(index) {
+                Some(text) => {
+                    text.set_string(&request.string);
+                    text.pos = request.pos;
+                    text.size = request.size;
+                    text.anchor = request.anchor;
+                    text.space = request.space;
+                }
+                None => self.texts.push(Text::new(
+                    &request.string,
+                    request.pos,
+                    request.size,
+                    request.anchor,
+                    request.space,
+                )),
+            }
+        }
+
+        for text in self.texts.iter_mut() {
+            text.update(font, render);
+        }
+    }
+
+    /// Queue the screen space glyphs and replace the world space instances of the glyph meshes.
+    pub fn draw(&mut self, render: &mut Render) {
+        let mut world: Vec<(Mesh, Vec<Instance>)> = vec![];
+        for text in self.texts.iter() {
+            for (mut instance, mesh) in text.instances() {
+                match text.space {
+                    Space::Screen => {
+                        instance.set_z(SCREEN_Z);
+                        render.draw_screen(mesh, instance);
+                    }
+                    Space::World => {
+                        instance.set_layer(Layer::Text, 0);
+                        match world.iter_mut().find(|(other, _)| *other == mesh) {
+                            Some((_, instances)) => instances.push(instance),
+                            None => world.push((mesh, vec![instance])),
+                        }
+                    }
+                }
+            }
+        }
+
+        // Remove the glyphs that aren't in the world anymore
+        for mesh in self.world_meshes.iter() {
+            if !world.iter().any(|(other, _)| other == mesh) {
+                render.set_instances(*mesh, &[]);
+            }
+        }
+
+        self.world_meshes = world.iter().map(|(mesh, _)| *mesh).collect();
+        for (mesh, instances) in world {
+            render.set_instances(mesh, &instances);
+        }
+    }
+}
+
+/// Convert the symbol of an anchor, like `'top-left` or `'center`.
+fn parse_anchor(sym: &Sym) -> GResult<Anchor> {
+    match &*sym.name() {
+        "top-left" => Ok(Anchor::TopLeft),
+        "top" => Ok(Anchor::Top),
+        "top-right" => Ok(Anchor::TopRight),
+        "left" => Ok(Anchor::Left),
+        "center" => Ok(Anchor::Center),
+        "right" => Ok(Anchor::Right),
+        "bottom-left" => Ok(Anchor::BottomLeft),
+        "bottom" => Ok(Anchor::Bottom),
+        "bottom-right" => Ok(Anchor::BottomRight),
+        name => bail!("unknown anchor {}", name),
+    }
+}
+
+/// Convert the symbol of a space, `'screen` or `'world`.
+fn parse_space(sym: &Sym) -> GResult<Space> {
+    match &*sym.name() {
+        "screen" => Ok(Space::Screen),
+        "world" => Ok(Space::World),
+        name => bail!("unknown space {}, expected screen or world", name),
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{
+    assets::SvgHandle,
+    object::{ObjectRegistry, ObjectSource},
+    render::Render,
+    replication::EntityId,
+    spawner::{SpawnKind, SpawnRequest},
+    svg::{SpawnMarker, Svg},
+    unit::{Allegiance, Health},
+    world::World,
+    Float,
+};
+use anyhow::{anyhow, Result};
+use log::error;
+
+/// A scene laid out in a single SVG, with the objects it's built from & the points where units
+/// and props are spawned.
+#[derive(Debug, Clone)]
+pub struct Level {
+    /// The SVG the level is built from.
+    svg: SvgHandle,
+    /// The names of the object definitions of the level, like "forest/ground".
+    objects: Vec<String>,
+    /// The `<spawn>` markers in the metadata.
+    markers: Vec<SpawnMarker>,
+}
+
+impl Level {
+    /// Register an object definition for every `<object>` element of the SVG, named after the
+    /// level like "forest/ground", and keep the spawn markers.
+    pub fn load(
+        name: &str,
+        svg: Svg,
+        render: &mut Render,
+        source: &ObjectSource,
+        defs: &mut ObjectRegistry,
+        handle: SvgHandle,
+    ) -> Result<Self> {
+        let (level_defs, markers) = svg.into_object_defs(render, source)?;
+
+        let mut objects = vec![];
+        for (object, def) in level_defs {
+            let meshes = std::iter::once(def.mesh)
+                .chain(def.parts.values().copied())
+                .collect::<Vec<_>>();
+
+            // Combine the memory usage of the meshes under the name of the definition
+            let object = format!("{}/{}", name, object);
+            let label = defs.register(&object, handle, def);
+            for mesh in meshes {
+                render.set_label(mesh, label);
+                render.set_shader(mesh, source.shader.as_deref())?;
+            }
+
+            objects.push(object);
+        }
+
+        Ok(Self {
+            svg: handle,
+            objects,
+            markers,
+        })
+    }
+
+    /// The SVG the level is built from.
+    pub fn svg(&self) -> SvgHandle {
+        self.svg
+    }
+
+    /// Spawn all objects of the level with its origin at a world position, and a unit or object
+    /// at every marker.
+    ///
+    /// A marker spawns the definition of the level with its name, or the one from
+    /// `assets/objects.toml` when the level has none. It's a unit when it has an `allegiance` or
+    /// a `health` attribute. Returns the spawned units with the names of their definitions.
+    pub fn spawn(&self, world: &mut World, x: f32, y: f32) -> Vec<(EntityId, String)> {
+        for def in &self.objects {
+            let request = SpawnRequest {
+                def: def.clone(),
+                kind: SpawnKind::Object,
+                x,
+                y,
+            };
+            if let Err(err) = world.spawn(&request) {
+                error!("spawning \"{}\" failed: {}", def, err);
+            }
+        }
+
+        let mut spawned = vec![];
+        for marker in &self.markers {
+            if let Err(err) = self.spawn_marker(world, marker, x, y, &mut spawned) {
+                error!("spawning marker \"{}\" failed: {}", marker.object, err);
+            }
+        }
+
+        spawned
+    }
+
+    /// Spawn the unit or object of a marker.
+    fn spawn_marker(
+        &self,
+        world: &mut World,
+        marker: &SpawnMarker,
+        x: f32,
+        y: f32,
+        spawned: &mut Vec<(EntityId, String)>,
+    ) -> Result<()> {
+        let def = self
+            .objects
+            .iter()
+            .find(|def| def.rsplit('/').next() == Some(&marker.object))
+            .cloned()
+            .unwrap_or_else(|| marker.object.clone());
+
+        let health = marker
+            .attributes
+            .get("health")
+            .map(|health| {
+                health
+                    .parse::<Float>()
+                    .map_err(|err| anyhow!("Invalid health \"{}\": {}", health, err))
+            })
+            .transpose()?;
+        let allegiance = marker
+            .attributes
+            .get("allegiance")
+            .map(|allegiance| match allegiance.as_str() {
+                "ally" => Ok(Allegiance::Ally),
+                "enemy" => Ok(Allegiance::Enemy),
+                other => Err(anyhow!(
+                    "Unknown allegiance {}, expected ally or enemy",
+                    other
+                )),
+            })
+            .transpose()?;
+        let kind = if allegiance.is_some() || health.is_some() {
+            SpawnKind::Unit {
+                allegiance: allegiance.unwrap_or_default(),
+                tint: None,
+            }
+        } else {
+            SpawnKind::Object
+        };
+
+        let request = SpawnRequest {
+            def,
+            kind,
+            x: x + marker.pos.0,
+            y: y + marker.pos.1,
+        };
+        if let Some(entity) = world.spawn(&request)? {
+            if let (Some(health), Some(unit)) = (health, world.units.get_mut(entity.into())) {
+                unit.health = Health::new(health);
+            }
+
+            spawned.push((entity, request.def));
+        }
+
+        Ok(())
+    }
+}
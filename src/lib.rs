@@ -0,0 +1,139 @@
+pub mod animation;
+pub mod args;
+pub mod assets;
+pub mod audio;
+pub mod camera;
+pub mod capture;
+pub mod checkpoint;
+pub mod clock;
+pub mod combat_log;
+pub mod config;
+pub mod controller;
+pub mod crash;
+pub mod cursor;
+pub mod debug;
+pub mod event;
+pub mod gradient;
+pub mod headless;
+pub mod i18n;
+pub mod input;
+pub mod inventory;
+pub mod jobs;
+pub mod key;
+pub mod label;
+pub mod level;
+pub mod loading;
+pub mod log_view;
+pub mod logger;
+pub mod motion;
+pub mod network;
+pub mod nine_slice;
+pub mod object;
+pub mod pacing;
+pub mod particles;
+pub mod physics;
+pub mod platform;
+pub mod post;
+pub mod profiler;
+pub mod projectile;
+pub mod random;
+pub mod render;
+pub mod replay;
+pub mod replication;
+pub mod save;
+pub mod scene;
+pub mod script;
+pub mod shader;
+pub mod simulation;
+pub mod spawner;
+pub mod stress;
+pub mod svg;
+pub mod text;
+pub mod texture;
+pub mod transform;
+pub mod tween;
+pub mod ui;
+pub mod unit;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+pub mod world;
+pub mod world_ui;
+
+use crate::i18n::I18n;
+use anyhow::{anyhow, Result};
+use glsp::{GFn, GSend, Root, Runtime, ToCallArgs, Val};
+use log::{error, warn};
+
+/// The precision of the physics, WebGL & the browser are faster with single precision.
+#[cfg(not(target_arch = "wasm32"))]
+pub type Float = f64;
+#[cfg(target_arch = "wasm32")]
+pub type Float = f32;
+pub type Vec2 = nalgebra::Vector2<Float>;
+
+/// The script with the functions the engine calls.
+pub const MAIN_SCRIPT: &str = include_str!("../scripts/main.glsp");
+
+/// The font used for all text.
+pub const FONT: &[u8] = include_bytes!("../assets/FetteNationalFraktur.ttf");
+
+/// The language used when translations are missing.
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// Load the translations and select the language, falling back when it's unknown.
+pub fn load_i18n(language: &str) -> Result<I18n> {
+    let mut i18n = I18n::new(FALLBACK_LANGUAGE);
+    i18n.add_language("en", include_str!("../locales/en.toml"))?;
+    i18n.add_language("nl", include_str!("../locales/nl.toml"))?;
+    if let Err(err) = i18n.set_language(language) {
+        warn!("selecting language failed: {}", err);
+    }
+
+    Ok(i18n)
+}
+
+/// Evaluate the main script, the libraries should be added before.
+pub fn load_scripts(runtime: &Runtime) -> Result<()> {
+    runtime
+        .run(|| {
+            glsp::eval_multi(&glsp::parse_all(MAIN_SCRIPT, None)?, None)?;
+
+            Ok(())
+        })
+        .ok_or_else(|| anyhow!("Evaluating the main script failed"))
+}
+
+/// Run a GameLisp function with arguments, returns whether the function exists and succeeded.
+pub fn call_script<A>(runtime: &Runtime, function: &str, args: &A) -> bool
+where
+    A: ToCallArgs + GSend + ?Sized,
+{
+    struct RuntimeResult(bool);
+
+    let result: RuntimeResult = runtime
+        .run(|| {
+            let func: Root<GFn> = match glsp::global(function) {
+                Ok(Val::GFn(func)) => func,
+                Ok(val) => {
+                    error!("invalid {} function: {}", function, val);
+
+                    return Ok(RuntimeResult(false));
+                }
+                Err(err) => {
+                    error!("error finding {} function: {}", function, err);
+
+                    return Ok(RuntimeResult(false));
+                }
+            };
+            if let Err(err) = glsp::call::<_, _, Val>(&func, args) {
+                error!("calling {} function failed: {}", function, err);
+
+                return Ok(RuntimeResult(false));
+            }
+
+            Ok(RuntimeResult(true))
+        })
+        .unwrap_or(RuntimeResult(false));
+
+    result.0
+}
@@ -0,0 +1,262 @@
+use crate::{
+    i18n::I18n,
+    jobs::JobPool,
+    render::{Instance, Mesh, Render},
+    text::{Alignment, FontInstance, TextLayout},
+};
+use anyhow::Result;
+use glsp::GSend;
+use std::collections::VecDeque;
+use usvg::Color;
+
+/// Size of the progress bar in pixels.
+const BAR_SIZE: (f32, f32) = (400.0, 24.0);
+/// Amount of pieces the filled part of the progress bar is made of.
+const BAR_SEGMENTS: usize = 100;
+/// Scale of the glyphs of the text.
+const TEXT_SCALE: f32 = 0.2;
+/// Distance between the lines of a tip in pixels.
+const TIP_LINE_HEIGHT: f32 = 24.0;
+/// How long a single tip is shown in seconds.
+const TIP_DURATION: f64 = 4.0;
+
+/// The z index of the progress bar background.
+const BACKGROUND_Z: u8 = 0;
+/// The z index of the filled part of the progress bar and the text.
+const FOREGROUND_Z: u8 = 1;
+
+/// A single loading step, receiving the assets loaded so far.
+///
+/// Returns `None` while it's waiting for its background job.
+type Step<T> = Box<dyn FnMut(&mut T, &mut Render) -> Option<Result<()>>>;
+
+/// What a loading step loads, translated when it's shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    /// The translation key.
+    pub key: String,
+    /// The values of the placeholders in the translation by their name.
+    pub args: Vec<(String, String)>,
+}
+
+/// Loads assets incrementally so the window keeps rendering while loading.
+///
+/// The assets are parsed on the worker threads of the job pool, only the uploads happen on the
+/// main thread in the order the assets are added.
+pub struct Loader<T> {
+    /// The steps that still need to be executed with their description.
+    steps: VecDeque<(Status, Step<T>)>,
+    /// The amount of steps added.
+    total: usize,
+    /// The assets loaded so far.
+    assets: T,
+    /// The worker threads parsing the assets.
+    jobs: JobPool,
+}
+
+// The steps only parse & upload assets, the loader is used while the scripts run
+impl<T> GSend for Loader<T> {}
+
+impl<T> Loader<T> {
+    /// Create a loader without any steps.
+    pub fn new(assets: T, jobs: JobPool) -> Self {
+        Self {
+            steps: VecDeque::new(),
+            total: 0,
+            assets,
+            jobs,
+        }
+    }
+
+    /// Start a job parsing an asset in the background, and add a step to the end of the queue
+    /// uploading the result.
+    ///
+    /// The description is a translation key.
+    pub fn add<V, J, F>(&mut self, description: &str, job: J, upload: F)
+    where
+        V: Send + 'static,
+        J: FnOnce() -> Result<V> + Send + 'static,
+        F: FnOnce(V, &mut T, &mut Render) -> Result<()> + 'static,
+    {
+        self.add_with_args(description, &[], job, upload);
+    }
+
+    /// Add a step like `add`, with values for the placeholders of the translated description.
+    pub fn add_with_args<V, J, F>(
+        &mut self,
+        description: &str,
+        args: &[(&str, &str)],
+        job: J,
+        upload: F,
+    ) where
+        V: Send + 'static,
+        J: FnOnce() -> Result<V> + Send + 'static,
+        F: FnOnce(V, &mut T, &mut Render) -> Result<()> + 'static,
+    {
+        let mut handle = self.jobs.spawn(job);
+        let mut upload = Some(upload);
+        let step: Step<T> = Box::new(move |assets, render| {
+            let value = handle.try_take()?;
+            let upload = upload.take()?;
+
+            Some(value.and_then(|value| upload(value, assets, render)))
+        });
+
+        let status = Status {
+            key: description.to_string(),
+            args: args
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        };
+        self.steps.push_back((status, step));
+        self.total += 1;
+    }
+
+    /// Execute steps until the time budget in seconds is spent.
+    ///
+    /// Stops early when the next step is still waiting for its background job.
+    pub fn step(&mut self, render: &mut Render, budget: f64) -> Result<()> {
+        let start = miniquad::date::now();
+
+        while let Some((_, step)) = self.steps.front_mut() {
+            match step(&mut self.assets, render) {
+                Some(result) => {
+                    self.steps.pop_front();
+                    result?;
+                }
+                None => break,
+            }
+
+            if miniquad::date::now() - start >= budget {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How much is loaded, between 0.0 and 1.0.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.total - self.steps.len()) as f32 / self.total as f32
+        }
+    }
+
+    /// The description of the next step.
+    pub fn status(&self) -> Option<&Status> {
+        self.steps.front().map(|(status, _)| status)
+    }
+
+    /// Whether all steps are executed.
+    pub fn is_done(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Get the loaded assets.
+    pub fn finish(self) -> T {
+        self.assets
+    }
+}
+
+/// Screen space progress bar with tips, shown while loading.
+pub struct LoadingScreen {
+    /// Mesh for the background of the progress bar.
+    background_mesh: Mesh,
+    /// Mesh for a single piece of the filled part of the progress bar.
+    segment_mesh: Mesh,
+    /// Translation keys of the tips cycled through while loading.
+    tips: Vec<String>,
+    /// The time the loading screen was created in seconds.
+    start_time: f64,
+}
+
+impl LoadingScreen {
+    /// Upload the meshes for the progress bar.
+    pub fn new(render: &mut Render, tips: Vec<String>) -> Self {
+        let background_mesh =
+            render.upload_rectangle(BAR_SIZE.0, BAR_SIZE.1, Color::new(0x10, 0x10, 0x10), 0.8);
+        let segment_mesh = render.upload_rectangle(
+            BAR_SIZE.0 / BAR_SEGMENTS as f32,
+            BAR_SIZE.1,
+            Color::new(0xff, 0xed, 0x48),
+            1.0,
+        );
+
+        Self {
+            background_mesh,
+            segment_mesh,
+            tips,
+            start_time: miniquad::date::now(),
+        }
+    }
+
+    /// Queue the screen space instances for the next frame.
+    ///
+    /// The status and the tips are translated every frame, so they follow the selected language.
+    pub fn draw(
+        &self,
+        progress: f32,
+        status: Option<&Status>,
+        screen_size: (f32, f32),
+        font: &mut FontInstance,
+        i18n: &I18n,
+        render: &mut Render,
+    ) {
+        // Center the progress bar
+        let x = (screen_size.0 - BAR_SIZE.0) / 2.0;
+        let y = (screen_size.1 - BAR_SIZE.1) / 2.0;
+
+        let mut background = Instance::new(x, y);
+        background.set_z(BACKGROUND_Z);
+        render.draw_screen(self.background_mesh, background);
+
+        // Fill the bar with segments, so no mesh has to be stretched
+        let segments = (progress.max(0.0).min(1.0) * BAR_SEGMENTS as f32).round() as usize;
+        let segment_width = BAR_SIZE.0 / BAR_SEGMENTS as f32;
+        for segment in 0..segments {
+            let mut instance = Instance::new(x + segment as f32 * segment_width, y);
+            instance.set_z(FOREGROUND_Z);
+            render.draw_screen(self.segment_mesh, instance);
+        }
+
+        // Show what's being loaded above the bar
+        if let Some(status) = status {
+            let args = status
+                .args
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>();
+            self.draw_text(&i18n.t(&status.key, &args), x, y - 12.0, font, render);
+        }
+
+        // Show a different tip every few seconds below the bar
+        if !self.tips.is_empty() {
+            let elapsed = miniquad::date::now() - self.start_time;
+            let tip = &self.tips[(elapsed / TIP_DURATION) as usize % self.tips.len()];
+
+            // Wrap long tips to the width of the bar
+            let layout = TextLayout::new()
+                .scale(TEXT_SCALE)
+                .line_height(TIP_LINE_HEIGHT)
+                .max_width(BAR_SIZE.0)
+                .alignment(Alignment::Center);
+            for (mut instance, mesh) in
+                font.layout(render, &i18n.t(tip, &[]), x, y + BAR_SIZE.1 + 32.0, &layout)
+            {
+                instance.set_z(FOREGROUND_Z);
+                render.draw_screen(mesh, instance);
+            }
+        }
+    }
+
+    /// Queue the instances of a line of text.
+    fn draw_text(&self, text: &str, x: f32, y: f32, font: &mut FontInstance, render: &mut Render) {
+        for (mut instance, mesh) in font.text_scaled(render, text, x, y, TEXT_SCALE) {
+            instance.set_z(FOREGROUND_Z);
+            render.draw_screen(mesh, instance);
+        }
+    }
+}
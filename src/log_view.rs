@@ -0,0 +1,119 @@
+use crate::{
+    logger::LogHistory,
+    render::{ClipRect, Instance, Mesh, Render},
+    text::FontInstance,
+};
+use log::Level;
+use usvg::Color;
+
+/// Amount of the most recent lines shown.
+const VISIBLE_LINES: usize = 16;
+/// Height of a single line in pixels.
+const LINE_HEIGHT: f32 = 16.0;
+/// Width of the panel in pixels.
+const WIDTH: f32 = 480.0;
+/// Space between the border of the panel and the text in pixels.
+const PADDING: f32 = 8.0;
+/// Space between the panel and the border of the screen in pixels.
+const MARGIN: f32 = 16.0;
+/// Scale of the glyphs.
+const TEXT_SCALE: f32 = 0.12;
+
+/// The z index of the background.
+const BACKGROUND_Z: u8 = 200;
+/// The z index of the text.
+const TEXT_Z: u8 = 201;
+
+/// Screen space panel showing the most recent log messages.
+pub struct LogView {
+    /// The messages shared with the logger.
+    history: LogHistory,
+    /// Whether the panel is drawn.
+    visible: bool,
+    /// Mesh for the background of the panel.
+    background_mesh: Mesh,
+}
+
+impl LogView {
+    /// Upload the background mesh.
+    pub fn new(render: &mut Render, history: LogHistory, visible: bool) -> Self {
+        let background_mesh = render.upload_rectangle(
+            WIDTH,
+            VISIBLE_LINES as f32 * LINE_HEIGHT + PADDING * 2.0,
+            Color::black(),
+            0.7,
+        );
+
+        Self {
+            history,
+            visible,
+            background_mesh,
+        }
+    }
+
+    /// Show or hide the panel.
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Show the panel, for when something needs the attention of the player.
+    pub fn show(&mut self) {
+        self.visible = true;
+    }
+
+    /// Queue the screen space instances in the top right corner for the next frame.
+    pub fn draw(&self, ui_size: (f32, f32), font: &mut FontInstance, render: &mut Render) {
+        if !self.visible {
+            return;
+        }
+
+        let x = ui_size.0 - WIDTH - MARGIN;
+        let y = MARGIN;
+        let height = VISIBLE_LINES as f32 * LINE_HEIGHT + PADDING * 2.0;
+
+        let mut background = Instance::new(x, y);
+        background.set_z(BACKGROUND_Z);
+        render.draw_screen(self.background_mesh, background);
+
+        let clip = ClipRect {
+            x: (x + PADDING) as i32,
+            y: (y + PADDING) as i32,
+            width: (WIDTH - PADDING * 2.0) as i32,
+            height: (height - PADDING * 2.0) as i32,
+        };
+
+        let history = match self.history.lock() {
+            Ok(history) => history,
+            Err(_) => return,
+        };
+
+        // Show the newest message at the bottom
+        let skip = history.len().saturating_sub(VISIBLE_LINES);
+        for (index, line) in history.iter().skip(skip).enumerate() {
+            let color = level_color(line.level);
+            let line_bottom = y + PADDING + (index + 1) as f32 * LINE_HEIGHT - 4.0;
+
+            for (mut instance, mesh) in font.text_scaled(
+                render,
+                &line.to_string(),
+                x + PADDING,
+                line_bottom,
+                TEXT_SCALE,
+            ) {
+                instance.set_z(TEXT_Z);
+                instance.set_color_multiplier(color.0, color.1, color.2);
+                render.draw_screen_clipped(mesh, instance, clip);
+            }
+        }
+    }
+}
+
+/// The color multiplier for the text of a level.
+fn level_color(level: Level) -> (f32, f32, f32) {
+    match level {
+        Level::Error => (1.0, 0.3, 0.3),
+        Level::Warn => (1.0, 0.85, 0.3),
+        Level::Info => (1.0, 1.0, 1.0),
+        Level::Debug | Level::Trace => (0.6, 0.6, 0.6),
+    }
+}
@@ -0,0 +1,191 @@
+use crate::config::LogConfig;
+use anyhow::{anyhow, Result};
+use glsp::{rfn, Runtime};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Formatter, Result as FmtResult},
+    iter,
+    sync::{Arc, Mutex},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, io::Write};
+
+/// Amount of lines kept in memory for the in-game view.
+pub const HISTORY_LENGTH: usize = 256;
+
+/// The target of the messages logged from scripts.
+const SCRIPT_TARGET: &str = "script";
+
+/// A single logged message.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    /// How important the message is.
+    pub level: Level,
+    /// The module the message is logged from.
+    pub target: String,
+    /// The formatted message.
+    pub message: String,
+}
+
+impl Display for LogLine {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "[{} {}] {}", self.level, self.target, self.message)
+    }
+}
+
+/// The most recent log lines, shared with the logger.
+pub type LogHistory = Arc<Mutex<VecDeque<LogLine>>>;
+
+/// Writes the messages to the console, an optional file and the history.
+struct Logger {
+    /// Level for modules without a filter.
+    level: LevelFilter,
+    /// Levels for specific modules and their submodules.
+    filters: Vec<(String, LevelFilter)>,
+    /// The file all messages are also written to.
+    #[cfg(not(target_arch = "wasm32"))]
+    file: Option<Mutex<File>>,
+    /// The most recent messages.
+    history: LogHistory,
+}
+
+impl Logger {
+    /// Get the level of the most specific filter matching the module.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.filters
+            .iter()
+            .filter(|(module, _)| {
+                target == module
+                    || (target.starts_with(module.as_str())
+                        && target[module.len()..].starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = LogLine {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        eprintln!("{}", line);
+        #[cfg(target_arch = "wasm32")]
+        crate::web::console(line.level, &line.to_string());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(Ok(mut file)) = self.file.as_ref().map(|file| file.lock()) {
+                // There's nowhere to report a failing log file to
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+
+        if let Ok(mut history) = self.history.lock() {
+            history.push_back(line);
+            while history.len() > HISTORY_LENGTH {
+                history.pop_front();
+            }
+        }
+    }
+
+    fn flush(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(Ok(mut file)) = self.file.as_ref().map(|file| file.lock()) {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Install the global logger, should only be called once.
+///
+/// Returns the history of the messages for showing them in-game.
+pub fn init(config: &LogConfig) -> Result<LogHistory> {
+    let level = parse_level(&config.level)?;
+    let filters = config
+        .filters
+        .iter()
+        .map(|(module, level)| Ok((module.clone(), parse_level(level)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let history = LogHistory::default();
+
+    let logger = Logger {
+        level,
+        filters,
+        #[cfg(not(target_arch = "wasm32"))]
+        file: match &config.file {
+            Some(path) => Some(Mutex::new(File::create(path)?)),
+            None => None,
+        },
+        history: history.clone(),
+    };
+
+    // Let everything through that one of the filters might want, the logger filters the rest
+    let max_level = logger
+        .filters
+        .iter()
+        .map(|(_, level)| *level)
+        .chain(iter::once(level))
+        .max()
+        .unwrap_or(level);
+
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(max_level);
+
+    Ok(history)
+}
+
+/// Bind the GameLisp functions.
+pub fn bind_functions(runtime: &Runtime) {
+    runtime.run(|| {
+        glsp::bind_rfn("log-error", rfn!(log_error))?;
+        glsp::bind_rfn("log-warn", rfn!(log_warn))?;
+        glsp::bind_rfn("log-info", rfn!(log_info))?;
+        glsp::bind_rfn("log-debug", rfn!(log_debug))?;
+
+        Ok(())
+    });
+}
+
+/// Parse a level name like "info".
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    level
+        .parse()
+        .map_err(|_| anyhow!("Unknown log level \"{}\"", level))
+}
+
+/// Log an error from a script.
+fn log_error(message: &str) {
+    log::error!(target: SCRIPT_TARGET, "{}", message);
+}
+
+/// Log a warning from a script.
+fn log_warn(message: &str) {
+    log::warn!(target: SCRIPT_TARGET, "{}", message);
+}
+
+/// Log information from a script.
+fn log_info(message: &str) {
+    log::info!(target: SCRIPT_TARGET, "{}", message);
+}
+
+/// Log a debug message from a script.
+fn log_debug(message: &str) {
+    log::debug!(target: SCRIPT_TARGET, "{}", message);
+}
@@ -1,176 +1,1815 @@
-mod object;
-mod physics;
-mod render;
-mod svg;
-mod text;
-mod unit;
-
-use crate::{
-    object::ObjectDef, physics::Physics, render::Render, svg::Svg, text::Font, unit::UnitBuilder,
-};
-use anyhow::Result;
-use glsp::{GFn, Lib, Root, Runtime, Val};
+use anyhow::{anyhow, Result};
+use glsp::{GSend, Lib, Runtime, ToCallArgs};
+use log::{error, info, warn};
 use miniquad::{
-    conf::{Conf, Loading},
-    Context, EventHandler, UserData,
+    conf::Conf, Context, EventHandler, KeyCode, KeyMods, MouseButton, TouchPhase, UserData,
+};
+use replace_me::{
+    args::{self, Args},
+    assets::{AssetManager, DataHandle, FontHandle, SvgHandle, SvgWatcher},
+    audio::Audio,
+    call_script,
+    camera::Camera,
+    capture::Capture,
+    checkpoint::Checkpoints,
+    clock::{Clock, ClockState},
+    config::{Config, UI_SCALE_STEP},
+    crash,
+    cursor::{Cursor, CursorKind},
+    debug::DebugPhysics,
+    event::EventBus,
+    headless,
+    i18n::I18n,
+    input::{GamepadButton, Gamepads, Input},
+    inventory::InventoryAction,
+    jobs::JobPool,
+    label::{LabelSystem, Labels},
+    level::Level,
+    load_i18n, load_scripts,
+    loading::{Loader, LoadingScreen},
+    log_view::LogView,
+    logger,
+    logger::LogHistory,
+    motion::Motion,
+    network::Lockstep,
+    object::ObjectRegistry,
+    pacing::FrameLimiter,
+    particles::Particles,
+    platform::Platform,
+    profiler::{Profiler, Stage},
+    random::Random,
+    render::{Readback, Render},
+    replay::{InputEvent, Replay, ReplayFrame, ReplaySession},
+    replication::Replication,
+    save::{SaveData, SaveQueue, SaveRequest, SaveSlots},
+    scene::{MainMenu, Pause, SceneManager, SceneRequest},
+    script::{self, ScriptWatcher},
+    shader::{ShaderSource, ShaderWatcher},
+    simulation::{self, Frame},
+    spawner::Spawner,
+    stress::{StressOptions, StressScene},
+    svg::Svg,
+    text::{Font, FontInstance},
+    tween::Tweens,
+    ui::Ui,
+    world::{Assets, World},
+    world_ui::WorldUi,
+    Float, MAIN_SCRIPT,
 };
+use std::mem;
 
-type Float = f64;
-type Vec2 = nalgebra::Vector2<Float>;
+/// Time in seconds that can be spent loading assets every frame.
+const LOADING_BUDGET: f64 = 1.0 / 60.0;
 
-const WIDTH: usize = 800;
-const HEIGHT: usize = 600;
+/// Amount of seconds a replay jumps with every seek.
+const SEEK_SECONDS: f64 = 5.0;
 
-const ZOOM_FACTOR: f32 = 30.0;
-const MAX_ZOOM: f32 = 20.0;
+/// Where the save slots are stored.
+const SAVE_DIRECTORY: &str = "saves";
+/// The slot overwritten with every quick save.
+const QUICK_SAVE_SLOT: &str = "quicksave";
+
+/// Printable ASCII characters, always uploaded regardless of the language.
+const DEFAULT_CHARSET: &str = concat!(
+    "!\"#$%&'()*+,-./0123456789:;<=>?@",
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`",
+    "abcdefghijklmnopqrstuvwxyz{|}~"
+);
 
 /// Our game state.
 struct Game {
-    /// The camera zoom value.
-    zoom: f32,
-    /// The object definition for arrows.
-    arrow_def: ObjectDef,
+    /// The options loaded at startup.
+    config: Config,
+    /// Path of the configuration file, changed options are written to it.
+    config_path: String,
+    /// Caps the frames per second.
+    frame_limiter: FrameLimiter,
     /// The scripting runtime.
     runtime: Runtime,
-    /// The physics system.
-    physics: Physics<Float>,
+    /// The uploaded font.
+    font: FontInstance,
+    /// The texts drawn by the scripts.
+    labels: LabelSystem,
+    /// The health bars & damage numbers above the units.
+    world_ui: WorldUi,
+    /// Loads the assets, removed when everything is loaded.
+    loader: Option<Loader<Assets>>,
+    /// Shows the progress of the loader.
+    loading_screen: LoadingScreen,
+    /// The gameplay state, available when all assets are loaded.
+    world: Option<World>,
+    /// The menus on top of the gameplay, the gameplay is paused while one is shown.
+    scenes: SceneManager,
+    /// Shows the most recent log messages.
+    log_view: LogView,
+    /// Draws the colliders of the physics bodies, toggled with F1.
+    debug_physics: DebugPhysics,
+    /// Measures the frame timings.
+    profiler: Profiler,
+    /// Keeps the most recent frames for exporting.
+    capture: Capture,
+    /// Records the gameplay or plays it back, available when all assets are loaded.
+    replay: Option<ReplaySession>,
+    /// The live input handled since the last frame, for the replay or the other player.
+    recorded_input: Vec<InputEvent>,
+    /// Exchanges the input with the other player in a multiplayer session.
+    lockstep: Option<Lockstep>,
+    /// Sends the world to the clients or the input to the server in a client-server session.
+    replication: Option<Replication>,
+    /// The named save slots.
+    saves: SaveSlots,
+    /// The slot to save to when the frame for the thumbnail is rendered.
+    pending_save: Option<String>,
+    /// Reads the connected gamepads.
+    gamepads: Gamepads,
+    /// Reads the SVGs, fonts & sounds.
+    asset_manager: AssetManager,
+    /// Replaces the meshes of the SVGs when their files change.
+    svg_watcher: SvgWatcher,
+    /// Reloads the shaders when their files change.
+    shader_watcher: ShaderWatcher,
+    /// Reloads the main script when its file changes.
+    script_watcher: ScriptWatcher,
+    /// The source of the main script that's running, checkpoints save the values it defines.
+    script_source: String,
+    /// Achievements, statistics & cloud saves of the store the game is distributed through.
+    platform: Platform,
+    /// Measures the renderer with a huge amount of instances, enabled with `--stress`.
+    stress: Option<StressScene>,
 }
 
 impl Game {
     /// Setup the ECS and load the systems.
-    pub fn new(ctx: &mut Context) -> Result<Self> {
+    pub fn new(
+        ctx: &mut Context,
+        config: Config,
+        config_path: String,
+        log_history: LogHistory,
+        stress: Option<StressOptions>,
+    ) -> Result<Self> {
         // Setup the OpenGL render part
         let mut render = Render::new(ctx);
+        render.set_ui_scale(config.display.ui_scale);
+        render.configure_post(&config.display.post);
+        for source in ShaderSource::custom() {
+            render.register_shader(ctx, &source)?;
+        }
+
+        // Load the translations
+        let i18n = load_i18n(&config.language)?;
+
+        // Read the assets from the assets directory while developing
+        let asset_manager = AssetManager::new(&config.assets.root);
+        let audio = Audio::new(&config.audio, &asset_manager)?;
+
+        // Parse & tessellate the assets on worker threads
+        let jobs = JobPool::new();
 
-        // Parse SVG and convert it to object definitions
-        let mut character_def = Svg::from_str(include_str!("../assets/single-character.svg"))?
-            .into_object_def(&mut render)?;
-        let mut ground_def =
-            Svg::from_str(include_str!("../assets/ground.svg"))?.into_object_def(&mut render)?;
-        let arrow_def =
-            Svg::from_str(include_str!("../assets/arrow.svg"))?.into_object_def(&mut render)?;
+        // Tessellate the glyphs used by the language while setting up the rest, so they don't have
+        // to be uploaded one by one when they're first shown
+        let charset = format!("{}{}", DEFAULT_CHARSET, i18n.charset());
+        let font_bytes = asset_manager.font(FontHandle::FRAKTUR)?;
+        let font = jobs.spawn(move || Font::from_bytes(font_bytes)?.tessellate(charset.chars()));
 
-        // Parse a font
-        let font = Font::from_bytes(include_bytes!("../assets/FetteNationalFraktur.ttf"))?.upload(
+        let loading_screen = LoadingScreen::new(
             &mut render,
-            "ABCDEFGHIJKLMOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789".chars(),
-        )?;
+            vec![
+                "tip-scroll-log".to_string(),
+                "tip-drag-items".to_string(),
+                "tip-use-item".to_string(),
+            ],
+        );
+
+        let log_view = LogView::new(&mut render, log_history, config.debug.log_view);
+        let debug_physics = DebugPhysics::new(&mut render, config.debug.physics);
+        let profiler = Profiler::new(
+            &mut render,
+            config.debug.profiler,
+            config.debug.memory_budget,
+        );
+
+        // Host or join a multiplayer session
+        let fixed_step = config.simulation.fixed_step();
+        let lockstep = Lockstep::connect(&config.network, fixed_step).unwrap_or_else(|err| {
+            error!("starting multiplayer failed, playing offline: {}", err);
+
+            None
+        });
+        let replication = Replication::connect(&config.network).unwrap_or_else(|err| {
+            error!(
+                "starting client-server networking failed, playing offline: {}",
+                err
+            );
+
+            None
+        });
 
-        // Instantiate the physics engine
-        let physics = Physics::new(9.81 * 100.0);
+        let stress = stress.and_then(|options| {
+            StressScene::new(&mut render, font_bytes, options)
+                .map_err(|err| error!("setting up stress scene failed: {}", err))
+                .ok()
+        });
+
+        let shader_watcher = ShaderWatcher::new(&config.assets.root);
+
+        // Queue the rest of the assets so they are loaded while showing the loading screen
+        let mut loader = Loader::new(Assets::default(), jobs);
+        loader.add(
+            "loading-cursors",
+            {
+                let asset_manager = asset_manager.clone();
+                move || {
+                    Ok(vec![
+                        (
+                            CursorKind::Default,
+                            asset_manager.svg(SvgHandle::CURSOR_DEFAULT)?,
+                        ),
+                        (
+                            CursorKind::Attack,
+                            asset_manager.svg(SvgHandle::CURSOR_ATTACK)?,
+                        ),
+                        (
+                            CursorKind::Forbidden,
+                            asset_manager.svg(SvgHandle::CURSOR_FORBIDDEN)?,
+                        ),
+                    ])
+                }
+            },
+            |variants, _, render| {
+                let mut cursor = Cursor::borrow_mut();
+                for (kind, svg) in variants {
+                    cursor.load_variant(render, kind, svg)?;
+                }
+
+                Ok(())
+            },
+        );
+        let sources = ObjectRegistry::parse_sources(&asset_manager.data(DataHandle::OBJECTS)?)?;
+        for (name, source) in sources {
+            let handle = SvgHandle::from_path(&source.svg)?;
+            let status_name = name.clone();
+            loader.add_with_args(
+                "loading-object",
+                &[("name", &status_name)],
+                {
+                    let asset_manager = asset_manager.clone();
+                    move || asset_manager.svg(handle)
+                },
+                move |svg, assets, render| {
+                    if source.level {
+                        let level =
+                            Level::load(&name, svg, render, &source, &mut assets.defs, handle)?;
+                        assets.levels.insert(name, level);
+
+                        return Ok(());
+                    }
+
+                    let def = svg.into_object_def(render, &source)?;
+                    let meshes = std::iter::once(def.mesh)
+                        .chain(def.parts.values().copied())
+                        .collect::<Vec<_>>();
+
+                    // Combine the memory usage of the meshes under the name of the definition
+                    let label = assets.defs.register(&name, handle, def);
+                    for mesh in meshes {
+                        render.set_label(mesh, label);
+                        render.set_shader(mesh, source.shader.as_deref())?;
+                    }
+
+                    Ok(())
+                },
+            );
+        }
+        loader.add(
+            "loading-panels",
+            {
+                let asset_manager = asset_manager.clone();
+                move || asset_manager.svg(SvgHandle::PANEL)?.into_nine_slice()
+            },
+            |panel, assets, _| {
+                assets.panel = Some(panel);
+
+                Ok(())
+            },
+        );
+
+        // Replace the OS cursor with the SVG cursors
+        ctx.show_mouse(false);
 
         // Setup the script runtime
+        let particles = Particles::new(&mut render);
+        let mut clock = Clock::new(fixed_step);
+        clock.set_fixed_only(config.simulation.deterministic);
         let runtime = Runtime::new();
         runtime.run(|| {
             glsp::add_lib(render);
-
-            glsp::eval_multi(
-                &glsp::parse_all(include_str!("../scripts/main.glsp"), None)?,
-                None,
-            )?;
+            glsp::add_lib(particles);
+            glsp::add_lib(Camera::new());
+            glsp::add_lib(Spawner::new());
+            glsp::add_lib(SaveQueue::new());
+            glsp::add_lib(Motion::new());
+            glsp::add_lib(Tweens::new());
+            glsp::add_lib(Labels::new());
+            glsp::add_lib(Ui::new());
+            glsp::add_lib(EventBus::new());
+            glsp::add_lib(Cursor::new());
+            glsp::add_lib(clock);
+            glsp::add_lib(Random::new(config.simulation.seed));
+            glsp::add_lib(i18n);
+            glsp::add_lib(Checkpoints::new());
+            glsp::add_lib(audio);
+            glsp::add_lib(Input::new(config.input.clone()));
 
             Ok(())
         });
+        Camera::bind_functions(&runtime);
+        Spawner::bind_functions(&runtime);
+        SaveQueue::bind_functions(&runtime);
+        Motion::bind_functions(&runtime);
+        Tweens::bind_functions(&runtime);
+        Labels::bind_functions(&runtime);
+        Ui::bind_functions(&runtime);
+        EventBus::bind_functions(&runtime);
+        Cursor::bind_functions(&runtime);
+        Clock::bind_functions(&runtime);
+        Random::bind_functions(&runtime);
+        I18n::bind_functions(&runtime);
+        Audio::bind_functions(&runtime);
+        Input::bind_functions(&runtime);
+        Particles::bind_functions(&runtime);
+        Render::bind_functions(&runtime);
+        logger::bind_functions(&runtime);
+        load_scripts(&runtime)?;
+
+        // The font should be tessellated by now
+        let font = font.wait()?;
+        let font = runtime
+            .run(|| {
+                Ok(Font::from_bytes(font_bytes)
+                    .and_then(|parsed| font.upload(parsed, &mut Render::borrow_mut())))
+            })
+            .unwrap_or_else(|| Err(anyhow!("Script runtime failed while uploading font")))?;
 
         Ok(Self {
-            physics,
-            zoom: 0.0,
-            arrow_def,
+            config,
+            config_path,
+            frame_limiter: FrameLimiter::new(),
             runtime,
+            font,
+            labels: LabelSystem::default(),
+            world_ui: WorldUi::new(),
+            loader: Some(loader),
+            loading_screen,
+            world: None,
+            scenes: SceneManager::new(),
+            log_view,
+            debug_physics,
+            profiler,
+            capture: Capture::new(),
+            replay: None,
+            recorded_input: vec![],
+            lockstep,
+            replication,
+            saves: SaveSlots::new(SAVE_DIRECTORY),
+            pending_save: None,
+            gamepads: Gamepads::new(),
+            asset_manager,
+            svg_watcher: SvgWatcher::new(),
+            shader_watcher,
+            script_watcher: ScriptWatcher::new(),
+            script_source: MAIN_SCRIPT.to_string(),
+            platform: Platform::new(),
+            stress,
         })
     }
 
-    /// Run a GameLisp function.
-    pub fn call(&self, function: &str) -> bool {
-        struct RuntimeResult(bool);
+    /// Execute the next loading steps and setup the world when everything is loaded.
+    fn load_step(&mut self) -> Result<()> {
+        let loader = match self.loader.as_mut() {
+            Some(loader) => loader,
+            None => return Ok(()),
+        };
+
+        self.runtime
+            .run(|| Ok(loader.step(&mut Render::borrow_mut(), LOADING_BUDGET)))
+            .unwrap_or_else(|| Err(anyhow!("Script runtime failed while loading assets")))?;
+
+        if loader.is_done() {
+            let assets = match self.loader.take() {
+                Some(loader) => loader.finish(),
+                None => return Ok(()),
+            };
 
-        let result: RuntimeResult = self
+            // Setup the world with the loaded assets
+            let fixed_step = self.config.simulation.fixed_step() as Float;
+            let world = self
+                .runtime
+                .run(|| Ok(World::new(assets, &mut Render::borrow_mut(), fixed_step)))
+                .unwrap_or_else(|| Err(anyhow!("Script runtime failed while setting up world")))?;
+
+            // Let the scripts tell the kinds of objects apart
+            let tags = world.defs.tags();
+            self.runtime.run(|| {
+                Spawner::borrow_mut().set_object_tags(tags);
+
+                Ok(())
+            });
+            self.world = Some(world);
+
+            info!("all assets loaded");
+
+            // Let the main script setup the scene requested at startup, otherwise the player
+            // starts it from the main menu
+            match self.config.debug.scene.clone() {
+                Some(scene) => self.start_scene(&scene),
+                None if self.shows_menus() => self.show_main_menu(),
+                None => self.start_replay(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start the session with a scene of the main script.
+    fn start_scene(&mut self, scene: &str) {
+        self.start_replay();
+
+        self.call_with_args("engine:load-scene", &(scene,));
+        let authoritative = !self.is_client();
+        simulation::apply_requests(&self.runtime, self.world.as_mut(), authoritative);
+    }
+
+    /// Whether the world is simulated by the server instead.
+    fn is_client(&self) -> bool {
+        matches!(self.replication, Some(Replication::Client(_)))
+    }
+
+    /// Whether the menus can be shown, they would desync multiplayer sessions & replays.
+    fn shows_menus(&self) -> bool {
+        self.lockstep.is_none() && self.replication.is_none() && self.config.debug.replay.is_none()
+    }
+
+    /// Show the main menu.
+    fn show_main_menu(&mut self) {
+        let menu = self
             .runtime
-            .run(|| {
-                let update_func: Root<GFn> = match glsp::global(function) {
-                    Ok(Val::GFn(update)) => update,
-                    Ok(val) => {
-                        eprintln!("invalid {} function: {}", function, val);
+            .run(|| Ok(MainMenu::new(&mut Render::borrow_mut())));
+
+        if let Some(menu) = menu {
+            self.scenes.push(Box::new(menu));
+        }
+    }
 
-                        return Ok(RuntimeResult(false));
+    /// Pause the gameplay with the pause menu on top of it.
+    fn show_pause(&mut self) {
+        let pause = self
+            .runtime
+            .run(|| Ok(Pause::new(&mut Render::borrow_mut())));
+
+        if let Some(pause) = pause {
+            self.scenes.push(Box::new(pause));
+        }
+    }
+
+    /// Handle what the menus requested.
+    fn scene_requested(&mut self, ctx: &mut Context) {
+        for request in self.scenes.drain_requests() {
+            match request {
+                SceneRequest::Play(scene) => self.start_scene(&scene),
+                SceneRequest::Quit => ctx.request_quit(),
+            }
+        }
+    }
+
+    /// Start recording the session from the first frame of the world, or play back a replay.
+    fn start_replay(&mut self) {
+        // Every session starts with the same clock so the fixed steps line up
+        self.runtime.run(|| {
+            Clock::borrow_mut().restore(ClockState::default());
+            Checkpoints::borrow_mut().clear();
+
+            Ok(())
+        });
+
+        let fixed_step = self.config.simulation.fixed_step();
+        let seed = self.config.simulation.seed;
+        let replay = match &self.config.debug.replay {
+            Some(path) => Replay::load(path, fixed_step).unwrap_or_else(|err| {
+                error!("loading replay \"{}\" failed: {}", path, err);
+
+                Replay::new(fixed_step, seed)
+            }),
+            None => Replay::new(fixed_step, seed),
+        };
+
+        // The scripts get the same random numbers as when the replay was recorded
+        let replay_seed = replay.seed();
+        self.runtime.run(|| {
+            Random::borrow_mut().set_seed(replay_seed);
+
+            Ok(())
+        });
+        self.replay = Some(ReplaySession::new(replay));
+        self.recorded_input.clear();
+
+        self.checkpoint();
+    }
+
+    /// Save the state before the next frame when the replay needs it for seeking.
+    fn checkpoint(&mut self) {
+        let frame = match self.replay.as_ref() {
+            Some(replay) if replay.needs_checkpoint() => replay.cursor(),
+            _ => return,
+        };
+        let world = match self.world.as_ref() {
+            Some(world) => world,
+            None => return,
+        };
+
+        let script_source = &self.script_source;
+        let saved = self
+            .runtime
+            .run(|| Ok(Checkpoints::borrow_mut().save(frame, world, script_source)))
+            .unwrap_or_else(|| Err(anyhow!("Script runtime failed while saving checkpoint")));
+        if let Err(err) = saved {
+            error!("saving checkpoint of frame {} failed: {}", frame, err);
+        }
+    }
+
+    /// Write the recorded session to a file.
+    fn save_replay(&self) {
+        if let Some(replay) = self.replay.as_ref() {
+            match replay.replay().save() {
+                Ok(path) => info!("replay written to \"{}\"", path),
+                Err(err) => error!("writing replay failed: {}", err),
+            }
+        }
+    }
+
+    /// Write the recorded session to the file passed with `--record`.
+    fn save_recording(&self) {
+        if let (Some(path), Some(replay)) = (&self.config.debug.record, self.replay.as_ref()) {
+            match replay.replay().save_to(path) {
+                Ok(()) => info!("replay written to \"{}\"", path),
+                Err(err) => error!("writing replay to \"{}\" failed: {}", path, err),
+            }
+        }
+    }
+
+    /// Save the game to a slot after the next frame is rendered, so it can be the thumbnail.
+    ///
+    /// Without a name a new slot is created.
+    fn request_save(&mut self, name: Option<&str>) {
+        if self.world.is_none() || self.lockstep.is_some() || self.replication.is_some() {
+            warn!("saving is only possible in a single player game");
+
+            return;
+        }
+
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => {
+                // Number the slots after the highest existing one
+                let last = self
+                    .saves
+                    .list()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|save| save.name.strip_prefix("slot-")?.parse::<u32>().ok())
+                    .max()
+                    .unwrap_or(0);
+                format!("slot-{}", last + 1)
+            }
+        };
+        self.pending_save = Some(name);
+    }
+
+    /// Write the clock & the world to a slot.
+    fn save_game(&mut self, name: &str, thumbnail: Option<&Readback>) {
+        let world = match self.world.as_ref() {
+            Some(world) => world,
+            None => return,
+        };
+
+        let clock = self
+            .runtime
+            .run(|| Ok(Clock::borrow().state()))
+            .unwrap_or_default();
+        let data = SaveData {
+            clock,
+            world: world.save_state(),
+            entities: Some(world.save_entities()),
+        };
+
+        match self.saves.save(name, &data, thumbnail) {
+            Ok(header) => {
+                info!(
+                    "saved to slot \"{}\" after {:.0} seconds of playing",
+                    header.name, header.playtime
+                );
+
+                self.platform.sync_saves(self.saves.directory());
+            }
+            Err(err) => error!("saving to slot \"{}\" failed: {}", name, err),
+        }
+    }
+
+    /// Continue from the most recent save.
+    fn load_latest_save(&mut self) {
+        // Fetch the saves made on other devices first
+        self.platform.sync_saves(self.saves.directory());
+
+        match self.saves.list() {
+            Ok(saves) => match saves.first() {
+                Some(header) => self.load_game(&header.name),
+                None => error!("loading save failed: no saves found"),
+            },
+            Err(err) => error!("loading save failed: {}", err),
+        }
+    }
+
+    /// Continue from a slot, the saved units & objects replace everything that's spawned.
+    ///
+    /// The replay stops recording, it can only be played back from the start of a new world.
+    fn load_game(&mut self, name: &str) {
+        if self.world.is_none() || self.lockstep.is_some() || self.replication.is_some() {
+            warn!("loading is only possible in a single player game");
+
+            return;
+        }
+
+        let (header, data) = match self.saves.load(name) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                error!("loading slot \"{}\" failed: {}", name, err);
+
+                return;
+            }
+        };
+
+        let mut spawned = vec![];
+        if let Some(world) = self.world.as_mut() {
+            world.restore_state(&data.world);
+            if let Some(entities) = data.entities.as_ref() {
+                spawned = world.restore_entities(entities);
+            }
+        }
+        self.runtime.run(|| {
+            Clock::borrow_mut().restore(data.clock);
+
+            Ok(())
+        });
+        self.replay = None;
+        self.recorded_input.clear();
+
+        info!("loaded slot \"{}\"", header.name);
+
+        // The restored units are new entities, so the main script can find them again
+        for (entity, def) in spawned {
+            self.call_with_args("engine:on-spawn", &(entity.to_script(), def));
+        }
+    }
+
+    /// Save or load the slots requested by the scripts.
+    fn save_requested(&mut self) {
+        let requests = self
+            .runtime
+            .run(|| Ok(SaveQueue::borrow_mut().drain()))
+            .unwrap_or_default();
+
+        for request in requests {
+            match request {
+                // Saved when the frame for the thumbnail is rendered
+                SaveRequest::Save(name) => self.request_save(Some(&name)),
+                SaveRequest::Load(name) => self.load_game(&name),
+            }
+        }
+    }
+
+    /// Jump through the replay by an amount of seconds, negative to rewind.
+    ///
+    /// The closest checkpoint before the target is restored and the frames after it are played
+    /// back, skipping ahead without a checkpoint in between continues from the cursor.
+    fn seek_replay(&mut self, ctx: &mut Context, seconds: f64) {
+        if self.lockstep.is_some() {
+            warn!("seeking replays is not possible in multiplayer");
+
+            return;
+        }
+
+        let (cursor, target) = match self.replay.as_ref() {
+            Some(replay) => match replay.seek(seconds) {
+                Some(target) => (replay.cursor(), target),
+                None => return,
+            },
+            None => return,
+        };
+        let world = match self.world.as_mut() {
+            Some(world) => world,
+            None => return,
+        };
+
+        let restored = self
+            .runtime
+            .run(|| {
+                let checkpoints = Checkpoints::borrow();
+                Ok(match checkpoints.closest(target) {
+                    Some(frame) if target < cursor || frame > cursor => {
+                        checkpoints.restore(frame, world).map(|()| Some(frame))
+                    }
+                    Some(_) => Ok(None),
+                    None if target < cursor => {
+                        Err(anyhow!("No checkpoint before frame {}", target))
                     }
-                    Err(err) => {
-                        eprintln!("error finding {} function: {}", function, err);
+                    None => Ok(None),
+                })
+            })
+            .unwrap_or_else(|| Err(anyhow!("Script runtime failed while seeking")));
+        match restored {
+            Ok(Some(frame)) => {
+                if let Some(replay) = self.replay.as_mut() {
+                    replay.jump_to(frame);
+                }
+            }
+            Ok(None) => (),
+            Err(err) => {
+                error!("seeking replay failed: {}", err);
+
+                return;
+            }
+        }
+        self.recorded_input.clear();
+
+        // Simulate the frames up to the target without drawing them
+        loop {
+            let frame = match self.replay.as_mut() {
+                Some(replay) if replay.cursor() < target => match replay.next_frame() {
+                    Some(frame) => frame,
+                    None => break,
+                },
+                _ => break,
+            };
+
+            self.advance(ctx, Some(frame));
+        }
+
+        info!("replay at frame {}", target);
+    }
+
+    /// Handle the live input, it's ignored while the replay is played back.
+    fn handle_input(&mut self, event: InputEvent) {
+        // The widgets get the clicks on them before the menus & the gameplay
+        let ui_scale = self.config.display.ui_scale;
+        let consumed = self
+            .runtime
+            .run(|| Ok(Ui::borrow_mut().input_event(&event, ui_scale)))
+            .unwrap_or_default();
+        if consumed {
+            return;
+        }
+
+        // The menus get the input before the gameplay
+        if self.scenes.input_event(&event) {
+            return;
+        }
+
+        // Escape or the start button pauses the gameplay
+        let pauses = match event {
+            InputEvent::KeyDown { key } => key.name() == "escape",
+            InputEvent::GamepadButtonDown { button } => button == GamepadButton::Start,
+            _ => false,
+        };
+        if pauses && self.world.is_some() && self.shows_menus() {
+            self.show_pause();
+
+            return;
+        }
+
+        // In multiplayer the input is applied when the tick it's scheduled for is simulated
+        if self.lockstep.is_some() && self.world.is_some() {
+            self.recorded_input.push(event);
+
+            return;
+        }
+
+        // The server simulates the world, the client only controls the interface itself
+        if let Some(Replication::Client(client)) = self.replication.as_mut() {
+            client.push_input(event);
+        }
+
+        if let Some(replay) = self.replay.as_ref() {
+            if replay.is_playing() {
+                return;
+            }
+
+            self.recorded_input.push(event);
+        }
+
+        self.apply_input(self.local_player(), event);
+    }
+
+    /// Let the world react to live, recorded or remote input.
+    fn apply_input(&mut self, player: usize, event: InputEvent) {
+        // Only the local player controls the interface
+        if player == self.local_player() {
+            match event {
+                InputEvent::MouseMotion { x, y } => self.mouse_motion(x as f32, y as f32),
+                InputEvent::MouseButtonDown { button, x, y } => {
+                    self.mouse_button_down(button.into(), x as f32, y as f32)
+                }
+                InputEvent::MouseButtonUp { button, x, y } => {
+                    self.mouse_button_up(button.into(), x as f32, y as f32)
+                }
+                InputEvent::MouseWheel { y } => self.mouse_wheel(y as f32),
+                _ => (),
+            }
+
+            // The actions the scripts check are only those of the local player
+            self.runtime.run(|| {
+                Input::borrow_mut().handle(&event);
+
+                Ok(())
+            });
+        }
+
+        // Let the main script react to the input of every player
+        let start = miniquad::date::now();
+        match event {
+            InputEvent::KeyDown { key } => {
+                self.call_with_args("engine:key-down", &(key, player));
+            }
+            InputEvent::KeyUp { key } => {
+                self.call_with_args("engine:key-up", &(key, player));
+            }
+            _ => {
+                let (x, y) = event.pos();
+                self.call_with_args("engine:on-player-input", &(player, event.name(), x, y));
+            }
+        }
+        self.profiler.add(Stage::Script, start);
+    }
+
+    /// The index of the player controlled on this machine.
+    fn local_player(&self) -> usize {
+        self.lockstep.as_ref().map_or(0, Lockstep::player)
+    }
+
+    /// Receive the packets of the client-server session, returning the input of the clients.
+    fn receive_replication(&mut self) -> Vec<(usize, InputEvent)> {
+        if self.world.is_none() {
+            return vec![];
+        }
+
+        let result = match self.replication.as_mut() {
+            Some(Replication::Server(server)) => server.poll(),
+            Some(Replication::Client(client)) => client.poll().map(|_| vec![]),
+            None => Ok(vec![]),
+        };
+
+        result.unwrap_or_else(|err| {
+            error!("receiving packets failed: {}", err);
+
+            vec![]
+        })
+    }
+
+    /// Send the world to the clients, or the input to the server.
+    fn send_replication(&mut self) {
+        let world = match self.world.as_mut() {
+            Some(world) => world,
+            None => return,
+        };
+
+        let (delta, elapsed) = self
+            .runtime
+            .run(|| {
+                let clock = Clock::borrow();
+
+                Ok((clock.delta(), clock.elapsed()))
+            })
+            .unwrap_or_default();
+
+        let result = match self.replication.as_mut() {
+            Some(Replication::Server(server)) => {
+                server.send_snapshot(elapsed, world.replicated_states())
+            }
+            Some(Replication::Client(client)) => {
+                client.advance(delta);
+                world.remote_units = client.entities();
+
+                client.send()
+            }
+            None => return,
+        };
+
+        if let Err(err) = result {
+            error!("sending packets failed: {}", err);
+        }
+    }
+
+    /// Simulate all ticks for which the input of both players has arrived.
+    fn update_lockstep(&mut self, ctx: &mut Context) {
+        let lockstep = match self.lockstep.as_mut() {
+            Some(lockstep) => lockstep,
+            None => return,
+        };
+        if let Err(err) = lockstep.poll() {
+            error!("receiving from the other player failed: {}", err);
+        }
+        lockstep.queue_input(&mut self.recorded_input);
+
+        while let Some(tick) = self.lockstep.as_mut().and_then(Lockstep::next_tick) {
+            for (player, events) in tick.inputs.into_iter().enumerate() {
+                for event in events {
+                    self.apply_input(player, event);
+                }
+            }
+
+            // Every tick is exactly a single fixed step
+            self.advance(
+                ctx,
+                Some(ReplayFrame {
+                    delta: self.config.simulation.fixed_step(),
+                    events: vec![],
+                }),
+            );
+
+            if let (Some(lockstep), Some(world)) = (self.lockstep.as_mut(), self.world.as_ref()) {
+                lockstep.report_hash(tick.tick, world.physics.state_hash());
+            }
+        }
+
+        if let Some(Err(err)) = self.lockstep.as_ref().map(Lockstep::send) {
+            error!("sending to the other player failed: {}", err);
+        }
+    }
+
+    /// Move the cursor, drag the camera and let the UI react to the hover position in UI pixels.
+    fn mouse_motion(&mut self, x: f32, y: f32) {
+        let start = miniquad::date::now();
+
+        if let Some(world) = self.world.as_mut() {
+            world.inventory_grid.mouse_motion(x, y);
+            world.combat_log.mouse_motion(x, y);
+        }
+
+        let ui_scale = self.config.display.ui_scale;
+        self.runtime.run(|| {
+            Cursor::borrow_mut().mouse_motion(x, y);
+
+            Camera::borrow_mut().drag(x * ui_scale, y * ui_scale);
+
+            Ok(())
+        });
+
+        self.profiler.add(Stage::Input, start);
+    }
+
+    /// Start dragging items or use them at a position in UI pixels, calling the item hooks, or pick
+    /// what's clicked in the world.
+    fn mouse_button_down(&mut self, button: MouseButton, x: f32, y: f32) {
+        let start = miniquad::date::now();
+
+        // Move the view while the middle mouse button is held
+        if button == MouseButton::Middle {
+            let ui_scale = self.config.display.ui_scale;
+            self.runtime.run(|| {
+                Camera::borrow_mut().start_drag(x * ui_scale, y * ui_scale);
+
+                Ok(())
+            });
+        }
+
+        // Pick what's under the cursor in the world, unless the click is on a panel
+        let ui_scale = self.config.display.ui_scale;
+        let picked = match self.world.as_ref() {
+            Some(world)
+                if button == MouseButton::Left
+                    && world
+                        .inventory_grid
+                        .slot_at(&world.inventory, x, y)
+                        .is_none()
+                    && !world.combat_log.contains(x, y) =>
+            {
+                self.runtime.run(|| {
+                    let (world_x, world_y) =
+                        Camera::borrow().screen_to_world(x * ui_scale, y * ui_scale);
+
+                    Ok((
+                        world.pick(world_x as Float, world_y as Float),
+                        world_x,
+                        world_y,
+                    ))
+                })
+            }
+            _ => None,
+        };
+
+        let action = match self.world.as_mut() {
+            Some(world) => world
+                .inventory_grid
+                .mouse_button_down(&world.inventory, button, x, y),
+            None => None,
+        };
+
+        self.profiler.add(Stage::Input, start);
+
+        // Let the main script select what's clicked
+        if let Some(click) = picked {
+            let start = miniquad::date::now();
+            self.call_with_args("engine:on-click", &click);
+            self.profiler.add(Stage::Script, start);
+        }
+
+        // Call the item hooks in the main script
+        let start = miniquad::date::now();
+        match action {
+            Some(InventoryAction::Use { slot, item }) => {
+                self.call_with_args("engine:on-item-use", &(item.index(), slot));
+            }
+            Some(InventoryAction::Equip { slot, item }) => {
+                self.call_with_args("engine:on-item-equip", &(item.index(), slot));
+            }
+            None => (),
+        }
+        self.profiler.add(Stage::Script, start);
+    }
 
-                        return Ok(RuntimeResult(false));
+    /// Drop the dragged items at a position in UI pixels.
+    fn mouse_button_up(&mut self, button: MouseButton, x: f32, y: f32) {
+        let start = miniquad::date::now();
+
+        if button == MouseButton::Middle {
+            self.runtime.run(|| {
+                Camera::borrow_mut().end_drag();
+
+                Ok(())
+            });
+        }
+
+        if let Some(world) = self.world.as_mut() {
+            world
+                .inventory_grid
+                .mouse_button_up(&mut world.inventory, &world.items, button, x, y);
+        }
+
+        self.profiler.add(Stage::Input, start);
+    }
+
+    /// Scroll the combat log or zoom the camera.
+    fn mouse_wheel(&mut self, y: f32) {
+        let start = miniquad::date::now();
+
+        // Scroll the log panel instead of zooming when hovering over it
+        let scrolled = match self.world.as_mut() {
+            Some(world) => world.combat_log.mouse_wheel(y),
+            None => false,
+        };
+        if !scrolled {
+            self.zoom_camera(y);
+        }
+
+        self.profiler.add(Stage::Input, start);
+    }
+
+    /// Stop or continue the simulation.
+    fn toggle_pause(&mut self) {
+        self.runtime.run(|| {
+            let mut clock = Clock::borrow_mut();
+            let paused = !clock.paused();
+            clock.set_paused(paused);
+
+            info!("{}", if paused { "paused" } else { "resumed" });
+
+            Ok(())
+        });
+    }
+
+    /// Pause and simulate a single fixed step.
+    fn step_once(&mut self) {
+        self.runtime.run(|| {
+            Clock::borrow_mut().step();
+
+            Ok(())
+        });
+    }
+
+    /// Simulate a single frame, with the delta & input of the replay when it's played back.
+    fn advance(&mut self, ctx: &mut Context, replayed: Option<ReplayFrame>) {
+        // Apply the recorded input before the frame, like the live input
+        let replayed_delta = replayed.map(|frame| {
+            for event in frame.events {
+                self.apply_input(self.local_player(), event);
+            }
+
+            frame.delta
+        });
+
+        // Measure the time since the previous frame and how many fixed steps fit in it
+        let (delta, elapsed, frame, fixed_steps) = self
+            .runtime
+            .run(|| {
+                let mut clock = Clock::borrow_mut();
+                match replayed_delta {
+                    Some(delta) => clock.tick_with(delta),
+                    None => clock.tick(),
+                }
+
+                let mut fixed_steps = 0;
+                while clock.fixed_update() {
+                    fixed_steps += 1;
+                }
+
+                Ok((clock.delta(), clock.elapsed(), clock.frame(), fixed_steps))
+            })
+            .unwrap_or_default();
+
+        // Keep loading until all assets are available
+        if self.loader.is_some() {
+            if let Err(err) = self.load_step() {
+                error!("loading assets failed: {}", err);
+
+                ctx.request_quit();
+            }
+
+            return;
+        }
+
+        // Record the live frame with the input handled since the previous one
+        if replayed_delta.is_none() {
+            if let Some(replay) = self.replay.as_mut() {
+                replay.record(delta, mem::take(&mut self.recorded_input));
+            }
+        }
+
+        // Animate the parts of the units every frame, so they move smoothly at any tick rate
+        if let Some(world) = self.world.as_mut() {
+            world.animate(delta as f32);
+        }
+        self.world_ui.update(delta as f32);
+        self.runtime.run(|| {
+            Particles::borrow_mut().update(delta as f32);
+
+            Ok(())
+        });
+
+        // Step the physics & run the scripts like the headless simulation, unless the server
+        // simulates the world
+        let frame = Frame {
+            delta,
+            elapsed,
+            frame,
+            fixed_steps,
+        };
+        let authoritative = !self.is_client();
+        match simulation::step(&self.runtime, self.world.as_mut(), frame, authoritative) {
+            Ok(simulated) => {
+                self.profiler.add_time(Stage::Physics, simulated.physics);
+                self.profiler.add_time(Stage::Script, simulated.script);
+                for (pos, amount) in simulated.damages {
+                    if let Some((x, y)) = pos {
+                        self.world_ui.damaged(x, y, amount as Float);
                     }
-                };
-                let _: Val = glsp::call(&update_func, &())?;
+                }
+            }
+            Err(err) => {
+                error!("{}", err);
+
+                ctx.request_quit();
+            }
+        }
+
+        let start = miniquad::date::now();
+        self.save_requested();
+        self.profiler.add(Stage::Script, start);
+
+        // Handle the gameplay events emitted this frame
+        let start = miniquad::date::now();
+        let world = &mut self.world;
+        let platform = &mut self.platform;
+        self.runtime.run(|| {
+            let events = EventBus::borrow_mut().drain();
+            for event in events.iter() {
+                platform.handle_event(event);
+            }
+            if let Some(world) = world.as_mut() {
+                let i18n = I18n::borrow();
+                for event in events.iter() {
+                    world.combat_log.handle_event(elapsed, event, &i18n);
+                }
+            }
+
+            Ok(())
+        });
+        self.profiler.add(Stage::Sync, start);
+
+        // Upload the glyphs of the new language when it's switched from a script
+        if let Err(err) = self.upload_language_glyphs() {
+            warn!("uploading glyphs for language failed: {}", err);
+        }
+
+        // Save the state for seeking before the next frame
+        self.checkpoint();
+    }
+
+    /// Change the scale of the screen space layer and persist it.
+    fn set_ui_scale(&mut self, scale: f32) {
+        self.config.display.set_ui_scale(scale);
+
+        let ui_scale = self.config.display.ui_scale;
+        self.runtime.run(|| {
+            Render::borrow_mut().set_ui_scale(ui_scale);
+
+            Ok(())
+        });
+
+        if let Err(err) = Config::modify_file(&self.config_path, |config| {
+            config.display.ui_scale = ui_scale
+        }) {
+            error!("saving configuration failed: {}", err);
+        }
+    }
+
+    /// Convert a position in logical pixels to UI pixels.
+    fn ui_pos(&self, x: f32, y: f32) -> (f32, f32) {
+        let ui_scale = self.config.display.ui_scale;
+
+        (x / ui_scale, y / ui_scale)
+    }
+
+    /// Convert a position from the window events in physical pixels to logical pixels.
+    fn logical_pos(&self, x: f32, y: f32) -> (f32, f32) {
+        self.runtime
+            .run(|| Ok(Camera::borrow().to_logical(x, y)))
+            .unwrap_or((x, y))
+    }
+
+    /// Zoom the camera with the mouse wheel.
+    fn zoom_camera(&mut self, y: f32) {
+        self.runtime.run(|| {
+            Camera::borrow_mut().zoom_by(y);
+
+            Ok(())
+        });
+    }
+
+    /// Follow the target of the camera and pass the view to the renderer.
+    fn update_camera(&mut self, width: f32, height: f32, dpi_scale: f32) {
+        let world = self.world.as_ref();
+        self.runtime.run(|| {
+            let mut camera = Camera::borrow_mut();
+            camera.set_viewport(width, height);
+            camera.set_dpi_scale(dpi_scale);
+
+            let target = camera
+                .target()
+                .and_then(|entity| world.and_then(|world| world.entity_position(entity)));
+            camera.update(Clock::borrow().delta() as f32, target);
+
+            Render::borrow_mut().set_camera(&camera);
+
+            Ok(())
+        });
+    }
+
+    /// Fit the camera & the panels to the new size of the window in logical pixels, so the input
+    /// handled before the next frame already uses it.
+    fn resize(&mut self, width: f32, height: f32, dpi_scale: f32) {
+        self.runtime.run(|| {
+            let mut camera = Camera::borrow_mut();
+            camera.set_viewport(width, height);
+            camera.set_dpi_scale(dpi_scale);
+            Render::borrow_mut().set_camera(&camera);
+
+            Ok(())
+        });
+
+        let ui_size = self.ui_pos(width, height);
+        if let Some(world) = self.world.as_mut() {
+            world.layout(ui_size);
+        }
+    }
+
+    /// Recompile the shaders of which the files changed, showing the log when it fails.
+    fn reload_shaders(&mut self, ctx: &mut Context) {
+        for source in self.shader_watcher.poll() {
+            let reloaded = source.and_then(|source| {
+                self.runtime
+                    .run(|| Ok(Render::borrow_mut().reload_shader(ctx, &source)))
+                    .unwrap_or_else(|| Err(anyhow!("Script runtime failed while reloading shader")))
+                    .map(|_| source.name)
+            });
+
+            match reloaded {
+                Ok(name) => info!("reloaded shader \"{}\"", name),
+                Err(err) => {
+                    error!("reloading shader failed, keeping the previous one: {}", err);
+                    self.log_view.show();
+                }
+            }
+        }
+    }
+
+    /// Replace the meshes of the SVGs of which the files changed, showing the log when it fails.
+    fn reload_svgs(&mut self) {
+        for (handle, svg) in self.svg_watcher.poll(&self.asset_manager) {
+            let world = self.world.as_ref();
+            let reloaded = svg.and_then(|svg| {
+                self.runtime
+                    .run(|| Ok(reload_svg(handle, svg, world)))
+                    .unwrap_or_else(|| Err(anyhow!("Script runtime failed while reloading SVG")))
+            });
+
+            match reloaded {
+                Ok(()) => info!("reloaded \"{}\"", handle.path()),
+                Err(err) => {
+                    error!(
+                        "reloading \"{}\" failed, keeping the previous version: {}",
+                        handle.path(),
+                        err
+                    );
+                    self.log_view.show();
+                }
+            }
+        }
+    }
+
+    /// Evaluate the main script again when its file changed, showing the log when it fails.
+    fn reload_script(&mut self) {
+        let source = match self.script_watcher.poll() {
+            Some(source) => source,
+            None => return,
+        };
+
+        let reloaded = source.and_then(|source| {
+            self.runtime
+                .run(|| Ok(script::reload(&source).map_err(|err| anyhow!("{}", err))))
+                .unwrap_or_else(|| Err(anyhow!("Script runtime failed while reloading script")))
+                .map(|()| source)
+        });
+
+        match reloaded {
+            Ok(source) => {
+                info!("reloaded main script");
+                self.script_source = source;
+            }
+            Err(err) => {
+                error!("reloading main script failed: {}", err);
+                self.log_view.show();
+            }
+        }
+    }
 
-                Ok(RuntimeResult(true))
+    /// Upload the glyphs of the selected language at once, instead of one by one when they're first
+    /// shown.
+    fn upload_language_glyphs(&mut self) -> Result<()> {
+        let font = &mut self.font;
+
+        self.runtime
+            .run(|| {
+                let mut i18n = I18n::borrow_mut();
+                if !i18n.take_changed() {
+                    return Ok(Ok(()));
+                }
+
+                Ok(font.upload_missing(&mut Render::borrow_mut(), i18n.charset().chars()))
             })
-            .expect("Something unexpected went wrong with calling a GameLisp function");
+            .unwrap_or_else(|| Err(anyhow!("Script runtime failed while uploading glyphs")))
+    }
+
+    /// Run a GameLisp function.
+    pub fn call(&self, function: &str) -> bool {
+        self.call_with_args(function, &())
+    }
 
-        result.0
+    /// Run a GameLisp function with arguments.
+    pub fn call_with_args<A>(&self, function: &str, args: &A) -> bool
+    where
+        A: ToCallArgs + GSend + ?Sized,
+    {
+        call_script(&self.runtime, function, args)
     }
 }
 
+/// The size of the window in logical pixels, the framebuffer is larger on HiDPI displays.
+fn logical_size(ctx: &Context) -> (f32, f32) {
+    let (width, height) = ctx.screen_size();
+    let dpi_scale = ctx.dpi_scale();
+
+    (width / dpi_scale, height / dpi_scale)
+}
+
+/// Draw the instances of the meshes uploaded from an SVG with its changed version.
+///
+/// Only the meshes are replaced, the colliders & the skeleton stay the same until the game is
+/// restarted.
+fn reload_svg(handle: SvgHandle, svg: Svg, world: Option<&World>) -> Result<()> {
+    let mut render = Render::borrow_mut();
+
+    let cursor = match handle {
+        SvgHandle::CURSOR_DEFAULT => Some(CursorKind::Default),
+        SvgHandle::CURSOR_ATTACK => Some(CursorKind::Attack),
+        SvgHandle::CURSOR_FORBIDDEN => Some(CursorKind::Forbidden),
+        _ => None,
+    };
+    if let Some(kind) = cursor {
+        return Cursor::borrow_mut().reload_variant(&mut render, kind, svg);
+    }
+
+    let world = world.ok_or_else(|| anyhow!("The world isn't loaded yet"))?;
+    if world.levels.values().any(|level| level.svg() == handle) {
+        return Err(anyhow!("Restart the game to see the changes to the level"));
+    }
+    let defs = world.defs.with_svg(handle).collect::<Vec<_>>();
+    if defs.is_empty() {
+        return Err(anyhow!("Restart the game to see the changes"));
+    }
+
+    for def in defs {
+        svg.replace_meshes(&mut render, def.mesh, &def.parts)?;
+    }
+
+    Ok(())
+}
+
 impl EventHandler for Game {
     fn update(&mut self, ctx: &mut Context) {
-        // Move the physics
-        self.physics.step();
+        self.profiler.next_frame();
 
-        // Call the update function in the main script
-        if !self.call("engine:update") {
-            ctx.request_quit();
+        self.platform.update();
+        self.runtime.run(|| {
+            Audio::borrow_mut().update();
+
+            Ok(())
+        });
+        self.reload_shaders(ctx);
+        self.reload_svgs();
+        self.reload_script();
+
+        // Gamepads are polled, so their input is handled like the events of the window
+        for event in self.gamepads.poll() {
+            self.handle_input(event);
         }
+
+        // The gameplay isn't advanced while a menu is shown
+        self.scene_requested(ctx);
+        if self.scenes.is_active() {
+            let delta = self
+                .runtime
+                .run(|| Ok(Clock::borrow_mut().skip()))
+                .unwrap_or_default();
+            let scenes = &mut self.scenes;
+            self.runtime.run(|| {
+                scenes.update(delta as f32, &mut Ui::borrow_mut(), &I18n::borrow());
+
+                Ok(())
+            });
+
+            return;
+        }
+
+        // In multiplayer the ticks are driven by the input of both players
+        if self.lockstep.is_some() && self.world.is_some() {
+            self.update_lockstep(ctx);
+
+            return;
+        }
+
+        // Apply the input the clients sent to the server
+        for (player, event) in self.receive_replication() {
+            self.apply_input(player, event);
+        }
+
+        // Take the delta & input from the replay while it's played back
+        let replayed = self.replay.as_mut().and_then(ReplaySession::next_frame);
+        self.advance(ctx, replayed);
+
+        self.send_replication();
     }
 
     fn draw(&mut self, ctx: &mut Context) {
-        self.runtime.run(|| {
-            // Render the buffer
-            Render::borrow_mut().render(ctx);
+        let (width, height) = logical_size(ctx);
+        let ui_size = self.ui_pos(width, height);
 
-            Ok(())
+        let start = miniquad::date::now();
+
+        if let Some(world) = self.world.as_mut() {
+            world.layout(ui_size);
+        }
+        self.update_camera(width, height, ctx.dpi_scale());
+
+        // The closure can't capture the runtime itself
+        let Game {
+            loader,
+            world,
+            loading_screen,
+            font,
+            log_view,
+            profiler,
+            capture,
+            stress,
+            labels,
+            pending_save,
+            debug_physics,
+            scenes,
+            world_ui,
+            ..
+        } = self;
+        let readback = self.runtime.run(|| {
+            let mut render = Render::borrow_mut();
+            let i18n = I18n::borrow();
+
+            match (&*loader, &*world) {
+                // Show the progress while loading
+                (Some(loader), _) => loading_screen.draw(
+                    loader.progress(),
+                    loader.status(),
+                    ui_size,
+                    font,
+                    &i18n,
+                    &mut render,
+                ),
+                // Draw the inventory on top of the world
+                (None, Some(world)) => {
+                    let alpha = Clock::borrow().alpha() as Float;
+                    world.draw(&mut render, alpha);
+                    Particles::borrow().draw(&mut render);
+                    debug_physics.draw(&world.physics, &mut render);
+                    world.inventory_grid.draw(
+                        &world.inventory,
+                        &world.items,
+                        font,
+                        &i18n,
+                        &mut render,
+                    );
+                    world.combat_log.draw(font, &mut render);
+                    world_ui.draw(world, alpha, &Camera::borrow(), font, &mut render);
+                }
+                (None, None) => (),
+            }
+
+            // Draw the texts of the scripts on top of the world
+            labels.update(Labels::borrow_mut().drain(), font, &mut render);
+            labels.draw(&mut render);
+
+            // Draw the menus on top of the gameplay
+            scenes.draw(ui_size, font, &i18n, &mut render);
+            Ui::borrow_mut().draw(ui_size, font, &mut render);
+
+            if let Some(stress) = stress.as_mut() {
+                stress.update(&mut render);
+            }
+
+            log_view.draw(ui_size, font, &mut render);
+            profiler.draw(ui_size, font, &mut render);
+
+            // Draw the mouse cursor on top of everything
+            Cursor::borrow().draw(&mut render);
+
+            // Render the buffer, reading it back when a frame should be captured or saved
+            let capture_frame = capture.wants_frame();
+            if capture_frame || pending_save.is_some() {
+                render.request_readback();
+            }
+            profiler.add(Stage::Render, start);
+            render.render(ctx);
+            profiler.update_render(&mut render);
+            let readback = render.take_readback();
+            if let (true, Some(readback)) = (capture_frame, readback.as_ref()) {
+                capture.push(readback);
+            }
+
+            Ok(readback)
         });
 
+        // Save with the rendered frame as the thumbnail
+        if let Some(name) = self.pending_save.take() {
+            self.save_game(&name, readback.flatten().as_ref());
+        }
+
         // Call the render function in the main script
-        if !self.call("engine:render") {
+        let start = miniquad::date::now();
+        if self.world.is_some() && !self.call("engine:render") {
             ctx.request_quit();
         }
+        self.profiler.add(Stage::Script, start);
+
+        // Don't render faster than the player wants
+        self.frame_limiter.wait(&self.config.display);
     }
 
     fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
-        self.runtime.run(|| {
-            // Set the camera position
-            Render::borrow_mut().set_camera_pos(-x, -y);
+        let (x, y) = self.logical_pos(x, y);
+        let (x, y) = self.ui_pos(x, y);
+        self.handle_input(InputEvent::MouseMotion {
+            x: x.into(),
+            y: y.into(),
+        });
+    }
 
-            Ok(())
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let (x, y) = self.logical_pos(x, y);
+        let (x, y) = self.ui_pos(x, y);
+        self.handle_input(InputEvent::MouseButtonDown {
+            button: button.into(),
+            x: x.into(),
+            y: y.into(),
         });
     }
 
-    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
-        self.zoom += y;
-        self.zoom = self.zoom.max(-MAX_ZOOM).min(MAX_ZOOM);
-        let zoom = 1.0 + (self.zoom / ZOOM_FACTOR);
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let (x, y) = self.logical_pos(x, y);
+        let (x, y) = self.ui_pos(x, y);
+        self.handle_input(InputEvent::MouseButtonUp {
+            button: button.into(),
+            x: x.into(),
+            y: y.into(),
+        });
+    }
 
-        self.runtime.run(|| {
-            // Set the camera zoom
-            Render::borrow_mut().set_camera_zoom(zoom);
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        repeat: bool,
+    ) {
+        if !repeat {
+            self.handle_input(InputEvent::KeyDown {
+                key: keycode.into(),
+            });
+        }
 
-            Ok(())
+        match keycode {
+            KeyCode::F1 => self.debug_physics.toggle(),
+            KeyCode::F2 => self.profiler.toggle(),
+            KeyCode::F3 => self.log_view.toggle(),
+            KeyCode::F5 => self.save_replay(),
+            KeyCode::F6 => self.seek_replay(ctx, -SEEK_SECONDS),
+            KeyCode::F7 => self.seek_replay(ctx, SEEK_SECONDS),
+            KeyCode::F9 => self.capture.export(),
+            KeyCode::P => self.toggle_pause(),
+            KeyCode::Period => self.step_once(),
+            _ => (),
+        }
+
+        if !keymods.ctrl {
+            return;
+        }
+
+        // Change the UI scale with the same shortcuts as browsers use for zooming
+        match keycode {
+            KeyCode::Equal | KeyCode::KpAdd => {
+                self.set_ui_scale(self.config.display.ui_scale + UI_SCALE_STEP)
+            }
+            KeyCode::Minus | KeyCode::KpSubtract => {
+                self.set_ui_scale(self.config.display.ui_scale - UI_SCALE_STEP)
+            }
+            KeyCode::Key0 | KeyCode::Kp0 => self.set_ui_scale(1.0),
+            // Saving & loading
+            KeyCode::S if keymods.shift => self.request_save(None),
+            KeyCode::S => self.request_save(Some(QUICK_SAVE_SLOT)),
+            KeyCode::L => self.load_latest_save(),
+            _ => (),
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
+        self.handle_input(InputEvent::KeyUp {
+            key: keycode.into(),
         });
     }
+
+    fn touch_event(&mut self, ctx: &mut Context, phase: TouchPhase, _id: u64, x: f32, y: f32) {
+        match phase {
+            // A touch doesn't move the pointer before pressing, so update the hover state first
+            TouchPhase::Started => {
+                self.mouse_motion_event(ctx, x, y);
+                self.mouse_button_down_event(ctx, MouseButton::Left, x, y);
+            }
+            TouchPhase::Moved => self.mouse_motion_event(ctx, x, y),
+            // Also drop dragged items when the touch is interrupted
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.mouse_button_up_event(ctx, MouseButton::Left, x, y)
+            }
+        }
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        let dpi_scale = ctx.dpi_scale();
+        self.resize(width / dpi_scale, height / dpi_scale, dpi_scale);
+    }
+
+    fn window_minimized_event(&mut self, _ctx: &mut Context) {
+        self.frame_limiter.set_background(true);
+    }
+
+    fn window_restored_event(&mut self, _ctx: &mut Context) {
+        self.frame_limiter.set_background(false);
+    }
+
+    fn quit_requested_event(&mut self, _ctx: &mut Context) {
+        self.save_recording();
+    }
+
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        self.handle_input(InputEvent::MouseWheel { y: y.into() });
+    }
+}
+
+/// Load the configuration with the arguments as overrides, falling back to the defaults.
+///
+/// The flags are applied last on whichever configuration is used, the error is returned so it can
+/// be logged once the logger is set up.
+fn load_config(args: &Args) -> (Config, Option<anyhow::Error>) {
+    let (mut config, config_error) = match Config::load(&args.config, &args.overrides) {
+        Ok(config) => (config, None),
+        // The file is what failed most of the time, the overrides might not be valid either
+        Err(err) => (
+            Config::load_defaults(&args.overrides).unwrap_or_default(),
+            Some(err),
+        ),
+    };
+
+    // The flags take precedence over everything in the file & the overrides
+    args.apply(&mut config);
+
+    (config, config_error)
 }
 
 fn main() {
+    // The browser has no command line, the query of the URL is used instead
+    #[cfg(not(target_arch = "wasm32"))]
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    #[cfg(target_arch = "wasm32")]
+    let args = replace_me::web::args();
+
+    let args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, args::USAGE);
+
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", args::USAGE);
+
+        return;
+    }
+
+    let (config, config_error) = load_config(&args);
+
+    // Setup the logger as soon as the configuration for it is known
+    let log_history = logger::init(&config.log).unwrap_or_else(|err| {
+        eprintln!("setting up logger failed: {}", err);
+
+        LogHistory::default()
+    });
+    if let Some(err) = config_error {
+        error!("loading configuration failed, using the defaults: {}", err);
+    }
+
+    // Simulate without a window, for tests & servers
+    if args.headless {
+        let result = if args.check_determinism {
+            headless::check_determinism(&config, args.steps, args.test.as_deref())
+        } else {
+            headless::run(&config, args.steps, args.test.as_deref()).map(|_| ())
+        };
+        if let Err(err) = result {
+            error!("headless simulation failed: {}", err);
+
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    // The window always waits for the vertical blank, miniquad doesn't expose the swap interval
+    if !config.display.vsync {
+        warn!("vsync can't be turned off on this platform, use display.fps_cap instead");
+    }
+
+    // Write a report instead of silently closing the window when something goes wrong
+    let crash_info = crash::install(&config, log_history.clone());
+
     miniquad::start(
         Conf {
-            window_title: concat!("replace_me - ", env!("CARGO_PKG_VERSION")).to_string(),
-            window_width: WIDTH as i32,
-            window_height: HEIGHT as i32,
-            loading: Loading::Embedded,
-            sample_count: 8,
+            window_title: config.window.title.clone(),
+            window_width: config.window.width,
+            window_height: config.window.height,
+            fullscreen: config.window.fullscreen,
+            sample_count: config.window.sample_count,
+            high_dpi: config.window.high_dpi,
             ..Default::default()
         },
-        |mut ctx| {
+        move |mut ctx| {
+            crash_info.detect_gpu();
+
             UserData::owning(
-                Game::new(&mut ctx).expect("Setting up game state failed"),
+                Game::new(&mut ctx, config, args.config, log_history, args.stress)
+                    .expect("Setting up game state failed"),
                 ctx,
             )
         },
@@ -0,0 +1,197 @@
+use crate::replication::EntityId;
+use glsp::{lib, rfn, Runtime};
+use std::{collections::HashMap, mem};
+
+/// A change to the motion of a unit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MotionCommand {
+    /// Push it during the next fixed step.
+    Force(f32, f32),
+    /// Change its momentum at once.
+    Impulse(f32, f32),
+    /// Replace its linear velocity in world units per second.
+    LinearVelocity(f32, f32),
+    /// Replace its angular velocity in radians per second.
+    AngularVelocity(f32),
+    /// Move it with a linear & angular velocity when it's kinematic, like a moving platform.
+    KinematicVelocity(f32, f32, f32),
+    /// Walk in a direction with its character controller, from -1.0 to 1.0.
+    Walk(f32),
+    /// Jump with its character controller as soon as it's on the ground.
+    Jump,
+}
+
+/// A blast pushing the bodies around a world position away from it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Explosion {
+    /// Horizontal world position of the center.
+    pub x: f32,
+    /// Vertical world position of the center.
+    pub y: f32,
+    /// Distance in world units at which nothing is pushed anymore.
+    pub radius: f32,
+    /// The impulse at the center.
+    pub strength: f32,
+}
+
+/// The velocity of a unit at the start of the frame.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct UnitVelocity {
+    /// The linear velocity in world units per second.
+    pub linear: (f32, f32),
+    /// The angular velocity in radians per second.
+    pub angular: f32,
+}
+
+lib! {
+/// Queue of forces, velocities & explosions the scripts apply to the units, with the positions &
+/// velocities of the units so the scripts can read them.
+pub struct Motion {
+    /// The commands since the last drain.
+    commands: Vec<(EntityId, MotionCommand)>,
+    /// The explosions since the last drain.
+    explosions: Vec<Explosion>,
+    /// The velocities of all units, updated every frame before the scripts are called.
+    velocities: HashMap<EntityId, UnitVelocity>,
+    /// The world positions of all units, updated every frame before the scripts are called.
+    positions: HashMap<EntityId, (f32, f32)>,
+}
+}
+
+impl Default for Motion {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Motion {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self {
+            commands: vec![],
+            explosions: vec![],
+            velocities: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Take all commands since the last drain.
+    pub fn drain(&mut self) -> Vec<(EntityId, MotionCommand)> {
+        mem::take(&mut self.commands)
+    }
+
+    /// Take all explosions since the last drain.
+    pub fn drain_explosions(&mut self) -> Vec<Explosion> {
+        mem::take(&mut self.explosions)
+    }
+
+    /// Replace the velocities the scripts can read.
+    pub fn set_velocities(&mut self, velocities: HashMap<EntityId, UnitVelocity>) {
+        self.velocities = velocities;
+    }
+
+    /// Replace the positions the scripts can read.
+    pub fn set_positions(&mut self, positions: HashMap<EntityId, (f32, f32)>) {
+        self.positions = positions;
+    }
+
+    /// Push a unit during the next fixed step, apply it every frame for a continuous push.
+    fn apply_force(&mut self, entity: (i32, i32), x: f32, y: f32) {
+        self.commands
+            .push((EntityId::from_script(entity), MotionCommand::Force(x, y)));
+    }
+
+    /// Change the momentum of a unit at once, like for jumping or knockback.
+    fn apply_impulse(&mut self, entity: (i32, i32), x: f32, y: f32) {
+        self.commands
+            .push((EntityId::from_script(entity), MotionCommand::Impulse(x, y)));
+    }
+
+    /// Replace the linear velocity of a unit.
+    fn set_velocity(&mut self, entity: (i32, i32), x: f32, y: f32) {
+        self.commands.push((
+            EntityId::from_script(entity),
+            MotionCommand::LinearVelocity(x, y),
+        ));
+    }
+
+    /// Replace the angular velocity of a unit.
+    fn set_angular_velocity(&mut self, entity: (i32, i32), angular: f32) {
+        self.commands.push((
+            EntityId::from_script(entity),
+            MotionCommand::AngularVelocity(angular),
+        ));
+    }
+
+    /// Move a kinematic unit with a linear & an angular velocity, it pushes the other bodies along.
+    fn set_kinematic_velocity(&mut self, entity: (i32, i32), x: f32, y: f32, angular: Option<f32>) {
+        self.commands.push((
+            EntityId::from_script(entity),
+            MotionCommand::KinematicVelocity(x, y, angular.unwrap_or_default()),
+        ));
+    }
+
+    /// Walk a unit like a platformer character in a direction from -1.0 for left to 1.0 for right,
+    /// it keeps walking until it's stopped with 0.0.
+    fn walk(&mut self, entity: (i32, i32), direction: f32) {
+        self.commands.push((
+            EntityId::from_script(entity),
+            MotionCommand::Walk(direction),
+        ));
+    }
+
+    /// Let a unit jump as soon as it stands on the ground, or just walked off it.
+    fn jump(&mut self, entity: (i32, i32)) {
+        self.commands
+            .push((EntityId::from_script(entity), MotionCommand::Jump));
+    }
+
+    /// Push everything within a radius of a world position away from it, the units that are hit
+    /// are passed to `engine:on-explosion`.
+    fn explode(&mut self, x: f32, y: f32, radius: f32, strength: f32) {
+        self.explosions.push(Explosion {
+            x,
+            y,
+            radius,
+            strength,
+        });
+    }
+
+    /// The linear velocity of a unit at the start of the frame, `#n` when it doesn't exist.
+    fn velocity(&self, entity: (i32, i32)) -> Option<(f32, f32)> {
+        self.velocities
+            .get(&EntityId::from_script(entity))
+            .map(|velocity| velocity.linear)
+    }
+
+    /// The angular velocity of a unit at the start of the frame, `#n` when it doesn't exist.
+    fn angular_velocity(&self, entity: (i32, i32)) -> Option<f32> {
+        self.velocities
+            .get(&EntityId::from_script(entity))
+            .map(|velocity| velocity.angular)
+    }
+
+    /// The world position of a unit at the start of the frame, `#n` when it doesn't exist.
+    fn position(&self, entity: (i32, i32)) -> Option<(f32, f32)> {
+        self.positions.get(&EntityId::from_script(entity)).copied()
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("apply-force", rfn!(Self::apply_force))?;
+            glsp::bind_rfn("apply-impulse", rfn!(Self::apply_impulse))?;
+            glsp::bind_rfn("set-velocity", rfn!(Self::set_velocity))?;
+            glsp::bind_rfn("set-angular-velocity", rfn!(Self::set_angular_velocity))?;
+            glsp::bind_rfn("set-kinematic-velocity", rfn!(Self::set_kinematic_velocity))?;
+            glsp::bind_rfn("move", rfn!(Self::walk))?;
+            glsp::bind_rfn("jump", rfn!(Self::jump))?;
+            glsp::bind_rfn("explode", rfn!(Self::explode))?;
+            glsp::bind_rfn("velocity", rfn!(Self::velocity))?;
+            glsp::bind_rfn("angular-velocity", rfn!(Self::angular_velocity))?;
+            glsp::bind_rfn("entity-pos", rfn!(Self::position))?;
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{
+    clock::MAX_DELTA,
+    config::{NetworkConfig, NetworkMode},
+    replay::InputEvent,
+};
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    mem,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Amount of ticks the local input is scheduled ahead, hides the latency of the connection.
+pub const INPUT_DELAY: u64 = 3;
+/// Amount of players in a session, the host is the first.
+pub const PLAYER_COUNT: usize = 2;
+/// Largest packet that can be received in bytes.
+const MAX_PACKET_SIZE: usize = 65_507;
+/// Seconds without packets after which the peer is reported missing.
+const TIMEOUT: f64 = 5.0;
+/// Amount of the most recent state hashes sent with every packet.
+const SENT_HASHES: usize = 8;
+
+/// The input of a player for a single tick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TickInput {
+    tick: u64,
+    events: Vec<InputEvent>,
+}
+
+/// The hash of the simulation after a tick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TickHash {
+    tick: u64,
+    /// The bits of the hash, TOML only has signed integers.
+    hash: i64,
+}
+
+/// Everything exchanged between the peers, sent every frame.
+///
+/// Input is sent again until the peer acknowledges it, so lost packets don't matter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Packet {
+    /// All input before this tick is received.
+    ack: u64,
+    /// The input not yet acknowledged.
+    inputs: Vec<TickInput>,
+    /// The most recent state hashes.
+    hashes: Vec<TickHash>,
+}
+
+/// The input of all players for a tick that can be simulated.
+#[derive(Debug, Clone)]
+pub struct Tick {
+    /// The number of the tick.
+    pub tick: u64,
+    /// The input of every player, indexed by the player.
+    pub inputs: Vec<Vec<InputEvent>>,
+}
+
+/// Deterministic lockstep between two peers over UDP.
+///
+/// Every tick is only simulated when the input of both players has arrived, the simulations are
+/// compared by exchanging a hash of the physics state.
+pub struct Lockstep {
+    /// The non-blocking socket.
+    socket: UdpSocket,
+    /// The address of the other player, the host learns it from the first packet.
+    peer: Option<SocketAddr>,
+    /// The index of the local player.
+    player: usize,
+    /// The duration of a tick in seconds.
+    fixed_step: f64,
+    /// The time not yet consumed by scheduled input in seconds.
+    accumulator: f64,
+    /// The time the input was last scheduled in seconds.
+    last_time: f64,
+    /// The next tick to simulate.
+    tick: u64,
+    /// The tick the next local input is scheduled for.
+    next_input_tick: u64,
+    /// The local input that's not simulated or acknowledged yet.
+    local: BTreeMap<u64, Vec<InputEvent>>,
+    /// The remote input that's not simulated yet.
+    remote: BTreeMap<u64, Vec<InputEvent>>,
+    /// The peer received all local input before this tick.
+    acked: u64,
+    /// All remote input before this tick is received.
+    received: u64,
+    /// The hashes of the local simulation that aren't compared yet.
+    local_hashes: BTreeMap<u64, u64>,
+    /// The hashes of the remote simulation that aren't compared yet.
+    remote_hashes: BTreeMap<u64, u64>,
+    /// The time of the last received packet in seconds.
+    last_receive: f64,
+    /// Whether the missing peer is already reported.
+    timed_out: bool,
+    /// Whether the simulations went out of sync.
+    desynced: bool,
+}
+
+impl Lockstep {
+    /// Host a session or join one, `None` when playing offline.
+    pub fn connect(config: &NetworkConfig, fixed_step: f64) -> Result<Option<Self>> {
+        if cfg!(target_arch = "wasm32") && config.mode != NetworkMode::Offline {
+            return Err(anyhow!("Multiplayer is not supported in the browser"));
+        }
+
+        let (socket, peer, player) = match config.mode {
+            NetworkMode::Offline | NetworkMode::Server | NetworkMode::Client => return Ok(None),
+            NetworkMode::Host => {
+                let socket = UdpSocket::bind(("0.0.0.0", config.port))?;
+                info!("hosting on port {}", config.port);
+
+                (socket, None, 0)
+            }
+            NetworkMode::Join => {
+                let peer = (config.host.as_str(), config.port)
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| anyhow!("Host \"{}\" not found", config.host))?;
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                info!("joining {}", peer);
+
+                (socket, Some(peer), 1)
+            }
+        };
+        socket.set_nonblocking(true)?;
+
+        // Both players start with empty input for the delayed ticks
+        let empty = (0..INPUT_DELAY)
+            .map(|tick| (tick, vec![]))
+            .collect::<BTreeMap<_, _>>();
+
+        let now = miniquad::date::now();
+        Ok(Some(Self {
+            socket,
+            peer,
+            player,
+            fixed_step,
+            accumulator: 0.0,
+            last_time: now,
+            tick: 0,
+            next_input_tick: INPUT_DELAY,
+            local: empty.clone(),
+            remote: empty,
+            acked: INPUT_DELAY,
+            received: INPUT_DELAY,
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            last_receive: now,
+            timed_out: false,
+            desynced: false,
+        }))
+    }
+
+    /// The index of the local player.
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    /// Schedule the local input for the ticks that are due, the events go to the first one.
+    ///
+    /// Input is never scheduled more than the delay ahead of the simulation, so waiting for the
+    /// peer pauses the game instead of building up input.
+    pub fn queue_input(&mut self, events: &mut Vec<InputEvent>) {
+        let now = miniquad::date::now();
+        self.accumulator = (self.accumulator + now - self.last_time).min(MAX_DELTA);
+        self.last_time = now;
+
+        while self.accumulator >= self.fixed_step && self.next_input_tick < self.tick + INPUT_DELAY
+        {
+            self.accumulator -= self.fixed_step;
+
+            self.local.insert(self.next_input_tick, mem::take(events));
+            self.next_input_tick += 1;
+        }
+    }
+
+    /// Take the input of the next tick when the input of all players has arrived.
+    pub fn next_tick(&mut self) -> Option<Tick> {
+        let local = self.local.get(&self.tick)?.clone();
+        let remote = self.remote.remove(&self.tick)?;
+
+        let mut inputs = vec![vec![]; PLAYER_COUNT];
+        inputs[self.player] = local;
+        inputs[1 - self.player] = remote;
+
+        let tick = Tick {
+            tick: self.tick,
+            inputs,
+        };
+        self.tick += 1;
+        self.forget_local();
+
+        Some(tick)
+    }
+
+    /// Store the hash of the local simulation after a tick, to compare it with the peer.
+    pub fn report_hash(&mut self, tick: u64, hash: u64) {
+        self.local_hashes.insert(tick, hash);
+        self.compare_hashes();
+    }
+
+    /// Handle all packets that arrived since the last poll.
+    pub fn poll(&mut self) -> Result<()> {
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let (size, from) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            // The host accepts the first peer sending a packet
+            match self.peer {
+                Some(peer) if peer != from => continue,
+                Some(_) => (),
+                None => {
+                    info!("{} joined", from);
+                    self.peer = Some(from);
+                }
+            }
+
+            match std::str::from_utf8(&buffer[..size])
+                .map_err(|err| anyhow!(err))
+                .and_then(|text| Ok(toml::from_str::<Packet>(text)?))
+            {
+                Ok(packet) => self.receive(packet),
+                Err(err) => warn!("ignoring invalid packet from {}: {}", from, err),
+            }
+        }
+
+        let now = miniquad::date::now();
+        if !self.timed_out && now - self.last_receive > TIMEOUT {
+            warn!("no packets received for {} seconds, waiting", TIMEOUT);
+            self.timed_out = true;
+        }
+
+        Ok(())
+    }
+
+    /// Send the unacknowledged input & the recent hashes to the peer.
+    pub fn send(&self) -> Result<()> {
+        let peer = match self.peer {
+            Some(peer) => peer,
+            None => return Ok(()),
+        };
+
+        let packet = Packet {
+            ack: self.received,
+            inputs: self
+                .local
+                .range(self.acked..)
+                .map(|(&tick, events)| TickInput {
+                    tick,
+                    events: events.clone(),
+                })
+                .collect(),
+            hashes: self
+                .local_hashes
+                .iter()
+                .rev()
+                .take(SENT_HASHES)
+                .map(|(&tick, &hash)| TickHash {
+                    tick,
+                    hash: hash as i64,
+                })
+                .collect(),
+        };
+
+        self.socket
+            .send_to(toml::to_string(&packet)?.as_bytes(), peer)?;
+
+        Ok(())
+    }
+
+    /// Store the input & hashes of a packet.
+    fn receive(&mut self, packet: Packet) {
+        self.last_receive = miniquad::date::now();
+        self.timed_out = false;
+
+        self.acked = self.acked.max(packet.ack);
+        self.forget_local();
+
+        for input in packet.inputs {
+            if input.tick >= self.tick {
+                self.remote.entry(input.tick).or_insert(input.events);
+            }
+        }
+        while self.received < self.tick || self.remote.contains_key(&self.received) {
+            self.received += 1;
+        }
+
+        for hash in packet.hashes {
+            self.remote_hashes.insert(hash.tick, hash.hash as u64);
+        }
+        self.compare_hashes();
+    }
+
+    /// Remove the local input that's simulated & acknowledged.
+    fn forget_local(&mut self) {
+        let keep = self.acked.min(self.tick);
+        self.local = self.local.split_off(&keep);
+    }
+
+    /// Compare the hashes known from both simulations, reporting the first difference.
+    fn compare_hashes(&mut self) {
+        let compared = self
+            .local_hashes
+            .iter()
+            .filter_map(|(tick, local)| {
+                self.remote_hashes
+                    .get(tick)
+                    .map(|remote| (*tick, local == remote))
+            })
+            .collect::<Vec<_>>();
+
+        for (tick, equal) in compared {
+            if !equal && !self.desynced {
+                error!("simulation out of sync with the peer at tick {}", tick);
+                self.desynced = true;
+            }
+
+            self.local_hashes.remove(&tick);
+            self.remote_hashes.remove(&tick);
+        }
+
+        // The peer only sends the most recent hashes, older ones will never be compared
+        let oldest = self
+            .tick
+            .saturating_sub(INPUT_DELAY + SENT_HASHES as u64 * 2);
+        self.local_hashes = self.local_hashes.split_off(&oldest);
+        self.remote_hashes = self.remote_hashes.split_off(&oldest);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::render::{Mesh, Render, Vertex};
+use anyhow::Result;
+use lyon::tessellation::VertexBuffers;
+use std::collections::HashMap;
+
+/// A mesh split into 9 regions by slice guides, which can be stretched to any size.
+///
+/// The corners keep their size, the edges stretch in a single direction and the center stretches
+/// in both directions. Triangles crossing a guide are cut in pieces, so every triangle lies in a
+/// single region and is stretched without distorting it. The stretching is done by the nine-slice
+/// pipeline, so a single mesh can be drawn at any size by setting the size of its instances.
+pub struct NineSlice {
+    /// The lyon geometry in the original size.
+    geometry: VertexBuffers<Vertex, u32>,
+    /// The X positions of the left and right guides.
+    guides_x: (f32, f32),
+    /// The Y positions of the top and bottom guides.
+    guides_y: (f32, f32),
+    /// The top left corner of the bounding box of the geometry.
+    min: (f32, f32),
+    /// The bottom right corner of the bounding box of the geometry.
+    max: (f32, f32),
+}
+
+impl NineSlice {
+    /// Create the nine-slice from geometry and the position of the guides.
+    pub fn new(
+        geometry: VertexBuffers<Vertex, u32>,
+        left: f32,
+        top: f32,
+        right: f32,
+        bottom: f32,
+    ) -> Self {
+        // Calculate the bounding box of the geometry
+        let (min, max) = geometry.vertices.iter().fold(
+            ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN)),
+            |(min, max), vertex| {
+                let [x, y] = vertex.pos();
+
+                ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+            },
+        );
+
+        let guides_x = (left.min(right), left.max(right));
+        let guides_y = (top.min(bottom), top.max(bottom));
+
+        // Cut the triangles along the guides
+        let geometry = split(&geometry, 0, guides_x.0);
+        let geometry = split(&geometry, 0, guides_x.1);
+        let geometry = split(&geometry, 1, guides_y.0);
+        let geometry = split(&geometry, 1, guides_y.1);
+
+        Self {
+            geometry,
+            guides_x,
+            guides_y,
+            min,
+            max,
+        }
+    }
+
+    /// Build the geometry with the stretch of every vertex, with the origin at the top left
+    /// corner.
+    ///
+    /// Drawn with a size smaller than the corners the corners overlap.
+    pub fn geometry(&self) -> VertexBuffers<Vertex, u32> {
+        let mut geometry = self.geometry.clone();
+
+        for vertex in geometry.vertices.iter_mut() {
+            let [x, y] = vertex.pos();
+            let (x, stretch_x) = stretch(x, self.min.0, self.max.0, self.guides_x);
+            let (y, stretch_y) = stretch(y, self.min.1, self.max.1, self.guides_y);
+
+            vertex.set_pos([x, y]);
+            vertex.set_stretch([stretch_x, stretch_y]);
+        }
+
+        geometry
+    }
+
+    /// Upload the geometry and get a mesh, the instances must have a size.
+    pub fn upload(&self, render: &mut Render) -> Result<Mesh> {
+        let mesh = render.upload_buffers(&self.geometry())?;
+        render.set_label(mesh, "panels");
+
+        Ok(mesh)
+    }
+}
+
+/// Split the triangles crossing a guide on an axis, so all triangles lie on a single side of it.
+fn split(
+    geometry: &VertexBuffers<Vertex, u32>,
+    axis: usize,
+    guide: f32,
+) -> VertexBuffers<Vertex, u32> {
+    let mut vertices = geometry.vertices.clone();
+    let mut indices = Vec::with_capacity(geometry.indices.len());
+    // The vertices on the guide of the edges already cut, shared by both triangles of the edge
+    let mut cuts = HashMap::new();
+
+    // The distance to the guide
+    let distance = |vertices: &[Vertex], index: u32| vertices[index as usize].pos()[axis] - guide;
+
+    for triangle in geometry.indices.chunks_exact(3) {
+        let distances = [
+            distance(&vertices, triangle[0]),
+            distance(&vertices, triangle[1]),
+            distance(&vertices, triangle[2]),
+        ];
+        if distances.iter().all(|d| *d <= 0.0) || distances.iter().all(|d| *d >= 0.0) {
+            indices.extend_from_slice(triangle);
+            continue;
+        }
+
+        // Clip the triangle to both sides of the guide
+        for &side in &[-1.0, 1.0] {
+            let mut polygon = Vec::with_capacity(4);
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+                let (distance_a, distance_b) = (distances[corner], distances[(corner + 1) % 3]);
+
+                if distance_a * side >= 0.0 {
+                    polygon.push(a);
+                }
+                if distance_a * distance_b < 0.0 {
+                    // The edge crosses the guide, add a vertex where it does
+                    let cut = *cuts.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                        let (from, to, t) = if a < b {
+                            (a, b, distance_a / (distance_a - distance_b))
+                        } else {
+                            (b, a, distance_b / (distance_b - distance_a))
+                        };
+                        let vertex = vertices[from as usize].lerp(&vertices[to as usize], t);
+                        vertices.push(vertex);
+
+                        (vertices.len() - 1) as u32
+                    });
+                    polygon.push(cut);
+                }
+            }
+
+            // The clipped part is convex, so it can be fanned
+            for i in 1..polygon.len().saturating_sub(1) {
+                indices.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+            }
+        }
+    }
+
+    VertexBuffers { vertices, indices }
+}
+
+/// Split a coordinate on a single axis into the position at a size of zero & the part of the size
+/// it moves with, the drawn position is `pos + stretch * size`.
+fn stretch(pos: f32, min: f32, max: f32, (start, end): (f32, f32)) -> (f32, f32) {
+    // The size of the corners
+    let start_size = start - min;
+    let end_size = max - end;
+
+    if pos <= start {
+        // Keep the start corner fixed
+        (pos - min, 0.0)
+    } else if pos >= end {
+        // Move the end corner with the end of the size
+        (pos - max, 1.0)
+    } else {
+        // Stretch the middle part over the space between the corners
+        let stretch = (pos - start) / (end - start).max(f32::EPSILON);
+
+        (start_size - stretch * (start_size + end_size), stretch)
+    }
+}
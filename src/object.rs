@@ -1,9 +1,28 @@
 use crate::{
-    physics::{Physics, RigidBody},
-    render::{Instance, Mesh},
+    animation::Skeleton,
+    assets::SvgHandle,
+    physics::{BodyKind, CollisionGroup, Physics, RigidBody},
+    render::{Instance, Layer, Mesh},
     Float, Vec2,
 };
-use nphysics2d::object::{ColliderDesc, Ground, RigidBodyDesc};
+use anyhow::{anyhow, Result};
+use nphysics2d::{
+    algebra::Velocity2,
+    object::{BodyStatus, ColliderDesc, Ground, RigidBodyDesc},
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// A spawned object without health, like the ground.
+#[derive(Debug, Clone)]
+pub struct Object {
+    /// The mesh the instance belongs to.
+    pub mesh: Mesh,
+    /// The rendered mesh instance.
+    pub instance: Instance,
+    /// The physics body.
+    pub rigid_body: RigidBody,
+}
 
 /// Definition that can be used to spawn objects.
 ///
@@ -11,40 +30,96 @@ use nphysics2d::object::{ColliderDesc, Ground, RigidBodyDesc};
 pub struct ObjectDef {
     /// Mesh reference to render the object.
     pub mesh: Mesh,
+    /// The meshes of the named groups in the SVG, by their ID.
+    pub parts: HashMap<String, Mesh>,
+    /// The hierarchy of parts the units are drawn with instead of the mesh, when animated.
+    pub skeleton: Option<Skeleton>,
     /// Description of the rigid body (not applicable when ground).
     pub rigid_body: RigidBodyDesc<Float>,
     /// Description of the collision body.
     pub collider: ColliderDesc<Float>,
-    /// Whether the object is ground.
-    pub is_ground: bool,
+    /// How the bodies are moved, static ones are ground.
+    pub kind: BodyKind,
+    /// Whether the object only detects overlapping bodies, like a pickup zone.
+    pub is_sensor: bool,
+    /// The layer the instances are drawn in.
+    pub layer: Layer,
+    /// The collision group when it's spawned as an object, units & projectiles have their own.
+    pub group: CollisionGroup,
+    /// Labels the scripts can read to tell kinds of objects apart, like "projectile".
+    pub tags: Vec<String>,
+    /// Whether it sticks into what it hits when it's fired as a projectile, like an arrow.
+    pub sticks: bool,
 }
 
 impl ObjectDef {
     /// Spawn a instance of this object which can be added to the ECS system.
+    ///
+    /// The Z value is the depth inside the layer of the object, the ground is always in the ground
+    /// collision group.
     pub fn spawn(
         &mut self,
         physics: &mut Physics<Float>,
         pos: Vec2,
         z: u8,
+        group: CollisionGroup,
+    ) -> (Instance, RigidBody) {
+        self.spawn_as(physics, pos, z, group, self.kind)
+    }
+
+    /// Spawn a instance of this object with its body moved differently than the definition says.
+    pub fn spawn_as(
+        &mut self,
+        physics: &mut Physics<Float>,
+        pos: Vec2,
+        z: u8,
+        group: CollisionGroup,
+        kind: BodyKind,
     ) -> (Instance, RigidBody) {
         let mut instance = Instance::new(pos.x as f32, pos.y as f32);
-        instance.set_z(z);
+        instance.set_layer(self.layer, z);
 
-        if self.is_ground {
+        if kind == BodyKind::Static {
             (
                 instance,
-                physics.spawn_body(Ground::new(), &self.collider.set_translation(pos)),
+                physics.spawn_body(
+                    Ground::new(),
+                    &self
+                        .collider
+                        .set_translation(pos)
+                        .set_collision_groups(CollisionGroup::Ground.into()),
+                ),
             )
         } else {
-            (instance, self.spawn_rigid_body(physics, pos))
+            (instance, self.spawn_rigid_body(physics, pos, group, kind))
         }
     }
 
     /// Spawn a rigid body in the physics system.
-    pub fn spawn_rigid_body(&self, physics: &mut Physics<Float>, pos: Vec2) -> RigidBody {
-        let rigid_body = self.rigid_body.clone().translation(pos);
+    pub fn spawn_rigid_body(
+        &mut self,
+        physics: &mut Physics<Float>,
+        pos: Vec2,
+        group: CollisionGroup,
+        kind: BodyKind,
+    ) -> RigidBody {
+        // The description is changed in place because cloning its kinematic flags isn't sound with
+        // this version of nalgebra, a projectile of the same definition might have turned it
+        self.rigid_body
+            .set_translation(pos)
+            .set_rotation(0.0)
+            .set_velocity(Velocity2::zero());
+        // Sensors stay where they're spawned instead of falling through everything
+        if self.is_sensor || kind == BodyKind::Kinematic {
+            self.rigid_body.set_status(BodyStatus::Kinematic);
+        } else {
+            self.rigid_body.set_status(BodyStatus::Dynamic);
+        }
 
-        physics.spawn_rigid_body(&rigid_body, &self.collider)
+        physics.spawn_rigid_body(
+            &self.rigid_body,
+            self.collider.set_collision_groups(group.into()),
+        )
     }
 
     /// Get the mesh reference.
@@ -52,3 +127,150 @@ impl ObjectDef {
         self.mesh
     }
 }
+
+/// How an object definition is built, as written in `assets/objects.toml`.
+///
+/// The values that aren't set are read from the collider in the metadata of the SVG.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectSource {
+    /// Path of the SVG in the assets directory.
+    pub svg: String,
+    /// Mass per area of the collider.
+    pub density: Option<f64>,
+    /// How much it resists sliding over other colliders.
+    pub friction: Option<f64>,
+    /// How the bodies are moved, `static` for ground.
+    pub body: Option<BodyKind>,
+    /// The layer the instances are drawn in, the ground or the units layer by default.
+    pub layer: Option<Layer>,
+    /// Name of the registered shader the vector paths are drawn with instead of the vector one.
+    pub shader: Option<String>,
+    /// The collision group when it's spawned as an object.
+    pub collision_group: Option<ObjectGroup>,
+    /// Labels the scripts can read.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether a projectile sticks into what it hits instead of being removed.
+    #[serde(default)]
+    pub sticks: bool,
+    /// Whether the SVG is a level with several objects & spawn markers instead of a single object.
+    #[serde(default)]
+    pub level: bool,
+}
+
+/// The collision groups an object can be in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObjectGroup {
+    /// Static ground, collides with everything.
+    Ground,
+    /// Collides with everything.
+    Object,
+    /// A projectile shot by nobody.
+    Projectile,
+}
+
+impl From<ObjectGroup> for CollisionGroup {
+    fn from(group: ObjectGroup) -> Self {
+        match group {
+            ObjectGroup::Ground => CollisionGroup::Ground,
+            ObjectGroup::Object => CollisionGroup::Object,
+            ObjectGroup::Projectile => CollisionGroup::Projectile(None),
+        }
+    }
+}
+
+/// An object definition with what it's known by.
+struct Entry {
+    /// The name the scripts spawn it by.
+    ///
+    /// Definitions live as long as the game, so the name is leaked to be passed around in the
+    /// collision events.
+    name: &'static str,
+    /// The SVG it's built from.
+    svg: SvgHandle,
+    /// The definition itself.
+    def: ObjectDef,
+}
+
+/// The object definitions by the name the scripts spawn them by, read from a data file.
+#[derive(Default)]
+pub struct ObjectRegistry {
+    /// The definitions in the order they're registered.
+    entries: Vec<Entry>,
+}
+
+impl ObjectRegistry {
+    /// Parse the sources of the definitions by their name from TOML.
+    pub fn parse_sources(source: &str) -> Result<BTreeMap<String, ObjectSource>> {
+        toml::from_str(source).map_err(|err| anyhow!("Parsing object definitions failed: {}", err))
+    }
+
+    /// Add a definition, replacing the one with the same name.
+    ///
+    /// Returns the leaked name.
+    pub fn register(&mut self, name: &str, svg: SvgHandle, def: ObjectDef) -> &'static str {
+        self.entries.retain(|entry| entry.name != name);
+
+        let name = Box::leak(name.to_string().into_boxed_str());
+        self.entries.push(Entry { name, svg, def });
+
+        name
+    }
+
+    /// Get a definition by its name.
+    pub fn get(&self, name: &str) -> Result<&ObjectDef> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| &entry.def)
+            .ok_or_else(|| anyhow!("Object definition \"{}\" doesn't exist", name))
+    }
+
+    /// Get a definition by its name to spawn it.
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut ObjectDef> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.name == name)
+            .map(|entry| &mut entry.def)
+            .ok_or_else(|| anyhow!("Object definition \"{}\" doesn't exist", name))
+    }
+
+    /// The name of the definition a mesh belongs to.
+    pub fn name_of(&self, mesh: Mesh) -> Option<&'static str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.def.mesh == mesh)
+            .map(|entry| entry.name)
+    }
+
+    /// The definition a mesh belongs to.
+    pub fn by_mesh(&self, mesh: Mesh) -> Option<&ObjectDef> {
+        self.entries
+            .iter()
+            .find(|entry| entry.def.mesh == mesh)
+            .map(|entry| &entry.def)
+    }
+
+    /// The definitions built from an SVG.
+    pub fn with_svg(&self, svg: SvgHandle) -> impl Iterator<Item = &ObjectDef> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.svg == svg)
+            .map(|entry| &entry.def)
+    }
+
+    /// All definitions.
+    pub fn iter(&self) -> impl Iterator<Item = &ObjectDef> {
+        self.entries.iter().map(|entry| &entry.def)
+    }
+
+    /// The tags of all definitions by their name, for the scripts.
+    pub fn tags(&self) -> HashMap<String, Vec<String>> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.name.to_string(), entry.def.tags.clone()))
+            .collect()
+    }
+}
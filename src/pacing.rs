@@ -0,0 +1,70 @@
+use crate::config::DisplayConfig;
+
+/// Limits the frames per second by sleeping the remainder of every frame.
+#[derive(Debug)]
+pub struct FrameLimiter {
+    /// The time the previous frame ended in seconds.
+    last_frame: f64,
+    /// Whether the window is in the background.
+    background: bool,
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameLimiter {
+    /// Start limiting from now.
+    pub fn new() -> Self {
+        Self {
+            last_frame: miniquad::date::now(),
+            background: false,
+        }
+    }
+
+    /// Throttle harder when the window is in the background.
+    pub fn set_background(&mut self, background: bool) {
+        self.background = background;
+    }
+
+    /// The maximum frames per second for the configuration, if limited.
+    pub fn target_fps(&self, config: &DisplayConfig) -> Option<u32> {
+        let fps = if self.background {
+            config.background_fps
+        } else {
+            config.fps_cap
+        };
+
+        if fps == 0 {
+            None
+        } else {
+            Some(fps)
+        }
+    }
+
+    /// Sleep until the frame took as long as the target frame rate allows.
+    ///
+    /// Should be called at the end of every frame.
+    pub fn wait(&mut self, config: &DisplayConfig) {
+        if let Some(fps) = self.target_fps(config) {
+            let remaining = 1.0 / fps as f64 - (miniquad::date::now() - self.last_frame);
+            if remaining > 0.0 {
+                sleep(remaining);
+            }
+        }
+
+        self.last_frame = miniquad::date::now();
+    }
+}
+
+/// Block the thread for a duration in seconds.
+#[cfg(not(target_arch = "wasm32"))]
+fn sleep(seconds: f64) {
+    std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+}
+
+/// The browser can't block, but it already paces the frames itself.
+#[cfg(target_arch = "wasm32")]
+fn sleep(_seconds: f64) {}
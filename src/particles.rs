@@ -0,0 +1,335 @@
+use crate::render::{Instance, Layer, Mesh, Render};
+use glsp::{bail, lib, rfn, GResult, Runtime, Sym};
+use lyon::{math::Point, path::Path};
+use std::f32::consts::PI;
+use usvg::Color;
+
+/// Most particles alive at the same time, new ones aren't spawned above it.
+const MAX_PARTICLES: usize = 4096;
+
+/// The effects the scripts can spawn by name.
+const EMITTERS: &[EmitterDef] = &[
+    EmitterDef {
+        name: "blood",
+        shape: ParticleShape::Circle(3.0),
+        color: (0x8a, 0x03, 0x03),
+        burst: 24,
+        rate: 0.0,
+        duration: 0.0,
+        lifetime: (0.4, 0.8),
+        speed: (80.0, 220.0),
+        direction: -PI / 2.0,
+        spread: PI,
+        gravity: 600.0,
+        scale: &[(0.0, 1.0), (1.0, 0.3)],
+        alpha: &[(0.0, 1.0), (0.7, 1.0), (1.0, 0.0)],
+    },
+    EmitterDef {
+        name: "dust",
+        shape: ParticleShape::Circle(4.0),
+        color: (0xa0, 0x90, 0x80),
+        burst: 0,
+        rate: 30.0,
+        duration: 0.5,
+        lifetime: (0.6, 1.2),
+        speed: (10.0, 40.0),
+        direction: -PI / 2.0,
+        spread: PI,
+        gravity: -20.0,
+        scale: &[(0.0, 0.5), (1.0, 2.0)],
+        alpha: &[(0.0, 0.6), (1.0, 0.0)],
+    },
+    EmitterDef {
+        name: "sparks",
+        shape: ParticleShape::Triangle(4.0),
+        color: (0xff, 0xd0, 0x40),
+        burst: 16,
+        rate: 0.0,
+        duration: 0.0,
+        lifetime: (0.2, 0.4),
+        speed: (200.0, 400.0),
+        direction: 0.0,
+        spread: PI * 2.0,
+        gravity: 300.0,
+        scale: &[(0.0, 1.0), (1.0, 0.5)],
+        alpha: &[(0.0, 1.0), (1.0, 0.0)],
+    },
+];
+
+/// The mesh every particle of an emitter is drawn with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ParticleShape {
+    /// A circle with a radius in world units.
+    Circle(f32),
+    /// An equilateral triangle with the distance from the center to the corners in world units.
+    Triangle(f32),
+}
+
+impl ParticleShape {
+    /// The outline of the shape around the origin.
+    fn path(self) -> Path {
+        let (sides, radius) = match self {
+            ParticleShape::Circle(radius) => (12, radius),
+            ParticleShape::Triangle(radius) => (3, radius),
+        };
+
+        let mut builder = Path::builder();
+        for side in 0..sides {
+            let angle = side as f32 / sides as f32 * PI * 2.0;
+            let point = Point::new(angle.cos() * radius, angle.sin() * radius);
+            if side == 0 {
+                builder.move_to(point);
+            } else {
+                builder.line_to(point);
+            }
+        }
+        builder.close();
+
+        builder.build()
+    }
+}
+
+/// How a kind of effect spawns & moves its particles.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct EmitterDef {
+    /// The name the scripts spawn it by.
+    name: &'static str,
+    /// The mesh of the particles.
+    shape: ParticleShape,
+    /// The RGB color of the particles.
+    color: (u8, u8, u8),
+    /// Particles spawned at once when the emitter is spawned.
+    burst: u32,
+    /// Particles spawned every second while the emitter is active.
+    rate: f32,
+    /// How long the emitter spawns particles in seconds.
+    duration: f32,
+    /// The shortest & longest time a particle lives in seconds.
+    lifetime: (f32, f32),
+    /// The slowest & fastest starting speed in world units per second.
+    speed: (f32, f32),
+    /// The angle in radians the particles fly towards, 0.0 is to the right.
+    direction: f32,
+    /// The angle in radians around the direction the particles are spread over.
+    spread: f32,
+    /// Downwards acceleration in world units per second squared.
+    gravity: f32,
+    /// The scale over the life of a particle, as points from 0.0 to 1.0 of the life.
+    scale: &'static [(f32, f32)],
+    /// The opacity over the life of a particle, as points from 0.0 to 1.0 of the life.
+    alpha: &'static [(f32, f32)],
+}
+
+/// A single particle flying around.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Particle {
+    /// World position.
+    pos: (f32, f32),
+    /// Velocity in world units per second.
+    velocity: (f32, f32),
+    /// Rotation in radians, facing the direction it was shot in.
+    rotation: f32,
+    /// Time since it was spawned in seconds.
+    age: f32,
+    /// Time it lives in seconds.
+    lifetime: f32,
+}
+
+/// A spawned effect with its particles.
+#[derive(Debug, Clone, PartialEq)]
+struct Emitter {
+    /// The index of the definition.
+    def: usize,
+    /// World position the particles are spawned at.
+    pos: (f32, f32),
+    /// Time since it was spawned in seconds.
+    age: f32,
+    /// Particles that should have been spawned but didn't fit in the previous frames.
+    pending: f32,
+    /// The particles that are alive.
+    particles: Vec<Particle>,
+}
+
+lib! {
+/// Simulates the particles of the effects on the CPU, they're drawn as instances of a mesh for
+/// every kind of effect so all particles of a kind are a single draw call.
+pub struct Particles {
+    /// The uploaded mesh of every definition.
+    meshes: Vec<Mesh>,
+    /// The spawned effects.
+    emitters: Vec<Emitter>,
+    /// The state of the random number generator for spreading the particles.
+    seed: u64,
+}
+}
+
+impl Particles {
+    /// Upload the meshes of the effects.
+    pub fn new(render: &mut Render) -> Self {
+        let meshes = EMITTERS
+            .iter()
+            .map(|def| {
+                // Every effect sets the instances of its own mesh
+                let (r, g, b) = def.color;
+                let mesh = render.upload_path_unique(&def.shape.path(), Color::new(r, g, b), 1.0);
+                render.set_label(mesh, "particles");
+
+                mesh
+            })
+            .collect();
+
+        Self {
+            meshes,
+            emitters: vec![],
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// Spawn an effect at a world position.
+    fn spawn_emitter(&mut self, name: Sym, x: f32, y: f32) -> GResult<()> {
+        let def = match EMITTERS.iter().position(|def| def.name == &*name.name()) {
+            Some(def) => def,
+            None => bail!("unknown particle effect {}", name),
+        };
+
+        self.emitters.push(Emitter {
+            def,
+            pos: (x, y),
+            age: 0.0,
+            pending: EMITTERS[def].burst as f32,
+            particles: vec![],
+        });
+
+        Ok(())
+    }
+
+    /// Spawn, move & age the particles, removing the effects that are done.
+    pub fn update(&mut self, delta: f32) {
+        let mut alive = self.alive();
+        for index in 0..self.emitters.len() {
+            let def = &EMITTERS[self.emitters[index].def];
+
+            // Spawn the particles of this frame, the remainder is spawned in the next ones
+            let emitter = &mut self.emitters[index];
+            if emitter.age < def.duration {
+                emitter.pending += def.rate * delta.min(def.duration - emitter.age);
+            }
+            emitter.age += delta;
+            let count = (emitter.pending.floor() as usize).min(MAX_PARTICLES.saturating_sub(alive));
+            emitter.pending -= count as f32;
+            alive += count;
+
+            for _ in 0..count {
+                let particle = self.spawn_particle(def, self.emitters[index].pos);
+                self.emitters[index].particles.push(particle);
+            }
+
+            let emitter = &mut self.emitters[index];
+            for particle in emitter.particles.iter_mut() {
+                particle.velocity.1 += def.gravity * delta;
+                particle.pos.0 += particle.velocity.0 * delta;
+                particle.pos.1 += particle.velocity.1 * delta;
+                particle.age += delta;
+            }
+            emitter
+                .particles
+                .retain(|particle| particle.age < particle.lifetime);
+            emitter.pending = emitter.pending.min(MAX_PARTICLES as f32);
+        }
+
+        // An effect is done when it can't spawn anything anymore and all its particles are gone
+        self.emitters.retain(|emitter| {
+            emitter.age < EMITTERS[emitter.def].duration
+                || emitter.pending >= 1.0
+                || !emitter.particles.is_empty()
+        });
+    }
+
+    /// Pass the particles to the renderer, should be called every frame.
+    pub fn draw(&self, render: &mut Render) {
+        for (def_index, (def, mesh)) in EMITTERS.iter().zip(self.meshes.iter()).enumerate() {
+            let instances = self
+                .emitters
+                .iter()
+                .filter(|emitter| emitter.def == def_index)
+                .flat_map(|emitter| emitter.particles.iter())
+                .map(|particle| {
+                    let life = particle.age / particle.lifetime;
+
+                    let mut instance = Instance::new(particle.pos.0, particle.pos.1);
+                    instance.set_layer(Layer::Projectiles, 1);
+                    instance.set_rotation(particle.rotation);
+                    instance.set_scale(sample(def.scale, life));
+                    instance.set_color([1.0, 1.0, 1.0, sample(def.alpha, life)]);
+
+                    instance
+                })
+                .collect::<Vec<_>>();
+
+            render.set_instances(*mesh, &instances);
+        }
+    }
+
+    /// Amount of particles that are alive.
+    fn alive(&self) -> usize {
+        self.emitters
+            .iter()
+            .map(|emitter| emitter.particles.len())
+            .sum()
+    }
+
+    /// Create a particle at a position with a random direction, speed & lifetime.
+    fn spawn_particle(&mut self, def: &EmitterDef, pos: (f32, f32)) -> Particle {
+        let angle = def.direction + (self.random() - 0.5) * def.spread;
+        let speed = lerp(def.speed.0, def.speed.1, self.random());
+
+        Particle {
+            pos,
+            velocity: (angle.cos() * speed, angle.sin() * speed),
+            rotation: angle,
+            age: 0.0,
+            lifetime: lerp(def.lifetime.0, def.lifetime.1, self.random()).max(std::f32::EPSILON),
+        }
+    }
+
+    /// A random number from 0.0 to 1.0, with xorshift because the look doesn't need more.
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+
+        (self.seed >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("spawn-emitter", rfn!(Self::spawn_emitter))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Interpolate linearly between two values.
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+/// The value of a curve at a point, interpolating linearly between the points around it.
+fn sample(curve: &[(f32, f32)], t: f32) -> f32 {
+    let next = match curve.iter().position(|(point, _)| *point >= t) {
+        Some(0) => return curve[0].1,
+        Some(next) => next,
+        None => return curve.last().map_or(1.0, |(_, value)| *value),
+    };
+
+    let (from_t, from) = curve[next - 1];
+    let (to_t, to) = curve[next];
+
+    lerp(
+        from,
+        to,
+        (t - from_t) / (to_t - from_t).max(std::f32::EPSILON),
+    )
+}
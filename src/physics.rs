@@ -1,17 +1,162 @@
-use crate::{render::Instance, Float};
+use crate::{unit::Allegiance, Float};
 use generational_arena::Index;
-use nalgebra::{convert as f, RealField, Vector2};
-use ncollide2d::shape::{Shape, ShapeHandle};
+use nalgebra::{convert as f, Isometry2, Point2, RealField, Unit, UnitComplex, Vector2};
+use ncollide2d::{
+    bounding_volume::AABB,
+    pipeline::{narrow_phase::ContactEvent, CollisionGroups},
+    query::Proximity,
+    shape::{Shape, ShapeHandle},
+};
 use nphysics2d::{
-    force_generator::DefaultForceGeneratorSet,
-    joint::DefaultJointConstraintSet,
+    algebra::{Force2, ForceType, Velocity2},
+    force_generator::{
+        DefaultForceGeneratorHandle, DefaultForceGeneratorSet, ForceGenerator, Spring,
+    },
+    joint::{
+        DefaultJointConstraintHandle, DefaultJointConstraintSet, FixedConstraint,
+        PrismaticConstraint, RevoluteConstraint,
+    },
     material::{BasicMaterial, MaterialHandle},
     object::{
-        Body, BodyPartHandle, BodyStatus, ColliderDesc, DefaultBodyHandle, DefaultBodySet,
-        DefaultColliderSet, RigidBodyDesc,
+        Body, BodyPartHandle, BodySet, BodyStatus, ColliderDesc, DefaultBodyHandle, DefaultBodySet,
+        DefaultColliderSet, Ground, RigidBodyDesc,
     },
+    solver::IntegrationParameters,
     world::{DefaultGeometricalWorld, DefaultMechanicalWorld},
 };
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// How a body is moved by the simulation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BodyKind {
+    /// Moved by gravity, forces & contacts.
+    Dynamic,
+    /// Never moves, like the ground.
+    Static,
+    /// Only moved by its velocity, pushing dynamic bodies out of the way like a moving platform.
+    Kinematic,
+}
+
+impl From<BodyKind> for BodyStatus {
+    fn from(kind: BodyKind) -> Self {
+        match kind {
+            BodyKind::Dynamic => BodyStatus::Dynamic,
+            BodyKind::Static => BodyStatus::Static,
+            BodyKind::Kinematic => BodyStatus::Kinematic,
+        }
+    }
+}
+
+impl Default for BodyKind {
+    fn default() -> Self {
+        Self::Dynamic
+    }
+}
+
+/// The physical behavior of a body & its collider.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BodyParameters {
+    /// Mass per area of the collider.
+    pub density: f64,
+    /// How much it resists sliding over other colliders.
+    pub friction: f64,
+    /// How much it bounces, zero doesn't bounce at all.
+    pub restitution: f64,
+    /// How much the linear velocity slows down by itself.
+    pub linear_damping: f64,
+    /// How it's moved by the simulation.
+    pub kind: BodyKind,
+    /// Whether it only detects overlapping bodies without touching them.
+    pub sensor: bool,
+}
+
+impl Default for BodyParameters {
+    fn default() -> Self {
+        Self {
+            density: 0.2,
+            friction: 0.5,
+            restitution: 0.1,
+            linear_damping: 0.1,
+            kind: BodyKind::default(),
+            sensor: false,
+        }
+    }
+}
+
+/// Mass of the rod between the bodies of a distance joint, light compared to the units.
+const LINK_MASS: f64 = 1.0;
+
+/// Collision group of the static ground.
+const GROUND_GROUP: usize = 0;
+/// Collision group of the objects that aren't units or projectiles.
+const OBJECT_GROUP: usize = 1;
+/// Collision group of the units fighting for the player.
+const ALLY_GROUP: usize = 2;
+/// Collision group of the units fighting against the player.
+const ENEMY_GROUP: usize = 3;
+/// Collision group of the projectiles shot by allies.
+const ALLY_PROJECTILE_GROUP: usize = 4;
+/// Collision group of the projectiles shot by enemies.
+const ENEMY_PROJECTILE_GROUP: usize = 5;
+/// Collision group of the projectiles that aren't shot by a side.
+const PROJECTILE_GROUP: usize = 6;
+/// Collision group of the point & area queries, no collider blacklists it so they find all.
+const QUERY_GROUP: usize = 7;
+
+/// What a collider is, deciding what it collides with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CollisionGroup {
+    /// Static ground, collides with everything.
+    Ground,
+    /// Anything that isn't a unit or a projectile, collides with everything.
+    Object,
+    /// A unit fighting for a side, projectiles of the same side pass through it.
+    Unit(Allegiance),
+    /// A projectile shot by a side or by nobody, passes through the units of its side.
+    Projectile(Option<Allegiance>),
+}
+
+impl CollisionGroup {
+    /// The group the colliders of the units of a side are in.
+    fn unit_group(allegiance: Allegiance) -> usize {
+        match allegiance {
+            Allegiance::Ally => ALLY_GROUP,
+            Allegiance::Enemy => ENEMY_GROUP,
+        }
+    }
+
+    /// The group the colliders of the projectiles of a side are in.
+    fn projectile_group(allegiance: Allegiance) -> usize {
+        match allegiance {
+            Allegiance::Ally => ALLY_PROJECTILE_GROUP,
+            Allegiance::Enemy => ENEMY_PROJECTILE_GROUP,
+        }
+    }
+}
+
+impl From<CollisionGroup> for CollisionGroups {
+    fn from(group: CollisionGroup) -> Self {
+        match group {
+            CollisionGroup::Ground => CollisionGroups::new().with_membership(&[GROUND_GROUP]),
+            CollisionGroup::Object => CollisionGroups::new().with_membership(&[OBJECT_GROUP]),
+            CollisionGroup::Unit(allegiance) => CollisionGroups::new()
+                .with_membership(&[CollisionGroup::unit_group(allegiance)])
+                .with_blacklist(&[CollisionGroup::projectile_group(allegiance)]),
+            CollisionGroup::Projectile(Some(allegiance)) => CollisionGroups::new()
+                .with_membership(&[CollisionGroup::projectile_group(allegiance)])
+                .with_blacklist(&[CollisionGroup::unit_group(allegiance)]),
+            CollisionGroup::Projectile(None) => {
+                CollisionGroups::new().with_membership(&[PROJECTILE_GROUP])
+            }
+        }
+    }
+}
 
 /// Physics world.
 pub struct Physics<N: RealField> {
@@ -22,6 +167,25 @@ pub struct Physics<N: RealField> {
     colliders: DefaultColliderSet<N>,
     joint_constraints: DefaultJointConstraintSet<N>,
     force_generators: DefaultForceGeneratorSet<N>,
+
+    collisions: Vec<CollisionEvent>,
+    /// The positions of the rigid bodies before the last step, for interpolating.
+    previous: HashMap<DefaultBodyHandle, Isometry2<N>>,
+    /// The static body that joints fixed to the world are attached to, created by the first one.
+    fixed: Option<DefaultBodyHandle>,
+}
+
+/// Two bodies starting or stopping to touch, collected after every step.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CollisionEvent {
+    /// The colliders of the bodies started touching or overlapping.
+    Started(DefaultBodyHandle, DefaultBodyHandle),
+    /// The colliders of the bodies stopped touching or overlapping.
+    Stopped(DefaultBodyHandle, DefaultBodyHandle),
+    /// A body entered the area of a sensor, the body of the sensor is first.
+    Entered(DefaultBodyHandle, DefaultBodyHandle),
+    /// A body left the area of a sensor, the body of the sensor is first.
+    Exited(DefaultBodyHandle, DefaultBodyHandle),
 }
 
 impl<N: RealField> Physics<N> {
@@ -36,11 +200,24 @@ impl<N: RealField> Physics<N> {
             colliders: DefaultColliderSet::new(),
             joint_constraints: DefaultJointConstraintSet::new(),
             force_generators: DefaultForceGeneratorSet::new(),
+            collisions: vec![],
+            previous: HashMap::new(),
+            fixed: None,
         }
     }
 
     /// Run the simulation.
     pub fn step(&mut self) {
+        let bodies = &self.bodies;
+        self.previous = bodies
+            .iter()
+            .filter_map(|(handle, _)| {
+                bodies
+                    .rigid_body(handle)
+                    .map(|body| (handle, *body.position()))
+            })
+            .collect();
+
         self.mechanical_world.step(
             &mut self.geometrical_world,
             &mut self.bodies,
@@ -48,6 +225,113 @@ impl<N: RealField> Physics<N> {
             &mut self.joint_constraints,
             &mut self.force_generators,
         );
+
+        self.collect_collisions();
+    }
+
+    /// Take the collisions since the last time they were taken.
+    pub fn drain_collisions(&mut self) -> Vec<CollisionEvent> {
+        std::mem::take(&mut self.collisions)
+    }
+
+    /// Convert the contact & proximity events of the last step to events between bodies.
+    ///
+    /// The events are cleared by the next step so this must be called after every step.
+    fn collect_collisions(&mut self) {
+        let colliders = &self.colliders;
+        let body = |collider| colliders.get(collider).map(|collider| collider.body());
+
+        for event in self.geometrical_world.contact_events().iter() {
+            let collision = match *event {
+                ContactEvent::Started(a, b) => body(a)
+                    .zip(body(b))
+                    .map(|(a, b)| CollisionEvent::Started(a, b)),
+                ContactEvent::Stopped(a, b) => body(a)
+                    .zip(body(b))
+                    .map(|(a, b)| CollisionEvent::Stopped(a, b)),
+            };
+            self.collisions.extend(collision);
+        }
+
+        // Sensors only generate proximity events
+        for event in self.geometrical_world.proximity_events().iter() {
+            // Put the sensor first
+            let (sensor, other) = match colliders.get(event.collider2) {
+                Some(collider) if collider.is_sensor() => (event.collider2, event.collider1),
+                _ => (event.collider1, event.collider2),
+            };
+            let bodies = body(sensor).zip(body(other));
+            let collision = match (event.prev_status, event.new_status) {
+                (_, Proximity::Intersecting) => bodies.map(|(a, b)| CollisionEvent::Entered(a, b)),
+                (Proximity::Intersecting, _) => bodies.map(|(a, b)| CollisionEvent::Exited(a, b)),
+                _ => None,
+            };
+            self.collisions.extend(collision);
+        }
+    }
+
+    /// Set the duration of a single step in seconds.
+    pub fn set_timestep(&mut self, timestep: N) {
+        self.mechanical_world.set_timestep(timestep);
+    }
+
+    /// Save the positions & velocities of all rigid bodies.
+    pub fn save_state(&self) -> Vec<BodyState<N>> {
+        self.bodies
+            .iter()
+            .filter_map(|(handle, _)| {
+                self.bodies.rigid_body(handle).map(|body| BodyState {
+                    handle,
+                    position: *body.position(),
+                    velocity: *body.velocity(),
+                })
+            })
+            .collect()
+    }
+
+    /// Move the rigid bodies back to a saved state.
+    ///
+    /// Bodies spawned after saving are left alone, the contact caches aren't restored.
+    pub fn restore_state(&mut self, states: &[BodyState<N>]) {
+        // Jump to the restored positions instead of interpolating towards them
+        self.previous.clear();
+
+        for state in states {
+            if let Some(body) = self.bodies.rigid_body_mut(state.handle) {
+                body.set_position(state.position);
+                body.set_velocity(state.velocity);
+            }
+        }
+    }
+
+    /// Save the position & velocity of a body, the static ground only has the position of its
+    /// collider.
+    pub fn body_state(&self, rigid_body: &RigidBody) -> Option<BodyState<N>> {
+        let handle = rigid_body.body_index;
+        match self.bodies.rigid_body(handle) {
+            Some(body) => Some(BodyState {
+                handle,
+                position: *body.position(),
+                velocity: *body.velocity(),
+            }),
+            None => self
+                .colliders
+                .get(rigid_body.collider_index)
+                .map(|collider| BodyState {
+                    handle,
+                    position: *collider.position(),
+                    velocity: Velocity2::zero(),
+                }),
+        }
+    }
+
+    /// Move a rigid body to a saved position with the saved velocity, waking it up.
+    pub fn set_body_state(&mut self, rigid_body: &RigidBody, state: &BodyState<N>) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            body.set_position(state.position);
+            body.set_velocity(state.velocity);
+            body.activate();
+        }
     }
 
     /// Spawn a rigid body.
@@ -94,12 +378,649 @@ impl<N: RealField> Physics<N> {
         })
     }
 
+    /// Get the position (with rotation) of a rigid body between the last two steps.
+    ///
+    /// An alpha of 0.0 is the position before the last step, 1.0 the position after it.
+    pub fn interpolated_position(&self, rigid_body: &RigidBody, alpha: N) -> Option<(N, N, N)> {
+        let current = self.bodies.rigid_body(rigid_body.body_index)?.position();
+        let previous = self.previous.get(&rigid_body.body_index).unwrap_or(current);
+
+        let translation = previous
+            .translation
+            .vector
+            .lerp(&current.translation.vector, alpha);
+        // Turn the shortest way around
+        let rotation =
+            previous.rotation.angle() + previous.rotation.angle_to(&current.rotation) * alpha;
+
+        Some((translation.x, translation.y, rotation))
+    }
+
+    /// Get the linear velocity of a rigid body.
+    pub fn linear_velocity(&self, rigid_body: &RigidBody) -> Option<(N, N)> {
+        self.bodies
+            .rigid_body(rigid_body.body_index)
+            .map(|body| (body.velocity().linear.x, body.velocity().linear.y))
+    }
+
+    /// Get the angular velocity of a rigid body in radians per second.
+    pub fn angular_velocity(&self, rigid_body: &RigidBody) -> Option<N> {
+        self.bodies
+            .rigid_body(rigid_body.body_index)
+            .map(|body| body.velocity().angular)
+    }
+
+    /// Set the linear velocity of a rigid body, waking it up.
+    pub fn set_linear_velocity(&mut self, rigid_body: &RigidBody, (x, y): (N, N)) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            body.set_linear_velocity(Vector2::new(x, y));
+            body.activate();
+        }
+    }
+
+    /// Set the angular velocity of a rigid body in radians per second, waking it up.
+    pub fn set_angular_velocity(&mut self, rigid_body: &RigidBody, angular: N) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            body.set_angular_velocity(angular);
+            body.activate();
+        }
+    }
+
+    /// Move a kinematic body with a linear velocity & an angular velocity in radians per second.
+    ///
+    /// Moving it by its velocity instead of its position lets the contacts push the dynamic
+    /// bodies on it along, instead of it passing through them. Returns whether it's kinematic.
+    pub fn set_kinematic_velocity(
+        &mut self,
+        rigid_body: &RigidBody,
+        (x, y): (N, N),
+        angular: N,
+    ) -> bool {
+        match self.bodies.rigid_body_mut(rigid_body.body_index) {
+            Some(body) if body.is_kinematic() => {
+                body.set_velocity(Velocity2::new(Vector2::new(x, y), angular));
+                body.activate();
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Push a rigid body at its center of mass during the next step.
+    ///
+    /// Forces are cleared after every step, so a continuous push must be applied every step.
+    pub fn apply_force(&mut self, rigid_body: &RigidBody, force: (N, N)) {
+        self.apply(rigid_body, force, ForceType::Force);
+    }
+
+    /// Change the momentum of a rigid body at once, like for jumping or knockback.
+    pub fn apply_impulse(&mut self, rigid_body: &RigidBody, impulse: (N, N)) {
+        self.apply(rigid_body, impulse, ForceType::Impulse);
+    }
+
+    /// Apply a linear force of a type to a rigid body, waking it up.
+    fn apply(&mut self, rigid_body: &RigidBody, (x, y): (N, N), force_type: ForceType) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            body.apply_force(0, &Force2::linear(Vector2::new(x, y)), force_type, true);
+        }
+    }
+
+    /// Move a rigid body to a position, stopping it from moving.
+    pub fn set_translation(&mut self, rigid_body: &RigidBody, (x, y): (N, N)) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            let rotation = body.position().rotation.angle();
+            body.set_position(Isometry2::new(Vector2::new(x, y), rotation));
+
+            let angular = body.velocity().angular;
+            body.set_velocity(Velocity2::new(nalgebra::zero(), angular));
+        }
+    }
+
+    /// Turn a rigid body to an angle in radians, stopping it from spinning.
+    pub fn set_rotation(&mut self, rigid_body: &RigidBody, rotation: N) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            let translation = body.position().translation.vector;
+            body.set_position(Isometry2::new(translation, rotation));
+
+            let linear = body.velocity().linear;
+            body.set_velocity(Velocity2::new(linear, nalgebra::zero()));
+        }
+    }
+
+    /// The contact normal of a body pointing most against the gravity, pointing away from what
+    /// it touches, `None` when it doesn't touch anything.
+    ///
+    /// A body stands on flat ground when the normal points straight up.
+    pub fn ground_normal(&self, rigid_body: &RigidBody) -> Option<(N, N)> {
+        // A collider added since the last step doesn't touch anything yet
+        self.colliders
+            .get(rigid_body.collider_index)?
+            .graph_index()?;
+
+        let up = -self.mechanical_world.gravity.normalize();
+
+        self.geometrical_world
+            .contacts_with(&self.colliders, rigid_body.collider_index, true)?
+            .filter_map(|(first, _, _, _, _, manifold)| {
+                // The normal points from the first collider to the second
+                let normal = manifold.deepest_contact()?.contact.normal.into_inner();
+
+                Some(if first == rigid_body.collider_index {
+                    -normal
+                } else {
+                    normal
+                })
+            })
+            .max_by(|a, b| {
+                a.dot(&up)
+                    .partial_cmp(&b.dot(&up))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .map(|normal| (normal.x, normal.y))
+    }
+
+    /// The body of another collider the collider of a body touches since the last step.
+    pub fn touching(&self, rigid_body: &RigidBody) -> Option<DefaultBodyHandle> {
+        self.geometrical_world
+            .colliders_in_contact_with(&self.colliders, rigid_body.collider_index)?
+            .map(|(_, collider)| collider.body())
+            .next()
+    }
+
+    /// Stop a body where it is with its rotation, like an arrow hitting something.
+    ///
+    /// It's welded to the body it hit when that one can move, otherwise it's made static. Its
+    /// collider stops touching anything so it doesn't push the body it's stuck in. Returns the
+    /// weld, which should be removed with the body.
+    pub fn stick(&mut self, rigid_body: &RigidBody, target: DefaultBodyHandle) -> Option<Joint<N>> {
+        if let Some(collider) = self.colliders.get_mut(rigid_body.collider_index) {
+            collider.set_collision_groups(CollisionGroups::new().with_whitelist(&[]));
+        }
+
+        let target_position = self
+            .bodies
+            .rigid_body(target)
+            .filter(|body| !body.is_static())
+            .map(|body| *body.position());
+        let body = self.bodies.rigid_body_mut(rigid_body.body_index)?;
+        body.set_velocity(Velocity2::zero());
+
+        match target_position {
+            Some(target_position) => {
+                // Keep the current pose relative to the target
+                let relative = target_position.inverse() * body.position();
+                let anchors = JointAnchors::Fixed {
+                    anchor_b: Point2::from(relative.translation.vector),
+                    rotation_b: relative.rotation,
+                };
+
+                Some(self.insert_joint(
+                    BodyPartHandle(rigid_body.body_index, 0),
+                    BodyPartHandle(target, 0),
+                    anchors,
+                ))
+            }
+            None => {
+                body.set_status(BodyStatus::Static);
+
+                None
+            }
+        }
+    }
+
+    /// The bodies of which the collider contains a point in world coordinates, sensors are
+    /// skipped.
+    pub fn bodies_at(&self, (x, y): (N, N)) -> Vec<DefaultBodyHandle> {
+        self.geometrical_world
+            .interferences_with_point(
+                &self.colliders,
+                &Point2::new(x, y),
+                &CollisionGroups::new().with_membership(&[QUERY_GROUP]),
+            )
+            .filter(|(_, collider)| !collider.is_sensor())
+            .map(|(_, collider)| collider.body())
+            .collect()
+    }
+
+    /// Push the dynamic bodies with a collider within a radius of a point away from it, like a
+    /// bomb going off.
+    ///
+    /// The impulse falls off from the full strength at the center to nothing at the radius.
+    /// Returns the bodies that are hit with the part of the strength they got, sensors are
+    /// skipped.
+    pub fn explode(
+        &mut self,
+        (x, y): (N, N),
+        radius: N,
+        strength: N,
+    ) -> Vec<(DefaultBodyHandle, N)> {
+        if radius <= N::zero() {
+            return vec![];
+        }
+
+        let center = Point2::new(x, y);
+        let aabb = AABB::new(
+            Point2::new(x - radius, y - radius),
+            Point2::new(x + radius, y + radius),
+        );
+
+        // A body can have multiple colliders, the closest one counts
+        let mut hit: Vec<(DefaultBodyHandle, N)> = vec![];
+        for (_, collider) in self.geometrical_world.interferences_with_aabb(
+            &self.colliders,
+            &aabb,
+            &CollisionGroups::new().with_membership(&[QUERY_GROUP]),
+        ) {
+            if collider.is_sensor() {
+                continue;
+            }
+
+            let distance = collider.shape().as_point_query().map_or(radius, |query| {
+                query.distance_to_point(collider.position(), &center, true)
+            });
+            if distance >= radius {
+                continue;
+            }
+
+            let falloff = N::one() - distance / radius;
+            match hit.iter_mut().find(|(body, _)| *body == collider.body()) {
+                Some((_, existing)) => *existing = existing.max(falloff),
+                None => hit.push((collider.body(), falloff)),
+            }
+        }
+
+        // Bodies right at the center are blown against the gravity
+        let up = -self.mechanical_world.gravity.normalize();
+        for (handle, falloff) in hit.iter() {
+            if let Some(body) = self.bodies.rigid_body_mut(*handle) {
+                if !body.is_dynamic() {
+                    continue;
+                }
+
+                let direction = (body.position().translation.vector - center.coords)
+                    .try_normalize(N::default_epsilon())
+                    .unwrap_or(up);
+                body.apply_force(
+                    0,
+                    &Force2::linear(direction * strength * *falloff),
+                    ForceType::Impulse,
+                    true,
+                );
+            }
+        }
+
+        hit
+    }
+
+    /// The colliders with a shape of a specific kind, with their position in the world.
+    ///
+    /// Used for drawing the colliders, the shapes are cloned so they can outlive the physics.
+    pub fn debug_shapes<S: Shape<N> + Clone>(&self) -> Vec<(S, Isometry2<N>)> {
+        self.colliders
+            .iter()
+            .filter_map(|(_, collider)| {
+                collider
+                    .shape()
+                    .as_shape::<S>()
+                    .map(|shape| (shape.clone(), *collider.position()))
+            })
+            .collect()
+    }
+
+    /// The positions of the moving rigid bodies with their linear velocity.
+    ///
+    /// Used for drawing where the bodies are heading.
+    pub fn debug_velocities(&self) -> Vec<((N, N), (N, N))> {
+        self.bodies
+            .iter()
+            .filter_map(|(handle, _)| self.bodies.rigid_body(handle))
+            .filter(|body| body.velocity().linear.norm_squared() > N::zero())
+            .map(|body| {
+                let (position, velocity) =
+                    (body.position().translation.vector, body.velocity().linear);
+
+                ((position.x, position.y), (velocity.x, velocity.y))
+            })
+            .collect()
+    }
+
+    /// Change how a rigid body is moved by the simulation.
+    pub fn set_kind(&mut self, rigid_body: &RigidBody, kind: BodyKind) {
+        if let Some(body) = self.bodies.rigid_body_mut(rigid_body.body_index) {
+            body.set_status(kind.into());
+        }
+    }
+
+    /// Remove a body with its collider from the simulation.
+    pub fn remove(&mut self, rigid_body: RigidBody) {
+        self.previous.remove(&rigid_body.body_index);
+        self.colliders.remove(rigid_body.collider_index);
+        self.bodies.remove(rigid_body.body_index);
+    }
+
+    /// Connect two bodies with a hinge at a point in world coordinates, so they can only rotate
+    /// around it.
+    ///
+    /// The second body is the static world when it's `None`, like for a drawbridge.
+    pub fn add_revolute_joint(
+        &mut self,
+        a: &RigidBody,
+        b: Option<&RigidBody>,
+        anchor: (N, N),
+    ) -> Joint<N> {
+        let anchor = Point2::new(anchor.0, anchor.1);
+        let (part_a, part_b) = (self.joint_part(Some(a)), self.joint_part(b));
+
+        let anchors = JointAnchors::Revolute {
+            anchor_a: self.local_point(part_a, anchor),
+            anchor_b: self.local_point(part_b, anchor),
+        };
+
+        self.insert_joint(part_a, part_b, anchors)
+    }
+
+    /// Connect two bodies so they can only slide along an axis through a point in world
+    /// coordinates, without rotating.
+    ///
+    /// The limits are how far the second body can slide from the point, the second body is the
+    /// static world when it's `None`.
+    pub fn add_prismatic_joint(
+        &mut self,
+        a: &RigidBody,
+        b: Option<&RigidBody>,
+        anchor: (N, N),
+        axis: (N, N),
+        limits: Option<(N, N)>,
+    ) -> Joint<N> {
+        let anchor = Point2::new(anchor.0, anchor.1);
+        let (part_a, part_b) = (self.joint_part(Some(a)), self.joint_part(b));
+
+        // The axis turns with the first body
+        let axis = Vector2::new(axis.0, axis.1);
+        let axis = self
+            .bodies
+            .rigid_body(a.body_index)
+            .map_or(axis, |body| body.position().inverse_transform_vector(&axis));
+        let axis = Unit::try_new(axis, N::default_epsilon()).unwrap_or_else(Vector2::x_axis);
+
+        let anchors = JointAnchors::Prismatic {
+            anchor_a: self.local_point(part_a, anchor),
+            axis,
+            anchor_b: self.local_point(part_b, anchor),
+            limits,
+        };
+
+        self.insert_joint(part_a, part_b, anchors)
+    }
+
+    /// Keep a point on each body in world coordinates at the same distance, while both bodies can
+    /// still rotate freely.
+    ///
+    /// The second body is the static world when it's `None`, like for a swinging rope.
+    pub fn add_distance_joint(
+        &mut self,
+        a: &RigidBody,
+        b: Option<&RigidBody>,
+        anchor_a: (N, N),
+        anchor_b: (N, N),
+    ) -> Joint<N> {
+        let (anchor_a, anchor_b) = (
+            Point2::new(anchor_a.0, anchor_a.1),
+            Point2::new(anchor_b.0, anchor_b.1),
+        );
+        let (part_a, part_b) = (self.joint_part(Some(a)), self.joint_part(b));
+
+        let anchors = JointAnchors::Distance {
+            anchor_a: self.local_point(part_a, anchor_a),
+            anchor_b: self.local_point(part_b, anchor_b),
+            length: (anchor_b - anchor_a).norm(),
+        };
+
+        self.insert_joint(part_a, part_b, anchors)
+    }
+
+    /// Connect other bodies the same way as a joint, like the rebuilt bodies of a checkpoint.
+    ///
+    /// The anchors stay at the same places on the bodies, the rod of a distance joint is placed
+    /// between them again without its velocity.
+    pub fn rebuild_joint(
+        &mut self,
+        a: &RigidBody,
+        b: Option<&RigidBody>,
+        joint: &Joint<N>,
+    ) -> Joint<N> {
+        let (part_a, part_b) = (self.joint_part(Some(a)), self.joint_part(b));
+
+        self.insert_joint(part_a, part_b, joint.anchors.clone())
+    }
+
+    /// Add the constraints between two body parts.
+    fn insert_joint(
+        &mut self,
+        part_a: BodyPartHandle<DefaultBodyHandle>,
+        part_b: BodyPartHandle<DefaultBodyHandle>,
+        anchors: JointAnchors<N>,
+    ) -> Joint<N> {
+        let (constraints, link) = match anchors {
+            JointAnchors::Revolute { anchor_a, anchor_b } => {
+                let constraint = RevoluteConstraint::new(part_a, part_b, anchor_a, anchor_b);
+
+                (vec![self.joint_constraints.insert(constraint)], None)
+            }
+            JointAnchors::Prismatic {
+                anchor_a,
+                axis,
+                anchor_b,
+                limits,
+            } => {
+                let mut constraint =
+                    PrismaticConstraint::new(part_a, part_b, anchor_a, axis, anchor_b);
+                if let Some((min, max)) = limits {
+                    constraint.enable_min_offset(min);
+                    constraint.enable_max_offset(max);
+                }
+
+                (vec![self.joint_constraints.insert(constraint)], None)
+            }
+            JointAnchors::Distance {
+                anchor_a,
+                anchor_b,
+                length,
+            } => {
+                // There's no distance constraint, so both bodies are hinged to the ends of a light
+                // rod
+                let (world_a, world_b) = (
+                    self.world_point(part_a, anchor_a),
+                    self.world_point(part_b, anchor_b),
+                );
+                let delta = world_b - world_a;
+                let mass: N = f(LINK_MASS);
+                let link = RigidBodyDesc::new()
+                    .position(Isometry2::new(
+                        (world_a.coords + world_b.coords) * f::<f64, N>(0.5),
+                        delta.y.atan2(delta.x),
+                    ))
+                    .gravity_enabled(false)
+                    .mass(mass)
+                    .angular_inertia(mass * length.max(N::one()).powi(2) / f(12.0))
+                    .build();
+                let link = self.bodies.insert(link);
+                let part_link = BodyPartHandle(link, 0);
+                let half = length / f(2.0);
+
+                let to_a = RevoluteConstraint::new(
+                    part_a,
+                    part_link,
+                    anchor_a,
+                    Point2::new(-half, N::zero()),
+                );
+                let to_b = RevoluteConstraint::new(
+                    part_link,
+                    part_b,
+                    Point2::new(half, N::zero()),
+                    anchor_b,
+                );
+
+                (
+                    vec![
+                        self.joint_constraints.insert(to_a),
+                        self.joint_constraints.insert(to_b),
+                    ],
+                    Some(link),
+                )
+            }
+            JointAnchors::Fixed {
+                anchor_b,
+                rotation_b,
+            } => {
+                let constraint = FixedConstraint::new(
+                    part_a,
+                    part_b,
+                    Point2::origin(),
+                    UnitComplex::identity(),
+                    anchor_b,
+                    rotation_b,
+                );
+
+                (vec![self.joint_constraints.insert(constraint)], None)
+            }
+        };
+
+        Joint {
+            constraints,
+            link,
+            anchors,
+        }
+    }
+
+    /// Remove a joint, so the bodies move independently again.
+    ///
+    /// The joints of removed bodies don't constrain anything anymore but should still be removed.
+    pub fn remove_joint(&mut self, joint: Joint<N>) {
+        for constraint in joint.constraints {
+            self.joint_constraints.remove(constraint);
+        }
+        if let Some(link) = joint.link {
+            self.previous.remove(&link);
+            self.bodies.remove(link);
+        }
+    }
+
+    /// Accelerate all dynamic bodies in a direction in world units per second squared, like wind.
+    ///
+    /// Only the bodies with their center of mass inside the rectangle between the minimum &
+    /// maximum corner are affected when there's a zone.
+    pub fn add_constant_force(
+        &mut self,
+        (x, y): (N, N),
+        zone: Option<((N, N), (N, N))>,
+    ) -> Force<N> {
+        let params = ForceParams::Wind {
+            acceleration: Vector2::new(x, y),
+            zone: zone.map(|((min_x, min_y), (max_x, max_y))| {
+                (Point2::new(min_x, min_y), Point2::new(max_x, max_y))
+            }),
+        };
+
+        self.insert_force(None, None, params)
+    }
+
+    /// Pull two bodies towards their distance at the moment it's added with a force proportional
+    /// to how far they're pulled apart or pushed together.
+    pub fn add_spring(&mut self, a: &RigidBody, b: &RigidBody, stiffness: N) -> Force<N> {
+        let center = |rigid_body: &RigidBody| {
+            self.bodies
+                .rigid_body(rigid_body.body_index)
+                .map_or(Point2::origin(), |body| {
+                    Point2::from(body.position().translation.vector)
+                })
+        };
+        let length = nalgebra::distance(&center(a), &center(b));
+
+        self.insert_force(Some(a), Some(b), ForceParams::Spring { length, stiffness })
+    }
+
+    /// Apply a force generator of other physics to rebuilt bodies, the bodies of a spring are
+    /// pulled towards the same length as before.
+    pub fn rebuild_force(
+        &mut self,
+        a: Option<&RigidBody>,
+        b: Option<&RigidBody>,
+        force: &Force<N>,
+    ) -> Force<N> {
+        self.insert_force(a, b, force.params.clone())
+    }
+
+    /// Add the force generator, a spring without a body is attached to the static world.
+    fn insert_force(
+        &mut self,
+        a: Option<&RigidBody>,
+        b: Option<&RigidBody>,
+        params: ForceParams<N>,
+    ) -> Force<N> {
+        let generator: Box<dyn ForceGenerator<N, DefaultBodyHandle>> = match &params {
+            ForceParams::Wind { acceleration, zone } => Box::new(Wind {
+                acceleration: *acceleration,
+                zone: *zone,
+            }),
+            ForceParams::Spring { length, stiffness } => Box::new(Spring::new(
+                self.joint_part(a),
+                self.joint_part(b),
+                Point2::origin(),
+                Point2::origin(),
+                *length,
+                *stiffness,
+            )),
+        };
+
+        Force {
+            handle: self.force_generators.insert(generator),
+            params,
+        }
+    }
+
+    /// Stop applying the force of a force generator, returns whether it existed.
+    pub fn remove_force(&mut self, force: &Force<N>) -> bool {
+        self.force_generators.remove(force.handle).is_some()
+    }
+
+    /// The body part a joint is attached to, the static world when there's no rigid body.
+    fn joint_part(&mut self, rigid_body: Option<&RigidBody>) -> BodyPartHandle<DefaultBodyHandle> {
+        let handle = match (rigid_body, self.fixed) {
+            (Some(rigid_body), _) => rigid_body.body_index,
+            (None, Some(fixed)) => fixed,
+            (None, None) => {
+                let fixed = self.bodies.insert(Ground::new());
+                self.fixed = Some(fixed);
+
+                fixed
+            }
+        };
+
+        BodyPartHandle(handle, 0)
+    }
+
+    /// Convert a point in world coordinates to coordinates relative to a body part.
+    fn local_point(&self, part: BodyPartHandle<DefaultBodyHandle>, point: Point2<N>) -> Point2<N> {
+        self.bodies.rigid_body(part.0).map_or(point, |body| {
+            body.position().inverse_transform_point(&point)
+        })
+    }
+
+    /// Convert a point relative to a body part to world coordinates.
+    fn world_point(&self, part: BodyPartHandle<DefaultBodyHandle>, point: Point2<N>) -> Point2<N> {
+        self.bodies
+            .rigid_body(part.0)
+            .map_or(point, |body| body.position().transform_point(&point))
+    }
+
     /// Helps making constructing rigid bodies easier.
-    pub fn default_rigid_body_builder() -> RigidBodyDesc<N> {
+    pub fn rigid_body_builder(parameters: &BodyParameters) -> RigidBodyDesc<N> {
         RigidBodyDesc::new()
             .gravity_enabled(true)
-            .status(BodyStatus::Dynamic)
-            .linear_damping(f(0.1))
+            .status(parameters.kind.into())
+            .linear_damping(f(parameters.linear_damping))
         //.angular_damping(f(0.0))
         //.max_linear_velocity(f(200.0))
         //.max_angular_velocity(f(1.7))
@@ -108,16 +1029,226 @@ impl<N: RealField> Physics<N> {
     }
 
     /// Helps making constructing collision objects for rigid bodies easier.
-    pub fn default_collider_builder<S: Shape<N>>(shape: S) -> ColliderDesc<N> {
+    ///
+    /// The collider is an object, set the collision groups of other kinds when spawning.
+    pub fn collider_builder<S: Shape<N>>(shape: S, parameters: &BodyParameters) -> ColliderDesc<N> {
         ColliderDesc::new(ShapeHandle::new(shape))
+            .sensor(parameters.sensor)
+            .collision_groups(CollisionGroup::Object.into())
             .margin(f(0.1))
-            .density(f(0.2))
-            .material(MaterialHandle::new(BasicMaterial::new(f(0.1), f(0.5))))
+            .density(f(parameters.density))
+            .material(MaterialHandle::new(BasicMaterial::new(
+                f(parameters.restitution),
+                f(parameters.friction),
+            )))
+    }
+}
+
+impl Physics<Float> {
+    /// Hash the positions & velocities of all rigid bodies, to detect simulations going out of sync.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for state in self.save_state() {
+            let translation = state.position.translation.vector;
+            let linear = state.velocity.linear;
+
+            for value in [
+                translation.x,
+                translation.y,
+                state.position.rotation.angle(),
+                linear.x,
+                linear.y,
+                state.velocity.angular,
+            ]
+            .iter()
+            {
+                value.to_bits().hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+}
+
+/// Bodies connected by a joint, removed with `Physics::remove_joint`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joint<N: RealField> {
+    /// The constraints between the bodies.
+    constraints: Vec<DefaultJointConstraintHandle>,
+    /// The rod between the bodies of a distance joint.
+    link: Option<DefaultBodyHandle>,
+    /// Where the joint is attached, for connecting rebuilt bodies the same way.
+    anchors: JointAnchors<N>,
+}
+
+/// The points a joint is attached to, relative to the bodies.
+#[derive(Debug, Clone, PartialEq)]
+enum JointAnchors<N: RealField> {
+    /// A hinge.
+    Revolute {
+        anchor_a: Point2<N>,
+        anchor_b: Point2<N>,
+    },
+    /// A slider along an axis that turns with the first body.
+    Prismatic {
+        anchor_a: Point2<N>,
+        axis: Unit<Vector2<N>>,
+        anchor_b: Point2<N>,
+        limits: Option<(N, N)>,
+    },
+    /// A rod with a length between both anchors.
+    Distance {
+        anchor_a: Point2<N>,
+        anchor_b: Point2<N>,
+        length: N,
+    },
+    /// A weld keeping the origin of the first body at a pose relative to the second.
+    Fixed {
+        anchor_b: Point2<N>,
+        rotation_b: UnitComplex<N>,
+    },
+}
+
+/// A force generator applied every step, removed with `Physics::remove_force`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Force<N: RealField> {
+    /// The force generator in the set of the physics.
+    handle: DefaultForceGeneratorHandle,
+    /// What the force does, for applying it to rebuilt bodies the same way.
+    params: ForceParams<N>,
+}
+
+/// What a force generator does to the bodies.
+#[derive(Debug, Clone, PartialEq)]
+enum ForceParams<N: RealField> {
+    /// Wind, see `Wind`.
+    Wind {
+        acceleration: Vector2<N>,
+        zone: Option<(Point2<N>, Point2<N>)>,
+    },
+    /// A spring between two bodies with the length it pulls them towards.
+    Spring { length: N, stiffness: N },
+}
+
+/// Accelerates the dynamic bodies in a direction, optionally only inside a rectangle.
+struct Wind<N: RealField> {
+    /// The acceleration in world units per second squared.
+    acceleration: Vector2<N>,
+    /// The minimum & maximum corner of the affected area.
+    zone: Option<(Point2<N>, Point2<N>)>,
+}
+
+impl<N: RealField> ForceGenerator<N, DefaultBodyHandle> for Wind<N> {
+    fn apply(
+        &mut self,
+        _: &IntegrationParameters<N>,
+        bodies: &mut dyn BodySet<N, Handle = DefaultBodyHandle>,
+    ) {
+        let force = Force2::linear(self.acceleration);
+        let zone = self.zone;
+
+        bodies.foreach_mut(&mut |_, body| {
+            if !body.is_dynamic() {
+                return;
+            }
+
+            let inside = match (zone, body.part(0)) {
+                (None, _) => true,
+                (Some((min, max)), Some(part)) => {
+                    let center = part.center_of_mass();
+
+                    center.x >= min.x && center.x <= max.x && center.y >= min.y && center.y <= max.y
+                }
+                (Some(_), None) => false,
+            };
+            if inside {
+                body.apply_force(0, &force, ForceType::AccelerationChange, true);
+            }
+        });
     }
 }
 
 /// A rigid body component.
+#[derive(Debug, Clone)]
 pub struct RigidBody {
     body_index: DefaultBodyHandle,
     collider_index: Index,
 }
+
+impl RigidBody {
+    /// The handle of the body in the physics system.
+    pub fn handle(&self) -> DefaultBodyHandle {
+        self.body_index
+    }
+}
+
+/// The saved motion of a rigid body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    into = "StoredBody<N>",
+    from = "StoredBody<N>",
+    bound = "N: RealField + Serialize + DeserializeOwned"
+)]
+pub struct BodyState<N: RealField> {
+    handle: DefaultBodyHandle,
+    position: Isometry2<N>,
+    velocity: Velocity2<N>,
+}
+
+impl<N: RealField> BodyState<N> {
+    /// The handle of the saved body.
+    pub fn handle(&self) -> DefaultBodyHandle {
+        self.handle
+    }
+
+    /// The saved position without the rotation.
+    pub fn translation(&self) -> (N, N) {
+        let translation = self.position.translation.vector;
+
+        (translation.x, translation.y)
+    }
+}
+
+/// The motion of a rigid body as plain values, so it can be written to a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredBody<N> {
+    index: usize,
+    generation: u64,
+    x: N,
+    y: N,
+    rotation: N,
+    linear_x: N,
+    linear_y: N,
+    angular: N,
+}
+
+impl<N: RealField> From<BodyState<N>> for StoredBody<N> {
+    fn from(state: BodyState<N>) -> Self {
+        let (index, generation) = state.handle.into_raw_parts();
+        let translation = state.position.translation.vector;
+
+        Self {
+            index,
+            generation,
+            x: translation.x,
+            y: translation.y,
+            rotation: state.position.rotation.angle(),
+            linear_x: state.velocity.linear.x,
+            linear_y: state.velocity.linear.y,
+            angular: state.velocity.angular,
+        }
+    }
+}
+
+impl<N: RealField> From<StoredBody<N>> for BodyState<N> {
+    fn from(stored: StoredBody<N>) -> Self {
+        Self {
+            handle: Index::from_raw_parts(stored.index, stored.generation),
+            position: Isometry2::new(Vector2::new(stored.x, stored.y), stored.rotation),
+            velocity: Velocity2::new(
+                Vector2::new(stored.linear_x, stored.linear_y),
+                stored.angular,
+            ),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::event::Event;
+use anyhow::Result;
+use glsp::GSend;
+#[cfg(feature = "platform-log")]
+use log::info;
+use log::{debug, warn};
+use std::path::Path;
+
+/// Achievements unlocked when a wave starts, the wave number with the identifier.
+const WAVE_ACHIEVEMENTS: &[(u32, &str)] = &[(2, "survived-first-wave"), (10, "survived-ten-waves")];
+
+/// Services of the store the game is distributed through, like Steam or itch.io.
+///
+/// Gameplay code never calls this directly, the hooks are called when handling the events and
+/// after saving. All methods do nothing by default, so an integration only has to implement what
+/// the platform supports.
+///
+/// The services are used while the scripts run, so they can't hold any script values.
+pub trait PlatformServices: GSend {
+    /// Name of the platform for the log messages.
+    fn name(&self) -> &'static str;
+
+    /// Handle the callbacks of the platform, called every frame.
+    fn update(&mut self) {}
+
+    /// Unlock an achievement, unlocking it again should be ignored.
+    fn unlock_achievement(&mut self, _id: &str) {}
+
+    /// Set the value of a statistic.
+    fn set_stat(&mut self, _name: &str, _value: f64) {}
+
+    /// Synchronize the save files in a directory with the cloud storage of the platform.
+    ///
+    /// Called after saving and before loading.
+    fn sync_saves(&mut self, _directory: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Used when the game isn't started through a platform.
+#[cfg_attr(feature = "platform-log", allow(dead_code))]
+pub struct NoPlatform;
+
+impl PlatformServices for NoPlatform {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+}
+
+/// Logs every call, enabled with the `platform-log` feature to test the hooks without a store.
+#[cfg(feature = "platform-log")]
+pub struct LogPlatform;
+
+#[cfg(feature = "platform-log")]
+impl PlatformServices for LogPlatform {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn unlock_achievement(&mut self, id: &str) {
+        info!("achievement \"{}\" unlocked", id);
+    }
+
+    fn set_stat(&mut self, name: &str, value: f64) {
+        info!("stat \"{}\" set to {}", name, value);
+    }
+
+    fn sync_saves(&mut self, directory: &Path) -> Result<()> {
+        info!("synchronizing saves in \"{}\"", directory.display());
+
+        Ok(())
+    }
+}
+
+/// Calls the hooks of the platform services for the gameplay events.
+pub struct Platform {
+    /// The services of the platform selected with the features.
+    services: Box<dyn PlatformServices>,
+    /// Total damage dealt this session.
+    damage_dealt: f64,
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Platform {
+    /// Use the platform enabled with a feature, or no platform at all.
+    pub fn new() -> Self {
+        #[cfg(feature = "platform-log")]
+        let services: Box<dyn PlatformServices> = Box::new(LogPlatform);
+        #[cfg(not(feature = "platform-log"))]
+        let services: Box<dyn PlatformServices> = Box::new(NoPlatform);
+
+        debug!("using platform services \"{}\"", services.name());
+
+        Self {
+            services,
+            damage_dealt: 0.0,
+        }
+    }
+
+    /// Handle the callbacks of the platform.
+    pub fn update(&mut self) {
+        self.services.update();
+    }
+
+    /// Update the achievements & statistics for a gameplay event.
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::DamageDealt { amount } => {
+                self.damage_dealt += amount;
+                self.services.set_stat("damage-dealt", self.damage_dealt);
+            }
+            Event::WaveStarted { wave } => {
+                self.services.set_stat("highest-wave", f64::from(*wave));
+
+                for (_, id) in WAVE_ACHIEVEMENTS.iter().filter(|(min, _)| wave >= min) {
+                    self.services.unlock_achievement(id);
+                }
+            }
+        }
+    }
+
+    /// Synchronize the save files with the cloud storage, failures are only logged.
+    pub fn sync_saves(&mut self, directory: &Path) {
+        if let Err(err) = self.services.sync_saves(directory) {
+            warn!(
+                "synchronizing saves with \"{}\" failed: {}",
+                self.services.name(),
+                err
+            );
+        }
+    }
+}
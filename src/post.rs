@@ -0,0 +1,601 @@
+use crate::shader::ShaderSource;
+use anyhow::{anyhow, Result};
+use miniquad::{graphics::*, Context};
+use serde::{Deserialize, Serialize};
+
+/// The GLSL source of the vertex shader shared by all effects.
+pub const VERTEX: &str = include_str!("../assets/shaders/post.vert");
+/// The GLSL source of the fragment shader blurring the glowing paths for the bloom.
+const BLUR_FRAGMENT: &str = include_str!("../assets/shaders/blur.frag");
+
+/// Amount of colors on every axis of the color grading lookup table.
+const LUT_SIZE: usize = 16;
+/// How many times smaller the glowing paths are drawn for the bloom than the screen.
+const BLOOM_DOWNSAMPLE: u32 = 4;
+/// How many times the glowing paths are blurred horizontally & vertically.
+const BLUR_PASSES: usize = 2;
+
+/// The texture read by the shaders of the effects & their uniforms.
+fn meta() -> ShaderMeta {
+    ShaderMeta {
+        images: vec!["tex".to_string()],
+        uniforms: UniformBlockLayout {
+            uniforms: vec![
+                UniformDesc::new("u_resolution", UniformType::Float2),
+                UniformDesc::new("u_intensity", UniformType::Float1),
+            ],
+        },
+    }
+}
+
+/// The color grading shader also reads the lookup table.
+fn color_grading_meta() -> ShaderMeta {
+    ShaderMeta {
+        images: vec!["tex".to_string(), "lut".to_string()],
+        ..meta()
+    }
+}
+
+/// The bloom shader also reads the blurred glowing paths.
+fn bloom_meta() -> ShaderMeta {
+    ShaderMeta {
+        images: vec!["tex".to_string(), "bloom".to_string()],
+        ..meta()
+    }
+}
+
+/// The blur shader reads the glowing paths in one direction at a time.
+fn blur_meta() -> ShaderMeta {
+    ShaderMeta {
+        images: vec!["tex".to_string()],
+        uniforms: UniformBlockLayout {
+            uniforms: vec![
+                UniformDesc::new("u_resolution", UniformType::Float2),
+                UniformDesc::new("u_direction", UniformType::Float2),
+            ],
+        },
+    }
+}
+
+/// A full screen effect applied to the rendered frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PostEffect {
+    /// Smooth the jagged edges of the shapes.
+    Fxaa,
+    /// Darken the corners of the screen.
+    Vignette,
+    /// Split the red & blue channels towards the edges of the screen.
+    ChromaticAberration,
+    /// Map every color through a lookup table built from the color grading options.
+    ColorGrading,
+    /// Add the blurred paths marked with `glow="true"` on top, so they light up their
+    /// surroundings.
+    Bloom,
+}
+
+impl PostEffect {
+    /// Every effect, indexed like the pipelines.
+    pub const ALL: [PostEffect; 5] = [
+        PostEffect::Fxaa,
+        PostEffect::Vignette,
+        PostEffect::ChromaticAberration,
+        PostEffect::ColorGrading,
+        PostEffect::Bloom,
+    ];
+
+    /// The name used by the configuration & the scripts.
+    pub fn name(self) -> &'static str {
+        match self {
+            PostEffect::Fxaa => "fxaa",
+            PostEffect::Vignette => "vignette",
+            PostEffect::ChromaticAberration => "chromatic-aberration",
+            PostEffect::ColorGrading => "color-grading",
+            PostEffect::Bloom => "bloom",
+        }
+    }
+
+    /// Find the effect with a name, `None` when it doesn't exist.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|effect| effect.name() == name)
+    }
+
+    /// The position in `ALL`.
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The name of the fragment shader file without extension, like the reloadable shaders.
+    pub fn shader(self) -> &'static str {
+        match self {
+            PostEffect::Fxaa => "fxaa",
+            PostEffect::Vignette => "vignette",
+            PostEffect::ChromaticAberration => "chromatic_aberration",
+            PostEffect::ColorGrading => "color_grading",
+            PostEffect::Bloom => "bloom",
+        }
+    }
+
+    /// The GLSL source of the fragment shader.
+    fn fragment(self) -> &'static str {
+        match self {
+            PostEffect::Fxaa => include_str!("../assets/shaders/fxaa.frag"),
+            PostEffect::Vignette => include_str!("../assets/shaders/vignette.frag"),
+            PostEffect::ChromaticAberration => {
+                include_str!("../assets/shaders/chromatic_aberration.frag")
+            }
+            PostEffect::ColorGrading => include_str!("../assets/shaders/color_grading.frag"),
+            PostEffect::Bloom => include_str!("../assets/shaders/bloom.frag"),
+        }
+    }
+
+    /// The textures & uniforms of the shader.
+    fn meta(self) -> ShaderMeta {
+        match self {
+            PostEffect::ColorGrading => color_grading_meta(),
+            PostEffect::Bloom => bloom_meta(),
+            _ => meta(),
+        }
+    }
+}
+
+/// How the colors of the frame are changed by the color grading effect.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorGrading {
+    /// Multiplier of the distance of the colors to gray, 1.0 keeps them.
+    pub contrast: f32,
+    /// Multiplier of the distance of the colors to their brightness, 0.0 is black & white.
+    pub saturation: f32,
+    /// Shift from blue to red, between -1.0 and 1.0.
+    pub warmth: f32,
+}
+
+impl ColorGrading {
+    /// The RGBA pixels of the lookup table, a row of slices for every blue value.
+    fn lut(&self) -> Vec<u8> {
+        let max = (LUT_SIZE - 1) as f32;
+
+        let mut pixels = Vec::with_capacity(LUT_SIZE * LUT_SIZE * LUT_SIZE * 4);
+        for green in 0..LUT_SIZE {
+            for blue in 0..LUT_SIZE {
+                for red in 0..LUT_SIZE {
+                    let color = [red as f32 / max, green as f32 / max, blue as f32 / max];
+                    let [red, green, blue] = self.grade(color);
+                    let byte = |channel: f32| (channel.max(0.0).min(1.0) * 255.0).round() as u8;
+
+                    pixels.extend_from_slice(&[byte(red), byte(green), byte(blue), 255]);
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// Apply the contrast, saturation & warmth to a color.
+    fn grade(&self, [red, green, blue]: [f32; 3]) -> [f32; 3] {
+        let contrast = |channel: f32| (channel - 0.5) * self.contrast + 0.5;
+        let (red, green, blue) = (contrast(red), contrast(green), contrast(blue));
+
+        let luma = red * 0.299 + green * 0.587 + blue * 0.114;
+        let saturation = |channel: f32| luma + (channel - luma) * self.saturation;
+        let (red, green, blue) = (saturation(red), saturation(green), saturation(blue));
+
+        let warmth = self.warmth.max(-1.0).min(1.0) * 0.1;
+
+        [red + warmth, green, blue - warmth]
+    }
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            contrast: 1.0,
+            saturation: 1.0,
+            warmth: 0.0,
+        }
+    }
+}
+
+/// Which post-processing effects are applied in what order, and their options.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostConfig {
+    /// The enabled effects in the order they are applied, like `["fxaa", "vignette"]`.
+    pub effects: Vec<PostEffect>,
+    /// How much the corners are darkened, between 0.0 and 1.0.
+    pub vignette: f32,
+    /// How far the color channels are split at the edges in pixels.
+    pub chromatic_aberration: f32,
+    /// Multiplier of the brightness of the glowing paths added by the bloom.
+    pub bloom: f32,
+    /// The colors of the color grading effect, a table so it must come after the other options.
+    pub color_grading: ColorGrading,
+}
+
+impl Default for PostConfig {
+    fn default() -> Self {
+        Self {
+            effects: vec![],
+            vignette: 0.5,
+            chromatic_aberration: 2.0,
+            bloom: 1.0,
+            color_grading: ColorGrading::default(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Uniforms {
+    resolution: [f32; 2],
+    intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct BlurUniforms {
+    resolution: [f32; 2],
+    direction: [f32; 2],
+}
+
+/// The textures the effects read from & draw to, for the size of the screen.
+#[derive(Debug, Copy, Clone)]
+struct Targets {
+    /// The width & height in pixels.
+    size: (u32, u32),
+    /// The frame copied from the default framebuffer, read by the first effect.
+    frame: Texture,
+    /// The passes the effects in between alternate drawing to.
+    passes: [RenderPass; 2],
+    /// The smaller passes the glowing paths are drawn into & blurred between.
+    bloom: [RenderPass; 2],
+}
+
+/// A chain of full screen effects applied to the rendered frame, in a configurable order.
+///
+/// The world & screen space layers are still drawn into the default framebuffer, which has the
+/// multisampling & the stencil buffer of the clip paths that miniquad render targets lack. The
+/// frame is copied into a texture, every effect draws into the next target and the last one draws
+/// back to the screen. Nothing is copied when no effect is enabled.
+#[derive(Debug)]
+pub struct PostProcess {
+    /// The enabled effects in the order they are applied.
+    effects: Vec<PostEffect>,
+    /// The pipeline of every effect, indexed like `PostEffect::ALL`.
+    pipelines: Vec<Pipeline>,
+    /// The pipeline blurring the glowing paths for the bloom.
+    blur: Pipeline,
+    /// The quad covering the screen, its images are replaced by the textures an effect reads.
+    quad: Bindings,
+    /// The intermediate textures, created by the first frame with an effect and again when the
+    /// size of the screen changes.
+    targets: Option<Targets>,
+    /// The color grading lookup table, `None` until it's created by the next frame.
+    lut: Option<Texture>,
+    /// The options of the effects.
+    config: PostConfig,
+}
+
+impl PostProcess {
+    /// Compile the shaders of all effects, none of them are enabled.
+    pub fn new(ctx: &mut Context) -> Result<Self> {
+        let pipelines = PostEffect::ALL
+            .iter()
+            .map(|effect| {
+                create_pipeline(ctx, effect.name(), VERTEX, effect.fragment(), effect.meta())
+            })
+            .collect::<Result<_>>()?;
+        let blur = create_pipeline(ctx, "blur", VERTEX, BLUR_FRAGMENT, blur_meta())?;
+
+        #[rustfmt::skip]
+        let vertices: [f32; 8] = [
+            -1.0, -1.0,
+            1.0, -1.0,
+            1.0, 1.0,
+            -1.0, 1.0,
+        ];
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+        let quad = Bindings {
+            vertex_buffers: vec![Buffer::immutable(ctx, BufferType::VertexBuffer, &vertices)],
+            index_buffer: Buffer::immutable(ctx, BufferType::IndexBuffer, &indices),
+            images: vec![],
+        };
+
+        Ok(Self {
+            effects: vec![],
+            pipelines,
+            blur,
+            quad,
+            targets: None,
+            lut: None,
+            config: PostConfig::default(),
+        })
+    }
+
+    /// Apply the order & options of the effects.
+    pub fn configure(&mut self, config: &PostConfig) {
+        self.set_effects(&config.effects);
+        self.set_color_grading(config.color_grading);
+        self.config = config.clone();
+    }
+
+    /// The enabled effects in the order they are applied.
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Enable only these effects, applied in the order of the slice.
+    ///
+    /// An effect that's listed more than once is only applied at its first position.
+    pub fn set_effects(&mut self, effects: &[PostEffect]) {
+        self.effects.clear();
+        for effect in effects {
+            if !self.effects.contains(effect) {
+                self.effects.push(*effect);
+            }
+        }
+    }
+
+    /// Enable or disable an effect, an effect that's enabled is applied after the others.
+    pub fn set_enabled(&mut self, effect: PostEffect, enabled: bool) {
+        self.effects.retain(|other| *other != effect);
+        if enabled {
+            self.effects.push(effect);
+        }
+    }
+
+    /// Change the colors of the color grading effect, the lookup table is created again by the
+    /// next frame.
+    pub fn set_color_grading(&mut self, grading: ColorGrading) {
+        self.config.color_grading = grading;
+
+        if let Some(lut) = self.lut.take() {
+            lut.delete();
+        }
+    }
+
+    /// Replace the sources of the shader of an effect or the blur of the bloom, keeping the old
+    /// pipeline when they don't compile.
+    pub fn reload_shader(&mut self, ctx: &mut Context, source: &ShaderSource) -> Result<()> {
+        if source.name == "blur" {
+            self.blur =
+                create_pipeline(ctx, "blur", &source.vertex, &source.fragment, blur_meta())?;
+
+            return Ok(());
+        }
+
+        let effect = PostEffect::ALL
+            .iter()
+            .copied()
+            .find(|effect| effect.shader() == source.name)
+            .ok_or_else(|| anyhow!("Unknown shader \"{}\"", source.name))?;
+        self.pipelines[effect.index()] = create_pipeline(
+            ctx,
+            effect.name(),
+            &source.vertex,
+            &source.fragment,
+            effect.meta(),
+        )?;
+
+        Ok(())
+    }
+
+    /// The pass the glowing paths should be drawn into for the bloom of this frame, `None` when
+    /// it's disabled.
+    ///
+    /// The pass is smaller than the screen and isn't cleared.
+    pub fn bloom_pass(&mut self, ctx: &mut Context) -> Option<RenderPass> {
+        if !self.effects.contains(&PostEffect::Bloom) {
+            return None;
+        }
+
+        self.screen_targets(ctx).map(|targets| targets.bloom[0])
+    }
+
+    /// Run the enabled effects over the frame in the default framebuffer.
+    pub fn apply(&mut self, ctx: &mut Context) {
+        if self.effects.is_empty() {
+            return;
+        }
+        let targets = match self.screen_targets(ctx) {
+            Some(targets) => targets,
+            None => return,
+        };
+        let (width, height) = (targets.size.0 as f32, targets.size.1 as f32);
+        let lut = self.lut(ctx);
+
+        copy_frame(ctx, &targets.frame);
+
+        let mut source = targets.frame;
+        for (index, effect) in self.effects.iter().enumerate() {
+            // The last effect draws to the screen, the others to the texture the next one reads
+            let pass = if index + 1 == self.effects.len() {
+                None
+            } else {
+                Some(targets.passes[index % 2])
+            };
+
+            // The glowing paths are blurred before they're added
+            if *effect == PostEffect::Bloom {
+                self.blur(ctx, &targets);
+            }
+
+            ctx.begin_pass(pass, PassAction::Nothing);
+
+            let mut bindings = self.quad.clone();
+            bindings.images = match effect {
+                PostEffect::ColorGrading => vec![source, lut],
+                PostEffect::Bloom => vec![source, targets.bloom[0].texture(ctx)],
+                _ => vec![source],
+            };
+            let intensity = match effect {
+                PostEffect::Vignette => self.config.vignette,
+                PostEffect::ChromaticAberration => self.config.chromatic_aberration,
+                PostEffect::Bloom => self.config.bloom,
+                PostEffect::Fxaa | PostEffect::ColorGrading => 1.0,
+            };
+
+            ctx.apply_pipeline(&self.pipelines[effect.index()]);
+            ctx.apply_bindings(&bindings);
+            ctx.apply_uniforms(&Uniforms {
+                resolution: [width, height],
+                intensity,
+            });
+            ctx.draw(0, 6, 1);
+
+            ctx.end_render_pass();
+
+            if let Some(pass) = pass {
+                source = pass.texture(ctx);
+            }
+        }
+    }
+
+    /// Blur the glowing paths in the first bloom pass horizontally & vertically, the result ends
+    /// up in the same pass.
+    fn blur(&self, ctx: &mut Context, targets: &Targets) {
+        let texture = targets.bloom[0].texture(ctx);
+        let resolution = [texture.width as f32, texture.height as f32];
+
+        for _ in 0..BLUR_PASSES {
+            for (index, direction) in [[1.0, 0.0], [0.0, 1.0]].iter().enumerate() {
+                // Read from one pass & draw into the other one
+                let (from, to) = (targets.bloom[index], targets.bloom[1 - index]);
+
+                ctx.begin_pass(to, PassAction::Nothing);
+
+                let mut bindings = self.quad.clone();
+                bindings.images = vec![from.texture(ctx)];
+
+                ctx.apply_pipeline(&self.blur);
+                ctx.apply_bindings(&bindings);
+                ctx.apply_uniforms(&BlurUniforms {
+                    resolution,
+                    direction: *direction,
+                });
+                ctx.draw(0, 6, 1);
+
+                ctx.end_render_pass();
+            }
+        }
+    }
+
+    /// The intermediate textures for the size of the screen, replacing the old ones when it
+    /// changed.
+    ///
+    /// Returns `None` while the window has no size.
+    fn screen_targets(&mut self, ctx: &mut Context) -> Option<Targets> {
+        let (width, height) = ctx.screen_size();
+        let size = (width as u32, height as u32);
+        if size.0 == 0 || size.1 == 0 {
+            return None;
+        }
+
+        match self.targets {
+            Some(targets) if targets.size == size => return Some(targets),
+            Some(targets) => {
+                targets.frame.delete();
+                for pass in targets.passes.iter().chain(targets.bloom.iter()) {
+                    pass.delete(ctx);
+                }
+            }
+            None => (),
+        }
+
+        let texture = |ctx: &mut Context, format, (width, height)| {
+            Texture::new_render_texture(
+                ctx,
+                TextureParams {
+                    format,
+                    width,
+                    height,
+                    ..Default::default()
+                },
+            )
+        };
+        // The default framebuffer doesn't always have an alpha channel to copy
+        let frame = texture(ctx, TextureFormat::RGB8, size);
+        let pass = |ctx: &mut Context, size| {
+            let texture = texture(ctx, TextureFormat::RGBA8, size);
+
+            RenderPass::new(ctx, texture, None)
+        };
+        let passes = [pass(ctx, size), pass(ctx, size)];
+        let bloom_size = (
+            (size.0 / BLOOM_DOWNSAMPLE).max(1),
+            (size.1 / BLOOM_DOWNSAMPLE).max(1),
+        );
+        let bloom = [pass(ctx, bloom_size), pass(ctx, bloom_size)];
+
+        let targets = Targets {
+            size,
+            frame,
+            passes,
+            bloom,
+        };
+        self.targets = Some(targets);
+
+        Some(targets)
+    }
+
+    /// The color grading lookup table, created when it's missing.
+    fn lut(&mut self, ctx: &mut Context) -> Texture {
+        let grading = self.config.color_grading;
+
+        *self.lut.get_or_insert_with(|| {
+            Texture::from_rgba8(
+                ctx,
+                (LUT_SIZE * LUT_SIZE) as u16,
+                LUT_SIZE as u16,
+                &grading.lut(),
+            )
+        })
+    }
+}
+
+/// Compile the shader of an effect, all effects draw the same quad.
+fn create_pipeline(
+    ctx: &mut Context,
+    name: &str,
+    vertex: &str,
+    fragment: &str,
+    meta: ShaderMeta,
+) -> Result<Pipeline> {
+    let shader = Shader::new(ctx, vertex, fragment, meta)
+        .map_err(|err| anyhow!("Building {} shader failed: {}", name, err))?;
+
+    Ok(Pipeline::new(
+        ctx,
+        &[BufferLayout::default()],
+        &[VertexAttribute::new("a_pos", VertexFormat::Float2)],
+        shader,
+    ))
+}
+
+/// Copy the pixels of the default framebuffer into a texture of the same size.
+fn copy_frame(ctx: &mut Context, texture: &Texture) {
+    // miniquad remembers which textures are bound, forget them so binding one directly doesn't
+    // leave it with a wrong idea of the state
+    ctx.commit_frame();
+
+    // Safe because the texture is alive and the default framebuffer is bound after a pass ended
+    unsafe {
+        miniquad::gl::glActiveTexture(miniquad::gl::GL_TEXTURE0);
+        miniquad::gl::glBindTexture(miniquad::gl::GL_TEXTURE_2D, texture.gl_internal_id());
+        miniquad::gl::glCopyTexImage2D(
+            miniquad::gl::GL_TEXTURE_2D,
+            0,
+            miniquad::gl::GL_RGB,
+            0,
+            0,
+            texture.width as i32,
+            texture.height as i32,
+            0,
+        );
+        miniquad::gl::glBindTexture(miniquad::gl::GL_TEXTURE_2D, 0);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::{
+    render::{Instance, Mesh, MeshMemory, Render, RenderTimings},
+    text::FontInstance,
+};
+use log::warn;
+use std::{cmp::Ordering, collections::VecDeque};
+use usvg::Color;
+
+/// Amount of frames shown in the graph.
+const WINDOW: usize = 120;
+/// Horizontal space a single frame takes in the graph in pixels.
+const FRAME_WIDTH: f32 = 3.0;
+/// Height of the graph in pixels.
+const GRAPH_HEIGHT: f32 = 80.0;
+/// The frame time at the top of the graph in seconds.
+const GRAPH_MAX: f64 = 0.05;
+/// Size of the dot marking a frame in pixels.
+const DOT_SIZE: f32 = 2.0;
+/// Amount of stages measured.
+const STAGE_COUNT: usize = 9;
+/// Frame time budgets drawn as horizontal lines in seconds.
+const BUDGETS: [f64; 2] = [1.0 / 60.0, 1.0 / 30.0];
+/// Amount of assets using the most memory shown.
+const MEMORY_ASSETS: usize = 3;
+/// Bytes in a megabyte.
+const MEGABYTE: usize = 1024 * 1024;
+
+/// Height of a single line of text in pixels.
+const LINE_HEIGHT: f32 = 16.0;
+/// Space between the border of the panel and the contents in pixels.
+const PADDING: f32 = 8.0;
+/// Space between the panel and the border of the screen in pixels.
+const MARGIN: f32 = 16.0;
+/// Scale of the glyphs.
+const TEXT_SCALE: f32 = 0.12;
+
+/// The z index of the background.
+const BACKGROUND_Z: u8 = 210;
+/// The z index of the graph and the text.
+const FOREGROUND_Z: u8 = 211;
+
+/// A part of the frame that's measured separately.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Handling the window events.
+    Input,
+    /// Running the GameLisp functions.
+    Script,
+    /// Stepping the physics.
+    Physics,
+    /// Passing the gameplay events to the systems.
+    Sync,
+    /// Queueing the instances.
+    Render,
+    /// Tessellating paths & creating meshes, part of the stage that needed the mesh.
+    Meshes,
+    /// Creating bindings & uploading the instances to the GPU.
+    Upload,
+    /// Issuing the draw calls & presenting the frame.
+    Submit,
+    /// Applying the full screen effects to the rendered frame.
+    Post,
+}
+
+impl Stage {
+    /// All stages in the order they are shown.
+    pub const ALL: [Stage; STAGE_COUNT] = [
+        Stage::Input,
+        Stage::Script,
+        Stage::Physics,
+        Stage::Sync,
+        Stage::Render,
+        Stage::Meshes,
+        Stage::Upload,
+        Stage::Submit,
+        Stage::Post,
+    ];
+
+    /// The label shown in the overlay.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Input => "input",
+            Stage::Script => "script",
+            Stage::Physics => "physics",
+            Stage::Sync => "sync",
+            Stage::Render => "render",
+            Stage::Meshes => "meshes",
+            Stage::Upload => "upload",
+            Stage::Submit => "submit",
+            Stage::Post => "post",
+        }
+    }
+
+    /// The index in the timings of a frame.
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The measured durations of a single frame in seconds.
+#[derive(Debug, Default, Copy, Clone)]
+struct FrameTimings {
+    /// Time between the start of this frame and the next one.
+    total: f64,
+    /// Time spent in every stage.
+    stages: [f64; STAGE_COUNT],
+}
+
+/// Measures the time spent in the stages of the frames, optionally shown as an overlay.
+pub struct Profiler {
+    /// The timings of the most recent frames, the newest at the back.
+    frames: VecDeque<FrameTimings>,
+    /// The timings of the frame being measured.
+    current: FrameTimings,
+    /// The time the frame being measured started in seconds.
+    frame_start: f64,
+    /// Whether the overlay is drawn.
+    visible: bool,
+    /// Mesh for the background of the overlay.
+    background_mesh: Mesh,
+    /// Mesh for the dot of a single frame.
+    dot_mesh: Mesh,
+    /// Mesh for the horizontal budget lines.
+    budget_mesh: Mesh,
+    /// Mesh for the vertical line marking the worst frame.
+    marker_mesh: Mesh,
+    /// The GPU memory used by every asset, the largest first.
+    memory: Vec<(&'static str, MeshMemory)>,
+    /// Bytes of GPU memory that can be used before warning, 0 for no limit.
+    memory_budget: usize,
+    /// Whether exceeding the memory budget is already reported.
+    over_budget: bool,
+    /// Amount of world space instances drawn & culled by the last render.
+    culling: (usize, usize),
+}
+
+impl Profiler {
+    /// Upload the meshes for the overlay, the memory budget is in megabytes.
+    pub fn new(render: &mut Render, visible: bool, memory_budget: u32) -> Self {
+        let graph_width = WINDOW as f32 * FRAME_WIDTH;
+
+        Self {
+            frames: VecDeque::with_capacity(WINDOW),
+            current: FrameTimings::default(),
+            frame_start: miniquad::date::now(),
+            visible,
+            background_mesh: render.upload_rectangle(
+                graph_width + PADDING * 2.0,
+                Self::height(),
+                Color::black(),
+                0.7,
+            ),
+            dot_mesh: render.upload_rectangle(DOT_SIZE, DOT_SIZE, Color::white(), 1.0),
+            budget_mesh: render.upload_rectangle(
+                graph_width,
+                1.0,
+                Color::new(0x80, 0x80, 0x80),
+                1.0,
+            ),
+            marker_mesh: render.upload_rectangle(
+                1.0,
+                GRAPH_HEIGHT,
+                Color::new(0xff, 0x40, 0x40),
+                1.0,
+            ),
+            memory: vec![],
+            memory_budget: memory_budget as usize * MEGABYTE,
+            over_budget: false,
+            culling: (0, 0),
+        }
+    }
+
+    /// Show or hide the overlay.
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Finish measuring the current frame and start measuring the next one.
+    ///
+    /// Should be called at the start of every frame.
+    pub fn next_frame(&mut self) {
+        let now = miniquad::date::now();
+        self.current.total = now - self.frame_start;
+        self.frame_start = now;
+
+        self.frames.push_back(self.current);
+        while self.frames.len() > WINDOW {
+            self.frames.pop_front();
+        }
+
+        self.current = FrameTimings::default();
+    }
+
+    /// Add the time since the start, retrieved with `miniquad::date::now()`, to a stage.
+    pub fn add(&mut self, stage: Stage, start: f64) {
+        self.current.stages[stage.index()] += miniquad::date::now() - start;
+    }
+
+    /// Add a time in seconds that was measured elsewhere to a stage.
+    pub fn add_time(&mut self, stage: Stage, seconds: f64) {
+        self.current.stages[stage.index()] += seconds;
+    }
+
+    /// Take the time the renderer spent, measure the GPU memory used by the meshes, warning when
+    /// it exceeds the budget, and count the instances that were culled.
+    ///
+    /// Should be called every frame after rendering.
+    pub fn update_render(&mut self, render: &mut Render) {
+        let RenderTimings {
+            meshes,
+            upload,
+            submit,
+            post,
+        } = render.take_timings();
+        self.current.stages[Stage::Meshes.index()] += meshes;
+        self.current.stages[Stage::Upload.index()] += upload;
+        self.current.stages[Stage::Submit.index()] += submit;
+        self.current.stages[Stage::Post.index()] += post;
+
+        self.memory = render.memory_usage();
+        self.culling = render.culling();
+
+        let total = self.memory_total().total();
+        if self.memory_budget == 0 || total <= self.memory_budget {
+            self.over_budget = false;
+        } else if !self.over_budget {
+            warn!(
+                "meshes use {:.1} MB of GPU memory, exceeding the budget of {:.1} MB",
+                megabytes(total),
+                megabytes(self.memory_budget)
+            );
+            for (label, memory) in self.memory.iter().take(MEMORY_ASSETS) {
+                warn!("{} uses {:.1} MB", label, megabytes(memory.total()));
+            }
+
+            self.over_budget = true;
+        }
+    }
+
+    /// Queue the screen space instances in the bottom right corner for the next frame.
+    pub fn draw(&self, ui_size: (f32, f32), font: &mut FontInstance, render: &mut Render) {
+        if !self.visible || self.frames.is_empty() {
+            return;
+        }
+
+        let graph_width = WINDOW as f32 * FRAME_WIDTH;
+        let x = ui_size.0 - graph_width - PADDING * 2.0 - MARGIN;
+        let y = ui_size.1 - Self::height() - MARGIN;
+
+        let mut background = Instance::new(x, y);
+        background.set_z(BACKGROUND_Z);
+        render.draw_screen(self.background_mesh, background);
+
+        // Draw the graph at the top of the panel
+        let graph_x = x + PADDING;
+        let graph_bottom = y + PADDING + GRAPH_HEIGHT;
+        let graph_y = |time: f64| graph_bottom - (time / GRAPH_MAX).min(1.0) as f32 * GRAPH_HEIGHT;
+
+        for budget in BUDGETS.iter() {
+            let mut instance = Instance::new(graph_x, graph_y(*budget));
+            instance.set_z(FOREGROUND_Z);
+            render.draw_screen(self.budget_mesh, instance);
+        }
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            let mut instance = Instance::new(
+                graph_x + index as f32 * FRAME_WIDTH,
+                graph_y(frame.total) - DOT_SIZE / 2.0,
+            );
+            instance.set_z(FOREGROUND_Z);
+            let color = budget_color(frame.total);
+            instance.set_color_multiplier(color.0, color.1, color.2);
+            render.draw_screen(self.dot_mesh, instance);
+        }
+
+        // Mark the slowest frame in the window
+        let (worst_index, worst) = self
+            .frames
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total.partial_cmp(&b.total).unwrap_or(Ordering::Equal))
+            .map_or((0, FrameTimings::default()), |(index, frame)| {
+                (index, *frame)
+            });
+        let mut marker = Instance::new(
+            graph_x + worst_index as f32 * FRAME_WIDTH,
+            graph_bottom - GRAPH_HEIGHT,
+        );
+        marker.set_z(FOREGROUND_Z);
+        render.draw_screen(self.marker_mesh, marker);
+
+        // Show the totals below the graph
+        let average =
+            self.frames.iter().map(|frame| frame.total).sum::<f64>() / self.frames.len() as f64;
+        let mut lines = vec![format!(
+            "{:.0} fps   frame {:.2} ms   worst {:.2} ms",
+            1.0 / average.max(std::f64::EPSILON),
+            average * 1000.0,
+            worst.total * 1000.0
+        )];
+
+        // Show the average & maximum of every stage
+        for stage in Stage::ALL.iter() {
+            let times = self.frames.iter().map(|frame| frame.stages[stage.index()]);
+            let average = times.clone().sum::<f64>() / self.frames.len() as f64;
+            let max = times.fold(0.0, f64::max);
+
+            lines.push(format!(
+                "{}   {:.2} ms   max {:.2} ms   worst frame {:.2} ms",
+                stage.name(),
+                average * 1000.0,
+                max * 1000.0,
+                worst.stages[stage.index()] * 1000.0
+            ));
+        }
+
+        // Show how many instances are outside of the view of the camera
+        let (drawn, culled) = self.culling;
+        lines.push(format!("instances drawn {}   culled {}", drawn, culled));
+
+        // Show the memory of all meshes and the assets using the most
+        let total = self.memory_total();
+        lines.push(format!(
+            "gpu {:.1} MB{}   vertices {:.1} MB   indices {:.1} MB   instances {:.1} MB",
+            megabytes(total.total()),
+            if self.over_budget { " over budget" } else { "" },
+            megabytes(total.vertices),
+            megabytes(total.indices),
+            megabytes(total.instances)
+        ));
+        for (label, memory) in self.memory.iter().take(MEMORY_ASSETS) {
+            lines.push(format!(
+                "   {}   {:.1} MB",
+                label,
+                megabytes(memory.total())
+            ));
+        }
+
+        for (index, line) in lines.iter().enumerate() {
+            let line_bottom = graph_bottom + PADDING + (index + 1) as f32 * LINE_HEIGHT - 4.0;
+
+            for (mut instance, mesh) in
+                font.text_scaled(render, line, graph_x, line_bottom, TEXT_SCALE)
+            {
+                instance.set_z(FOREGROUND_Z);
+                render.draw_screen(mesh, instance);
+            }
+        }
+    }
+
+    /// The memory of all assets combined.
+    fn memory_total(&self) -> MeshMemory {
+        let mut total = MeshMemory::default();
+        for (_, memory) in self.memory.iter() {
+            total += *memory;
+        }
+
+        total
+    }
+
+    /// The height of the overlay in pixels.
+    fn height() -> f32 {
+        GRAPH_HEIGHT + PADDING * 3.0 + (STAGE_COUNT + 3 + MEMORY_ASSETS) as f32 * LINE_HEIGHT
+    }
+}
+
+/// The color multiplier for a frame time compared to the budgets.
+fn budget_color(time: f64) -> (f32, f32, f32) {
+    if time <= BUDGETS[0] {
+        (0.3, 1.0, 0.3)
+    } else if time <= BUDGETS[1] {
+        (1.0, 0.85, 0.3)
+    } else {
+        (1.0, 0.3, 0.3)
+    }
+}
+
+/// Convert bytes to megabytes.
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / MEGABYTE as f64
+}
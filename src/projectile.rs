@@ -0,0 +1,147 @@
+use crate::{
+    object::ObjectDef,
+    physics::{CollisionGroup, Joint, Physics, RigidBody},
+    render::{Instance, Mesh},
+    unit::Allegiance,
+    Float, Vec2,
+};
+use nalgebra::RealField;
+use nphysics2d::{algebra::Velocity2, object::DefaultBodyHandle};
+use std::collections::HashMap;
+
+/// Seconds before a projectile that didn't hit anything is removed.
+const TIME_TO_LIVE: Float = 5.0;
+/// Seconds a projectile that sticks stays in what it hit.
+const STUCK_TIME: Float = 10.0;
+
+/// A flying object that's removed when it hits something, like an arrow.
+///
+/// When its definition sticks it stays in what it hit for a while instead.
+#[derive(Debug, Clone)]
+pub struct Projectile {
+    /// The mesh the instance belongs to.
+    pub mesh: Mesh,
+    /// The rendered mesh instance, turned towards where it's flying.
+    pub instance: Instance,
+    /// The physics body.
+    pub rigid_body: RigidBody,
+    /// The side that shot it, it passes through the units of that side.
+    pub allegiance: Option<Allegiance>,
+    /// Seconds left before it's removed.
+    time_left: Float,
+    /// Whether it sticks into what it hits.
+    sticks: bool,
+    /// The body it's stuck in.
+    stuck_to: Option<DefaultBodyHandle>,
+    /// The weld to the body it's stuck in when that body can move.
+    weld: Option<Joint<Float>>,
+}
+
+impl Projectile {
+    /// Launch a projectile from a position with a velocity in world units per second.
+    ///
+    /// It passes through the units of the side that shot it.
+    pub fn fire(
+        def: &mut ObjectDef,
+        physics: &mut Physics<Float>,
+        pos: Vec2,
+        velocity: Vec2,
+        allegiance: Option<Allegiance>,
+    ) -> Self {
+        let rotation = rotation_along(velocity.x, velocity.y);
+
+        // The description of the definition is changed in place like when spawning an object
+        let rigid_body = physics.spawn_rigid_body(
+            def.rigid_body
+                .set_translation(pos)
+                .set_rotation(rotation)
+                .set_velocity(Velocity2::linear(velocity.x, velocity.y)),
+            def.collider
+                .set_collision_groups(CollisionGroup::Projectile(allegiance).into()),
+        );
+
+        let mut instance = Instance::new(pos.x as f32, pos.y as f32);
+        instance.set_layer(def.layer, 0);
+        instance.set_rotation(rotation as f32);
+
+        Self {
+            mesh: def.mesh(),
+            instance,
+            rigid_body,
+            allegiance,
+            time_left: TIME_TO_LIVE,
+            sticks: def.sticks,
+            stuck_to: None,
+            weld: None,
+        }
+    }
+
+    /// The body it's stuck in, if it hit something and sticks.
+    pub fn stuck_to(&self) -> Option<DefaultBodyHandle> {
+        self.stuck_to
+    }
+
+    /// Turn the projectile along its velocity after a physics step.
+    ///
+    /// Returns `false` when it hit something it doesn't stick in or when its time is up, and it
+    /// should be removed.
+    pub fn update(&mut self, physics: &mut Physics<Float>, delta: Float) -> bool {
+        self.time_left -= delta;
+        if self.time_left <= 0.0 {
+            return false;
+        }
+        if self.stuck_to.is_some() {
+            // Keep the rotation it had when it hit
+            return true;
+        }
+
+        if let Some(target) = physics.touching(&self.rigid_body) {
+            if !self.sticks {
+                return false;
+            }
+
+            self.weld = physics.stick(&self.rigid_body, target);
+            self.stuck_to = Some(target);
+            self.time_left = STUCK_TIME;
+
+            return true;
+        }
+
+        if let Some((x, y)) = physics.linear_velocity(&self.rigid_body) {
+            let rotation = rotation_along(x, y);
+            physics.set_rotation(&self.rigid_body, rotation);
+            self.instance.set_rotation(rotation as f32);
+        }
+
+        true
+    }
+
+    /// Stick in the body it was stuck in again after the physics are rebuilt, with the new
+    /// handles of the rebuilt bodies by their old ones.
+    pub fn rebuild_weld(
+        &mut self,
+        physics: &mut Physics<Float>,
+        rebuilt: &HashMap<DefaultBodyHandle, DefaultBodyHandle>,
+    ) {
+        self.stuck_to = self
+            .stuck_to
+            .and_then(|target| rebuilt.get(&target).copied());
+        self.weld = match self.stuck_to {
+            Some(target) => physics.stick(&self.rigid_body, target),
+            None => None,
+        };
+    }
+
+    /// Remove the body and the weld holding it.
+    pub fn remove(self, physics: &mut Physics<Float>) {
+        if let Some(weld) = self.weld {
+            physics.remove_joint(weld);
+        }
+        physics.remove(self.rigid_body);
+    }
+}
+
+/// The rotation of a mesh pointing up to make it point along a vector.
+fn rotation_along(x: Float, y: Float) -> Float {
+    y.atan2(x) + Float::frac_pi_2()
+}
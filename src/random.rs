@@ -0,0 +1,101 @@
+use glsp::{lib, rfn, Runtime};
+
+lib! {
+/// Random numbers for the scripts from a seed, so a replay or a lockstep session started with the
+/// same seed gets the same numbers.
+pub struct Random {
+    /// The seed the numbers were last started from.
+    seed: u64,
+    /// The state of the xorshift generator, never zero.
+    state: u64,
+}
+}
+
+impl Random {
+    /// Start generating from a seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: scramble(seed),
+        }
+    }
+
+    /// Start generating from a seed again.
+    pub fn set_seed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    /// The seed the numbers were last started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The state of the generator, saved for seeking in replays.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Continue from a previously saved state.
+    pub fn restore(&mut self, state: u64) {
+        self.state = if state == 0 { scramble(0) } else { state };
+    }
+
+    /// The next 64 random bits, with xorshift64*.
+    fn next_bits(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// GameLisp function returning a random number from 0.0 up to 1.0.
+    fn random(&mut self) -> f64 {
+        // The upper 53 bits fit exactly in the mantissa
+        (self.next_bits() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// GameLisp function returning a random number from a minimum up to a maximum.
+    fn random_range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.random()
+    }
+
+    /// GameLisp function returning a random integer from a minimum up to a maximum, the maximum
+    /// itself is never returned.
+    fn random_int(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+
+        let range = (i64::from(max) - i64::from(min)) as u64;
+        (i64::from(min) + (self.next_bits() % range) as i64) as i32
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("random", rfn!(Self::random))?;
+            glsp::bind_rfn("random-range", rfn!(Self::random_range))?;
+            glsp::bind_rfn("random-int", rfn!(Self::random_int))?;
+            glsp::bind_rfn("random-seed", rfn!(Self::seed))?;
+            glsp::bind_rfn("set-random-seed", rfn!(Self::set_seed))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Spread the bits of a seed with splitmix64, so similar seeds give different numbers and zero
+/// doesn't get xorshift stuck.
+fn scramble(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+
+    if z == 0 {
+        0x9e37_79b9_7f4a_7c15
+    } else {
+        z
+    }
+}
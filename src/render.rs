@@ -1,243 +1,1987 @@
-use anyhow::Result;
-use glsp::{bail, lib, rdata, rdata_impls, rfn, GResult, Runtime};
+use crate::{
+    camera::Camera,
+    gradient::Gradient,
+    post::{ColorGrading, PostConfig, PostEffect, PostProcess},
+    shader::ShaderSource,
+    texture::Image,
+};
+use anyhow::{anyhow, Result};
+use generational_arena::{Arena, Index};
+use glsp::{bail, lib, rdata, rdata_impls, rfn, GResult, Runtime, Sym};
+use log::warn;
 use lyon::{
     math::Point,
-    path::PathEvent,
+    path::{Path, PathEvent},
     tessellation::{
         geometry_builder::{FillVertexConstructor, StrokeVertexConstructor},
         BuffersBuilder, FillAttributes, FillOptions, FillTessellator, StrokeAttributes,
-        VertexBuffers,
+        StrokeOptions, StrokeTessellator, VertexBuffers,
     },
 };
 use miniquad::{graphics::*, Context};
-use std::mem;
-use usvg::Color;
+use serde::Deserialize;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem,
+    ops::Range,
+};
+use usvg::{Color, Transform};
+
+const MAX_MESH_INSTANCES: usize = 1024 * 1024;
+const MAX_SCREEN_INSTANCES: usize = 1024;
+/// Label of the meshes that don't belong to a named asset.
+const UNLABELED: &str = "other";
+
+/// Smallest allowed scale of the screen space layer.
+pub const MIN_UI_SCALE: f32 = 0.75;
+/// Largest allowed scale of the screen space layer.
+pub const MAX_UI_SCALE: f32 = 2.0;
+
+rdata! {
+/// A reference to an uploaded vector path.
+///
+/// The slot of a removed mesh is reused, the generation in the index keeps a stale reference from
+/// drawing the mesh that's uploaded into it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Mesh(Index);
+}
+
+/// A reference to an uploaded bitmap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureId(usize);
+
+/// A bitmap stretched over a rectangle of a mesh.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TexturedRect {
+    /// The uploaded bitmap.
+    pub texture: TextureId,
+    /// The top left corner relative to the origin of the mesh.
+    pub pos: (f32, f32),
+    /// The width & height.
+    pub size: (f32, f32),
+}
+
+/// A shape that clips a range of the triangles of a mesh, like an SVG clip path.
+///
+/// The shape is drawn into the stencil buffer before the triangles, which are only drawn where it
+/// was marked.
+#[derive(Debug, Clone)]
+pub struct ClipMask {
+    /// The tessellated shape, only its positions are used.
+    pub mask: VertexBuffers<Vertex, u32>,
+    /// The indices of the clipped triangles in the geometry of the mesh.
+    pub indices: Range<usize>,
+}
+
+lib! {
+/// A wrapper around the OpenGL calls so the main file won't be polluted.
+pub struct Render {
+    /// The OpenGL pipelines for the pass rendering to the render target, missing when offscreen.
+    pipelines: Option<Pipelines>,
+    /// The pipelines of the registered shaders the vector paths of a mesh can be drawn with
+    /// instead, by their name.
+    custom_pipelines: HashMap<&'static str, Pipeline>,
+    /// When the renderer was created, the time uniform of the custom shaders is relative to it.
+    start_time: f64,
+    /// The full screen effects applied to the rendered frame, missing when offscreen.
+    post: Option<PostProcess>,
+    /// The draw calls with bindings that will be generated, by the index of their mesh.
+    draw_calls: Arena<DrawCall>,
+    /// Whether some draw calls are missing bindings.
+    missing_bindings: bool,
+    /// The meshes by the hash of their geometry, so identical geometry shares a draw call.
+    meshes_by_geometry: HashMap<u64, Mesh>,
+    /// The meshes of which the geometry changed with `update_mesh`, copied into their buffers by
+    /// the next render.
+    updated_meshes: Vec<Mesh>,
+    /// The world space lines to draw in the next frame, with their color & thickness.
+    lines: Vec<(Vec<Point>, [f32; 4], f32)>,
+    /// The mesh all lines of a frame are combined into, created when the first line is drawn.
+    line_mesh: Option<Mesh>,
+    /// The uploaded textures, `None` until they are created by the next render.
+    textures: Vec<Option<Texture>>,
+    /// The decoded bitmaps waiting to be created by the next render, with the index of their
+    /// texture.
+    pending_textures: Vec<(usize, Image)>,
+    /// The textures by the hash of their file, so the same file is only decoded once.
+    textures_by_hash: HashMap<u64, TextureId>,
+
+    /// Converts world positions to clip space, set from the camera every frame.
+    camera_transform: [f32; 16],
+    /// The top left & bottom right world positions of what the camera sees, the world space
+    /// instances outside of it aren't drawn.
+    view: ((f32, f32), (f32, f32)),
+    /// Scale of everything drawn in screen space.
+    ui_scale: f32,
+    /// Physical pixels of the framebuffer per logical pixel, set from the camera.
+    dpi_scale: f32,
+
+    /// Whether the pixels of the next frame should be read back.
+    readback_requested: bool,
+    /// The pixels of the last frame that were read back.
+    readback: Option<Readback>,
+    /// Amount of world space instances uploaded by the last render.
+    uploaded_instances: usize,
+    /// Amount of world space instances drawn by the last render.
+    drawn_instances: usize,
+    /// Amount of world space instances skipped by the last render because they weren't visible.
+    culled_instances: usize,
+    /// The time spent on the main thread since the last `take_timings`.
+    timings: RenderTimings,
+}
+}
+
+impl Render {
+    /// Setup the OpenGL pipeline and the texture for the framebuffer.
+    pub fn new(ctx: &mut Context) -> Self {
+        // Create an OpenGL pipeline for rendering to the render target
+        let pipeline = create_pipeline(
+            ctx,
+            geom_shader::VERTEX,
+            geom_shader::FRAGMENT,
+            geom_shader::meta(),
+            ChunkKind::Vector,
+        )
+        .expect("Building offscreen shader failed");
+        let textured_pipeline = create_pipeline(
+            ctx,
+            textured_shader::VERTEX,
+            textured_shader::FRAGMENT,
+            textured_shader::meta(),
+            ChunkKind::Textured,
+        )
+        .expect("Building textured shader failed");
+        let nine_slice_pipeline = create_pipeline(
+            ctx,
+            nine_slice_shader::VERTEX,
+            nine_slice_shader::FRAGMENT,
+            geom_shader::meta(),
+            ChunkKind::NineSlice,
+        )
+        .expect("Building nine-slice shader failed");
+        let (mask_pipeline, clipped_pipeline, unmask_pipeline) =
+            create_clip_pipelines(ctx, geom_shader::VERTEX, geom_shader::FRAGMENT)
+                .expect("Building clipping shaders failed");
+        let glow_pipeline = create_pipeline(
+            ctx,
+            geom_shader::VERTEX,
+            glow_shader::FRAGMENT,
+            geom_shader::meta(),
+            ChunkKind::Vector,
+        )
+        .expect("Building glow shader failed");
+        let nine_slice_glow_pipeline = create_pipeline(
+            ctx,
+            nine_slice_shader::VERTEX,
+            glow_shader::FRAGMENT,
+            geom_shader::meta(),
+            ChunkKind::NineSlice,
+        )
+        .expect("Building nine-slice glow shader failed");
+        let post = PostProcess::new(ctx).expect("Building post-processing shaders failed");
+
+        Self {
+            pipelines: Some(Pipelines {
+                vector: pipeline,
+                textured: textured_pipeline,
+                nine_slice: nine_slice_pipeline,
+                mask: mask_pipeline,
+                clipped: clipped_pipeline,
+                unmask: unmask_pipeline,
+                glow: glow_pipeline,
+                nine_slice_glow: nine_slice_glow_pipeline,
+            }),
+            post: Some(post),
+            ..Self::offscreen()
+        }
+    }
+
+    /// Setup a renderer without a graphics context, for benchmarking without a window.
+    ///
+    /// The meshes are uploaded & get instances like usual, but nothing is ever drawn.
+    pub fn offscreen() -> Self {
+        Self {
+            pipelines: None,
+            post: None,
+            custom_pipelines: HashMap::new(),
+            start_time: miniquad::date::now(),
+            draw_calls: Arena::new(),
+            missing_bindings: false,
+            meshes_by_geometry: HashMap::new(),
+            updated_meshes: vec![],
+            lines: vec![],
+            line_mesh: None,
+            textures: vec![],
+            pending_textures: vec![],
+            textures_by_hash: HashMap::new(),
+            camera_transform: Camera::new().transform(),
+            view: Camera::new().visible_rect(),
+            ui_scale: 1.0,
+            dpi_scale: 1.0,
+            readback_requested: false,
+            readback: None,
+            uploaded_instances: 0,
+            drawn_instances: 0,
+            culled_instances: 0,
+            timings: RenderTimings::default(),
+        }
+    }
+
+    /// Upload a lyon path.
+    ///
+    /// Returns a reference that can be used to add instances.
+    pub fn upload_path<P>(&mut self, path: P, color: Color, opacity: f32) -> Mesh
+    where
+        P: IntoIterator<Item = PathEvent>,
+    {
+        self.tessellate_and_upload(path, color, opacity, true)
+    }
+
+    /// Upload a lyon path as a mesh of its own, even when a mesh with the same geometry exists.
+    ///
+    /// The world space instances of shared meshes are replaced by every caller, a unique mesh
+    /// can be given instances from multiple places that don't know about each other.
+    pub fn upload_path_unique<P>(&mut self, path: P, color: Color, opacity: f32) -> Mesh
+    where
+        P: IntoIterator<Item = PathEvent>,
+    {
+        self.tessellate_and_upload(path, color, opacity, false)
+    }
+
+    /// Tessellate & upload a lyon path, optionally sharing the mesh with the same geometry.
+    fn tessellate_and_upload<P>(
+        &mut self,
+        path: P,
+        color: Color,
+        opacity: f32,
+        shared: bool,
+    ) -> Mesh
+    where
+        P: IntoIterator<Item = PathEvent>,
+    {
+        let start = miniquad::date::now();
+
+        // Tessalate the path, converting it to vertices & indices
+        let geometry = tessellate_path(path, color, opacity).unwrap();
+        let mesh = self.add_draw_call(&geometry, &[], &[], shared);
+
+        self.add_mesh_time(start);
+
+        mesh
+    }
+
+    /// Upload a rectangle with the top left corner at the origin.
+    ///
+    /// Returns a reference that can be used to add instances.
+    pub fn upload_rectangle(
+        &mut self,
+        width: f32,
+        height: f32,
+        color: Color,
+        opacity: f32,
+    ) -> Mesh {
+        let mut builder = Path::builder();
+        builder.move_to(Point::new(0.0, 0.0));
+        builder.line_to(Point::new(width, 0.0));
+        builder.line_to(Point::new(width, height));
+        builder.line_to(Point::new(0.0, height));
+        builder.close();
+
+        self.upload_path(&builder.build(), color, opacity)
+    }
+
+    /// Upload a white rectangle with the top left corner at the origin that's stretched to the
+    /// size of its instances and colored by their tint.
+    ///
+    /// Returns a reference that can be used to add instances.
+    pub fn upload_stretchable_rectangle(&mut self) -> Mesh {
+        let mut builder = Path::builder();
+        builder.move_to(Point::new(0.0, 0.0));
+        builder.line_to(Point::new(1.0, 0.0));
+        builder.line_to(Point::new(1.0, 1.0));
+        builder.line_to(Point::new(0.0, 1.0));
+        builder.close();
+
+        let start = miniquad::date::now();
+        let mut geometry =
+            tessellate_path(&builder.build(), Color::new(0xff, 0xff, 0xff), 1.0).unwrap();
+        // The corners of the unit square move with the size
+        for vertex in geometry.vertices.iter_mut() {
+            vertex.stretch = vertex.pos;
+            vertex.pos = [0.0, 0.0];
+        }
+        let mesh = self.add_draw_call(&geometry, &[], &[], true);
+        self.add_mesh_time(start);
+
+        mesh
+    }
+
+    /// Upload lyon geometry.
+    ///
+    /// Returns a reference that can be used to add instances.
+    pub fn upload_buffers(&mut self, geometry: &VertexBuffers<Vertex, u32>) -> Result<Mesh> {
+        self.upload_textured(geometry, &[], &[])
+    }
+
+    /// Upload lyon geometry with bitmaps, the bitmaps are drawn below the geometry.
+    ///
+    /// Ranges of triangles can be clipped by masks, they're drawn in the order of the geometry.
+    ///
+    /// Returns a reference that can be used to add instances.
+    pub fn upload_textured(
+        &mut self,
+        geometry: &VertexBuffers<Vertex, u32>,
+        images: &[TexturedRect],
+        clips: &[ClipMask],
+    ) -> Result<Mesh> {
+        let start = miniquad::date::now();
+        let mesh = self.add_draw_call(geometry, images, clips, true);
+        self.add_mesh_time(start);
+
+        Ok(mesh)
+    }
+
+    /// Decode a PNG file so it can be drawn on meshes, it's created on the GPU by the next render.
+    ///
+    /// Uploading the same file again returns the same texture.
+    pub fn upload_texture(&mut self, bytes: &[u8]) -> Result<TextureId> {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some(&texture) = self.textures_by_hash.get(&hash) {
+            return Ok(texture);
+        }
+
+        let start = miniquad::date::now();
+        let image = Image::from_png(bytes)?;
+        self.add_mesh_time(start);
+
+        let texture = TextureId(self.textures.len());
+        self.textures.push(None);
+        self.pending_textures.push((texture.0, image));
+        self.textures_by_hash.insert(hash, texture);
+
+        Ok(texture)
+    }
+
+    /// Add the time since the start, retrieved with `miniquad::date::now()`, to the time spent
+    /// tessellating & creating meshes.
+    pub fn add_mesh_time(&mut self, start: f64) {
+        self.timings.meshes += miniquad::date::now() - start;
+    }
+
+    /// Take the time spent since the last call.
+    pub fn take_timings(&mut self) -> RenderTimings {
+        mem::take(&mut self.timings)
+    }
+
+    /// Create an OpenGL draw call for the geometry, or get the shared mesh that already has the
+    /// same geometry.
+    ///
+    /// Identical meshes share their world space instances, only instances of meshes that are
+    /// drawn in screen space or from a single place can't get mixed up. Meshes that aren't shared
+    /// always get a draw call of their own.
+    fn add_draw_call(
+        &mut self,
+        geometry: &VertexBuffers<Vertex, u32>,
+        images: &[TexturedRect],
+        clips: &[ClipMask],
+        shared: bool,
+    ) -> Mesh {
+        let hash = geometry_hash(geometry, images, clips);
+        let chunks = mesh_chunks(geometry, images, clips);
+        if let (true, Some(&mesh)) = (shared, self.meshes_by_geometry.get(&hash)) {
+            // Don't trust the hash alone
+            let dc = &mut self.draw_calls[mesh.0];
+            if dc.has_chunks(&chunks) {
+                dc.references += 1;
+
+                return mesh;
+            }
+        }
+
+        let draw_call = DrawCall {
+            bounds: chunk_bounds(&chunks),
+            chunks,
+            instances: vec![],
+            visible_instances: vec![],
+            screen_instances: vec![],
+            label: UNLABELED,
+            shared,
+            references: 1,
+            shader: None,
+        };
+        // Return the draw call in a newtype struct so it can be used as a reference
+        let mesh = Mesh(self.draw_calls.insert(draw_call));
+
+        // Tell the next render loop to create bindings for this
+        self.missing_bindings = true;
+        if shared {
+            self.meshes_by_geometry.insert(hash, mesh);
+        }
+
+        mesh
+    }
+
+    /// Replace the geometry of a mesh, the instances are drawn with the new geometry from the next
+    /// render.
+    ///
+    /// Meshes that share their geometry with the mesh get the new geometry as well.
+    pub fn replace_geometry(
+        &mut self,
+        mesh: Mesh,
+        geometry: &VertexBuffers<Vertex, u32>,
+        images: &[TexturedRect],
+        clips: &[ClipMask],
+    ) {
+        let dc = match self.draw_calls.get_mut(mesh.0) {
+            Some(dc) => dc,
+            None => {
+                warn!("can't replace the geometry of removed mesh {:?}", mesh);
+
+                return;
+            }
+        };
+
+        // The mesh can't be found anymore by its old geometry
+        self.meshes_by_geometry.retain(|_, other| *other != mesh);
+        if dc.shared {
+            self.meshes_by_geometry
+                .entry(geometry_hash(geometry, images, clips))
+                .or_insert(mesh);
+        }
+
+        dc.delete_bindings();
+        dc.chunks = mesh_chunks(geometry, images, clips);
+        dc.bounds = chunk_bounds(&dc.chunks);
+        // The new instance buffer is empty
+        dc.visible_instances.clear();
+
+        self.missing_bindings = true;
+    }
+
+    /// Replace the vertices & indices of a mesh that changes often, like water or destructible
+    /// terrain, the instances are drawn with the new geometry from the next render.
+    ///
+    /// The geometry is copied into the buffers of the mesh while it fits, otherwise they're created
+    /// again with room to grow. The mesh isn't shared with identical geometry anymore.
+    pub fn update_mesh(&mut self, mesh: Mesh, geometry: &VertexBuffers<Vertex, u16>) {
+        let dc = match self.draw_calls.get_mut(mesh.0) {
+            Some(dc) => dc,
+            None => {
+                warn!("can't update the geometry of removed mesh {:?}", mesh);
+
+                return;
+            }
+        };
+
+        // Uploading the geometry it had shouldn't return a mesh that changes
+        self.meshes_by_geometry.retain(|_, other| *other != mesh);
+        dc.shared = false;
+
+        let fits = match dc.chunks.as_slice() {
+            [chunk] => {
+                chunk.bindings.is_some()
+                    && chunk.capacity.map_or(false, |(vertices, indices)| {
+                        geometry.vertices.len() <= vertices && geometry.indices.len() <= indices
+                    })
+            }
+            _ => false,
+        };
+
+        let mut chunk = Chunk::new(geometry.vertices.clone(), geometry.indices.clone());
+        if fits {
+            // Keep the buffers, only their contents are replaced
+            let old = &dc.chunks[0];
+            chunk.capacity = old.capacity;
+            chunk.bindings = old.bindings.clone();
+            chunk.screen_bindings = old.screen_bindings.clone();
+
+            if !self.updated_meshes.contains(&mesh) {
+                self.updated_meshes.push(mesh);
+            }
+        } else {
+            dc.delete_bindings();
+            // The new instance buffer is empty
+            dc.visible_instances.clear();
+
+            // Leave room so geometry that grows a bit doesn't create the buffers every time
+            chunk.capacity = Some((
+                geometry.vertices.len().next_power_of_two(),
+                geometry.indices.len().next_power_of_two(),
+            ));
+
+            self.missing_bindings = true;
+        }
+
+        dc.chunks = vec![chunk];
+        dc.bounds = chunk_bounds(&dc.chunks);
+    }
+
+    /// Delete the buffers of a mesh, it can't be drawn anymore and its slot is reused by the next
+    /// upload.
+    ///
+    /// Identical geometry is shared, so the buffers are only deleted when everything that uploaded
+    /// it removed the mesh. Returns whether the mesh still existed.
+    pub fn remove_mesh(&mut self, mesh: Mesh) -> bool {
+        match self.draw_calls.get_mut(mesh.0) {
+            Some(dc) if dc.references > 1 => {
+                dc.references -= 1;
+
+                return true;
+            }
+            Some(_) => (),
+            None => return false,
+        }
+
+        let mut dc = match self.draw_calls.remove(mesh.0) {
+            Some(dc) => dc,
+            None => return false,
+        };
+        dc.delete_bindings();
+
+        self.meshes_by_geometry.retain(|_, other| *other != mesh);
+
+        true
+    }
+
+    /// Draw a line between two world positions in the next frame, with an RGBA color and a
+    /// thickness in world units.
+    pub fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), color: [f32; 4], thickness: f32) {
+        self.draw_polyline(&[from, to], color, thickness);
+    }
+
+    /// Draw connected lines through world positions in the next frame, with an RGBA color and a
+    /// thickness in world units.
+    ///
+    /// All lines of a frame are drawn as a single mesh, so they don't have to be uploaded before.
+    pub fn draw_polyline(&mut self, points: &[(f32, f32)], color: [f32; 4], thickness: f32) {
+        if points.len() < 2 {
+            return;
+        }
+
+        self.lines.push((
+            points.iter().map(|&(x, y)| Point::new(x, y)).collect(),
+            color,
+            thickness,
+        ));
+    }
+
+    /// Combine the lines drawn since the last render into their mesh, it's hidden when there are
+    /// none.
+    fn upload_lines(&mut self) {
+        let mesh = match (self.line_mesh, self.lines.is_empty()) {
+            (Some(mesh), true) => {
+                self.set_instances(mesh, &[]);
+
+                return;
+            }
+            (None, true) => return,
+            (Some(mesh), false) => mesh,
+            (None, false) => {
+                let mesh = self.add_draw_call(&VertexBuffers::new(), &[], &[], false);
+                self.set_label(mesh, "lines");
+                self.line_mesh = Some(mesh);
+
+                mesh
+            }
+        };
+
+        let start = miniquad::date::now();
+        let mut geometry: VertexBuffers<Vertex, u16> = VertexBuffers::new();
+        let mut tessellator = StrokeTessellator::new();
+        for (points, color, thickness) in self.lines.drain(..) {
+            let mut builder = Path::builder();
+            builder.move_to(points[0]);
+            for point in &points[1..] {
+                builder.line_to(*point);
+            }
+
+            let result = tessellator.tessellate(
+                &builder.build(),
+                &StrokeOptions::default().with_line_width(thickness),
+                &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: StrokeAttributes| Vertex {
+                    pos: pos.to_array(),
+                    color,
+                    uv: [0.0, 0.0],
+                    stretch: [0.0, 0.0],
+                    glow: 0.0,
+                }),
+            );
+            if let Err(err) = result {
+                warn!("tessellating line failed: {:?}", err);
+            }
+        }
+        self.add_mesh_time(start);
+
+        // The points are world positions, so the mesh is drawn once at the origin
+        self.update_mesh(mesh, &geometry);
+        let mut instance = Instance::new(0.0, 0.0);
+        instance.set_layer(Layer::Text, 0);
+        self.set_instances(mesh, &[instance]);
+    }
+
+    /// Render the graphics.
+    pub fn render(&mut self, ctx: &mut Context) {
+        // There's nothing to draw with without a graphics context
+        let pipelines = match self.pipelines {
+            Some(pipelines) => pipelines,
+            None => return,
+        };
+        let (width, height) = ctx.screen_size();
+
+        // The lines are tessellated before the bindings are created, so a new mesh gets them
+        self.upload_lines();
+
+        let start = miniquad::date::now();
+
+        // Create the textures & bindings if necessary, the bindings refer to the textures
+        if self.missing_bindings {
+            for (index, image) in self.pending_textures.drain(..) {
+                self.textures[index] = Some(Texture::from_rgba8(
+                    ctx,
+                    image.width,
+                    image.height,
+                    &image.pixels,
+                ));
+            }
+
+            let textures = &self.textures;
+            self.draw_calls.iter_mut().for_each(|(_, dc)| {
+                // Create bindings if missing
+                if !dc.has_bindings() {
+                    dc.create_bindings(ctx, textures);
+                }
+            });
+
+            self.missing_bindings = false;
+        }
+
+        // Copy the changed geometry into the buffers of the updated meshes
+        for mesh in self.updated_meshes.drain(..) {
+            if let Some(dc) = self.draw_calls.get(mesh.0) {
+                for chunk in dc.chunks.iter() {
+                    if let Some(bindings) = chunk.bindings.as_ref() {
+                        bindings.vertex_buffers[0].update(ctx, &chunk.vertices);
+                        bindings.index_buffer.update(ctx, &chunk.indices);
+                    }
+                }
+            }
+        }
+        let mut upload_time = miniquad::date::now() - start;
+
+        // Render the pass to the render target, the stencil buffer is used by the clipped meshes
+        ctx.begin_default_pass(PassAction::Clear {
+            color: Some((0.4, 0.7, 1.0, 1.0)),
+            depth: Some(1.0),
+            stencil: Some(0),
+        });
+
+        // The pipeline is only switched when the next chunk is of another kind, `None` after a
+        // custom shader
+        let mut kind = Some(ChunkKind::Vector);
+        apply_pipeline(ctx, &pipelines.vector, self.camera_transform);
+        let time = (miniquad::date::now() - self.start_time) as f32;
+        ctx.apply_scissor_rect(0, 0, width as i32, height as i32);
+
+        // The draw calls with glowing instances, drawn again for the bloom
+        let mut glowing = vec![];
+        let mut glowing_screen = vec![];
+
+        // Render the separate draw calls
+        self.uploaded_instances = 0;
+        self.drawn_instances = 0;
+        self.culled_instances = 0;
+        for (index, dc) in self.draw_calls.iter_mut() {
+            // Only render when we actually have instances
+            if dc.instances.is_empty() {
+                continue;
+            }
+
+            // Skip the instances outside of the view of the camera
+            let visible = dc.visible(self.view);
+            self.drawn_instances += visible.len();
+            self.culled_instances += dc.instances.len() - visible.len();
+            if visible.is_empty() {
+                continue;
+            }
+
+            // The buffer still contains the visible instances of the last upload when they didn't
+            // change, it's not touched while nothing is visible
+            if visible != dc.visible_instances {
+                let upload_start = miniquad::date::now();
+
+                // Upload the instance positions, the buffer is shared by all chunks
+                if let Some(bindings) = dc.chunks.first().and_then(|chunk| chunk.bindings.as_ref())
+                {
+                    bindings.vertex_buffers[1].update(ctx, &visible);
+                }
+                self.uploaded_instances += visible.len();
+                upload_time += miniquad::date::now() - upload_start;
+
+                dc.visible_instances = visible;
+            }
+            if dc.glows() {
+                glowing.push(index);
+            }
+
+            for chunk in dc.chunks.iter() {
+                match custom_pipeline(&self.custom_pipelines, dc.shader, chunk.kind()) {
+                    Some(pipeline) => {
+                        apply_custom_pipeline(ctx, pipeline, self.camera_transform, time);
+                        kind = None;
+                    }
+                    None if kind != Some(chunk.kind()) => {
+                        kind = Some(chunk.kind());
+                        apply_pipeline(ctx, pipelines.get(chunk.kind()), self.camera_transform);
+                    }
+                    None => (),
+                }
+
+                ctx.apply_bindings(chunk.bindings.as_ref().unwrap());
+                ctx.draw(
+                    0,
+                    chunk.indices.len() as i32,
+                    dc.visible_instances.len() as i32,
+                );
+            }
+        }
+
+        ctx.end_render_pass();
+
+        // Render the screen space instances on top of the world, only clearing the depth
+        ctx.begin_default_pass(PassAction::Clear {
+            color: None,
+            depth: Some(1.0),
+            stencil: Some(0),
+        });
+
+        // Map the scaled logical pixel coordinates with the origin at the top left to the clip
+        // space, the size of the framebuffer is in physical pixels
+        let pixel_scale = self.ui_scale * self.dpi_scale;
+        let (scale_x, scale_y) = (2.0 * pixel_scale / width, 2.0 * pixel_scale / height);
+        let screen_transform = [
+            scale_x, 0.0, 0.0, 0.0, 0.0, -scale_y, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -1.0, 1.0, 0.0,
+            1.0,
+        ];
+        let mut kind = Some(ChunkKind::Vector);
+        apply_pipeline(ctx, &pipelines.vector, screen_transform);
+
+        for (index, dc) in self.draw_calls.iter_mut() {
+            if dc.screen_instances.is_empty() {
+                continue;
+            }
+
+            // Group the instances by their clipping rectangle so every group is a single draw
+            dc.screen_instances.sort_by_key(|(clip, _)| *clip);
+
+            for (clip, instances) in clip_groups(&dc.screen_instances) {
+                // The screen instances are queued every frame so always upload them
+                let upload_start = miniquad::date::now();
+                if let Some(bindings) = dc
+                    .chunks
+                    .first()
+                    .and_then(|chunk| chunk.screen_bindings.as_ref())
+                {
+                    bindings.vertex_buffers[1].update(ctx, &instances);
+                }
+                upload_time += miniquad::date::now() - upload_start;
+
+                apply_clip(ctx, clip, pixel_scale, (width, height));
+                for chunk in dc.chunks.iter() {
+                    match custom_pipeline(&self.custom_pipelines, dc.shader, chunk.kind()) {
+                        Some(pipeline) => {
+                            apply_custom_pipeline(ctx, pipeline, screen_transform, time);
+                            kind = None;
+                        }
+                        None if kind != Some(chunk.kind()) => {
+                            kind = Some(chunk.kind());
+                            apply_pipeline(ctx, pipelines.get(chunk.kind()), screen_transform);
+                        }
+                        None => (),
+                    }
+
+                    ctx.apply_bindings(chunk.screen_bindings.as_ref().unwrap());
+                    ctx.draw(0, chunk.indices.len() as i32, instances.len() as i32);
+                }
+            }
+
+            // The glowing instances are kept until they're drawn for the bloom
+            if dc.glows() {
+                glowing_screen.push(index);
+            } else {
+                dc.screen_instances.clear();
+            }
+        }
+
+        ctx.end_render_pass();
+
+        // Draw the glowing paths again into the smaller pass of the bloom
+        if let Some(pass) = self.post.as_mut().and_then(|post| post.bloom_pass(ctx)) {
+            self.draw_glow(
+                ctx,
+                &pipelines,
+                pass,
+                &glowing,
+                &glowing_screen,
+                screen_transform,
+            );
+        }
+        for index in glowing_screen {
+            if let Some(dc) = self.draw_calls.get_mut(index) {
+                dc.screen_instances.clear();
+            }
+        }
+
+        // The effects change the frame in the default framebuffer, so the readback includes them
+        let post_start = miniquad::date::now();
+        if let Some(post) = self.post.as_mut() {
+            post.apply(ctx);
+        }
+        let post_time = miniquad::date::now() - post_start;
+
+        if self.readback_requested {
+            self.readback = Some(Readback::read(width as usize, height as usize));
+            self.readback_requested = false;
+        }
+
+        ctx.commit_frame();
+
+        // Creating the bindings counts as uploading, the effects separately, everything else as
+        // submitting
+        let total = miniquad::date::now() - start;
+        self.timings.upload += upload_time;
+        self.timings.post += post_time;
+        self.timings.submit += (total - upload_time - post_time).max(0.0);
+    }
+
+    /// Draw the glowing draw calls into the pass of the bloom, the world space instances are
+    /// still in their buffers and the screen space ones are uploaded again.
+    ///
+    /// Only the chunks with glowing vertices are drawn, without their clip paths.
+    fn draw_glow(
+        &self,
+        ctx: &mut Context,
+        pipelines: &Pipelines,
+        pass: RenderPass,
+        world: &[Index],
+        screen: &[Index],
+        screen_transform: [f32; 16],
+    ) {
+        let texture = pass.texture(ctx);
+        let (width, height) = (texture.width as f32, texture.height as f32);
+        // The clipping rectangles are in the logical pixels of the screen
+        let scale = width / ctx.screen_size().0 * self.dpi_scale;
+
+        ctx.begin_pass(pass, PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+
+        for dc in world.iter().filter_map(|index| self.draw_calls.get(*index)) {
+            for chunk in dc.chunks.iter().filter(|chunk| chunk.glows) {
+                apply_pipeline(ctx, pipelines.glow(chunk.kind()), self.camera_transform);
+                ctx.apply_bindings(chunk.bindings.as_ref().unwrap());
+                ctx.draw(
+                    0,
+                    chunk.indices.len() as i32,
+                    dc.visible_instances.len() as i32,
+                );
+            }
+        }
+
+        for dc in screen
+            .iter()
+            .filter_map(|index| self.draw_calls.get(*index))
+        {
+            for (clip, instances) in clip_groups(&dc.screen_instances) {
+                if let Some(bindings) = dc
+                    .chunks
+                    .first()
+                    .and_then(|chunk| chunk.screen_bindings.as_ref())
+                {
+                    bindings.vertex_buffers[1].update(ctx, &instances);
+                }
+
+                apply_clip(ctx, clip, self.ui_scale * scale, (width, height));
+                for chunk in dc.chunks.iter().filter(|chunk| chunk.glows) {
+                    apply_pipeline(ctx, pipelines.glow(chunk.kind()), screen_transform);
+                    ctx.apply_bindings(chunk.screen_bindings.as_ref().unwrap());
+                    ctx.draw(0, chunk.indices.len() as i32, instances.len() as i32);
+                }
+            }
+        }
+
+        ctx.end_render_pass();
+    }
+
+    /// Replace the world space instances of a mesh, the visible ones are uploaded with the next
+    /// render.
+    ///
+    /// Nothing is uploaded when the visible instances are the same as the last upload, so static
+    /// objects don't cost any bandwidth.
+    pub fn set_instances(&mut self, mesh: Mesh, instances: &[Instance]) {
+        let instances = &instances[..instances.len().min(MAX_MESH_INSTANCES)];
+        let dc = match self.draw_calls.get_mut(mesh.0) {
+            Some(dc) => dc,
+            None => {
+                warn!("can't set the instances of removed mesh {:?}", mesh);
+
+                return;
+            }
+        };
+        if dc.instances == instances {
+            return;
+        }
+
+        dc.instances.clear();
+        dc.instances.extend_from_slice(instances);
+    }
+
+    /// Amount of world space instances that changed and were uploaded by the last render.
+    pub fn uploaded_instances(&self) -> usize {
+        self.uploaded_instances
+    }
+
+    /// Amount of world space instances drawn & culled by the last render.
+    pub fn culling(&self) -> (usize, usize) {
+        (self.drawn_instances, self.culled_instances)
+    }
+
+    /// Replace the sources of a shader, keeping the old pipeline when they don't compile.
+    pub fn reload_shader(&mut self, ctx: &mut Context, source: &ShaderSource) -> Result<()> {
+        let pipelines = self
+            .pipelines
+            .as_mut()
+            .ok_or_else(|| anyhow!("Can't reload shaders without a graphics context"))?;
+
+        match source.name {
+            "geom" => {
+                pipelines.vector = create_pipeline(
+                    ctx,
+                    &source.vertex,
+                    &source.fragment,
+                    geom_shader::meta(),
+                    ChunkKind::Vector,
+                )?;
+                let (mask, clipped, unmask) =
+                    create_clip_pipelines(ctx, &source.vertex, &source.fragment)?;
+                pipelines.mask = mask;
+                pipelines.clipped = clipped;
+                pipelines.unmask = unmask;
+
+                Ok(())
+            }
+            "textured" => {
+                pipelines.textured = create_pipeline(
+                    ctx,
+                    &source.vertex,
+                    &source.fragment,
+                    textured_shader::meta(),
+                    ChunkKind::Textured,
+                )?;
+
+                Ok(())
+            }
+            "nine_slice" => {
+                pipelines.nine_slice = create_pipeline(
+                    ctx,
+                    &source.vertex,
+                    &source.fragment,
+                    geom_shader::meta(),
+                    ChunkKind::NineSlice,
+                )?;
+
+                Ok(())
+            }
+            "glow" => {
+                pipelines.glow = create_pipeline(
+                    ctx,
+                    &source.vertex,
+                    &source.fragment,
+                    geom_shader::meta(),
+                    ChunkKind::Vector,
+                )?;
+
+                Ok(())
+            }
+            "nine_slice_glow" => {
+                pipelines.nine_slice_glow = create_pipeline(
+                    ctx,
+                    &source.vertex,
+                    &source.fragment,
+                    geom_shader::meta(),
+                    ChunkKind::NineSlice,
+                )?;
+
+                Ok(())
+            }
+            name if self.custom_pipelines.contains_key(name) => self.register_shader(ctx, source),
+            // Every other shader belongs to a full screen effect
+            _ => self
+                .post
+                .as_mut()
+                .ok_or_else(|| anyhow!("Can't reload shaders without a graphics context"))?
+                .reload_shader(ctx, source),
+        }
+    }
+
+    /// Add a shader the vector paths of meshes can be drawn with instead of the vector shader, or
+    /// replace the one with the same name.
+    ///
+    /// Besides the uniforms of the vector shader it gets `u_time`, the seconds since the start.
+    pub fn register_shader(&mut self, ctx: &mut Context, source: &ShaderSource) -> Result<()> {
+        let pipeline = create_pipeline(
+            ctx,
+            &source.vertex,
+            &source.fragment,
+            custom_shader::meta(),
+            ChunkKind::Vector,
+        )?;
+        self.custom_pipelines.insert(source.name, pipeline);
+
+        Ok(())
+    }
+
+    /// Draw the vector paths of a mesh with a registered shader, or with the vector shader again.
+    ///
+    /// A shared mesh is also drawn with it wherever the same geometry is uploaded.
+    pub fn set_shader(&mut self, mesh: Mesh, shader: Option<&str>) -> Result<()> {
+        // Nothing is drawn without a graphics context, so there are no shaders to check
+        if self.pipelines.is_none() {
+            return Ok(());
+        }
+
+        let shader = match shader {
+            Some(shader) => Some(
+                *self
+                    .custom_pipelines
+                    .keys()
+                    .find(|name| **name == shader)
+                    .ok_or_else(|| anyhow!("Unknown shader \"{}\"", shader))?,
+            ),
+            None => None,
+        };
+        if let Some(dc) = self.draw_calls.get_mut(mesh.0) {
+            dc.shader = shader;
+        }
+
+        Ok(())
+    }
+
+    /// The top left & bottom right corners around the geometry of a mesh, before it's moved by
+    /// the instances.
+    ///
+    /// Returns `None` when the mesh is removed.
+    pub fn mesh_bounds(&self, mesh: Mesh) -> Option<((f32, f32), (f32, f32))> {
+        self.draw_calls.get(mesh.0).map(|dc| dc.bounds)
+    }
+
+    /// Name the asset a mesh belongs to, the memory usage of meshes with the same label is
+    /// combined.
+    pub fn set_label(&mut self, mesh: Mesh, label: &'static str) {
+        if let Some(dc) = self.draw_calls.get_mut(mesh.0) {
+            dc.label = label;
+        }
+    }
+
+    /// The GPU memory used by the meshes of every label, the largest first.
+    pub fn memory_usage(&self) -> Vec<(&'static str, MeshMemory)> {
+        let mut usage: Vec<(&'static str, MeshMemory)> = vec![];
+        for (_, dc) in self.draw_calls.iter() {
+            let memory = dc.memory();
+            match usage.iter_mut().find(|(label, _)| *label == dc.label) {
+                Some((_, total)) => *total += memory,
+                None => usage.push((dc.label, memory)),
+            }
+        }
+
+        usage.sort_by_key(|(_, memory)| std::cmp::Reverse(memory.total()));
+
+        usage
+    }
+
+    /// Draw an instance of a mesh in screen space for the next frame.
+    ///
+    /// The position of the instance is in pixels with the origin at the top left of the screen,
+    /// multiplied by the UI scale.
+    pub fn draw_screen(&mut self, mesh: Mesh, instance: Instance) {
+        self.queue_screen_instance(mesh, instance, None);
+    }
+
+    /// Draw an instance of a mesh in screen space for the next frame, clipped to a rectangle.
+    pub fn draw_screen_clipped(&mut self, mesh: Mesh, instance: Instance, clip: ClipRect) {
+        self.queue_screen_instance(mesh, instance, Some(clip));
+    }
+
+    /// Add a screen space instance to the draw call of the mesh.
+    fn queue_screen_instance(&mut self, mesh: Mesh, instance: Instance, clip: Option<ClipRect>) {
+        let dc = match self.draw_calls.get_mut(mesh.0) {
+            Some(dc) => dc,
+            None => {
+                warn!("can't draw removed mesh {:?}", mesh);
+
+                return;
+            }
+        };
+        if dc.screen_instances.len() < MAX_SCREEN_INSTANCES {
+            dc.screen_instances.push((clip, instance));
+        }
+    }
+
+    /// Render the world from the view of the camera.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera_transform = camera.transform();
+        self.view = camera.visible_rect();
+        self.dpi_scale = camera.dpi_scale();
+    }
+
+    /// Read the pixels of the next rendered frame back from the GPU.
+    pub fn request_readback(&mut self) {
+        self.readback_requested = true;
+    }
+
+    /// Get the pixels of the last frame that was read back.
+    pub fn take_readback(&mut self) -> Option<Readback> {
+        self.readback.take()
+    }
+
+    /// Set the scale of the screen space layer, clamped to the allowed range.
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale.max(MIN_UI_SCALE).min(MAX_UI_SCALE);
+    }
+
+    /// The scale of the screen space layer.
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    /// Apply the order & options of the post-processing effects.
+    pub fn configure_post(&mut self, config: &PostConfig) {
+        if let Some(post) = self.post.as_mut() {
+            post.configure(config);
+        }
+    }
+
+    /// GameLisp function returning the enabled post-processing effects in the order they are
+    /// applied.
+    fn post_effects(&self) -> GResult<Vec<Sym>> {
+        self.post
+            .iter()
+            .flat_map(|post| post.effects())
+            .map(|effect| glsp::sym(effect.name()))
+            .collect()
+    }
+
+    /// GameLisp function enabling only the post-processing effects, like `'fxaa` or `'vignette`,
+    /// applied in the order of the arguments.
+    fn set_post_effects(&mut self, effects: &[Sym]) -> GResult<()> {
+        let effects = effects
+            .iter()
+            .map(parse_post_effect)
+            .collect::<GResult<Vec<_>>>()?;
+        if let Some(post) = self.post.as_mut() {
+            post.set_effects(&effects);
+        }
+
+        Ok(())
+    }
+
+    /// GameLisp function enabling or disabling a post-processing effect, an enabled effect is
+    /// applied after the others.
+    fn set_post_effect_enabled(&mut self, effect: Sym, enabled: bool) -> GResult<()> {
+        let effect = parse_post_effect(&effect)?;
+        if let Some(post) = self.post.as_mut() {
+            post.set_enabled(effect, enabled);
+        }
+
+        Ok(())
+    }
+
+    /// GameLisp function changing the colors of the color grading effect.
+    fn set_color_grading(&mut self, contrast: f32, saturation: f32, warmth: f32) {
+        if let Some(post) = self.post.as_mut() {
+            post.set_color_grading(ColorGrading {
+                contrast,
+                saturation,
+                warmth,
+            });
+        }
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("post-effects", rfn!(Self::post_effects))?;
+            glsp::bind_rfn("set-post-effects", rfn!(Self::set_post_effects))?;
+            glsp::bind_rfn(
+                "set-post-effect-enabled",
+                rfn!(Self::set_post_effect_enabled),
+            )?;
+            glsp::bind_rfn("set-color-grading", rfn!(Self::set_color_grading))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Convert the symbol of a post-processing effect, like `'chromatic-aberration`.
+fn parse_post_effect(sym: &Sym) -> GResult<PostEffect> {
+    match PostEffect::from_name(&sym.name()) {
+        Some(effect) => Ok(effect),
+        None => bail!("unknown post-processing effect {}", sym),
+    }
+}
+
+/// Compile the shader sources and create the pipeline for rendering a kind of chunk.
+///
+/// The shaders share the vertex layout, attributes a shader doesn't use are skipped.
+fn create_pipeline(
+    ctx: &mut Context,
+    vertex: &str,
+    fragment: &str,
+    meta: ShaderMeta,
+    kind: ChunkKind,
+) -> Result<Pipeline> {
+    let shader = Shader::new(ctx, vertex, fragment, meta).map_err(|err| anyhow!("{}", err))?;
+
+    Ok(Pipeline::with_params(
+        ctx,
+        &[
+            BufferLayout::default(),
+            BufferLayout {
+                step_func: VertexStep::PerInstance,
+                ..Default::default()
+            },
+        ],
+        &[
+            VertexAttribute::with_buffer("a_pos", VertexFormat::Float2, 0),
+            VertexAttribute::with_buffer("a_color", VertexFormat::Float4, 0),
+            VertexAttribute::with_buffer("a_uv", VertexFormat::Float2, 0),
+            VertexAttribute::with_buffer("a_stretch", VertexFormat::Float2, 0),
+            VertexAttribute::with_buffer("a_glow", VertexFormat::Float1, 0),
+            VertexAttribute::with_buffer("a_inst_pos", VertexFormat::Float3, 1),
+            VertexAttribute::with_buffer("a_inst_rot", VertexFormat::Float1, 1),
+            VertexAttribute::with_buffer("a_inst_scale", VertexFormat::Float2, 1),
+            VertexAttribute::with_buffer("a_inst_size", VertexFormat::Float2, 1),
+            VertexAttribute::with_buffer("a_inst_color", VertexFormat::Float4, 1),
+        ],
+        shader,
+        pipeline_params(kind),
+    ))
+}
+
+/// Create the pipelines marking the masks in the stencil buffer, drawing the clipped vector paths
+/// and clearing the masks again, from the sources of the vector shader.
+fn create_clip_pipelines(
+    ctx: &mut Context,
+    vertex: &str,
+    fragment: &str,
+) -> Result<(Pipeline, Pipeline, Pipeline)> {
+    Ok((
+        create_pipeline(ctx, vertex, fragment, geom_shader::meta(), ChunkKind::Mask)?,
+        create_pipeline(
+            ctx,
+            vertex,
+            fragment,
+            geom_shader::meta(),
+            ChunkKind::Clipped,
+        )?,
+        create_pipeline(
+            ctx,
+            vertex,
+            fragment,
+            geom_shader::meta(),
+            ChunkKind::Unmask,
+        )?,
+    ))
+}
+
+/// The depth & stencil state of the pipeline drawing a kind of chunk.
+fn pipeline_params(kind: ChunkKind) -> PipelineParams {
+    // Apply a stencil operation to the pixels passing a test against the mask value
+    let stencil = |test_func: CompareFunc, pass_op: StencilOp| {
+        let face = StencilFaceState {
+            fail_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            pass_op,
+            test_func,
+            test_ref: 1,
+            test_mask: 0xff,
+            write_mask: 0xff,
+        };
+
+        Some(StencilState {
+            front: face,
+            back: face,
+        })
+    };
+    let drawn = PipelineParams {
+        depth_test: Comparison::LessOrEqual,
+        depth_write: true,
+        ..Default::default()
+    };
+    // The masks are only written to the stencil buffer
+    let hidden = PipelineParams {
+        color_write: (false, false, false, false),
+        ..Default::default()
+    };
+
+    match kind {
+        ChunkKind::Vector | ChunkKind::Textured | ChunkKind::NineSlice => drawn,
+        ChunkKind::Mask => PipelineParams {
+            stencil_test: stencil(CompareFunc::Always, StencilOp::Replace),
+            ..hidden
+        },
+        ChunkKind::Clipped => PipelineParams {
+            stencil_test: stencil(CompareFunc::Equal, StencilOp::Keep),
+            ..drawn
+        },
+        ChunkKind::Unmask => PipelineParams {
+            stencil_test: stencil(CompareFunc::Always, StencilOp::Zero),
+            ..hidden
+        },
+    }
+}
+
+/// The OpenGL pipelines for every kind of chunk.
+#[derive(Debug, Copy, Clone)]
+struct Pipelines {
+    /// The vector paths.
+    vector: Pipeline,
+    /// The parts of meshes that are filled with a bitmap.
+    textured: Pipeline,
+    /// The meshes that are stretched to the size of their instances.
+    nine_slice: Pipeline,
+    /// The masks marked in the stencil buffer.
+    mask: Pipeline,
+    /// The vector paths only drawn inside of the marked masks.
+    clipped: Pipeline,
+    /// The masks cleared from the stencil buffer.
+    unmask: Pipeline,
+    /// The glowing vector paths drawn into the bloom.
+    glow: Pipeline,
+    /// The glowing stretched meshes drawn into the bloom.
+    nine_slice_glow: Pipeline,
+}
+
+impl Pipelines {
+    /// The pipeline drawing a kind of chunk.
+    fn get(&self, kind: ChunkKind) -> &Pipeline {
+        match kind {
+            ChunkKind::Vector => &self.vector,
+            ChunkKind::Textured => &self.textured,
+            ChunkKind::NineSlice => &self.nine_slice,
+            ChunkKind::Mask => &self.mask,
+            ChunkKind::Clipped => &self.clipped,
+            ChunkKind::Unmask => &self.unmask,
+        }
+    }
+
+    /// The pipeline drawing a kind of chunk into the bloom.
+    fn glow(&self, kind: ChunkKind) -> &Pipeline {
+        match kind {
+            ChunkKind::NineSlice => &self.nine_slice_glow,
+            _ => &self.glow,
+        }
+    }
+}
+
+/// Split the screen space instances, sorted by their clipping rectangle, into the groups sharing
+/// one.
+fn clip_groups(
+    instances: &[(Option<ClipRect>, Instance)],
+) -> Vec<(Option<ClipRect>, Vec<Instance>)> {
+    let mut groups: Vec<(Option<ClipRect>, Vec<Instance>)> = vec![];
+    for (clip, instance) in instances {
+        match groups.last_mut() {
+            Some((last, group)) if last == clip => group.push(*instance),
+            _ => groups.push((*clip, vec![*instance])),
+        }
+    }
+
+    groups
+}
+
+/// Only draw inside of a clipping rectangle in scaled pixels, or in the whole target without one.
+fn apply_clip(ctx: &mut Context, clip: Option<ClipRect>, scale: f32, (width, height): (f32, f32)) {
+    match clip {
+        // OpenGL expects the origin of the scissor rectangle at the bottom left
+        Some(clip) => {
+            let clip = clip.scaled(scale);
+            ctx.apply_scissor_rect(
+                clip.x,
+                height as i32 - clip.y - clip.height,
+                clip.width,
+                clip.height,
+            )
+        }
+        None => ctx.apply_scissor_rect(0, 0, width as i32, height as i32),
+    }
+}
+
+/// Switch to a pipeline, the uniforms have to be applied again for its shader.
+fn apply_pipeline(ctx: &mut Context, pipeline: &Pipeline, transform: [f32; 16]) {
+    ctx.apply_pipeline(pipeline);
+    ctx.apply_uniforms(&geom_shader::Uniforms { transform });
+}
 
-const MAX_MESH_INSTANCES: usize = 1024 * 1024;
+/// The pipeline of the registered shader drawing a kind of chunk of a mesh instead, only the
+/// vector paths can be drawn with one.
+fn custom_pipeline<'a>(
+    pipelines: &'a HashMap<&'static str, Pipeline>,
+    shader: Option<&str>,
+    kind: ChunkKind,
+) -> Option<&'a Pipeline> {
+    match kind {
+        ChunkKind::Vector => pipelines.get(shader?),
+        _ => None,
+    }
+}
 
-rdata! {
-/// A reference to an uploaded vector path.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Mesh(usize);
+/// Switch to the pipeline of a registered shader, with its extra uniforms.
+fn apply_custom_pipeline(ctx: &mut Context, pipeline: &Pipeline, transform: [f32; 16], time: f32) {
+    ctx.apply_pipeline(pipeline);
+    ctx.apply_uniforms(&custom_shader::Uniforms { transform, time });
 }
 
-lib! {
-/// A wrapper around the OpenGL calls so the main file won't be polluted.
-pub struct Render {
-    /// The OpenGL pipeline for the pass rendering to the render target.
-    pipeline: Pipeline,
-    /// A list of draw calls with bindings that will be generated.
-    draw_calls: Vec<DrawCall>,
-    /// Whether some draw calls are missing bindings.
-    missing_bindings: bool,
+/// Bytes allocated on the GPU for meshes.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MeshMemory {
+    /// The vertex buffers.
+    pub vertices: usize,
+    /// The index buffers.
+    pub indices: usize,
+    /// The world & screen space instance buffers.
+    pub instances: usize,
+}
 
-    camera_pan: (f32, f32),
-    camera_zoom: f32,
+impl MeshMemory {
+    /// All buffers combined.
+    pub fn total(&self) -> usize {
+        self.vertices + self.indices + self.instances
+    }
 }
+
+impl std::ops::AddAssign for MeshMemory {
+    fn add_assign(&mut self, other: Self) {
+        self.vertices += other.vertices;
+        self.indices += other.indices;
+        self.instances += other.instances;
+    }
 }
 
-impl Render {
-    /// Setup the OpenGL pipeline and the texture for the framebuffer.
-    pub fn new(ctx: &mut Context) -> Self {
-        // Create an OpenGL pipeline for rendering to the render target
-        let shader = Shader::new(
-            ctx,
-            geom_shader::VERTEX,
-            geom_shader::FRAGMENT,
-            geom_shader::META,
+/// Tessellate a lyon path into vertices & indices that can be uploaded.
+///
+/// Doesn't need the graphics context, so it can be done on any thread.
+pub fn tessellate_path<P>(path: P, color: Color, opacity: f32) -> Result<VertexBuffers<Vertex, u32>>
+where
+    P: IntoIterator<Item = PathEvent>,
+{
+    let mut geometry = VertexBuffers::new();
+    FillTessellator::new()
+        .tessellate(
+            path,
+            &FillOptions::default(),
+            &mut BuffersBuilder::new(&mut geometry, VertexCtor::new(color, opacity)),
         )
-        .expect("Building offscreen shader failed");
-        let pipeline = Pipeline::with_params(
-            ctx,
-            &[
-                BufferLayout::default(),
-                BufferLayout {
-                    step_func: VertexStep::PerInstance,
-                    ..Default::default()
-                },
-            ],
-            &[
-                VertexAttribute::with_buffer("a_pos", VertexFormat::Float2, 0),
-                VertexAttribute::with_buffer("a_color", VertexFormat::Float4, 0),
-                VertexAttribute::with_buffer("a_inst_pos", VertexFormat::Float3, 1),
-                VertexAttribute::with_buffer("a_inst_rot", VertexFormat::Float1, 1),
-                VertexAttribute::with_buffer("a_inst_scale", VertexFormat::Float1, 1),
-                VertexAttribute::with_buffer("a_inst_color", VertexFormat::Float4, 1),
-            ],
-            shader,
-            PipelineParams {
-                depth_test: Comparison::LessOrEqual,
-                depth_write: true,
-                ..Default::default()
-            },
-        );
+        .map_err(|err| anyhow!("tessellation failed: {:?}", err))?;
 
-        Self {
-            pipeline,
-            draw_calls: vec![],
-            missing_bindings: false,
-            camera_pan: (0.0, 0.0),
-            camera_zoom: 1.0,
+    Ok(geometry)
+}
+
+/// Hash the vertices & indices of tessellated geometry and the bitmaps & masks drawn with it.
+fn geometry_hash(
+    geometry: &VertexBuffers<Vertex, u32>,
+    images: &[TexturedRect],
+    clips: &[ClipMask],
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for geometry in std::iter::once(geometry).chain(clips.iter().map(|clip| &clip.mask)) {
+        geometry.indices.hash(&mut hasher);
+        for vertex in geometry.vertices.iter() {
+            vertex.bits().hash(&mut hasher);
         }
     }
+    for clip in clips.iter() {
+        clip.indices.hash(&mut hasher);
+    }
+    for image in images.iter() {
+        image.texture.hash(&mut hasher);
+        [image.pos.0, image.pos.1, image.size.0, image.size.1]
+            .iter()
+            .for_each(|value| value.to_bits().hash(&mut hasher));
+    }
 
-    /// Upload a lyon path.
-    ///
-    /// Returns a reference that can be used to add instances.
-    pub fn upload_path<P>(&mut self, path: P, color: Color, opacity: f32) -> Mesh
-    where
-        P: IntoIterator<Item = PathEvent>,
-    {
-        // Tessalate the path, converting it to vertices & indices
-        let mut geometry: VertexBuffers<Vertex, u16> = VertexBuffers::new();
-        let mut tessellator = FillTessellator::new();
-        {
-            tessellator
-                .tessellate(
-                    path,
-                    &FillOptions::default(),
-                    &mut BuffersBuilder::new(&mut geometry, VertexCtor::new(color, opacity)),
-                )
-                .unwrap();
-        }
-        let vertices = geometry.vertices.clone();
-        let indices = geometry.indices;
-
-        // Create an OpenGL draw call for the path
-        let draw_call = DrawCall {
-            vertices,
-            indices,
-            bindings: None,
-            instances: vec![],
-            refresh_instances: false,
-        };
-        self.draw_calls.push(draw_call);
+    hasher.finish()
+}
 
-        // Tell the next render loop to create bindings for this
-        self.missing_bindings = true;
+/// The top left & bottom right corners of the rectangle around the vertices of the chunks.
+fn chunk_bounds(chunks: &[Chunk]) -> ((f32, f32), (f32, f32)) {
+    chunks.iter().flat_map(|chunk| chunk.vertices.iter()).fold(
+        ((0.0, 0.0), (0.0, 0.0)),
+        |((min_x, min_y), (max_x, max_y)), vertex| {
+            let [x, y] = vertex.pos;
+            ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+        },
+    )
+}
 
-        // Return the draw call in a newtype struct so it can be used as a reference
-        Mesh(self.draw_calls.len() - 1)
+/// The chunks of a mesh, a quad for every bitmap followed by the tessellated geometry.
+///
+/// A clipped range of the geometry is drawn between marking & clearing its mask.
+fn mesh_chunks(
+    geometry: &VertexBuffers<Vertex, u32>,
+    images: &[TexturedRect],
+    clips: &[ClipMask],
+) -> Vec<Chunk> {
+    let mut chunks = images.iter().map(Chunk::textured).collect::<Vec<_>>();
+    if clips.is_empty() {
+        // A mesh with only bitmaps doesn't need an empty chunk
+        chunks.extend(
+            split_geometry(geometry)
+                .into_iter()
+                .filter(|chunk| images.is_empty() || !chunk.indices.is_empty()),
+        );
+
+        return chunks;
     }
 
-    /// Upload lyon geometry.
-    ///
-    /// Returns a reference that can be used to add instances.
-    pub fn upload_buffers(&mut self, geometry: &VertexBuffers<Vertex, u16>) -> Result<Mesh> {
-        let vertices = geometry.vertices.clone();
-        let indices = geometry.indices.clone();
+    let mut start = 0;
+    for clip in clips.iter() {
+        chunks.extend(split_geometry(&sub_geometry(
+            geometry,
+            start..clip.indices.start,
+        )));
+
+        let mask = split_geometry(&clip.mask);
+        chunks.extend(mask.iter().cloned().map(|chunk| chunk.clip(Clip::Mask)));
+        chunks.extend(
+            split_geometry(&sub_geometry(geometry, clip.indices.clone()))
+                .into_iter()
+                .map(|chunk| chunk.clip(Clip::Clipped)),
+        );
+        chunks.extend(mask.into_iter().map(|chunk| chunk.clip(Clip::Unmask)));
 
-        // Create an OpenGL draw call for the path
-        let draw_call = DrawCall {
-            vertices,
-            indices,
-            bindings: None,
-            instances: vec![],
-            refresh_instances: false,
-        };
-        self.draw_calls.push(draw_call);
+        start = clip.indices.end;
+    }
+    chunks.extend(split_geometry(&sub_geometry(
+        geometry,
+        start..geometry.indices.len(),
+    )));
 
-        // Tell the next render loop to create bindings for this
-        self.missing_bindings = true;
+    chunks.retain(|chunk| !chunk.indices.is_empty());
 
-        // Return the draw call in a newtype struct so it can be used as a reference
-        Ok(Mesh(self.draw_calls.len() - 1))
+    chunks
+}
+
+/// The triangles of a range of indices, with only the vertices they use.
+fn sub_geometry(
+    geometry: &VertexBuffers<Vertex, u32>,
+    indices: Range<usize>,
+) -> VertexBuffers<Vertex, u32> {
+    let mut sub = VertexBuffers::new();
+    let mut remapped: HashMap<u32, u32> = HashMap::new();
+    for &index in geometry.indices[indices].iter() {
+        let local = *remapped.entry(index).or_insert_with(|| {
+            sub.vertices.push(geometry.vertices[index as usize]);
+
+            (sub.vertices.len() - 1) as u32
+        });
+        sub.indices.push(local);
     }
 
-    /// Render the graphics.
-    pub fn render(&mut self, ctx: &mut Context) {
-        let (width, height) = ctx.screen_size();
+    sub
+}
 
-        // Create bindings & update the instance vertices if necessary
-        if self.missing_bindings {
-            self.draw_calls.iter_mut().for_each(|dc| {
-                // Create bindings if missing
-                if dc.bindings.is_none() {
-                    dc.create_bindings(ctx);
-                }
-            });
+/// Split tessellated geometry into chunks of whole triangles that can be indexed with 16 bits.
+fn split_geometry(geometry: &VertexBuffers<Vertex, u32>) -> Vec<Chunk> {
+    let max_vertices = u16::MAX as usize + 1;
 
-            self.missing_bindings = false;
+    // Most meshes fit in a single chunk
+    if geometry.vertices.len() <= max_vertices {
+        return vec![Chunk::new(
+            geometry.vertices.clone(),
+            geometry.indices.iter().map(|&index| index as u16).collect(),
+        )];
+    }
+
+    let mut chunks = vec![];
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    // The index of every vertex of the geometry in the current chunk
+    let mut remapped: HashMap<u32, u16> = HashMap::new();
+    for triangle in geometry.indices.chunks(3) {
+        let new_vertices = triangle
+            .iter()
+            .filter(|index| !remapped.contains_key(index))
+            .count();
+        if vertices.len() + new_vertices > max_vertices {
+            chunks.push(Chunk::new(
+                mem::take(&mut vertices),
+                mem::take(&mut indices),
+            ));
+            remapped.clear();
         }
 
-        // Render the pass to the render target
-        ctx.begin_default_pass(PassAction::clear_color(0.4, 0.7, 1.0, 1.0));
+        for &index in triangle {
+            let local = *remapped.entry(index).or_insert_with(|| {
+                vertices.push(geometry.vertices[index as usize]);
 
-        // Render the separate draw calls
-        for dc in self.draw_calls.iter_mut() {
-            // Only render when we actually have instances
-            if dc.instances.is_empty() {
-                continue;
-            }
+                (vertices.len() - 1) as u16
+            });
+            indices.push(local);
+        }
+    }
+    if !indices.is_empty() {
+        chunks.push(Chunk::new(vertices, indices));
+    }
 
-            let bindings = dc.bindings.as_ref().unwrap();
-            if dc.refresh_instances {
-                // Upload the instance positions
-                bindings.vertex_buffers[1].update(ctx, &dc.instances);
+    chunks
+}
 
-                dc.refresh_instances = false;
-            }
+/// The pixels of a rendered frame.
+#[derive(Debug, Clone)]
+pub struct Readback {
+    /// Width in pixels.
+    pub width: usize,
+    /// Height in pixels.
+    pub height: usize,
+    /// RGBA values of the pixels, starting with the top row.
+    pub pixels: Vec<u8>,
+}
 
-            ctx.apply_pipeline(&self.pipeline);
-            ctx.apply_scissor_rect(0, 0, width as i32, height as i32);
-            ctx.apply_bindings(bindings);
-            ctx.apply_uniforms(&geom_shader::Uniforms {
-                zoom: (self.camera_zoom / width, self.camera_zoom / height),
-                pan: (self.camera_pan.0, self.camera_pan.1),
-            });
-            ctx.draw(0, dc.indices.len() as i32, dc.instances.len() as i32);
+impl Readback {
+    /// Read the pixels of the default framebuffer.
+    fn read(width: usize, height: usize) -> Self {
+        let mut pixels = vec![0; width * height * 4];
+        // Safe because the buffer is large enough for the requested RGBA pixels
+        unsafe {
+            miniquad::gl::glReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                miniquad::gl::GL_RGBA,
+                miniquad::gl::GL_UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut _,
+            );
         }
 
-        ctx.end_render_pass();
+        // OpenGL starts with the bottom row
+        let pixels = pixels
+            .chunks_exact(width * 4)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
 
-        ctx.commit_frame();
+        Self {
+            width,
+            height,
+            pixels,
+        }
     }
 
-    /// Set the camera panning position.
-    pub fn set_camera_pos(&mut self, x: f32, y: f32) {
-        self.camera_pan.0 = x;
-        self.camera_pan.1 = y;
-    }
+    /// Keep only every n-th pixel in both directions.
+    pub fn downscaled(&self, factor: usize) -> Self {
+        let factor = factor.max(1);
+        let width = self.width / factor;
+        let height = self.height / factor;
+
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row = y * factor * self.width;
+            for x in 0..width {
+                let index = (row + x * factor) * 4;
+                pixels.extend_from_slice(&self.pixels[index..index + 4]);
+            }
+        }
 
-    /// Set the camera zooming.
-    pub fn set_camera_zoom(&mut self, zoom: f32) {
-        self.camera_zoom = zoom;
+        Self {
+            width,
+            height,
+            pixels,
+        }
     }
+}
 
-    /// Bind the GameLisp functions.
-    pub fn bind_functions(runtime: &Runtime) {
-        runtime.run(|| {
-            glsp::bind_rfn("set_camera_pos", rfn!(Self::set_camera_pos))?;
-            glsp::bind_rfn("set_camera_zoom", rfn!(Self::set_camera_zoom))?;
+/// Rectangle in screen space pixels, with the origin at the top left, used for clipping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClipRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
 
-            Ok(())
-        });
+impl ClipRect {
+    /// Multiply the rectangle, converting it from UI pixels to screen pixels.
+    fn scaled(self, scale: f32) -> Self {
+        Self {
+            x: (self.x as f32 * scale) as i32,
+            y: (self.y as f32 * scale) as i32,
+            width: (self.width as f32 * scale).ceil() as i32,
+            height: (self.height as f32 * scale).ceil() as i32,
+        }
     }
 }
 
-/// A single uploaded mesh as a draw call.
-#[derive(Debug)]
-struct DrawCall {
+/// Which pipeline draws a chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ChunkKind {
+    /// Vector paths with a color for every vertex.
+    Vector,
+    /// A bitmap.
+    Textured,
+    /// Vector paths stretched to the size of the instance.
+    NineSlice,
+    /// A mask marked in the stencil buffer without drawing it.
+    Mask,
+    /// Vector paths only drawn where the mask is marked.
+    Clipped,
+    /// A mask cleared from the stencil buffer.
+    Unmask,
+}
+
+/// How a chunk takes part in clipping with the stencil buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Clip {
+    /// Drawn everywhere.
+    None,
+    /// Marks its triangles in the stencil buffer.
+    Mask,
+    /// Only drawn inside the marked triangles.
+    Clipped,
+    /// Clears its triangles from the stencil buffer.
+    Unmask,
+}
+
+/// A part of a mesh with few enough vertices to be indexed with 16 bits, which every GPU supports.
+#[derive(Debug, Clone)]
+struct Chunk {
     /// Render vertices, build by lyon path.
     vertices: Vec<Vertex>,
     /// Render indices, build by lyon path.
     indices: Vec<u16>,
     /// Render bindings, generated on render loop if empty.
     bindings: Option<Bindings>,
+    /// Render bindings for the screen space instances, generated together with the bindings.
+    screen_bindings: Option<Bindings>,
+    /// The bitmap the chunk is filled with, drawn with the textured pipeline.
+    texture: Option<TextureId>,
+    /// Whether vertices move with the size of the instances, drawn with the nine-slice pipeline.
+    stretchable: bool,
+    /// Whether it's a mask or clipped by one, drawn with the pipelines using the stencil buffer.
+    clip: Clip,
+    /// Whether some vertices glow, drawn again into the bloom.
+    glows: bool,
+    /// The amount of vertices & indices the buffers have room for when the geometry is updated,
+    /// `None` when the buffers can't be changed.
+    capacity: Option<(usize, usize)>,
+}
+
+impl Chunk {
+    /// Create a chunk without bindings.
+    fn new(vertices: Vec<Vertex>, indices: Vec<u16>) -> Self {
+        let stretchable = vertices.iter().any(|vertex| vertex.stretch != [0.0, 0.0]);
+        let glows = vertices.iter().any(|vertex| vertex.glow > 0.0);
+
+        Self {
+            stretchable,
+            glows,
+            vertices,
+            indices,
+            bindings: None,
+            screen_bindings: None,
+            texture: None,
+            clip: Clip::None,
+            capacity: None,
+        }
+    }
+
+    /// Use the chunk for clipping.
+    fn clip(mut self, clip: Clip) -> Self {
+        self.clip = clip;
+
+        self
+    }
+
+    /// Create a quad that stretches a bitmap over a rectangle.
+    fn textured(image: &TexturedRect) -> Self {
+        let ((left, top), (width, height)) = (image.pos, image.size);
+        let vertex = |x: f32, y: f32, u: f32, v: f32| Vertex {
+            pos: [left + x * width, top + y * height],
+            // The instance color is multiplied with white, so it tints the bitmap
+            color: [1.0; 4],
+            uv: [u, v],
+            stretch: [0.0, 0.0],
+            glow: 0.0,
+        };
+
+        let mut chunk = Self::new(
+            vec![
+                vertex(0.0, 0.0, 0.0, 0.0),
+                vertex(1.0, 0.0, 1.0, 0.0),
+                vertex(1.0, 1.0, 1.0, 1.0),
+                vertex(0.0, 1.0, 0.0, 1.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        chunk.texture = Some(image.texture);
+
+        chunk
+    }
+
+    /// The pipeline the chunk is drawn with.
+    fn kind(&self) -> ChunkKind {
+        match self.clip {
+            Clip::Mask => ChunkKind::Mask,
+            Clip::Clipped => ChunkKind::Clipped,
+            Clip::Unmask => ChunkKind::Unmask,
+            Clip::None if self.texture.is_some() => ChunkKind::Textured,
+            Clip::None if self.stretchable => ChunkKind::NineSlice,
+            Clip::None => ChunkKind::Vector,
+        }
+    }
+
+    /// Whether the vertices & indices are the same as the other chunk.
+    fn has_geometry(&self, other: &Chunk) -> bool {
+        self.texture == other.texture
+            && self.clip == other.clip
+            && self.indices == other.indices
+            && self.vertices.len() == other.vertices.len()
+            && self
+                .vertices
+                .iter()
+                .zip(other.vertices.iter())
+                .all(|(a, b)| a.bits() == b.bits())
+    }
+}
+
+/// A single uploaded mesh as a draw call for every chunk.
+#[derive(Debug)]
+struct DrawCall {
+    /// The geometry, split into parts that can be indexed with 16 bits.
+    chunks: Vec<Chunk>,
+    /// The top left & bottom right corners of the geometry, relative to the instance position.
+    bounds: ((f32, f32), (f32, f32)),
     /// List of instances to render.
     instances: Vec<Instance>,
-    /// Whether the instance information should be reuploaded to the GPU.
-    refresh_instances: bool,
+    /// The instances in the buffer on the GPU, the ones that were visible during the last upload.
+    visible_instances: Vec<Instance>,
+    /// List of instances to render in screen space with an optional clip, cleared every frame.
+    screen_instances: Vec<(Option<ClipRect>, Instance)>,
+    /// Name of the asset the mesh belongs to, for the memory usage.
+    label: &'static str,
+    /// Whether uploading the same geometry again returns this mesh.
+    shared: bool,
+    /// How many uploads returned this mesh, it's only removed when all of them removed it.
+    references: usize,
+    /// Name of the registered shader the vector paths are drawn with instead of the vector one.
+    shader: Option<&'static str>,
 }
 
 impl DrawCall {
-    /// Create bindings if they are missing.
-    fn create_bindings(&mut self, ctx: &mut Context) {
-        // The vertex buffer of the vector paths
-        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &self.vertices);
-        // The index buffer of the vector paths
-        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &self.indices);
+    /// Whether some chunks have glowing vertices.
+    fn glows(&self) -> bool {
+        self.chunks.iter().any(|chunk| chunk.glows)
+    }
+
+    /// Whether the chunks have the same geometry.
+    fn has_chunks(&self, chunks: &[Chunk]) -> bool {
+        self.chunks.len() == chunks.len()
+            && self
+                .chunks
+                .iter()
+                .zip(chunks.iter())
+                .all(|(a, b)| a.has_geometry(b))
+    }
+
+    /// The instances that can overlap the view rectangle.
+    ///
+    /// The bounds are turned into a circle around the origin so rotating the instances doesn't
+    /// matter, this can keep some instances just outside of the view but never skips visible ones.
+    fn visible(&self, ((left, top), (right, bottom)): ((f32, f32), (f32, f32))) -> Vec<Instance> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds;
+        let radius = min_x
+            .abs()
+            .max(max_x.abs())
+            .hypot(min_y.abs().max(max_y.abs()));
+
+        self.instances
+            .iter()
+            .filter(|instance| {
+                // Stretched vertices move at most the size of the instance
+                let (width, height) = instance.size();
+                let (scale_x, scale_y) = instance.scale();
+                let radius =
+                    (radius + width.abs().hypot(height.abs())) * scale_x.abs().max(scale_y.abs());
+
+                instance.x() + radius >= left
+                    && instance.x() - radius <= right
+                    && instance.y() + radius >= top
+                    && instance.y() - radius <= bottom
+            })
+            .copied()
+            .collect()
+    }
 
+    /// Whether the bindings of the chunks are created.
+    fn has_bindings(&self) -> bool {
+        self.chunks.iter().all(|chunk| chunk.bindings.is_some())
+    }
+
+    /// The bytes allocated on the GPU for the buffers.
+    fn memory(&self) -> MeshMemory {
+        MeshMemory {
+            vertices: self
+                .chunks
+                .iter()
+                .map(|chunk| {
+                    chunk
+                        .capacity
+                        .map_or(chunk.vertices.len(), |(vertices, _)| vertices)
+                        * mem::size_of::<Vertex>()
+                })
+                .sum(),
+            indices: self
+                .chunks
+                .iter()
+                .map(|chunk| {
+                    chunk
+                        .capacity
+                        .map_or(chunk.indices.len(), |(_, indices)| indices)
+                        * mem::size_of::<u16>()
+                })
+                .sum(),
+            // The instance buffers have a fixed size, allocated together with the bindings
+            instances: if self.has_bindings() {
+                (MAX_MESH_INSTANCES + MAX_SCREEN_INSTANCES) * mem::size_of::<Instance>()
+            } else {
+                0
+            },
+        }
+    }
+
+    /// Delete the buffers of the bindings, they have to be created again before drawing.
+    fn delete_bindings(&mut self) {
+        // The instance buffers are shared by the chunks
+        if let Some(chunk) = self.chunks.first() {
+            for bindings in chunk.bindings.iter().chain(chunk.screen_bindings.iter()) {
+                bindings.vertex_buffers[1].delete();
+            }
+        }
+
+        for chunk in self.chunks.iter_mut() {
+            if let Some(bindings) = chunk.bindings.take() {
+                bindings.vertex_buffers[0].delete();
+                bindings.index_buffer.delete();
+            }
+            chunk.screen_bindings = None;
+        }
+    }
+
+    /// Create bindings if they are missing, the textures of the chunks must be created.
+    fn create_bindings(&mut self, ctx: &mut Context, textures: &[Option<Texture>]) {
         // A dynamic buffer that will contain all positions for all instances
         let instance_positions = Buffer::stream(
             ctx,
@@ -245,12 +1989,60 @@ impl DrawCall {
             MAX_MESH_INSTANCES * mem::size_of::<Instance>(),
         );
 
-        let bindings = Bindings {
-            vertex_buffers: vec![vertex_buffer, instance_positions],
-            index_buffer,
-            images: vec![],
-        };
-        self.bindings = Some(bindings);
+        // A smaller dynamic buffer for the instances drawn in screen space
+        let screen_instance_positions = Buffer::stream(
+            ctx,
+            BufferType::VertexBuffer,
+            MAX_SCREEN_INSTANCES * mem::size_of::<Instance>(),
+        );
+
+        // The instance buffers are shared by the chunks
+        for chunk in self.chunks.iter_mut() {
+            let (vertex_buffer, index_buffer) = match chunk.capacity {
+                // Buffers with room for the geometry of a mesh that's updated
+                Some((vertices, indices)) => {
+                    let vertex_buffer = Buffer::stream(
+                        ctx,
+                        BufferType::VertexBuffer,
+                        vertices * mem::size_of::<Vertex>(),
+                    );
+                    vertex_buffer.update(ctx, &chunk.vertices);
+                    let index_buffer = Buffer::stream(
+                        ctx,
+                        BufferType::IndexBuffer,
+                        indices * mem::size_of::<u16>(),
+                    );
+                    index_buffer.update(ctx, &chunk.indices);
+
+                    (vertex_buffer, index_buffer)
+                }
+                // The vertex & index buffers of the vector paths
+                None => (
+                    Buffer::immutable(ctx, BufferType::VertexBuffer, &chunk.vertices),
+                    Buffer::immutable(ctx, BufferType::IndexBuffer, &chunk.indices),
+                ),
+            };
+            // The bitmap sampled by the textured pipeline
+            let images = chunk
+                .texture
+                .and_then(|texture| textures[texture.0])
+                .into_iter()
+                .collect::<Vec<_>>();
+
+            let bindings = Bindings {
+                vertex_buffers: vec![vertex_buffer, instance_positions],
+                index_buffer,
+                images: images.clone(),
+            };
+            chunk.bindings = Some(bindings);
+
+            let screen_bindings = Bindings {
+                vertex_buffers: vec![vertex_buffer, screen_instance_positions],
+                index_buffer,
+                images,
+            };
+            chunk.screen_bindings = Some(screen_bindings);
+        }
     }
 }
 
@@ -259,6 +2051,111 @@ impl DrawCall {
 pub struct Vertex {
     pos: [f32; 2],
     color: [f32; 4],
+    /// The position on the bitmap of textured chunks, from 0.0 to 1.0.
+    uv: [f32; 2],
+    /// How far the vertex moves with the size of the instance on both axes, from 0.0 to 1.0.
+    stretch: [f32; 2],
+    /// How much of the color is drawn into the bloom, 1.0 for paths marked with `glow="true"`.
+    glow: f32,
+}
+
+impl Vertex {
+    /// The exact bits of the position, color, texture coordinates, stretch & glow, for comparing
+    /// & hashing.
+    fn bits(&self) -> [u32; 11] {
+        [
+            self.pos[0].to_bits(),
+            self.pos[1].to_bits(),
+            self.color[0].to_bits(),
+            self.color[1].to_bits(),
+            self.color[2].to_bits(),
+            self.color[3].to_bits(),
+            self.uv[0].to_bits(),
+            self.uv[1].to_bits(),
+            self.stretch[0].to_bits(),
+            self.stretch[1].to_bits(),
+            self.glow.to_bits(),
+        ]
+    }
+
+    /// Get the position.
+    pub fn pos(&self) -> [f32; 2] {
+        self.pos
+    }
+
+    /// Set the position.
+    pub fn set_pos(&mut self, pos: [f32; 2]) {
+        self.pos = pos;
+    }
+
+    /// Interpolate linearly between this and another vertex.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut vertex = *self;
+        for (a, b) in vertex.pos.iter_mut().zip(other.pos.iter()) {
+            *a += (b - *a) * t;
+        }
+        for (a, b) in vertex.color.iter_mut().zip(other.color.iter()) {
+            *a += (b - *a) * t;
+        }
+
+        vertex
+    }
+
+    /// Move the position with the transform of an SVG element.
+    pub fn transform(&mut self, transform: &Transform) {
+        let (x, y) = transform.apply(self.pos[0] as f64, self.pos[1] as f64);
+        self.pos = [x as f32, y as f32];
+    }
+
+    /// Set how far the vertex moves with the size of the instance, drawing the mesh with the
+    /// nine-slice pipeline when it's not zero.
+    pub fn set_stretch(&mut self, stretch: [f32; 2]) {
+        self.stretch = stretch;
+    }
+
+    /// Set how much of the color is drawn into the bloom, the mesh is drawn again for the bloom
+    /// when it's not zero.
+    pub fn set_glow(&mut self, glow: f32) {
+        self.glow = glow;
+    }
+}
+
+/// The time the renderer spent on the main thread in seconds.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RenderTimings {
+    /// Tessellating paths & creating meshes.
+    pub meshes: f64,
+    /// Creating bindings & uploading instances.
+    pub upload: f64,
+    /// Issuing the draw calls & presenting the frame.
+    pub submit: f64,
+    /// Applying the full screen effects.
+    pub post: f64,
+}
+
+/// Amount of Z values in every layer.
+pub const LAYER_DEPTH: u8 = 64;
+
+/// The layers of the world, every layer is drawn on top of the previous ones regardless of the
+/// order the meshes are uploaded in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layer {
+    /// The terrain.
+    Ground,
+    /// Characters & buildings.
+    Units,
+    /// Arrows & other things flying around.
+    Projectiles,
+    /// Text in the world.
+    Text,
+}
+
+impl Layer {
+    /// The lowest Z value of the layer, the instances in it can be `LAYER_DEPTH` higher.
+    pub fn z(self) -> u8 {
+        self as u8 * LAYER_DEPTH
+    }
 }
 
 rdata! {
@@ -268,9 +2165,9 @@ rdata! {
 pub struct Instance {
     position: [f32; 3],
     rotation: f32,
-    scale: f32,
-    color: [f32; 3],
-    alpha: f32,
+    scale: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
 }
 
 meths {
@@ -293,9 +2190,9 @@ impl Instance {
         Self {
             position: [x, y, 0.0],
             rotation: 0.0,
-            scale: 1.0,
-            color: [1.0, 1.0, 1.0],
-            alpha: 1.0,
+            scale: [1.0, 1.0],
+            size: [0.0, 0.0],
+            color: [1.0, 1.0, 1.0, 1.0],
         }
     }
 
@@ -329,14 +2226,43 @@ impl Instance {
         u8::MAX - (self.position[2] * 255.0) as u8
     }
 
-    /// Set the scale.
+    /// Set the Z position to a depth inside a layer, clamped to the depth of the layer.
+    pub fn set_layer(&mut self, layer: Layer, depth: u8) {
+        self.set_z(layer.z() + depth.min(LAYER_DEPTH - 1));
+    }
+
+    /// Set the same scale for both axes, keeping the flips.
     pub fn set_scale(&mut self, scale: f32) {
-        self.scale = scale;
+        self.scale = [
+            scale.abs().copysign(self.scale[0]),
+            scale.abs().copysign(self.scale[1]),
+        ];
+    }
+
+    /// Set the horizontal & vertical scale, a negative scale mirrors the mesh along the axis.
+    pub fn set_scale_xy(&mut self, scale_x: f32, scale_y: f32) {
+        self.scale = [scale_x, scale_y];
+    }
+
+    /// Get the horizontal & vertical scale.
+    pub fn scale(&self) -> (f32, f32) {
+        (self.scale[0], self.scale[1])
+    }
+
+    /// Mirror the mesh horizontally, like a character facing left instead of right.
+    pub fn set_flip_x(&mut self, flipped: bool) {
+        let sign = if flipped { -1.0 } else { 1.0 };
+        self.scale[0] = self.scale[0].abs() * sign;
+    }
+
+    /// Set the size nine-slice meshes are stretched to, other meshes ignore it.
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        self.size = [width, height];
     }
 
-    /// Get the scale.
-    pub fn scale(&self) -> f32 {
-        self.scale
+    /// Get the size nine-slice meshes are stretched to.
+    pub fn size(&self) -> (f32, f32) {
+        (self.size[0], self.size[1])
     }
 
     /// Set the rotation.
@@ -349,20 +2275,34 @@ impl Instance {
         self.rotation
     }
 
-    /// Set the color multiplier.
+    /// Set the color multiplier, keeping the alpha.
     pub fn set_color_multiplier(&mut self, r: f32, g: f32, b: f32) {
-        self.color = [r, g, b];
+        self.color[0] = r;
+        self.color[1] = g;
+        self.color[2] = b;
     }
 
     /// Get the color multiplier.
     pub fn color_multiplier(&self) -> (f32, f32, f32) {
         (self.color[0], self.color[1], self.color[2])
     }
+
+    /// Set the RGBA tint, multiplied with the colors of the mesh.
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    /// Get the RGBA tint.
+    pub fn color(&self) -> [f32; 4] {
+        self.color
+    }
 }
 
 /// Used by lyon to create vertices.
 pub struct VertexCtor {
     color: [f32; 4],
+    /// Colors the vertices by their position instead.
+    gradient: Option<Gradient>,
 }
 
 impl VertexCtor {
@@ -374,6 +2314,27 @@ impl VertexCtor {
                 color.blue as f32 / 255.0,
                 alpha,
             ],
+            gradient: None,
+        }
+    }
+
+    /// Sample the color of every vertex from a gradient, the alpha is multiplied with it.
+    pub fn with_gradient(gradient: Gradient, alpha: f32) -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0, alpha],
+            gradient: Some(gradient),
+        }
+    }
+
+    /// The color of a vertex at a position.
+    fn color_at(&self, position: Point) -> [f32; 4] {
+        match &self.gradient {
+            Some(gradient) => {
+                let [r, g, b, a] = gradient.color_at(position);
+
+                [r, g, b, a * self.color[3]]
+            }
+            None => self.color,
         }
     }
 }
@@ -382,7 +2343,10 @@ impl FillVertexConstructor<Vertex> for VertexCtor {
     fn new_vertex(&mut self, position: Point, _: FillAttributes) -> Vertex {
         Vertex {
             pos: position.to_array(),
-            color: self.color,
+            color: self.color_at(position),
+            uv: [0.0, 0.0],
+            stretch: [0.0, 0.0],
+            glow: 0.0,
         }
     }
 }
@@ -391,7 +2355,10 @@ impl StrokeVertexConstructor<Vertex> for VertexCtor {
     fn new_vertex(&mut self, position: Point, _: StrokeAttributes) -> Vertex {
         Vertex {
             pos: position.to_array(),
-            color: self.color,
+            color: self.color_at(position),
+            uv: [0.0, 0.0],
+            stretch: [0.0, 0.0],
+            glow: 0.0,
         }
     }
 }
@@ -399,63 +2366,72 @@ impl StrokeVertexConstructor<Vertex> for VertexCtor {
 mod geom_shader {
     use miniquad::graphics::*;
 
-    pub const VERTEX: &str = r#"#version 100
+    pub const VERTEX: &str = include_str!("../assets/shaders/geom.vert");
 
-uniform vec2 u_zoom;
-uniform vec2 u_pan;
+    pub const FRAGMENT: &str = include_str!("../assets/shaders/geom.frag");
 
-attribute vec2 a_pos;
-attribute vec4 a_color;
-attribute vec3 a_inst_pos;
-attribute float a_inst_rot;
-attribute float a_inst_scale;
-attribute vec4 a_inst_color;
-
-varying lowp vec4 color;
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![UniformDesc::new("u_transform", UniformType::Mat4)],
+            },
+        }
+    }
 
-void main() {
-    // Rotate vertices around the zero center
-    float s = sin(a_inst_rot);
-    float c = cos(a_inst_rot);
-    mat2 rotation_mat = mat2(c, -s, s, c);
-    vec2 rotated_pos = a_pos * rotation_mat;
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct Uniforms {
+        pub transform: [f32; 16],
+    }
+}
 
-    // Scale the rotated vertices
-    vec2 scaled_pos = rotated_pos * a_inst_scale;
+mod textured_shader {
+    use miniquad::graphics::*;
 
-    // Offset scaled position with instance position
-    // Offset with the camera multiplied by the Z position
-    vec2 pos = scaled_pos + a_inst_pos.xy + u_pan * a_inst_pos.z;
+    pub const VERTEX: &str = include_str!("../assets/shaders/textured.vert");
 
-    gl_Position = vec4(pos * vec2(1.0, -1.0) * u_zoom, a_inst_pos.z, 1.0);
+    pub const FRAGMENT: &str = include_str!("../assets/shaders/textured.frag");
 
-    color = a_color * a_inst_color;
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["tex".to_string()],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![UniformDesc::new("u_transform", UniformType::Mat4)],
+            },
+        }
+    }
 }
-"#;
 
-    pub const FRAGMENT: &str = r#"#version 100
+mod nine_slice_shader {
+    pub const VERTEX: &str = include_str!("../assets/shaders/nine_slice.vert");
 
-varying lowp vec4 color;
-
-void main() {
-    gl_FragColor = color;
+    pub const FRAGMENT: &str = include_str!("../assets/shaders/nine_slice.frag");
 }
-"#;
 
-    pub const META: ShaderMeta = ShaderMeta {
-        images: &[],
-        uniforms: UniformBlockLayout {
-            uniforms: &[
-                UniformDesc::new("u_zoom", UniformType::Float2),
-                UniformDesc::new("u_pan", UniformType::Float2),
-            ],
-        },
-    };
+mod custom_shader {
+    use miniquad::graphics::*;
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![
+                    UniformDesc::new("u_transform", UniformType::Mat4),
+                    UniformDesc::new("u_time", UniformType::Float1),
+                ],
+            },
+        }
+    }
 
     #[repr(C)]
     #[derive(Debug)]
     pub struct Uniforms {
-        pub zoom: (f32, f32),
-        pub pan: (f32, f32),
+        pub transform: [f32; 16],
+        pub time: f32,
     }
 }
+
+mod glow_shader {
+    pub const FRAGMENT: &str = include_str!("../assets/shaders/glow.frag");
+}
@@ -0,0 +1,300 @@
+use crate::{
+    checkpoint::CHECKPOINT_INTERVAL,
+    input::{GamepadAxis, GamepadButton},
+    key::Key,
+};
+use anyhow::{anyhow, Result};
+use miniquad::MouseButton;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Version of the file format, replays with another version can't be played back.
+const REPLAY_VERSION: u32 = 1;
+
+/// A mouse button that can be stored in a replay.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+    Unknown,
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Self {
+        match button {
+            MouseButton::Left => Button::Left,
+            MouseButton::Right => Button::Right,
+            MouseButton::Middle => Button::Middle,
+            MouseButton::Unknown => Button::Unknown,
+        }
+    }
+}
+
+impl From<Button> for MouseButton {
+    fn from(button: Button) -> Self {
+        match button {
+            Button::Left => MouseButton::Left,
+            Button::Right => MouseButton::Right,
+            Button::Middle => MouseButton::Middle,
+            Button::Unknown => MouseButton::Unknown,
+        }
+    }
+}
+
+/// Input that influences the gameplay.
+///
+/// The positions are in UI pixels, so a replay plays back the same with another UI scale. They're
+/// stored as `f64` so they survive the text format exactly.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum InputEvent {
+    /// The mouse moved.
+    MouseMotion { x: f64, y: f64 },
+    /// A mouse button is pressed.
+    MouseButtonDown { button: Button, x: f64, y: f64 },
+    /// A mouse button is released.
+    MouseButtonUp { button: Button, x: f64, y: f64 },
+    /// The mouse wheel is scrolled.
+    MouseWheel { y: f64 },
+    /// A key is pressed, repeats while holding it aren't included.
+    KeyDown { key: Key },
+    /// A key is released.
+    KeyUp { key: Key },
+    /// A button of a gamepad is pressed.
+    GamepadButtonDown { button: GamepadButton },
+    /// A button of a gamepad is released.
+    GamepadButtonUp { button: GamepadButton },
+    /// A stick of a gamepad moved to a position between -1.0 & 1.0.
+    GamepadAxis { axis: GamepadAxis, value: f64 },
+}
+
+impl InputEvent {
+    /// The name of the kind of input, as passed to the scripts.
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputEvent::MouseMotion { .. } => "mouse-motion",
+            InputEvent::MouseButtonDown { .. } => "mouse-button-down",
+            InputEvent::MouseButtonUp { .. } => "mouse-button-up",
+            InputEvent::MouseWheel { .. } => "mouse-wheel",
+            InputEvent::KeyDown { .. } => "key-down",
+            InputEvent::KeyUp { .. } => "key-up",
+            InputEvent::GamepadButtonDown { .. } => "gamepad-button-down",
+            InputEvent::GamepadButtonUp { .. } => "gamepad-button-up",
+            InputEvent::GamepadAxis { .. } => "gamepad-axis",
+        }
+    }
+
+    /// The position in UI pixels, the scrolled amount for the mouse wheel or the position of a
+    /// gamepad stick.
+    ///
+    /// Keys & buttons don't have a position.
+    pub fn pos(&self) -> (f64, f64) {
+        match *self {
+            InputEvent::MouseMotion { x, y }
+            | InputEvent::MouseButtonDown { x, y, .. }
+            | InputEvent::MouseButtonUp { x, y, .. } => (x, y),
+            InputEvent::MouseWheel { y } => (0.0, y),
+            InputEvent::GamepadAxis { value, .. } => (value, 0.0),
+            InputEvent::KeyDown { .. }
+            | InputEvent::KeyUp { .. }
+            | InputEvent::GamepadButtonDown { .. }
+            | InputEvent::GamepadButtonUp { .. } => (0.0, 0.0),
+        }
+    }
+}
+
+/// Everything needed to simulate a single frame again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// The clamped time since the previous frame in seconds.
+    pub delta: f64,
+    /// The input handled before the frame, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<InputEvent>,
+}
+
+/// A recorded session, starting when the world is created.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// Version of the file format.
+    version: u32,
+    /// The duration of a fixed step the session was recorded with.
+    fixed_step: f64,
+    /// The seed of the random numbers the session was recorded with, missing in older replays.
+    #[serde(default)]
+    seed: u64,
+    /// All recorded frames.
+    frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// Create an empty replay.
+    pub fn new(fixed_step: f64, seed: u64) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            fixed_step,
+            seed,
+            frames: vec![],
+        }
+    }
+
+    /// The seed of the random numbers the session was recorded with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Read a replay from a TOML file, it must be recorded with the same fixed step.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load<P: AsRef<Path>>(path: P, fixed_step: f64) -> Result<Self> {
+        let replay: Self = toml::from_str(&std::fs::read_to_string(path)?)?;
+
+        if replay.version != REPLAY_VERSION {
+            Err(anyhow!(
+                "Replay version {} is not supported, expected {}",
+                replay.version,
+                REPLAY_VERSION
+            ))
+        } else if (replay.fixed_step - fixed_step).abs() > f64::EPSILON {
+            Err(anyhow!(
+                "Replay is recorded with a fixed step of {} seconds instead of {}",
+                replay.fixed_step,
+                fixed_step
+            ))
+        } else {
+            Ok(replay)
+        }
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load<P: AsRef<Path>>(_path: P, _fixed_step: f64) -> Result<Self> {
+        Err(anyhow!("Loading replays is not supported in the browser"))
+    }
+
+    /// Write the replay to a new TOML file in the working directory, returning the path.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) -> Result<String> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let path = format!("replay-{}.toml", timestamp);
+        self.save_to(&path)?;
+
+        Ok(path)
+    }
+
+    /// Write the replay to a TOML file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self) -> Result<String> {
+        Err(anyhow!("Saving replays is not supported in the browser"))
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save_to<P: AsRef<Path>>(&self, _path: P) -> Result<()> {
+        Err(anyhow!("Saving replays is not supported in the browser"))
+    }
+}
+
+/// Records the frames of the current session and plays them back.
+///
+/// Playing back continues from the cursor until the last recorded frame, after that the new
+/// frames are recorded again.
+pub struct ReplaySession {
+    /// The recorded frames.
+    replay: Replay,
+    /// The frame that's simulated next.
+    cursor: usize,
+}
+
+impl ReplaySession {
+    /// Start recording or playing back a replay from the first frame.
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    /// Whether the frames come from the replay instead of the live input.
+    pub fn is_playing(&self) -> bool {
+        self.cursor < self.replay.frames.len()
+    }
+
+    /// The frame that's simulated next.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Take the next frame to play back, `None` when recording.
+    pub fn next_frame(&mut self) -> Option<ReplayFrame> {
+        let frame = self.replay.frames.get(self.cursor).cloned()?;
+        self.cursor += 1;
+
+        Some(frame)
+    }
+
+    /// Add a frame with the live input.
+    pub fn record(&mut self, delta: f64, events: Vec<InputEvent>) {
+        self.replay.frames.push(ReplayFrame { delta, events });
+        self.cursor = self.replay.frames.len();
+    }
+
+    /// Whether a checkpoint should be saved before the next frame.
+    pub fn needs_checkpoint(&self) -> bool {
+        self.cursor % CHECKPOINT_INTERVAL == 0
+    }
+
+    /// The frame an amount of seconds from the cursor, negative to rewind, `None` when there's
+    /// nothing to seek to.
+    ///
+    /// The frames from the closest checkpoint to the target should be played back immediately.
+    pub fn seek(&self, seconds: f64) -> Option<usize> {
+        let target = self.frame_after(seconds);
+        if target == self.cursor {
+            None
+        } else {
+            Some(target)
+        }
+    }
+
+    /// Continue playing back from the frame a checkpoint is restored for.
+    pub fn jump_to(&mut self, frame: usize) {
+        self.cursor = frame.min(self.replay.frames.len());
+    }
+
+    /// The recorded frames.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Find the frame an amount of seconds from the cursor, clamped to the recorded frames.
+    fn frame_after(&self, seconds: f64) -> usize {
+        let mut remaining = seconds.abs();
+        let mut frame = self.cursor;
+
+        if seconds < 0.0 {
+            while frame > 0 && remaining > 0.0 {
+                frame -= 1;
+                remaining -= self.replay.frames[frame].delta;
+            }
+        } else {
+            while frame < self.replay.frames.len() && remaining > 0.0 {
+                remaining -= self.replay.frames[frame].delta;
+                frame += 1;
+            }
+        }
+
+        frame
+    }
+}
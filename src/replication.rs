@@ -0,0 +1,609 @@
+use crate::{
+    config::{NetworkConfig, NetworkMode},
+    replay::InputEvent,
+    unit::{Allegiance, Health},
+};
+use anyhow::{anyhow, Result};
+use generational_arena::Index;
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    f32::consts::PI,
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Amount of snapshots kept for building deltas & interpolating.
+const SNAPSHOT_HISTORY: usize = 32;
+/// Time in seconds the clients show the world behind the server, so there are always two
+/// snapshots to interpolate between.
+const INTERPOLATION_DELAY: f64 = 0.1;
+/// Difference in seconds between the shown & the wanted time at which the clients jump.
+const MAX_TIME_DRIFT: f64 = 0.25;
+/// Largest packet that can be received in bytes.
+const MAX_PACKET_SIZE: usize = 65_507;
+/// Amount of changed entities sent in a single packet, a snapshot with more is split up so every
+/// packet stays well below `MAX_PACKET_SIZE`.
+const ENTITIES_PER_PACKET: usize = 128;
+/// Amount of removed entities sent in a single packet.
+const REMOVED_PER_PACKET: usize = 512;
+/// Seconds without packets after which a client is dropped or the server is reported missing.
+const TIMEOUT: f64 = 5.0;
+
+/// Identifies an entity on both the server and the clients.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct EntityId {
+    index: u64,
+    generation: u64,
+}
+
+impl From<Index> for EntityId {
+    fn from(index: Index) -> Self {
+        let (index, generation) = index.into_raw_parts();
+
+        Self {
+            index: index as u64,
+            generation,
+        }
+    }
+}
+
+impl EntityId {
+    /// The index & generation the scripts know the entity by, GameLisp integers are 32 bits.
+    pub fn to_script(self) -> (i32, i32) {
+        (self.index as i32, self.generation as i32)
+    }
+
+    /// Convert the index & generation from the scripts back.
+    pub fn from_script((index, generation): (i32, i32)) -> Self {
+        Self {
+            index: index as u32 as u64,
+            generation: generation as u32 as u64,
+        }
+    }
+}
+
+impl From<EntityId> for Index {
+    fn from(id: EntityId) -> Self {
+        Index::from_raw_parts(id.index as usize, id.generation)
+    }
+}
+
+/// The components of an entity that are sent to the clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicatedState {
+    /// Name of the object definition, the clients draw the entity with its mesh.
+    pub def: String,
+    /// Horizontal position of the instance.
+    pub x: f32,
+    /// Vertical position of the instance.
+    pub y: f32,
+    /// Rotation of the instance in radians.
+    pub rotation: f32,
+    /// The side the entity fights for.
+    pub allegiance: Allegiance,
+    /// The lifepoints, a table so it must come after the other components.
+    pub health: Health,
+}
+
+impl ReplicatedState {
+    /// Interpolate the transform towards a newer state, the other components are taken from the
+    /// newer state when it's more than halfway.
+    fn lerp(&self, newer: &Self, t: f32) -> Self {
+        let nearest = if t < 0.5 { self } else { newer };
+
+        // Rotate along the shortest way
+        let mut rotation_delta = (newer.rotation - self.rotation) % (PI * 2.0);
+        if rotation_delta > PI {
+            rotation_delta -= PI * 2.0;
+        } else if rotation_delta < -PI {
+            rotation_delta += PI * 2.0;
+        }
+
+        Self {
+            def: nearest.def.clone(),
+            x: self.x + (newer.x - self.x) * t,
+            y: self.y + (newer.y - self.y) * t,
+            rotation: self.rotation + rotation_delta * t,
+            allegiance: nearest.allegiance,
+            health: nearest.health,
+        }
+    }
+}
+
+/// The replicated state of all entities at a moment.
+pub type Snapshot = BTreeMap<EntityId, ReplicatedState>;
+
+/// A changed entity in a snapshot delta.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntityUpdate {
+    id: EntityId,
+    state: ReplicatedState,
+}
+
+/// Sent from the server to a client every frame, a snapshot with many changes is split over
+/// multiple packets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotPacket {
+    /// The number of the snapshot.
+    tick: u64,
+    /// Which of the packets of the snapshot this is, starting at zero.
+    part: usize,
+    /// The amount of packets the snapshot is split over.
+    parts: usize,
+    /// The time on the server in seconds.
+    time: f64,
+    /// The snapshot the changes are relative to, all entities are sent without it.
+    base: Option<u64>,
+    /// All commands before this sequence number are received.
+    command_ack: u64,
+    /// The index of the player the client controls.
+    player: usize,
+    /// The entities that are new or changed since the base.
+    changed: Vec<EntityUpdate>,
+    /// The entities that are removed since the base.
+    removed: Vec<EntityId>,
+}
+
+/// The input of a client for a single frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Command {
+    sequence: u64,
+    events: Vec<InputEvent>,
+}
+
+/// Sent from a client to the server every frame.
+///
+/// Commands are sent again until the server acknowledges them, so lost packets don't matter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CommandPacket {
+    /// The newest snapshot that's received.
+    snapshot_ack: Option<u64>,
+    /// The commands that aren't acknowledged yet.
+    commands: Vec<Command>,
+}
+
+/// A server-authoritative session, as an alternative to lockstep.
+pub enum Replication {
+    /// Simulates the world and sends it to the clients.
+    Server(ReplicationServer),
+    /// Shows the world of the server and sends the input to it.
+    Client(ReplicationClient),
+}
+
+impl Replication {
+    /// Start a server or connect to one, `None` when not using client-server networking.
+    pub fn connect(config: &NetworkConfig) -> Result<Option<Self>> {
+        if cfg!(target_arch = "wasm32")
+            && (config.mode == NetworkMode::Server || config.mode == NetworkMode::Client)
+        {
+            return Err(anyhow!(
+                "Client-server networking is not supported in the browser"
+            ));
+        }
+
+        match config.mode {
+            NetworkMode::Offline | NetworkMode::Host | NetworkMode::Join => Ok(None),
+            NetworkMode::Server => {
+                let socket = UdpSocket::bind(("0.0.0.0", config.port))?;
+                socket.set_nonblocking(true)?;
+                info!("serving on port {}", config.port);
+
+                Ok(Some(Replication::Server(ReplicationServer {
+                    socket,
+                    clients: vec![],
+                    history: VecDeque::with_capacity(SNAPSHOT_HISTORY),
+                    tick: 0,
+                    next_player: 1,
+                })))
+            }
+            NetworkMode::Client => {
+                let server = (config.host.as_str(), config.port)
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| anyhow!("Server \"{}\" not found", config.host))?;
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                socket.set_nonblocking(true)?;
+                info!("connecting to {}", server);
+
+                Ok(Some(Replication::Client(ReplicationClient {
+                    socket,
+                    server,
+                    player: None,
+                    snapshots: VecDeque::with_capacity(SNAPSHOT_HISTORY),
+                    parts: BTreeMap::new(),
+                    time: 0.0,
+                    pending: vec![],
+                    commands: BTreeMap::new(),
+                    next_sequence: 0,
+                    last_receive: miniquad::date::now(),
+                    timed_out: false,
+                })))
+            }
+        }
+    }
+}
+
+/// A client connected to the server.
+struct Client {
+    /// Where the packets are sent to.
+    addr: SocketAddr,
+    /// The index of the player the client controls.
+    player: usize,
+    /// The newest snapshot the client received.
+    acked: Option<u64>,
+    /// All commands before this sequence number are received.
+    command_ack: u64,
+    /// The time of the last received packet in seconds.
+    last_receive: f64,
+}
+
+/// Sends snapshot deltas of the replicated components to the clients.
+pub struct ReplicationServer {
+    /// The non-blocking socket.
+    socket: UdpSocket,
+    /// The connected clients.
+    clients: Vec<Client>,
+    /// The most recent snapshots, the newest at the back.
+    history: VecDeque<(u64, Snapshot)>,
+    /// The number of the next snapshot.
+    tick: u64,
+    /// The player index of the next client that connects.
+    next_player: usize,
+}
+
+impl ReplicationServer {
+    /// Handle the packets of the clients, returning the new input with the player it's from.
+    pub fn poll(&mut self) -> Result<Vec<(usize, InputEvent)>> {
+        let mut input = vec![];
+
+        while let Some((packet, from)) = receive::<CommandPacket>(&self.socket)? {
+            let client = match self.clients.iter().position(|client| client.addr == from) {
+                Some(index) => &mut self.clients[index],
+                None => {
+                    info!("{} connected as player {}", from, self.next_player);
+                    self.clients.push(Client {
+                        addr: from,
+                        player: self.next_player,
+                        acked: None,
+                        command_ack: 0,
+                        last_receive: 0.0,
+                    });
+                    self.next_player += 1;
+
+                    self.clients.last_mut().expect("Client just added")
+                }
+            };
+
+            client.last_receive = miniquad::date::now();
+            client.acked = client.acked.max(packet.snapshot_ack);
+
+            // Only handle the commands that weren't received before, in order
+            for command in packet.commands {
+                if command.sequence == client.command_ack {
+                    input.extend(
+                        command
+                            .events
+                            .into_iter()
+                            .map(|event| (client.player, event)),
+                    );
+                    client.command_ack += 1;
+                }
+            }
+        }
+
+        let now = miniquad::date::now();
+        self.clients.retain(|client| {
+            let connected = now - client.last_receive < TIMEOUT;
+            if !connected {
+                info!("player {} timed out", client.player);
+            }
+
+            connected
+        });
+
+        Ok(input)
+    }
+
+    /// Send the changes since the last acknowledged snapshot to every client.
+    pub fn send_snapshot(&mut self, time: f64, snapshot: Snapshot) -> Result<()> {
+        let tick = self.tick;
+        self.tick += 1;
+
+        for client in self.clients.iter() {
+            // Send everything when the client didn't receive any snapshot that's still known
+            let base = client.acked.and_then(|acked| {
+                self.history
+                    .iter()
+                    .find(|(tick, _)| *tick == acked)
+                    .map(|(tick, base)| (*tick, base))
+            });
+
+            let changed = snapshot
+                .iter()
+                .filter(|(id, state)| base.map_or(true, |(_, base)| base.get(id) != Some(state)))
+                .map(|(id, state)| EntityUpdate {
+                    id: *id,
+                    state: state.clone(),
+                })
+                .collect::<Vec<_>>();
+            let removed = base.map_or(vec![], |(_, base)| {
+                base.keys()
+                    .filter(|id| !snapshot.contains_key(id))
+                    .copied()
+                    .collect::<Vec<_>>()
+            });
+
+            // Split the changes so a full snapshot of a big world still fits in the packets
+            let parts = ((changed.len() + ENTITIES_PER_PACKET - 1) / ENTITIES_PER_PACKET)
+                .max((removed.len() + REMOVED_PER_PACKET - 1) / REMOVED_PER_PACKET)
+                .max(1);
+            let mut changed = changed.chunks(ENTITIES_PER_PACKET);
+            let mut removed = removed.chunks(REMOVED_PER_PACKET);
+            for part in 0..parts {
+                let packet = SnapshotPacket {
+                    tick,
+                    part,
+                    parts,
+                    time,
+                    base: base.map(|(tick, _)| tick),
+                    command_ack: client.command_ack,
+                    player: client.player,
+                    changed: changed.next().map_or(vec![], <[_]>::to_vec),
+                    removed: removed.next().map_or(vec![], <[_]>::to_vec),
+                };
+                self.socket.send_to(&encode(&packet)?, client.addr)?;
+            }
+        }
+
+        self.history.push_back((tick, snapshot));
+        while self.history.len() > SNAPSHOT_HISTORY {
+            self.history.pop_front();
+        }
+
+        Ok(())
+    }
+}
+
+/// Receives the snapshots of the server and interpolates between them.
+pub struct ReplicationClient {
+    /// The non-blocking socket.
+    socket: UdpSocket,
+    /// The address of the server.
+    server: SocketAddr,
+    /// The index of the player this client controls, known after the first snapshot.
+    player: Option<usize>,
+    /// The most recent complete snapshots with the server time, the newest at the back.
+    snapshots: VecDeque<(u64, f64, Snapshot)>,
+    /// The received packets of the snapshots that are split & not complete yet, by their tick.
+    parts: BTreeMap<u64, Vec<SnapshotPacket>>,
+    /// The server time that's shown in seconds.
+    time: f64,
+    /// The input since the last command.
+    pending: Vec<InputEvent>,
+    /// The commands that aren't acknowledged yet.
+    commands: BTreeMap<u64, Vec<InputEvent>>,
+    /// The sequence number of the next command.
+    next_sequence: u64,
+    /// The time of the last received packet in seconds.
+    last_receive: f64,
+    /// Whether the missing server is already reported.
+    timed_out: bool,
+}
+
+impl ReplicationClient {
+    /// Queue input to send with the next command.
+    pub fn push_input(&mut self, event: InputEvent) {
+        self.pending.push(event);
+    }
+
+    /// Handle the snapshots the server sent since the last poll.
+    pub fn poll(&mut self) -> Result<()> {
+        while let Some((packet, from)) = receive::<SnapshotPacket>(&self.socket)? {
+            if from != self.server {
+                continue;
+            }
+
+            self.last_receive = miniquad::date::now();
+            self.timed_out = false;
+
+            if self.player != Some(packet.player) {
+                info!("connected as player {}", packet.player);
+                self.player = Some(packet.player);
+            }
+
+            // Forget the commands the server received
+            self.commands = self.commands.split_off(&packet.command_ack);
+
+            if let Some(packet) = self.join_parts(packet) {
+                self.apply_snapshot(packet);
+            }
+        }
+
+        if !self.timed_out && miniquad::date::now() - self.last_receive > TIMEOUT {
+            warn!(
+                "no packets received from the server for {} seconds",
+                TIMEOUT
+            );
+            self.timed_out = true;
+        }
+
+        Ok(())
+    }
+
+    /// Turn the pending input into a command and send all unacknowledged commands.
+    pub fn send(&mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.commands
+                .insert(self.next_sequence, std::mem::take(&mut self.pending));
+            self.next_sequence += 1;
+        }
+
+        let packet = CommandPacket {
+            snapshot_ack: self.snapshots.back().map(|(tick, _, _)| *tick),
+            commands: self
+                .commands
+                .iter()
+                .map(|(&sequence, events)| Command {
+                    sequence,
+                    events: events.clone(),
+                })
+                .collect(),
+        };
+        self.socket.send_to(&encode(&packet)?, self.server)?;
+
+        Ok(())
+    }
+
+    /// Move the shown time forward, staying a bit behind the newest snapshot.
+    pub fn advance(&mut self, delta: f64) {
+        let newest = match self.snapshots.back() {
+            Some((_, time, _)) => *time,
+            None => return,
+        };
+
+        let wanted = newest - INTERPOLATION_DELAY;
+        self.time += delta;
+        if (self.time - wanted).abs() > MAX_TIME_DRIFT {
+            self.time = wanted;
+        }
+    }
+
+    /// Interpolate the entities at the shown time.
+    pub fn entities(&self) -> Snapshot {
+        // Find the snapshots around the shown time
+        let newer_index = self
+            .snapshots
+            .iter()
+            .position(|(_, time, _)| *time > self.time);
+        let (older, newer) = match newer_index {
+            Some(0) | None => match self.snapshots.back() {
+                Some((_, _, snapshot)) => return snapshot.clone(),
+                None => return Snapshot::new(),
+            },
+            Some(index) => (&self.snapshots[index - 1], &self.snapshots[index]),
+        };
+
+        let t = ((self.time - older.1) / (newer.1 - older.1).max(std::f64::EPSILON)) as f32;
+        newer
+            .2
+            .iter()
+            .map(|(id, state)| {
+                let state = match older.2.get(id) {
+                    Some(old) => old.lerp(state, t),
+                    None => state.clone(),
+                };
+
+                (*id, state)
+            })
+            .collect()
+    }
+
+    /// Collect the packets of a split snapshot, returns them joined into one when the last one
+    /// arrived.
+    fn join_parts(&mut self, packet: SnapshotPacket) -> Option<SnapshotPacket> {
+        if packet.parts <= 1 {
+            return Some(packet);
+        }
+
+        // Forget the snapshots that are already older than the newest complete one
+        if let Some((newest, _, _)) = self.snapshots.back() {
+            self.parts = self.parts.split_off(&(newest + 1));
+        }
+        while self.parts.len() > SNAPSHOT_HISTORY {
+            let oldest = *self.parts.keys().next()?;
+            self.parts.remove(&oldest);
+        }
+
+        let tick = packet.tick;
+        let parts = packet.parts;
+        let received = self.parts.entry(tick).or_insert_with(Vec::new);
+        if received.iter().all(|part| part.part != packet.part) {
+            received.push(packet);
+        }
+        if received.len() < parts {
+            return None;
+        }
+
+        let mut received = self.parts.remove(&tick)?.into_iter();
+        let mut joined = received.next()?;
+        for part in received {
+            joined.changed.extend(part.changed);
+            joined.removed.extend(part.removed);
+        }
+        joined.parts = 1;
+
+        Some(joined)
+    }
+
+    /// Build the complete snapshot from a delta and store it.
+    fn apply_snapshot(&mut self, packet: SnapshotPacket) {
+        // Ignore old & duplicate snapshots
+        if self
+            .snapshots
+            .back()
+            .map_or(false, |(tick, _, _)| *tick >= packet.tick)
+        {
+            return;
+        }
+
+        let mut snapshot = match packet.base {
+            Some(base) => match self.snapshots.iter().find(|(tick, _, _)| *tick == base) {
+                Some((_, _, snapshot)) => snapshot.clone(),
+                // The base is forgotten, wait for a snapshot against a newer one
+                None => return,
+            },
+            None => Snapshot::new(),
+        };
+        for id in packet.removed {
+            snapshot.remove(&id);
+        }
+        for update in packet.changed {
+            snapshot.insert(update.id, update.state);
+        }
+
+        self.snapshots
+            .push_back((packet.tick, packet.time, snapshot));
+        while self.snapshots.len() > SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+/// Write a packet as TOML, through a `toml::Value` because that puts the tables after the plain
+/// values, which the serializer requires.
+fn encode<T: Serialize>(packet: &T) -> Result<Vec<u8>> {
+    let encoded = toml::to_string(&toml::Value::try_from(packet)?)?.into_bytes();
+    if encoded.len() > MAX_PACKET_SIZE {
+        return Err(anyhow!(
+            "Packet of {} bytes is larger than the maximum of {} bytes",
+            encoded.len(),
+            MAX_PACKET_SIZE
+        ));
+    }
+
+    Ok(encoded)
+}
+
+/// Receive a single packet from a non-blocking socket, `None` when there are no more.
+///
+/// Invalid packets are logged & skipped.
+fn receive<T: DeserializeOwned>(socket: &UdpSocket) -> Result<Option<(T, SocketAddr)>> {
+    let mut buffer = vec![0; MAX_PACKET_SIZE];
+    loop {
+        let (size, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        match std::str::from_utf8(&buffer[..size])
+            .map_err(|err| anyhow!(err))
+            .and_then(|text| Ok(toml::from_str::<T>(text)?))
+        {
+            Ok(packet) => return Ok(Some((packet, from))),
+            Err(err) => warn!("ignoring invalid packet from {}: {}", from, err),
+        }
+    }
+}
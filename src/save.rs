@@ -0,0 +1,352 @@
+use crate::{
+    clock::ClockState,
+    render::Readback,
+    unit::Health,
+    world::{EntityState, WorldState},
+};
+use anyhow::{anyhow, Result};
+use glsp::{lib, rfn, Runtime};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Version of the save format, older saves are migrated when loading.
+const SAVE_VERSION: u32 = 3;
+/// Width of the thumbnail in pixels, the height follows from the aspect ratio of the window.
+#[cfg(not(target_arch = "wasm32"))]
+const THUMBNAIL_WIDTH: usize = 160;
+
+/// Converts the data of a save from a version to the next one.
+type Migration = fn(&mut toml::Value) -> Result<()>;
+
+/// The migrations for every version, the first one upgrades version 1 to 2.
+///
+/// When changing anything in `SaveData` or the state it contains, bump `SAVE_VERSION` and add a
+/// migration here rewriting the old values, so older saves keep loading.
+const MIGRATIONS: &[Migration] = &[add_entities, add_max_health];
+
+/// Information about a save, read without loading the rest of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveHeader {
+    /// Version of the format of the data.
+    pub version: u32,
+    /// Name of the slot.
+    pub name: String,
+    /// The time it's saved in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The time played in seconds.
+    pub playtime: f64,
+    /// File name of the GIF image with a frame captured when saving.
+    pub thumbnail: Option<String>,
+}
+
+/// Everything stored to continue playing later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveData {
+    /// The state of the clock.
+    pub clock: ClockState,
+    /// The state of the world.
+    pub world: WorldState,
+    /// The spawned units & objects, spawned again when loading.
+    ///
+    /// Saves from before they were stored only move the bodies that still exist.
+    pub entities: Option<Vec<EntityState>>,
+}
+
+/// A save or load the scripts requested.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveRequest {
+    /// Save to a slot by name.
+    Save(String),
+    /// Continue from a slot by name.
+    Load(String),
+}
+
+lib! {
+/// Queue of saves & loads requested by the scripts, they're handled after the script returns.
+pub struct SaveQueue {
+    /// The requests since the last drain.
+    requests: Vec<SaveRequest>,
+}
+}
+
+impl Default for SaveQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self { requests: vec![] }
+    }
+
+    /// Take the requests made since the last time they were taken.
+    pub fn drain(&mut self) -> Vec<SaveRequest> {
+        self.requests.drain(..).collect()
+    }
+
+    /// Save to a slot by name.
+    fn save_game(&mut self, name: &str) {
+        self.requests.push(SaveRequest::Save(name.to_string()));
+    }
+
+    /// Continue from a slot by name.
+    fn load_game(&mut self, name: &str) {
+        self.requests.push(SaveRequest::Load(name.to_string()));
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("save-game", rfn!(Self::save_game))?;
+            glsp::bind_rfn("load-game", rfn!(Self::load_game))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// A save file, the data is kept as a generic value so it can be migrated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SaveFile {
+    header: SaveHeader,
+    data: toml::Value,
+}
+
+/// Manages the named save slots, stored as TOML files in a directory.
+pub struct SaveSlots {
+    /// The directory with the save files.
+    directory: PathBuf,
+}
+
+impl SaveSlots {
+    /// Use the files in a directory, it's created when saving for the first time.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// The directory with the save files.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Read the headers of all saves, the most recent first.
+    ///
+    /// Files that can't be read are skipped with a warning.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn list(&self) -> Result<Vec<SaveHeader>> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            // Nothing is saved yet
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut headers = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "toml")
+            {
+                continue;
+            }
+
+            match std::fs::read_to_string(&path)
+                .map_err(|err| anyhow!(err))
+                .and_then(|text| Ok(toml::from_str::<SaveFile>(&text)?))
+            {
+                Ok(file) => headers.push(file.header),
+                Err(err) => log::warn!("skipping save \"{}\": {}", path.display(), err),
+            }
+        }
+
+        headers.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+
+        Ok(headers)
+    }
+
+    /// Write the data to a slot, replacing the previous save with the same name.
+    ///
+    /// The frame is stored next to it as a small GIF image.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(
+        &self,
+        name: &str,
+        data: &SaveData,
+        thumbnail: Option<&Readback>,
+    ) -> Result<SaveHeader> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let name = slot_name(name)?;
+        std::fs::create_dir_all(&self.directory)?;
+
+        let thumbnail = match thumbnail {
+            Some(readback) => {
+                let file_name = format!("{}.gif", name);
+                write_thumbnail(&self.directory.join(&file_name), readback)?;
+
+                Some(file_name)
+            }
+            None => None,
+        };
+
+        let header = SaveHeader {
+            version: SAVE_VERSION,
+            name: name.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            playtime: data.clock.elapsed,
+            thumbnail,
+        };
+        let file = SaveFile {
+            header: header.clone(),
+            data: toml::Value::try_from(data)?,
+        };
+        std::fs::write(self.path(name), toml::to_string(&file)?)?;
+
+        Ok(header)
+    }
+
+    /// Read the data of a slot, migrating it when it's saved with an older version.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(&self, name: &str) -> Result<(SaveHeader, SaveData)> {
+        let name = slot_name(name)?;
+        let mut file: SaveFile = toml::from_str(&std::fs::read_to_string(self.path(name))?)?;
+
+        if file.header.version == 0 || file.header.version > SAVE_VERSION {
+            return Err(anyhow!(
+                "Save version {} is not supported, expected {} or older",
+                file.header.version,
+                SAVE_VERSION
+            ));
+        }
+
+        // Apply all migrations from the version of the file up to the current one
+        for (index, migration) in MIGRATIONS
+            .iter()
+            .enumerate()
+            .skip(file.header.version as usize - 1)
+        {
+            migration(&mut file.data).map_err(|err| {
+                anyhow!("Migrating save to version {} failed: {}", index + 2, err)
+            })?;
+        }
+        file.header.version = SAVE_VERSION;
+
+        let data = file.data.try_into()?;
+
+        Ok((file.header, data))
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn list(&self) -> Result<Vec<SaveHeader>> {
+        Err(anyhow!("Saving is not supported in the browser"))
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(
+        &self,
+        _name: &str,
+        _data: &SaveData,
+        _thumbnail: Option<&Readback>,
+    ) -> Result<SaveHeader> {
+        Err(anyhow!("Saving is not supported in the browser"))
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(&self, _name: &str) -> Result<(SaveHeader, SaveData)> {
+        Err(anyhow!("Loading saves is not supported in the browser"))
+    }
+
+    /// The path of the file of a slot.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.toml", name))
+    }
+}
+
+/// Version 2 stores the spawned entities, there's nothing to rewrite since version 1 saves are
+/// loaded without them.
+fn add_entities(_data: &mut toml::Value) -> Result<()> {
+    Ok(())
+}
+
+/// Version 3 stores the lifepoints of the units with the amount they're spawned with, which older
+/// saves don't know so the default is used unless they have more.
+fn add_max_health(data: &mut toml::Value) -> Result<()> {
+    let entities = match data.get_mut("entities").and_then(toml::Value::as_array_mut) {
+        Some(entities) => entities,
+        None => return Ok(()),
+    };
+
+    for kind in entities
+        .iter_mut()
+        .filter_map(|entity| entity.get_mut("kind"))
+        .filter_map(toml::Value::as_table_mut)
+    {
+        let current = match kind.get("health").and_then(toml::Value::as_float) {
+            Some(current) => current,
+            // Objects don't have lifepoints
+            None => continue,
+        };
+
+        let mut health = toml::value::Table::new();
+        health.insert("current".to_string(), toml::Value::Float(current));
+        health.insert(
+            "max".to_string(),
+            toml::Value::Float(current.max(Health::default().max() as f64)),
+        );
+        kind.insert("health".to_string(), toml::Value::Table(health));
+    }
+
+    Ok(())
+}
+
+/// Check that the name of a slot can be used as a file name.
+#[cfg(not(target_arch = "wasm32"))]
+fn slot_name(name: &str) -> Result<&str> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+    {
+        Ok(name)
+    } else {
+        Err(anyhow!(
+            "Slot name \"{}\" can only contain letters, digits, dashes & underscores",
+            name
+        ))
+    }
+}
+
+/// Write a downscaled frame as a GIF image.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_thumbnail(path: &std::path::Path, readback: &Readback) -> Result<()> {
+    use gif::{Encoder, Frame};
+    use std::{fs::File, io::BufWriter};
+
+    let Readback {
+        width,
+        height,
+        mut pixels,
+    } = readback.downscaled(readback.width / THUMBNAIL_WIDTH);
+    if width == 0 || height == 0 {
+        return Err(anyhow!("The captured frame is empty"));
+    }
+
+    // GIF dimensions are limited to 16 bits, which the downscaled frame won't exceed
+    let (width, height) = (width as u16, height as u16);
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+    encoder.write_frame(&Frame::from_rgba(width, height, &mut pixels))?;
+
+    Ok(())
+}
@@ -0,0 +1,308 @@
+use crate::{
+    i18n::I18n,
+    input::GamepadButton,
+    render::{Instance, Mesh, Render},
+    replay::InputEvent,
+    text::{Alignment, FontInstance, TextLayout},
+    ui::Ui,
+};
+use glsp::GSend;
+use log::info;
+use usvg::Color;
+
+/// Size of the panel behind the text of the built-in scenes in pixels.
+const PANEL_SIZE: (f32, f32) = (420.0, 200.0);
+/// Scale of the glyphs of the titles.
+const TITLE_SCALE: f32 = 0.4;
+/// Scale of the glyphs of the other lines.
+const TEXT_SCALE: f32 = 0.2;
+/// Width of the buttons of the main menu in pixels.
+const BUTTON_WIDTH: f32 = 200.0;
+/// The scene of the main script started from the main menu.
+const GAMEPLAY_SCENE: &str = "main";
+
+/// The z index of the panel.
+const BACKGROUND_Z: u8 = 2;
+/// The z index of the text on the panel.
+const FOREGROUND_Z: u8 = 3;
+
+/// A screen drawn on top of the gameplay that gets the input before it, like a menu.
+///
+/// The scenes are drawn while the scripts run, so they can't hold any script values.
+pub trait Scene: GSend {
+    /// Called when the scene is pushed on the stack.
+    fn enter(&mut self) {}
+
+    /// Advance the scene with the time since the previous frame in seconds, declaring its
+    /// widgets with the texts in the current language.
+    fn update(&mut self, _delta: f32, _ui: &mut Ui, _i18n: &I18n) -> Transition {
+        Transition::None
+    }
+
+    /// Queue the screen space instances for the next frame, with the texts in the current
+    /// language.
+    fn draw(&self, ui_size: (f32, f32), font: &mut FontInstance, i18n: &I18n, render: &mut Render);
+
+    /// React to the live input, the gameplay doesn't see it.
+    fn input_event(&mut self, event: &InputEvent) -> Transition;
+}
+
+/// What should happen with the stack of scenes after a scene handled something.
+pub enum Transition {
+    /// Keep the current scene.
+    None,
+    /// Remove the current scene, going back to the one below it or to the gameplay.
+    Pop,
+    /// Remove every scene and let the main script load a scene by name.
+    Play(String),
+    /// Close the game.
+    Quit,
+}
+
+/// What the game should do for the scenes, drained after the scenes are updated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneRequest {
+    /// Let the main script load a scene by name.
+    Play(String),
+    /// Close the game.
+    Quit,
+}
+
+/// The stack of scenes on top of the gameplay, the gameplay runs when it's empty.
+#[derive(Default)]
+pub struct SceneManager {
+    /// The scenes from the bottom to the top, only the top one gets the input.
+    stack: Vec<Box<dyn Scene>>,
+    /// What the game should do, waiting to be drained.
+    requests: Vec<SceneRequest>,
+}
+
+impl SceneManager {
+    /// Create an empty stack, starting with the gameplay.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a scene is shown, the gameplay is paused while there is.
+    pub fn is_active(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    /// Show a scene on top of the others.
+    pub fn push(&mut self, mut scene: Box<dyn Scene>) {
+        scene.enter();
+        self.stack.push(scene);
+    }
+
+    /// Advance the top scene, only its widgets are shown.
+    pub fn update(&mut self, delta: f32, ui: &mut Ui, i18n: &I18n) {
+        if let Some(scene) = self.stack.last_mut() {
+            let transition = scene.update(delta, ui, i18n);
+            self.apply(transition);
+        }
+    }
+
+    /// Draw every scene from the bottom to the top.
+    pub fn draw(
+        &self,
+        ui_size: (f32, f32),
+        font: &mut FontInstance,
+        i18n: &I18n,
+        render: &mut Render,
+    ) {
+        for scene in self.stack.iter() {
+            scene.draw(ui_size, font, i18n, render);
+        }
+    }
+
+    /// Pass the input to the top scene.
+    ///
+    /// Returns whether the input is consumed, moving the pointer & releasing keys or buttons are
+    /// always passed through so the cursor keeps following it and nothing stays held.
+    pub fn input_event(&mut self, event: &InputEvent) -> bool {
+        let scene = match self.stack.last_mut() {
+            Some(scene) => scene,
+            None => return false,
+        };
+
+        let transition = scene.input_event(event);
+        self.apply(transition);
+
+        !matches!(
+            event,
+            InputEvent::MouseMotion { .. }
+                | InputEvent::MouseButtonUp { .. }
+                | InputEvent::KeyUp { .. }
+                | InputEvent::GamepadButtonUp { .. }
+        )
+    }
+
+    /// Take what the game should do for the scenes.
+    pub fn drain_requests(&mut self) -> Vec<SceneRequest> {
+        self.requests.drain(..).collect()
+    }
+
+    /// Change the stack of scenes.
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::None => (),
+            Transition::Pop => {
+                self.stack.pop();
+            }
+            Transition::Play(name) => {
+                self.stack.clear();
+                self.requests.push(SceneRequest::Play(name));
+            }
+            Transition::Quit => self.requests.push(SceneRequest::Quit),
+        }
+    }
+}
+
+/// A panel with centered lines of text, the first one a title.
+struct Panel {
+    /// Mesh of the background.
+    background: Mesh,
+    /// The translation keys of the title & the other lines.
+    lines: Vec<&'static str>,
+}
+
+impl Panel {
+    /// Upload the background.
+    fn new(render: &mut Render, lines: Vec<&'static str>) -> Self {
+        let background = render.upload_rectangle(
+            PANEL_SIZE.0,
+            PANEL_SIZE.1,
+            Color::new(0x10, 0x10, 0x10),
+            0.8,
+        );
+
+        Self { background, lines }
+    }
+
+    /// Queue the panel in the center of the screen.
+    ///
+    /// The lines are translated every time, so changing the language changes them right away.
+    fn draw(&self, ui_size: (f32, f32), font: &mut FontInstance, i18n: &I18n, render: &mut Render) {
+        let x = (ui_size.0 - PANEL_SIZE.0) / 2.0;
+        let y = (ui_size.1 - PANEL_SIZE.1) / 2.0;
+
+        let mut background = Instance::new(x, y);
+        background.set_z(BACKGROUND_Z);
+        render.draw_screen(self.background, background);
+
+        // Space the lines evenly below the title
+        let mut line_y = y + PANEL_SIZE.1 * 0.3;
+        for (index, key) in self.lines.iter().enumerate() {
+            let scale = if index == 0 { TITLE_SCALE } else { TEXT_SCALE };
+
+            let layout = TextLayout::new()
+                .scale(scale)
+                .max_width(PANEL_SIZE.0)
+                .alignment(Alignment::Center);
+            let line = i18n.t(key, &[]);
+            for (mut instance, mesh) in font.layout(render, &line, x, line_y, &layout) {
+                instance.set_z(FOREGROUND_Z);
+                render.draw_screen(mesh, instance);
+            }
+
+            line_y += PANEL_SIZE.1 * if index == 0 { 0.3 } else { 0.2 };
+        }
+    }
+}
+
+/// Shown when the assets are loaded, starts the gameplay or quits.
+pub struct MainMenu {
+    /// The title.
+    panel: Panel,
+    /// The translation key of the button starting the gameplay.
+    play: &'static str,
+    /// The translation key of the button closing the game.
+    quit: &'static str,
+}
+
+impl MainMenu {
+    /// Upload the panel.
+    pub fn new(render: &mut Render) -> Self {
+        Self {
+            panel: Panel::new(render, vec!["menu-title"]),
+            play: "menu-play",
+            quit: "menu-quit",
+        }
+    }
+}
+
+impl Scene for MainMenu {
+    fn update(&mut self, _delta: f32, ui: &mut Ui, i18n: &I18n) -> Transition {
+        // The buttons are below the title on the panel
+        let (width, height) = ui.size();
+        ui.begin(
+            (width - BUTTON_WIDTH) / 2.0,
+            (height - PANEL_SIZE.1) / 2.0 + PANEL_SIZE.1 * 0.45,
+            BUTTON_WIDTH,
+        );
+
+        if ui.button(&i18n.t(self.play, &[])) {
+            Transition::Play(GAMEPLAY_SCENE.to_string())
+        } else if ui.button(&i18n.t(self.quit, &[])) {
+            Transition::Quit
+        } else {
+            Transition::None
+        }
+    }
+
+    fn draw(&self, ui_size: (f32, f32), font: &mut FontInstance, i18n: &I18n, render: &mut Render) {
+        self.panel.draw(ui_size, font, i18n, render);
+    }
+
+    fn input_event(&mut self, event: &InputEvent) -> Transition {
+        match event {
+            InputEvent::KeyDown { key } if key.name() == "enter" || key.name() == "space" => {
+                Transition::Play(GAMEPLAY_SCENE.to_string())
+            }
+            InputEvent::GamepadButtonDown {
+                button: GamepadButton::South,
+            }
+            | InputEvent::GamepadButtonDown {
+                button: GamepadButton::Start,
+            } => Transition::Play(GAMEPLAY_SCENE.to_string()),
+            InputEvent::KeyDown { key } if key.name() == "escape" => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+}
+
+/// Shown on top of the gameplay while it's paused.
+pub struct Pause {
+    /// The title & the controls.
+    panel: Panel,
+}
+
+impl Pause {
+    /// Upload the panel.
+    pub fn new(render: &mut Render) -> Self {
+        Self {
+            panel: Panel::new(render, vec!["pause-title", "pause-resume", "pause-quit"]),
+        }
+    }
+}
+
+impl Scene for Pause {
+    fn enter(&mut self) {
+        info!("paused");
+    }
+
+    fn draw(&self, ui_size: (f32, f32), font: &mut FontInstance, i18n: &I18n, render: &mut Render) {
+        self.panel.draw(ui_size, font, i18n, render);
+    }
+
+    fn input_event(&mut self, event: &InputEvent) -> Transition {
+        match event {
+            InputEvent::KeyDown { key } if key.name() == "escape" => Transition::Pop,
+            InputEvent::GamepadButtonDown {
+                button: GamepadButton::Start,
+            } => Transition::Pop,
+            InputEvent::KeyDown { key } if key.name() == "q" => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::anyhow;
+use anyhow::Result;
+use glsp::{DequeAccess, DequeOps, GResult, Sym, Val};
+
+/// Path of the main script relative to the working directory, the same as the embedded one.
+#[cfg(not(target_arch = "wasm32"))]
+const SCRIPT_PATH: &str = "scripts/main.glsp";
+/// Seconds between checking the script file for changes.
+#[cfg(not(target_arch = "wasm32"))]
+const POLL_INTERVAL: f64 = 0.5;
+
+/// Watches the main script file, so it can be edited while the game runs.
+///
+/// The script embedded in the binary is used until the file changes, there's no file to watch
+/// in the browser.
+pub struct ScriptWatcher {
+    /// The last modification time of the file.
+    #[cfg(not(target_arch = "wasm32"))]
+    modified: Option<std::time::SystemTime>,
+    /// The time the file was last checked in seconds.
+    #[cfg(not(target_arch = "wasm32"))]
+    last_poll: f64,
+}
+
+impl Default for ScriptWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptWatcher {
+    /// Watch the main script in the working directory.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            modified: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            last_poll: 0.0,
+        }
+    }
+
+    /// Read the source of the main script when the file changed since the last call.
+    ///
+    /// The file is only checked twice a second, an existing file is read on the first call.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> Option<Result<String>> {
+        let now = miniquad::date::now();
+        if now - self.last_poll < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = now;
+
+        // A missing file means there's nothing to reload
+        let modified = std::fs::metadata(SCRIPT_PATH)
+            .and_then(|meta| meta.modified())
+            .ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+
+        Some(
+            std::fs::read_to_string(SCRIPT_PATH)
+                .map_err(|err| anyhow!("Reading \"{}\" failed: {}", SCRIPT_PATH, err)),
+        )
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> Option<Result<String>> {
+        None
+    }
+}
+
+/// Evaluate a new version of the main script, should be called inside the runtime.
+///
+/// Globals can't be bound twice, so the functions & values the script defines at the top level
+/// are unbound first. Nothing is unbound when the script can't be parsed.
+pub fn reload(source: &str) -> GResult<()> {
+    let forms = glsp::parse_all(source, Some("main.glsp"))?;
+
+    for form in &forms {
+        if let Some(name) = defined_global(form) {
+            if glsp::has_global(name)? {
+                glsp::del_global(name)?;
+            }
+        }
+    }
+
+    glsp::eval_multi(&forms, None)?;
+
+    Ok(())
+}
+
+/// The name of the global a top level `(defn name ...)` or `(def name ...)` form binds.
+fn defined_global(form: &Val) -> Option<Sym> {
+    let form = match form {
+        Val::Arr(form) if form.len() >= 2 => form,
+        _ => return None,
+    };
+
+    match (form.get::<Val>(0).ok()?, form.get::<Val>(1).ok()?) {
+        (Val::Sym(kind), Val::Sym(name)) if matches!(&*kind.name(), "defn" | "def") => Some(name),
+        _ => None,
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::anyhow;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// The shaders that can be reloaded with the names of their vertex & fragment shader files
+/// without extensions, the effects share a vertex shader & the glow shares the ones of the paths.
+pub const SHADERS: &[(&str, &str, &str)] = &[
+    ("geom", "geom", "geom"),
+    ("textured", "textured", "textured"),
+    ("nine_slice", "nine_slice", "nine_slice"),
+    ("glow", "geom", "glow"),
+    ("nine_slice_glow", "nine_slice", "glow"),
+    ("fxaa", "post", "fxaa"),
+    ("vignette", "post", "vignette"),
+    ("chromatic_aberration", "post", "chromatic_aberration"),
+    ("color_grading", "post", "color_grading"),
+    ("bloom", "post", "bloom"),
+    ("blur", "post", "blur"),
+];
+/// The shaders meshes can be drawn with instead of the vector shader, with their embedded vertex
+/// & fragment sources, they can be reloaded like the others.
+const CUSTOM_SHADERS: &[(&str, &str, &str)] = &[(
+    "wobble",
+    include_str!("../assets/shaders/wobble.vert"),
+    include_str!("../assets/shaders/wobble.frag"),
+)];
+/// Seconds between checking the shader files for changes.
+#[cfg(not(target_arch = "wasm32"))]
+const POLL_INTERVAL: f64 = 0.5;
+
+/// The sources of a vertex & fragment shader pair.
+#[derive(Debug, Clone)]
+pub struct ShaderSource {
+    /// Name of the shader, the first item in `SHADERS`.
+    pub name: &'static str,
+    /// The GLSL source of the vertex shader.
+    pub vertex: String,
+    /// The GLSL source of the fragment shader.
+    pub fragment: String,
+}
+
+impl ShaderSource {
+    /// The embedded sources of the custom shaders, to register them with the renderer.
+    pub fn custom() -> Vec<Self> {
+        CUSTOM_SHADERS
+            .iter()
+            .map(|&(name, vertex, fragment)| Self {
+                name,
+                vertex: vertex.to_string(),
+                fragment: fragment.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Watches the shader files in the assets directory, so they can be edited while the game runs.
+///
+/// The shaders embedded in the binary are used until a file changes, there are no files to
+/// watch in the browser.
+pub struct ShaderWatcher {
+    /// The directory with the shader files.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    directory: PathBuf,
+    /// The last modification time of the files of every shader, indexed like `SHADERS` followed
+    /// by the custom shaders.
+    #[cfg(not(target_arch = "wasm32"))]
+    modified: Vec<Option<std::time::SystemTime>>,
+    /// The time the files were last checked in seconds.
+    #[cfg(not(target_arch = "wasm32"))]
+    last_poll: f64,
+}
+
+impl ShaderWatcher {
+    /// Watch the `shaders` directory in the assets root.
+    pub fn new(assets_root: &str) -> Self {
+        Self {
+            directory: PathBuf::from(assets_root).join("shaders"),
+            #[cfg(not(target_arch = "wasm32"))]
+            modified: vec![None; SHADERS.len() + CUSTOM_SHADERS.len()],
+            #[cfg(not(target_arch = "wasm32"))]
+            last_poll: 0.0,
+        }
+    }
+
+    /// Read the sources of the shaders of which a file changed since the last call.
+    ///
+    /// The files are only checked twice a second, existing files are read on the first call.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> Vec<Result<ShaderSource>> {
+        let now = miniquad::date::now();
+        if now - self.last_poll < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = now;
+
+        let mut changed = vec![];
+        let shaders = SHADERS
+            .iter()
+            .copied()
+            .chain(CUSTOM_SHADERS.iter().map(|&(name, ..)| (name, name, name)));
+        for (index, (name, vertex, fragment)) in shaders.enumerate() {
+            let vertex = self.directory.join(format!("{}.vert", vertex));
+            let fragment = self.directory.join(format!("{}.frag", fragment));
+
+            // Use the newest time of both files, a missing file means there's nothing to reload
+            let modified = match (last_modified(&vertex), last_modified(&fragment)) {
+                (Some(vertex), Some(fragment)) => vertex.max(fragment),
+                _ => continue,
+            };
+            if self.modified[index] == Some(modified) {
+                continue;
+            }
+            self.modified[index] = Some(modified);
+
+            changed.push(read_source(name, &vertex, &fragment));
+        }
+
+        changed
+    }
+
+    /// There's no filesystem in the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> Vec<Result<ShaderSource>> {
+        vec![]
+    }
+}
+
+/// The time a file was last modified, `None` when it can't be read.
+#[cfg(not(target_arch = "wasm32"))]
+fn last_modified(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Read both sources of a shader.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_source(
+    name: &'static str,
+    vertex: &std::path::Path,
+    fragment: &std::path::Path,
+) -> Result<ShaderSource> {
+    let read = |path: &std::path::Path| {
+        std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Reading \"{}\" failed: {}", path.display(), err))
+    };
+
+    Ok(ShaderSource {
+        name,
+        vertex: read(vertex)?,
+        fragment: read(fragment)?,
+    })
+}
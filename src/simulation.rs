@@ -0,0 +1,250 @@
+use crate::{
+    call_script,
+    camera::Camera,
+    event::EventBus,
+    motion::Motion,
+    spawner::Spawner,
+    tween::{Target, Tweens},
+    world::{Touch, World},
+};
+use anyhow::{anyhow, Result};
+use glsp::{Lib, Runtime, Val};
+use log::{error, warn};
+
+/// The time of a frame, measured by the clock before it's simulated.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Frame {
+    /// Seconds since the previous frame.
+    pub delta: f64,
+    /// Seconds of game time since the start.
+    pub elapsed: f64,
+    /// The number of the frame.
+    pub frame: u64,
+    /// Amount of fixed steps of the physics that fit in the frame.
+    pub fixed_steps: usize,
+}
+
+/// What the simulation of a frame left for the systems only the game has.
+#[derive(Debug, Default)]
+pub struct Simulated {
+    /// The damage the scripts dealt with the position of the damaged unit.
+    pub damages: Vec<(Option<(f32, f32)>, f32)>,
+    /// Seconds spent stepping the physics.
+    pub physics: f64,
+    /// Seconds spent in the scripts & applying their requests.
+    pub script: f64,
+}
+
+/// Simulate a frame the same way in the game & without a window: step the physics, advance the
+/// tweens, tell the main script about the collisions, call its update function and apply what it
+/// requested to the world.
+///
+/// A client doesn't simulate the world, it gets it from the server, so the physics aren't
+/// stepped and the requests of the scripts are dropped. Fails when the update function fails.
+pub fn step(
+    runtime: &Runtime,
+    mut world: Option<&mut World>,
+    frame: Frame,
+    authoritative: bool,
+) -> Result<Simulated> {
+    let mut simulated = Simulated::default();
+
+    // Move the physics with fixed steps
+    let start = miniquad::date::now();
+    if let (Some(world), true) = (world.as_deref_mut(), authoritative) {
+        for _ in 0..frame.fixed_steps {
+            world.step();
+        }
+    }
+    simulated.physics = miniquad::date::now() - start;
+
+    advance_tweens(
+        runtime,
+        world.as_deref_mut(),
+        frame.delta as f32,
+        authoritative,
+    );
+
+    // Let the scripts read the positions & velocities after the steps
+    if let Some(world) = world.as_deref() {
+        let velocities = world.unit_velocities();
+        let positions = world.unit_positions();
+        runtime.run(|| {
+            let mut motion = Motion::borrow_mut();
+            motion.set_velocities(velocities);
+            motion.set_positions(positions);
+
+            Ok(())
+        });
+    }
+
+    // Call the update function in the main script with the time
+    let start = miniquad::date::now();
+    if let Some(world) = world.as_deref_mut() {
+        collided(runtime, world);
+    }
+    if !call_script(
+        runtime,
+        "engine:update",
+        &(frame.delta, frame.elapsed, frame.frame),
+    ) {
+        return Err(anyhow!(
+            "Calling engine:update failed at frame {}",
+            frame.frame
+        ));
+    }
+    simulated.damages = apply_requests(runtime, world, authoritative);
+    simulated.script = miniquad::date::now() - start;
+
+    Ok(simulated)
+}
+
+/// Tell the main script about the bodies that touched during the fixed steps.
+fn collided(runtime: &Runtime, world: &mut World) {
+    for touch in world.drain_collisions() {
+        match touch {
+            Touch::Collision(a, b) => call_script(runtime, "engine:on-collision", &(a, b)),
+            Touch::SensorEnter(sensor, other) => {
+                call_script(runtime, "engine:on-sensor-enter", &(sensor, other))
+            }
+            Touch::SensorExit(sensor, other) => {
+                call_script(runtime, "engine:on-sensor-exit", &(sensor, other))
+            }
+        };
+    }
+}
+
+/// Advance the tweens of the scripts and apply them to the camera & the units, calling the
+/// functions of the finished ones.
+fn advance_tweens(
+    runtime: &Runtime,
+    mut world: Option<&mut World>,
+    delta: f32,
+    authoritative: bool,
+) {
+    runtime.run(|| {
+        let (values, finished) =
+            Tweens::borrow_mut().update(delta, |target, property| match target {
+                Target::Camera => Camera::borrow().tween_value(property),
+                // The clients get the positions of the units from the server
+                Target::Entity(_) if !authoritative => None,
+                Target::Entity(entity) => world.as_deref()?.tween_value(entity, property),
+            });
+
+        let mut camera = Camera::borrow_mut();
+        for (target, property, value) in values {
+            match target {
+                Target::Camera => camera.apply_tween(property, value),
+                Target::Entity(entity) => {
+                    if let Some(world) = world.as_deref_mut() {
+                        world.apply_tween(entity, property, value);
+                    }
+                }
+            }
+        }
+        drop(camera);
+
+        // Called after the tweens are released so a function can start another tween, a
+        // failing function doesn't stop the others
+        for callback in finished.iter() {
+            if let Err(err) = glsp::call::<_, _, Val>(callback, &()) {
+                error!("tween callback failed: {}", err);
+            }
+        }
+
+        Ok(())
+    });
+}
+
+/// Apply what the scripts requested to the world, the main script is told about the spawned
+/// units, the dead units & the explosions.
+///
+/// The requests are drained in between, so the requests of these functions are applied too.
+/// Returns the damage that was dealt.
+pub fn apply_requests(
+    runtime: &Runtime,
+    world: Option<&mut World>,
+    authoritative: bool,
+) -> Vec<(Option<(f32, f32)>, f32)> {
+    // Add the units & objects, remove the despawned units, start the animations and connect the
+    // joined units
+    let requests = runtime
+        .run(|| Ok(Spawner::borrow_mut().drain()))
+        .unwrap_or_default();
+    let world = match (world, authoritative) {
+        (_, false) => {
+            drain_requests(runtime);
+
+            return vec![];
+        }
+        (Some(world), true) => world,
+        (None, true) => {
+            if !requests.is_empty() {
+                warn!("can't spawn entities before the world is loaded");
+            }
+            drain_requests(runtime);
+
+            return vec![];
+        }
+    };
+    for (entity, def) in world.apply_spawn_requests(requests) {
+        call_script(runtime, "engine:on-spawn", &(entity.to_script(), def));
+    }
+
+    // Remove the lifepoints of the damaged units, the combat log & the statistics show the damage
+    let damages = runtime
+        .run(|| Ok(Spawner::borrow_mut().drain_damages()))
+        .unwrap_or_default();
+    let dealt = world.apply_damages(damages);
+    let dead = world.remove_dead();
+    runtime.run(|| {
+        let mut event_bus = EventBus::borrow_mut();
+        for (_, amount) in dealt.iter() {
+            event_bus.emit_damage_dealt(f64::from(*amount));
+        }
+
+        Ok(())
+    });
+    for (entity, allegiance) in dead {
+        call_script(
+            runtime,
+            "engine:on-death",
+            &(entity.to_script(), allegiance),
+        );
+    }
+
+    // Apply the forces & velocities the scripts gave the units before the next fixed steps, the
+    // main script can damage the units an explosion hits
+    let (commands, explosions) = runtime
+        .run(|| {
+            let mut motion = Motion::borrow_mut();
+
+            Ok((motion.drain(), motion.drain_explosions()))
+        })
+        .unwrap_or_default();
+    for (explosion, units) in world.apply_motion_requests(commands, explosions) {
+        let units = units
+            .into_iter()
+            .map(|(entity, falloff)| (entity.to_script(), falloff))
+            .collect::<Vec<_>>();
+        call_script(
+            runtime,
+            "engine:on-explosion",
+            &(explosion.x, explosion.y, units),
+        );
+    }
+
+    dealt
+}
+
+/// Drop the damage & motion the scripts requested without a world to apply them to.
+fn drain_requests(runtime: &Runtime) {
+    runtime.run(|| {
+        Spawner::borrow_mut().drain_damages();
+        let mut motion = Motion::borrow_mut();
+        motion.drain();
+        motion.drain_explosions();
+
+        Ok(())
+    });
+}
@@ -0,0 +1,513 @@
+use crate::{
+    replication::EntityId,
+    transform::{LocalTransform, Parent},
+    unit::Allegiance,
+};
+use glsp::{bail, lib, rfn, FromVal, GResult, Runtime, Sym, Val};
+use std::{collections::HashMap, mem};
+
+/// What kind of entity to spawn.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpawnKind {
+    /// A unit fighting for a side, tinted with the color of the side unless it has its own.
+    Unit {
+        allegiance: Allegiance,
+        tint: Option<[f32; 4]>,
+    },
+    /// A static or simulated object without health, like the ground.
+    Object,
+    /// An object flying with a velocity in world units per second, removed when it hits something.
+    ///
+    /// It passes through the units of the side that shot it.
+    Projectile {
+        velocity: (f32, f32),
+        allegiance: Option<Allegiance>,
+    },
+}
+
+/// An entity the scripts want to spawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnRequest {
+    /// Name of the object definition, like "character".
+    pub def: String,
+    /// What kind of entity it becomes.
+    pub kind: SpawnKind,
+    /// Horizontal world position.
+    pub x: f32,
+    /// Vertical world position.
+    pub y: f32,
+}
+
+/// How a joint connects two units.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JointKind {
+    /// A hinge at a world position.
+    Revolute { anchor: (f32, f32) },
+    /// A slider along an axis through a world position, optionally limited to a range.
+    Prismatic {
+        anchor: (f32, f32),
+        axis: (f32, f32),
+        limits: Option<(f32, f32)>,
+    },
+    /// A rod between a world position on each unit.
+    Distance {
+        anchor_a: (f32, f32),
+        anchor_b: (f32, f32),
+    },
+}
+
+/// A joint the scripts want to add.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JointRequest {
+    /// The number the scripts know the joint by.
+    pub id: i32,
+    /// How the units are connected.
+    pub kind: JointKind,
+    /// The first unit.
+    pub a: EntityId,
+    /// The second unit, the static world when it's `None`.
+    pub b: Option<EntityId>,
+}
+
+/// What a force generator the scripts add does.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ForceKind {
+    /// Accelerate all moving bodies in world units per second squared, optionally only inside the
+    /// rectangle between a minimum & maximum world position.
+    Wind {
+        acceleration: (f32, f32),
+        zone: Option<((f32, f32), (f32, f32))>,
+    },
+    /// Pull two units towards their current distance.
+    Spring {
+        a: EntityId,
+        b: EntityId,
+        stiffness: f32,
+    },
+}
+
+/// A force generator the scripts want to add.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForceRequest {
+    /// The number the scripts know the force by.
+    pub id: i32,
+    /// What the force does.
+    pub kind: ForceKind,
+}
+
+impl ForceRequest {
+    /// Whether the force is connected to a unit, winds aren't connected to any.
+    pub fn connects(&self, entity: EntityId) -> bool {
+        match self.kind {
+            ForceKind::Wind { .. } => false,
+            ForceKind::Spring { a, b, .. } => a == entity || b == entity,
+        }
+    }
+}
+
+/// Everything the scripts requested from the spawner since the last drain except the damage, in
+/// the order the world applies it.
+#[derive(Debug, Default)]
+pub struct SpawnRequests {
+    /// The units & objects to spawn.
+    pub spawns: Vec<SpawnRequest>,
+    /// The names of the levels to spawn with the world position of their origin.
+    pub levels: Vec<(String, f32, f32)>,
+    /// The units to remove.
+    pub despawns: Vec<EntityId>,
+    /// The units with the names of the animations to play.
+    pub animations: Vec<(EntityId, String)>,
+    /// The units with whether they're mirrored horizontally.
+    pub flips: Vec<(EntityId, bool)>,
+    /// The joints to add.
+    pub joints: Vec<JointRequest>,
+    /// The joints to remove.
+    pub removed_joints: Vec<i32>,
+    /// The force generators to add.
+    pub forces: Vec<ForceRequest>,
+    /// The force generators to remove.
+    pub removed_forces: Vec<i32>,
+    /// The units to attach to a parent, or to detach when it's `None`.
+    pub attachments: Vec<(EntityId, Option<(Parent, LocalTransform)>)>,
+}
+
+impl SpawnRequests {
+    /// Whether nothing was requested.
+    pub fn is_empty(&self) -> bool {
+        self.spawns.is_empty()
+            && self.levels.is_empty()
+            && self.despawns.is_empty()
+            && self.animations.is_empty()
+            && self.flips.is_empty()
+            && self.joints.is_empty()
+            && self.removed_joints.is_empty()
+            && self.forces.is_empty()
+            && self.removed_forces.is_empty()
+            && self.attachments.is_empty()
+    }
+}
+
+lib! {
+/// Queue of entities & levels spawned, despawned, damaged, animated, flipped, joined & attached
+/// and the forces added by the scripts, the world is changed after the script returns.
+pub struct Spawner {
+    /// The requests since the last drain.
+    requests: Vec<SpawnRequest>,
+    /// The units to remove since the last drain.
+    despawns: Vec<EntityId>,
+    /// The units with the lifepoints to remove from them since the last drain.
+    damages: Vec<(EntityId, f32)>,
+    /// The units with the names of the animations to play since the last drain.
+    animations: Vec<(EntityId, String)>,
+    /// The units with whether they're mirrored horizontally since the last drain.
+    flips: Vec<(EntityId, bool)>,
+    /// The joints to add since the last drain.
+    joints: Vec<JointRequest>,
+    /// The joints to remove since the last drain.
+    removed_joints: Vec<i32>,
+    /// The number of the next joint.
+    next_joint: i32,
+    /// The force generators to add since the last drain.
+    forces: Vec<ForceRequest>,
+    /// The force generators to remove since the last drain.
+    removed_forces: Vec<i32>,
+    /// The number of the next force generator.
+    next_force: i32,
+    /// The units to attach to a parent, or to detach when it's `None`, since the last drain.
+    attachments: Vec<(EntityId, Option<(Parent, LocalTransform)>)>,
+    /// The names of the levels with the world position of their origin since the last drain.
+    levels: Vec<(String, f32, f32)>,
+    /// The tags of the object definitions by their name, set when the world is loaded.
+    object_tags: HashMap<String, Vec<String>>,
+}
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Spawner {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self {
+            requests: vec![],
+            despawns: vec![],
+            damages: vec![],
+            animations: vec![],
+            flips: vec![],
+            joints: vec![],
+            removed_joints: vec![],
+            next_joint: 0,
+            forces: vec![],
+            removed_forces: vec![],
+            next_force: 0,
+            attachments: vec![],
+            levels: vec![],
+            object_tags: HashMap::new(),
+        }
+    }
+
+    /// Let the scripts read the tags of the object definitions.
+    pub fn set_object_tags(&mut self, object_tags: HashMap<String, Vec<String>>) {
+        self.object_tags = object_tags;
+    }
+
+    /// Take everything requested since the last drain except the damage.
+    pub fn drain(&mut self) -> SpawnRequests {
+        SpawnRequests {
+            spawns: mem::take(&mut self.requests),
+            levels: mem::take(&mut self.levels),
+            despawns: mem::take(&mut self.despawns),
+            animations: mem::take(&mut self.animations),
+            flips: mem::take(&mut self.flips),
+            joints: mem::take(&mut self.joints),
+            removed_joints: mem::take(&mut self.removed_joints),
+            forces: mem::take(&mut self.forces),
+            removed_forces: mem::take(&mut self.removed_forces),
+            attachments: mem::take(&mut self.attachments),
+        }
+    }
+
+    /// Take all damage dealt to units since the last drain.
+    pub fn drain_damages(&mut self) -> Vec<(EntityId, f32)> {
+        mem::take(&mut self.damages)
+    }
+
+    /// Spawn a unit, an enemy unless the side is `'ally`.
+    ///
+    /// The mesh is multiplied with the color of the side, or with an RGBA tint when it's passed
+    /// after the side.
+    fn spawn_unit(
+        &mut self,
+        def: Sym,
+        x: f32,
+        y: f32,
+        allegiance: Option<Sym>,
+        tint: Option<(f32, f32, f32, f32)>,
+    ) -> GResult<()> {
+        let allegiance = match allegiance {
+            Some(allegiance) => parse_allegiance(&allegiance)?,
+            None => Allegiance::default(),
+        };
+
+        self.requests.push(SpawnRequest {
+            def: def.name().to_string(),
+            kind: SpawnKind::Unit {
+                allegiance,
+                tint: tint.map(|(r, g, b, a)| [r, g, b, a]),
+            },
+            x,
+            y,
+        });
+
+        Ok(())
+    }
+
+    /// Spawn an object.
+    fn spawn_object(&mut self, def: Sym, x: f32, y: f32) {
+        self.requests.push(SpawnRequest {
+            def: def.name().to_string(),
+            kind: SpawnKind::Object,
+            x,
+            y,
+        });
+    }
+
+    /// Spawn all objects & markers of a level with its origin at a position.
+    fn spawn_level(&mut self, name: Sym, x: f32, y: f32) {
+        self.levels.push((name.name().to_string(), x, y));
+    }
+
+    /// Fire an arrow from a position towards another with a speed in world units per second.
+    ///
+    /// Arrows shot by a side, like `'ally`, pass through the units of that side.
+    fn fire_arrow(
+        &mut self,
+        from: (f32, f32),
+        to: (f32, f32),
+        speed: f32,
+        allegiance: Option<Sym>,
+    ) -> GResult<()> {
+        let allegiance = allegiance
+            .map(|allegiance| parse_allegiance(&allegiance))
+            .transpose()?;
+
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        // Fire upwards when both positions are the same
+        let (dx, dy) = if length <= std::f32::EPSILON {
+            (0.0, -1.0)
+        } else {
+            (dx / length, dy / length)
+        };
+
+        self.requests.push(SpawnRequest {
+            def: "arrow".to_string(),
+            kind: SpawnKind::Projectile {
+                velocity: (dx * speed, dy * speed),
+                allegiance,
+            },
+            x: from.0,
+            y: from.1,
+        });
+
+        Ok(())
+    }
+
+    /// The tags of an object definition as symbols.
+    fn object_tags(&self, def: Sym) -> GResult<Vec<Sym>> {
+        match self.object_tags.get(&*def.name()) {
+            Some(tags) => tags.iter().map(|tag| glsp::sym(tag)).collect(),
+            None => bail!("unknown object definition {}", def),
+        }
+    }
+
+    /// Remove a unit by the index & generation the scripts know it by.
+    fn despawn(&mut self, entity: (i32, i32)) {
+        self.despawns.push(EntityId::from_script(entity));
+    }
+
+    /// Remove lifepoints from a unit, it dies when none are left.
+    fn damage(&mut self, entity: (i32, i32), amount: f32) {
+        self.damages.push((EntityId::from_script(entity), amount));
+    }
+
+    /// Play an animation of a unit from the start, unless it's already playing.
+    fn play_animation(&mut self, entity: (i32, i32), name: &str) {
+        self.animations
+            .push((EntityId::from_script(entity), name.to_string()));
+    }
+
+    /// Mirror a unit horizontally or not, like a character facing left or right.
+    fn flip_x(&mut self, entity: (i32, i32), flipped: bool) {
+        self.flips.push((EntityId::from_script(entity), flipped));
+    }
+
+    /// Queue a joint, returns the number it can be removed with.
+    ///
+    /// The second unit is `#n` to join the first one to the world, it can't be an optional argument
+    /// because the anchor comes after it.
+    fn join(&mut self, kind: JointKind, a: (i32, i32), b: &Val) -> GResult<i32> {
+        let b = match b {
+            Val::Nil => None,
+            b => Some(EntityId::from_script(<(i32, i32)>::from_val(b)?)),
+        };
+
+        let id = self.next_joint;
+        self.next_joint += 1;
+
+        self.joints.push(JointRequest {
+            id,
+            kind,
+            a: EntityId::from_script(a),
+            b,
+        });
+
+        Ok(id)
+    }
+
+    /// Hinge two units at a world position, or a unit to the world when the second one is `#n`.
+    fn add_revolute_joint(&mut self, a: (i32, i32), b: Val, anchor: (f32, f32)) -> GResult<i32> {
+        self.join(JointKind::Revolute { anchor }, a, &b)
+    }
+
+    /// Let two units only slide along an axis through a world position, optionally between a
+    /// minimum & maximum distance.
+    fn add_prismatic_joint(
+        &mut self,
+        a: (i32, i32),
+        b: Val,
+        anchor: (f32, f32),
+        axis: (f32, f32),
+        min: Option<f32>,
+        max: Option<f32>,
+    ) -> GResult<i32> {
+        let limits = min.zip(max);
+
+        self.join(
+            JointKind::Prismatic {
+                anchor,
+                axis,
+                limits,
+            },
+            a,
+            &b,
+        )
+    }
+
+    /// Keep a world position on each unit at the same distance.
+    fn add_distance_joint(
+        &mut self,
+        a: (i32, i32),
+        b: Val,
+        anchor_a: (f32, f32),
+        anchor_b: (f32, f32),
+    ) -> GResult<i32> {
+        self.join(JointKind::Distance { anchor_a, anchor_b }, a, &b)
+    }
+
+    /// Remove a joint by the number it was added with.
+    fn remove_joint(&mut self, id: i32) {
+        self.removed_joints.push(id);
+    }
+
+    /// Queue a force generator, returns the number it can be removed with.
+    fn add_force(&mut self, kind: ForceKind) -> i32 {
+        let id = self.next_force;
+        self.next_force += 1;
+
+        self.forces.push(ForceRequest { id, kind });
+
+        id
+    }
+
+    /// Blow everything that moves in a direction, optionally only between a minimum & maximum
+    /// world position.
+    fn add_wind(
+        &mut self,
+        acceleration: (f32, f32),
+        min: Option<(f32, f32)>,
+        max: Option<(f32, f32)>,
+    ) -> i32 {
+        self.add_force(ForceKind::Wind {
+            acceleration,
+            zone: min.zip(max),
+        })
+    }
+
+    /// Pull two units towards the distance they have now.
+    fn add_spring(&mut self, a: (i32, i32), b: (i32, i32), stiffness: f32) -> i32 {
+        self.add_force(ForceKind::Spring {
+            a: EntityId::from_script(a),
+            b: EntityId::from_script(b),
+            stiffness,
+        })
+    }
+
+    /// Remove a wind or spring by the number it was added with.
+    fn remove_force(&mut self, id: i32) {
+        self.removed_forces.push(id);
+    }
+
+    /// Attach a unit to a parent unit at an offset, optionally rotated & scaled relative to it.
+    fn attach(
+        &mut self,
+        child: (i32, i32),
+        parent: (i32, i32),
+        x: f32,
+        y: f32,
+        rotation: Option<f32>,
+        scale: Option<f32>,
+    ) {
+        let local_transform = LocalTransform::new(x, y)
+            .rotation(rotation.unwrap_or(0.0))
+            .scale(scale.unwrap_or(1.0));
+
+        self.attachments.push((
+            EntityId::from_script(child),
+            Some((Parent(EntityId::from_script(parent)), local_transform)),
+        ));
+    }
+
+    /// Let an attached unit go where it's held.
+    fn detach(&mut self, child: (i32, i32)) {
+        self.attachments.push((EntityId::from_script(child), None));
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("spawn-unit", rfn!(Self::spawn_unit))?;
+            glsp::bind_rfn("spawn-object", rfn!(Self::spawn_object))?;
+            glsp::bind_rfn("spawn-level", rfn!(Self::spawn_level))?;
+            glsp::bind_rfn("object-tags", rfn!(Self::object_tags))?;
+            glsp::bind_rfn("fire-arrow", rfn!(Self::fire_arrow))?;
+            glsp::bind_rfn("despawn", rfn!(Self::despawn))?;
+            glsp::bind_rfn("damage", rfn!(Self::damage))?;
+            glsp::bind_rfn("play-animation", rfn!(Self::play_animation))?;
+            glsp::bind_rfn("flip-x", rfn!(Self::flip_x))?;
+            glsp::bind_rfn("add-revolute-joint", rfn!(Self::add_revolute_joint))?;
+            glsp::bind_rfn("add-prismatic-joint", rfn!(Self::add_prismatic_joint))?;
+            glsp::bind_rfn("add-distance-joint", rfn!(Self::add_distance_joint))?;
+            glsp::bind_rfn("remove-joint", rfn!(Self::remove_joint))?;
+            glsp::bind_rfn("add-wind", rfn!(Self::add_wind))?;
+            glsp::bind_rfn("add-spring", rfn!(Self::add_spring))?;
+            glsp::bind_rfn("remove-force", rfn!(Self::remove_force))?;
+            glsp::bind_rfn("attach", rfn!(Self::attach))?;
+            glsp::bind_rfn("detach", rfn!(Self::detach))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Convert the symbol of a side, `'ally` or `'enemy`.
+fn parse_allegiance(sym: &Sym) -> GResult<Allegiance> {
+    match &*sym.name() {
+        "ally" => Ok(Allegiance::Ally),
+        "enemy" => Ok(Allegiance::Enemy),
+        name => bail!("unknown allegiance {}, expected ally or enemy", name),
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{
+    render::{Instance, Mesh, Render},
+    svg::Svg,
+    text::Font,
+};
+use anyhow::Result;
+use log::info;
+use lyon::{math::Point, path::Path};
+use usvg::Color;
+
+/// Amount of instances spawned when no amount is given.
+pub const DEFAULT_INSTANCES: usize = 10_000;
+/// Amount of meshes the instances are divided over when no amount is given.
+pub const DEFAULT_MESHES: usize = 16;
+/// Amount of shapes in both directions of the generated SVG, kept small enough that the vertices can be indexed with 16 bits.
+const SVG_GRID: usize = 32;
+/// Space between the instances in pixels.
+const SPACING: f32 = 40.0;
+/// Seconds between logging the average instance sync time.
+const REPORT_INTERVAL: f64 = 5.0;
+
+/// The size of the stress scene, set with the `--stress`, `--instances=N`, `--meshes=M` &
+/// `--static` flags.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StressOptions {
+    /// Total amount of instances.
+    pub instances: usize,
+    /// Amount of meshes, every mesh has a different amount of vertices.
+    pub meshes: usize,
+    /// Whether the instances rotate, otherwise nothing has to be uploaded after the first frame.
+    pub moving: bool,
+}
+
+impl Default for StressOptions {
+    fn default() -> Self {
+        Self {
+            instances: DEFAULT_INSTANCES,
+            meshes: DEFAULT_MESHES,
+            moving: true,
+        }
+    }
+}
+
+/// A scene with a huge amount of moving instances, for measuring the renderer.
+///
+/// The timings of the uploads are logged when it's created, the time it takes to sync the
+/// instances is logged periodically.
+pub struct StressScene {
+    /// The instances of every mesh.
+    meshes: Vec<(Mesh, Vec<Instance>)>,
+    /// Whether the instances rotate.
+    moving: bool,
+    /// Total time spent syncing the instances since the last report in seconds.
+    sync_time: f64,
+    /// Amount of frames since the last report.
+    frames: usize,
+    /// Total amount of instances uploaded since the last report.
+    uploaded: usize,
+    /// The time of the last report in seconds.
+    last_report: f64,
+}
+
+impl StressScene {
+    /// Upload the meshes, a huge SVG & every glyph of the font, logging how long it takes.
+    pub fn new(render: &mut Render, font: &'static [u8], options: StressOptions) -> Result<Self> {
+        info!(
+            "starting stress scene with {} instances across {} meshes",
+            options.instances, options.meshes
+        );
+
+        let start = miniquad::date::now();
+        let meshes = (0..options.meshes.max(1))
+            .map(|index| {
+                // Every mesh has more sides than the previous one
+                let sides = 3 + index * 8;
+                let color = Color::new(
+                    (index * 53 % 256) as u8,
+                    (index * 97 % 256) as u8,
+                    (index * 151 % 256) as u8,
+                );
+
+                let mesh = render.upload_path(&polygon(sides, SPACING / 3.0), color, 1.0);
+                render.set_label(mesh, "stress");
+
+                mesh
+            })
+            .collect::<Vec<_>>();
+        info!(
+            "tessellating {} meshes took {:.2} ms",
+            meshes.len(),
+            (miniquad::date::now() - start) * 1000.0
+        );
+
+        let start = miniquad::date::now();
+        let svg = generate_svg(SVG_GRID).parse::<Svg>()?;
+        let parsed = miniquad::date::now();
+        let mesh = svg.upload(render)?;
+        render.set_label(mesh, "stress");
+        info!(
+            "parsing a SVG with {} shapes took {:.2} ms, uploading it {:.2} ms",
+            SVG_GRID * SVG_GRID,
+            (parsed - start) * 1000.0,
+            (miniquad::date::now() - parsed) * 1000.0
+        );
+
+        let start = miniquad::date::now();
+        let font = Font::from_bytes(font)?;
+        let chars = font.outlined_chars();
+        font.upload(render, chars.chars())?;
+        info!(
+            "uploading all {} glyphs of the font took {:.2} ms",
+            chars.chars().count(),
+            (miniquad::date::now() - start) * 1000.0
+        );
+
+        // Place the instances in a square grid around the origin, alternating the meshes
+        let columns = (options.instances as f32).sqrt().ceil() as usize;
+        let offset = columns as f32 * SPACING / 2.0;
+        let mut meshes = meshes
+            .into_iter()
+            .map(|mesh| (mesh, vec![]))
+            .collect::<Vec<_>>();
+        let mesh_count = meshes.len();
+        for index in 0..options.instances {
+            let mut instance = Instance::new(
+                (index % columns) as f32 * SPACING - offset,
+                (index / columns) as f32 * SPACING - offset,
+            );
+            instance.set_z(128);
+
+            meshes[index % mesh_count].1.push(instance);
+        }
+
+        Ok(Self {
+            meshes,
+            moving: options.moving,
+            sync_time: 0.0,
+            frames: 0,
+            uploaded: 0,
+            last_report: miniquad::date::now(),
+        })
+    }
+
+    /// Rotate all instances when they're moving and pass them to the renderer.
+    pub fn update(&mut self, render: &mut Render) {
+        // Count the instances the previous frame uploaded
+        self.uploaded += render.uploaded_instances();
+
+        let start = miniquad::date::now();
+
+        for (mesh, instances) in self.meshes.iter_mut() {
+            if self.moving {
+                for instance in instances.iter_mut() {
+                    let rotation = instance.rotation() + 0.01;
+                    instance.set_rotation(rotation);
+                }
+            }
+
+            render.set_instances(*mesh, instances);
+        }
+
+        let now = miniquad::date::now();
+        self.sync_time += now - start;
+        self.frames += 1;
+
+        if now - self.last_report >= REPORT_INTERVAL {
+            info!(
+                "syncing the instances took {:.3} ms on average over {} frames, {} instances were uploaded per frame",
+                self.sync_time / self.frames as f64 * 1000.0,
+                self.frames,
+                self.uploaded / self.frames
+            );
+
+            self.sync_time = 0.0;
+            self.frames = 0;
+            self.uploaded = 0;
+            self.last_report = now;
+        }
+    }
+}
+
+/// Build a polygon with an amount of sides around the origin.
+fn polygon(sides: usize, radius: f32) -> Path {
+    let mut builder = Path::builder();
+    for side in 0..sides {
+        let angle = side as f32 / sides as f32 * std::f32::consts::PI * 2.0;
+        let point = Point::new(angle.cos() * radius, angle.sin() * radius);
+        if side == 0 {
+            builder.move_to(point);
+        } else {
+            builder.line_to(point);
+        }
+    }
+    builder.close();
+
+    builder.build()
+}
+
+/// Generate a SVG with a grid of circles and stroked paths.
+fn generate_svg(size: usize) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}">"#,
+        size * 10
+    );
+    for y in 0..size {
+        for x in 0..size {
+            let (cx, cy) = (x * 10 + 5, y * 10 + 5);
+            if (x + y) % 2 == 0 {
+                svg.push_str(&format!(
+                    r##"<circle cx="{}" cy="{}" r="4" fill="#{:02x}{:02x}80"/>"##,
+                    cx,
+                    cy,
+                    x * 255 / size,
+                    y * 255 / size
+                ));
+            } else {
+                svg.push_str(&format!(
+                    r#"<path d="M{} {} q 4 -4 8 0 t 8 0" stroke="black" fill="none"/>"#,
+                    cx - 4,
+                    cy
+                ));
+            }
+        }
+    }
+    svg.push_str("</svg>");
+
+    svg
+}
@@ -1,40 +1,202 @@
 use crate::{
-    object::ObjectDef,
-    physics::Physics,
-    render::{Mesh, Render, Vertex, VertexCtor},
+    animation::{Animation, Keyframe, Part, Skeleton, Track},
+    gradient::Gradient,
+    nine_slice::NineSlice,
+    object::{ObjectDef, ObjectSource},
+    physics::{BodyKind, BodyParameters, CollisionGroup, Physics},
+    render::{ClipMask, Layer, Mesh, Render, TexturedRect, Vertex, VertexCtor},
 };
 use anyhow::{anyhow, Result};
 use lyon::{
     math::Point,
-    path::PathEvent,
+    path::{iterator::PathIterator, PathEvent},
     tessellation::{
-        BuffersBuilder, FillOptions, FillTessellator, LineCap, LineJoin, StrokeOptions,
-        StrokeTessellator, VertexBuffers,
+        BuffersBuilder, FillAttributes, FillOptions, FillTessellator, LineCap, LineJoin,
+        StrokeOptions, StrokeTessellator, TessellationError, VertexBuffers,
     },
 };
-use nalgebra::{convert as f, Isometry2, RealField, Vector2};
-use ncollide2d::shape::{Ball, Compound, Cuboid, ShapeHandle};
-use std::borrow::Cow;
-use usvg::{Color, NodeKind, Options, Paint, Path, PathSegment, ShapeRendering, Stroke, Tree};
+use nalgebra::{convert as f, Isometry2, Point2, RealField, Vector2};
+use ncollide2d::shape::{Ball, Capsule, Compound, ConvexPolygon, Cuboid, Polyline, ShapeHandle};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    mem,
+    str::FromStr,
+};
+use usvg::{
+    Color, ImageData, ImageFormat, Node, NodeExt, NodeKind, Options, Paint, Path, PathSegment,
+    ShapeRendering, Stroke, Transform, Tree, Units,
+};
 use xmltree::Element;
 
 const PATH_TOLERANCE: f32 = 0.01;
+/// Amount of corners of the polygon an ellipse collider is approximated with.
+const ELLIPSE_SEGMENTS: usize = 24;
 
 /// A parsed SVG containing the mesh and the specific metadata.
+#[derive(Clone)]
 pub struct Svg {
     /// The lyon geometry.
-    geometry: VertexBuffers<Vertex, u16>,
+    geometry: Geometry,
+    /// The geometry of every named group or layer, without the named groups inside of it.
+    parts: Parts,
+    /// The embedded bitmaps, drawn below the geometry.
+    images: Vec<SvgImage>,
     /// The metadata XML node.
     metadata: Option<Element>,
+    /// The point in document coordinates that's the origin of the meshes, which they're rotated &
+    /// scaled around.
+    origin: (f32, f32),
 }
 
-impl Svg {
+/// Tessellated paths with the masks clipping some of them.
+#[derive(Clone, Default)]
+struct Geometry {
+    /// The triangles of all paths.
+    buffers: VertexBuffers<Vertex, u32>,
+    /// The masks with the ranges of triangles they clip, in the order of the paths.
+    clips: Vec<ClipMask>,
+    /// The clipped group the last mask belongs to, the paths after each other in it share the
+    /// mask.
+    last_clip: Option<usize>,
+}
+
+impl Geometry {
+    /// Add the triangles of a path, clipped by the mask of a group numbered in the order of the
+    /// document.
+    fn append(
+        &mut self,
+        from: &VertexBuffers<Vertex, u32>,
+        clip: Option<(usize, &VertexBuffers<Vertex, u32>)>,
+    ) {
+        let start = self.buffers.indices.len();
+        append_geometry(&mut self.buffers, from);
+        let end = self.buffers.indices.len();
+
+        if let Some((group, mask)) = clip {
+            match self.clips.last_mut() {
+                Some(last) if last.indices.end == start && self.last_clip == Some(group) => {
+                    last.indices.end = end
+                }
+                _ => {
+                    self.clips.push(ClipMask {
+                        mask: mask.clone(),
+                        indices: start..end,
+                    });
+                    self.last_clip = Some(group);
+                }
+            }
+        }
+    }
+
+    /// Move all vertices, including the ones of the masks.
+    fn transform(&mut self, transform: &Transform) {
+        for vertex in self
+            .clips
+            .iter_mut()
+            .flat_map(|clip| clip.mask.vertices.iter_mut())
+            .chain(self.buffers.vertices.iter_mut())
+        {
+            vertex.transform(transform);
+        }
+    }
+
+    /// Upload it with the bitmaps drawn below it.
+    fn upload(&self, render: &mut Render, images: &[TexturedRect]) -> Result<Mesh> {
+        render.upload_textured(&self.buffers, images, &self.clips)
+    }
+
+    /// Replace the geometry of an uploaded mesh.
+    fn replace(&self, render: &mut Render, mesh: Mesh, images: &[TexturedRect]) {
+        render.replace_geometry(mesh, &self.buffers, images, &self.clips);
+    }
+}
+
+/// A point in an SVG where an object is spawned, from a `<spawn>` element in the metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnMarker {
+    /// The name of the object definition that's spawned.
+    pub object: String,
+    /// The position relative to the origin of the SVG.
+    pub pos: (f32, f32),
+    /// The other attributes of the element, like the allegiance or the health of a unit.
+    pub attributes: HashMap<String, String>,
+}
+
+/// A mistake in the metadata of an SVG, like a typo in an attribute of a collider.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SvgError {
+    /// An element doesn't have an attribute it needs.
+    MissingAttribute { element: String, attribute: String },
+    /// The value of an attribute can't be parsed.
+    InvalidAttribute {
+        element: String,
+        attribute: String,
+        reason: String,
+    },
+    /// A collider element that isn't one of the supported shapes.
+    UnknownCollider(String),
+    /// A collider element that doesn't describe a usable shape, like a circle without a radius.
+    InvalidCollider { element: String, reason: String },
+    /// A collider element without any shapes in it.
+    NoColliders(String),
+}
+
+impl Display for SvgError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SvgError::MissingAttribute { element, attribute } => {
+                write!(f, "Attribute \"{}\" missing in \"{}\"", attribute, element)
+            }
+            SvgError::InvalidAttribute {
+                element,
+                attribute,
+                reason,
+            } => write!(
+                f,
+                "Attribute \"{}\" in \"{}\" is invalid: {}",
+                attribute, element, reason
+            ),
+            SvgError::UnknownCollider(element) => {
+                write!(f, "Unrecognized metadata collider element \"{}\"", element)
+            }
+            SvgError::InvalidCollider { element, reason } => {
+                write!(f, "Collider element \"{}\" is invalid: {}", element, reason)
+            }
+            SvgError::NoColliders(element) => {
+                write!(f, "Could not find colliders in \"{}\"", element)
+            }
+        }
+    }
+}
+
+impl Error for SvgError {}
+
+/// A PNG embedded in the SVG with an image element.
+#[derive(Clone)]
+struct SvgImage {
+    /// The contents of the PNG file.
+    data: Vec<u8>,
+    /// The top left corner.
+    pos: (f32, f32),
+    /// The width & height the bitmap is stretched to.
+    size: (f32, f32),
+    /// The closest group with an ID it belongs to.
+    part: Option<String>,
+}
+
+impl FromStr for Svg {
+    type Err = anyhow::Error;
+
     /// Parse a SVG string.
-    pub fn from_str(svg: &str) -> Result<Self> {
-        // Simplify SVG
+    fn from_str(svg: &str) -> Result<Self> {
+        // Simplify SVG, the groups with an ID are kept so they can be split into parts
         let options = Options {
             shape_rendering: ShapeRendering::GeometricPrecision,
-            keep_named_groups: false,
+            keep_named_groups: true,
             ..Default::default()
         };
         let rtree = Tree::from_str(svg, &options)?;
@@ -43,15 +205,105 @@ impl Svg {
         let document = Element::parse(svg.as_bytes())?;
         let metadata = document.get_child("metadata").cloned();
 
+        // Without a pivot the top left corner of the view box is the origin
+        let origin = match metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get_child("pivot"))
+        {
+            Some(pivot) => (
+                required_attribute::<f32>(pivot, "x")?,
+                required_attribute::<f32>(pivot, "y")?,
+            ),
+            None => {
+                let view_box = rtree.svg_node().view_box.rect;
+
+                (view_box.x() as f32, view_box.y() as f32)
+            }
+        };
+
+        let mut glowing = HashSet::new();
+        glowing_ids(&document, &mut glowing)?;
+
+        let (mut geometry, mut parts, mut images) = parse_node(rtree, &glowing)?;
+
+        // Move everything so the origin is at zero
+        let to_origin = Transform::new_translate(-origin.0 as f64, -origin.1 as f64);
+        for geometry in parts.values_mut().chain(std::iter::once(&mut geometry)) {
+            geometry.transform(&to_origin);
+        }
+        for image in images.iter_mut() {
+            image.pos = (image.pos.0 - origin.0, image.pos.1 - origin.1);
+        }
+
         Ok(Self {
-            geometry: parse_node(rtree)?,
+            geometry,
+            parts,
+            images,
             metadata,
+            origin,
         })
     }
+}
 
+impl Svg {
     /// Upload it and get a mesh.
     pub fn upload(&self, render: &mut Render) -> Result<Mesh> {
-        render.upload_buffers(&self.geometry)
+        let images = self.upload_images(render, None)?;
+
+        self.geometry.upload(render, &images)
+    }
+
+    /// Upload the named groups & layers as separate meshes, keyed by their ID.
+    ///
+    /// A part doesn't contain the named groups inside of it, so they can be moved independently.
+    pub fn upload_parts(&self, render: &mut Render) -> Result<HashMap<String, Mesh>> {
+        self.parts
+            .iter()
+            .map(|(name, geometry)| {
+                let images = self.upload_images(render, Some(name))?;
+
+                Ok((name.clone(), geometry.upload(render, &images)?))
+            })
+            .collect()
+    }
+
+    /// Upload the bitmaps of the whole SVG or of a single part as textures.
+    fn upload_images(&self, render: &mut Render, part: Option<&str>) -> Result<Vec<TexturedRect>> {
+        self.images
+            .iter()
+            .filter(|image| part.map_or(true, |part| image.part.as_deref() == Some(part)))
+            .map(|image| {
+                Ok(TexturedRect {
+                    texture: render.upload_texture(&image.data)?,
+                    pos: image.pos,
+                    size: image.size,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the geometry of meshes uploaded from an older version of the SVG, so their instances
+    /// are drawn with the new version.
+    ///
+    /// Every part needs to exist in the new version, nothing is replaced otherwise.
+    pub fn replace_meshes(
+        &self,
+        render: &mut Render,
+        mesh: Mesh,
+        parts: &HashMap<String, Mesh>,
+    ) -> Result<()> {
+        if let Some(name) = parts.keys().find(|name| !self.parts.contains_key(*name)) {
+            return Err(anyhow!("Part \"{}\" is missing", name));
+        }
+
+        let images = self.upload_images(render, None)?;
+        self.geometry.replace(render, mesh, &images);
+        for (name, part) in parts.iter() {
+            let images = self.upload_images(render, Some(name))?;
+            self.parts[name].replace(render, *part, &images);
+        }
+
+        Ok(())
     }
 
     /// Get the value of a metadata field.
@@ -63,95 +315,464 @@ impl Svg {
             .flatten()
     }
 
-    /// Build an object definition.
+    /// Get the X and Y attributes of a metadata field, relative to the origin.
+    ///
+    /// Returns `None` when the field is missing.
+    pub fn metadata_point(&self, key: &str) -> Result<Option<(f32, f32)>> {
+        let element = match self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get_child(key))
+        {
+            Some(element) => element,
+            None => return Ok(None),
+        };
+
+        Ok(Some((
+            required_attribute::<f32>(element, "x")? - self.origin.0,
+            required_attribute::<f32>(element, "y")? - self.origin.1,
+        )))
+    }
+
+    /// Build an object definition, the values set in the source take precedence over the
+    /// attributes of the collider.
     ///
     /// Also upload the mesh.
-    pub fn into_object_def(self, render: &mut Render) -> Result<ObjectDef> {
+    pub fn into_object_def(self, render: &mut Render, source: &ObjectSource) -> Result<ObjectDef> {
         let mesh = self.upload(render)?;
+        let parts = self.upload_parts(render)?;
+        let skeleton = self.parse_metadata_skeleton(&parts)?;
 
-        let is_ground = self
+        let collider_element = self
             .metadata_collider_element()
-            .ok_or_else(|| anyhow!("Metadata tag missing"))?
-            .attributes
-            .contains_key("ground");
-
-        let rigid_body = Physics::default_rigid_body_builder();
-        let collider = Physics::default_collider_builder(
-            self.parse_metadata_colliders()
-                .ok_or_else(|| anyhow!("Could not find colliders in shape"))?,
-        );
+            .ok_or_else(|| anyhow!("Metadata tag missing"))?;
+
+        self.object_def(collider_element, source, mesh, parts, skeleton)
+    }
+
+    /// Build an object definition for every `<object>` element in the metadata, from the named
+    /// group it refers to, with the `<spawn>` markers.
+    ///
+    /// An object element is the collider of its definition, the values set in the source take
+    /// precedence over its attributes for all definitions. Also upload the meshes.
+    pub fn into_object_defs(
+        self,
+        render: &mut Render,
+        source: &ObjectSource,
+    ) -> Result<(BTreeMap<String, ObjectDef>, Vec<SpawnMarker>)> {
+        let metadata = self
+            .metadata
+            .as_ref()
+            .ok_or_else(|| anyhow!("Metadata tag missing"))?;
+        let elements = |name: &'static str| {
+            metadata
+                .children
+                .iter()
+                .filter_map(|node| node.as_element())
+                .filter(move |element| element.name == name)
+        };
+
+        let meshes = self.upload_parts(render)?;
+        let mut defs = BTreeMap::new();
+        for element in elements("object") {
+            let name = element
+                .attributes
+                .get("name")
+                .ok_or_else(|| anyhow!("Object without a \"name\" attribute"))?;
+            // The group is named like the object unless it's set
+            let group = element.attributes.get("group").unwrap_or(name);
+            let mesh = *meshes
+                .get(group)
+                .ok_or_else(|| anyhow!("Object \"{}\" has no group \"{}\"", name, group))?;
+
+            let def = self.object_def(element, source, mesh, HashMap::new(), None)?;
+            if defs.insert(name.clone(), def).is_some() {
+                return Err(anyhow!("Object \"{}\" is defined twice", name));
+            }
+        }
+
+        let markers = elements("spawn")
+            .map(|element| {
+                Ok(SpawnMarker {
+                    object: required_attribute(element, "object")?,
+                    pos: (
+                        required_attribute::<f32>(element, "x")? - self.origin.0,
+                        required_attribute::<f32>(element, "y")? - self.origin.1,
+                    ),
+                    attributes: element
+                        .attributes
+                        .iter()
+                        .filter(|(name, _)| !["object", "x", "y"].contains(&name.as_str()))
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((defs, markers))
+    }
+
+    /// Build an object definition with the shapes & the attributes of a collider element.
+    fn object_def(
+        &self,
+        collider_element: &Element,
+        source: &ObjectSource,
+        mesh: Mesh,
+        parts: HashMap<String, Mesh>,
+        skeleton: Option<Skeleton>,
+    ) -> Result<ObjectDef> {
+        let kind = source.body.unwrap_or_else(|| {
+            if collider_element.attributes.contains_key("ground") {
+                BodyKind::Static
+            } else if collider_element.attributes.contains_key("kinematic") {
+                BodyKind::Kinematic
+            } else {
+                BodyKind::Dynamic
+            }
+        });
+        let is_ground = kind == BodyKind::Static;
+
+        // The physical behavior can be tuned with attributes of the collider element
+        let defaults = BodyParameters::default();
+        let parameter = |name: &str, default: f64| -> Result<f64> {
+            Ok(parse_attribute(collider_element, name)?.map_or(default, |value| value as f64))
+        };
+        let parameters = BodyParameters {
+            density: source
+                .density
+                .map_or_else(|| parameter("density", defaults.density), Ok)?,
+            friction: source
+                .friction
+                .map_or_else(|| parameter("friction", defaults.friction), Ok)?,
+            restitution: parameter("restitution", defaults.restitution)?,
+            linear_damping: parameter("linear-damping", defaults.linear_damping)?,
+            kind,
+            sensor: collider_element.attributes.contains_key("sensor"),
+        };
+
+        let rigid_body = Physics::rigid_body_builder(&parameters);
+        let collider =
+            Physics::collider_builder(self.parse_colliders(collider_element)?, &parameters);
 
         Ok(ObjectDef {
-            is_ground,
+            // The ground is drawn below everything else
+            layer: source.layer.unwrap_or(if is_ground {
+                Layer::Ground
+            } else {
+                Layer::Units
+            }),
+            group: source.collision_group.map_or(
+                if is_ground {
+                    CollisionGroup::Ground
+                } else {
+                    CollisionGroup::Object
+                },
+                CollisionGroup::from,
+            ),
+            tags: source.tags.clone(),
+            sticks: source.sticks,
+            kind,
+            is_sensor: parameters.sensor,
             mesh,
+            parts,
+            skeleton,
             rigid_body,
             collider,
         })
     }
 
-    /// Get the colliders from the SVG metadata.
-    fn parse_metadata_colliders<N>(&self) -> Option<Compound<N>>
+    /// Build a nine-slice from the slice guides in the metadata.
+    pub fn into_nine_slice(self) -> Result<NineSlice> {
+        let slice = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get_child("slice"))
+            .ok_or_else(|| anyhow!("Metadata slice tag missing"))?;
+
+        // Parse the position of a single guide
+        let guide = |name: &str| required_attribute::<f32>(slice, name);
+        let (left, top, right, bottom) = (
+            guide("left")? - self.origin.0,
+            guide("top")? - self.origin.1,
+            guide("right")? - self.origin.0,
+            guide("bottom")? - self.origin.1,
+        );
+
+        Ok(NineSlice::new(
+            self.geometry.buffers,
+            left,
+            top,
+            right,
+            bottom,
+        ))
+    }
+
+    /// Get the hierarchy of parts and their animations from the SVG metadata.
+    ///
+    /// Returns `None` when there are no parts, the object is drawn as a single mesh then.
+    fn parse_metadata_skeleton(&self, meshes: &HashMap<String, Mesh>) -> Result<Option<Skeleton>> {
+        let metadata = match self.metadata.as_ref() {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        };
+        let parts_element = match metadata.get_child("parts") {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+
+        // The parents must be defined before their children
+        let mut names: Vec<&str> = vec![];
+        let mut parts = vec![];
+        for element in parts_element
+            .children
+            .iter()
+            .filter_map(|node| node.as_element())
+        {
+            let name = element
+                .attributes
+                .get("id")
+                .ok_or_else(|| anyhow!("Part without an \"id\" attribute"))?;
+            let mesh = *meshes
+                .get(name)
+                .ok_or_else(|| anyhow!("Part \"{}\" has no named group", name))?;
+            let parent = element
+                .attributes
+                .get("parent")
+                .map(|parent| {
+                    names
+                        .iter()
+                        .position(|existing| existing == parent)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Parent \"{}\" of part \"{}\" isn't defined before it",
+                                parent,
+                                name
+                            )
+                        })
+                })
+                .transpose()?;
+            let pivot = (
+                parse_attribute(element, "x")?.unwrap_or(0.0) - self.origin.0,
+                parse_attribute(element, "y")?.unwrap_or(0.0) - self.origin.1,
+            );
+
+            names.push(name);
+            parts.push(Part {
+                mesh,
+                parent,
+                pivot,
+            });
+        }
+
+        let mut animations = HashMap::new();
+        for element in metadata
+            .get_child("animations")
+            .iter()
+            .flat_map(|animations| animations.children.iter())
+            .filter_map(|node| node.as_element())
+        {
+            let name = element
+                .attributes
+                .get("name")
+                .ok_or_else(|| anyhow!("Animation without a \"name\" attribute"))?;
+
+            // Group the keyframes by the part they move
+            let mut tracks: Vec<Track> = vec![];
+            for key in element.children.iter().filter_map(|node| node.as_element()) {
+                let part_name = key
+                    .attributes
+                    .get("part")
+                    .ok_or_else(|| anyhow!("Key in animation \"{}\" without a part", name))?;
+                let part = names
+                    .iter()
+                    .position(|existing| existing == part_name)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Part \"{}\" in animation \"{}\" doesn't exist",
+                            part_name,
+                            name
+                        )
+                    })?;
+                let keyframe = Keyframe {
+                    time: parse_attribute(key, "time")?.unwrap_or(0.0),
+                    // Rotations are in degrees, like in SVG transforms
+                    rotation: parse_attribute(key, "rotate")?.unwrap_or(0.0).to_radians(),
+                    offset: (
+                        parse_attribute(key, "x")?.unwrap_or(0.0),
+                        parse_attribute(key, "y")?.unwrap_or(0.0),
+                    ),
+                };
+
+                match tracks.iter_mut().find(|track| track.part == part) {
+                    Some(track) => track.keyframes.push(keyframe),
+                    None => tracks.push(Track {
+                        part,
+                        keyframes: vec![keyframe],
+                    }),
+                }
+            }
+            for track in tracks.iter_mut() {
+                track
+                    .keyframes
+                    .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+            }
+
+            // Without a duration the animation ends at the last keyframe
+            let duration = match parse_attribute(element, "duration")? {
+                Some(duration) => duration,
+                None => tracks
+                    .iter()
+                    .filter_map(|track| track.keyframes.last())
+                    .map(|keyframe| keyframe.time)
+                    .fold(0.0, f32::max),
+            };
+
+            animations.insert(
+                name.clone(),
+                Animation {
+                    duration,
+                    looping: element.attributes.contains_key("loop"),
+                    tracks,
+                },
+            );
+        }
+
+        Ok(Some(Skeleton { parts, animations }))
+    }
+
+    /// Get the colliders from the shapes in a collider element of the SVG metadata, moved with the
+    /// origin.
+    fn parse_colliders<N>(&self, collider_element: &Element) -> Result<Compound<N>, SvgError>
     where
         N: RealField,
     {
-        // Get the colliders element in the metadata section
-        let shapes = self
-            .metadata_collider_element()?
+        let origin = Vector2::new(f(self.origin.0 as f64), f(self.origin.1 as f64));
+
+        let mut shapes = vec![];
+        // Text & comments between the shapes are ignored
+        for element in collider_element
             .children
             .iter()
-            .map(|node| {
-                let element = node.as_element().expect("Node is not a proper XML element");
-                let (offset, shape_handle) = match element.name.as_str() {
-                    // Parse an SVG circle element
-                    "circle" => {
-                        let offset_x = element.attributes["cx"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-                        let offset_y = element.attributes["cy"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-                        let offset = Vector2::new(f(offset_x), f(offset_y));
-
-                        let radius = element.attributes["r"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-                        let shape = Ball::<N>::new(f(radius));
-
-                        (offset, ShapeHandle::new(shape))
-                    }
-                    // Parse an SVG rectangle element
-                    "rect" => {
-                        let offset_x = element.attributes["x"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-                        let offset_y = element.attributes["y"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-                        let width = element.attributes["width"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-                        let height = element.attributes["height"]
-                            .parse::<f64>()
-                            .expect("Node is not a proper floating point");
-
-                        let offset =
-                            Vector2::new(f(offset_x + width / 2.0), f(offset_y + height / 2.0));
-
-                        let shape = Cuboid::<N>::new(Vector2::new(f(width / 2.0), f(height / 2.0)));
-
-                        (offset, ShapeHandle::new(shape))
+            .filter_map(|node| node.as_element())
+        {
+            let invalid = |reason: &str| SvgError::InvalidCollider {
+                element: element.name.clone(),
+                reason: reason.to_string(),
+            };
+            let positive = |name: &str| -> Result<f64, SvgError> {
+                let value = required_attribute::<f64>(element, name)?;
+                if value > 0.0 {
+                    Ok(value)
+                } else {
+                    Err(invalid(&format!("\"{}\" must be positive", name)))
+                }
+            };
+
+            let element_shapes = match element.name.as_str() {
+                // Parse an SVG circle element
+                "circle" => {
+                    let offset = Vector2::new(
+                        f(required_attribute::<f64>(element, "cx")?),
+                        f(required_attribute::<f64>(element, "cy")?),
+                    );
+                    let shape = Ball::<N>::new(f(positive("r")?));
+
+                    vec![(
+                        Isometry2::new(offset, nalgebra::zero()),
+                        ShapeHandle::new(shape),
+                    )]
+                }
+                // Parse an SVG ellipse element, it's a circle when both radii are the same
+                "ellipse" => {
+                    let offset = Vector2::new(
+                        f(required_attribute::<f64>(element, "cx")?),
+                        f(required_attribute::<f64>(element, "cy")?),
+                    );
+                    let shape = ellipse_shape::<N>(positive("rx")?, positive("ry")?)
+                        .ok_or_else(|| invalid("the ellipse is too small"))?;
+
+                    vec![(Isometry2::new(offset, nalgebra::zero()), shape)]
+                }
+                // Parse a capsule element, a line between the centers of its round ends
+                "capsule" => {
+                    let x1 = required_attribute::<f64>(element, "x1")?;
+                    let y1 = required_attribute::<f64>(element, "y1")?;
+                    let x2 = required_attribute::<f64>(element, "x2")?;
+                    let y2 = required_attribute::<f64>(element, "y2")?;
+                    let radius = positive("r")?;
+
+                    // The capsule shape lies along the Y axis, turn it towards the second end
+                    let (dx, dy) = (x2 - x1, y2 - y1);
+                    let offset = Vector2::new(f((x1 + x2) / 2.0), f((y1 + y2) / 2.0));
+                    let angle = (-dx).atan2(dy);
+                    let half_height = dx.hypot(dy) / 2.0;
+                    let shape = if half_height > 0.0 {
+                        ShapeHandle::new(Capsule::<N>::new(f(half_height), f(radius)))
+                    } else {
+                        ShapeHandle::new(Ball::<N>::new(f(radius)))
+                    };
+
+                    vec![(Isometry2::new(offset, f(angle)), shape)]
+                }
+                // Parse an SVG rectangle element
+                "rect" => {
+                    let offset_x = required_attribute::<f64>(element, "x")?;
+                    let offset_y = required_attribute::<f64>(element, "y")?;
+                    let width = positive("width")?;
+                    let height = positive("height")?;
+
+                    let offset =
+                        Vector2::new(f(offset_x + width / 2.0), f(offset_y + height / 2.0));
+
+                    let shape = Cuboid::<N>::new(Vector2::new(f(width / 2.0), f(height / 2.0)));
+
+                    vec![(
+                        Isometry2::new(offset, nalgebra::zero()),
+                        ShapeHandle::new(shape),
+                    )]
+                }
+                // Parse an SVG polygon or polyline element, the points are already in place
+                "polygon" | "polyline" => {
+                    outline_shapes(&parse_points(element)?, element.name == "polygon")
+                        .map_err(|err| invalid(&format!("triangulating failed: {:?}", err)))?
+                        .into_iter()
+                        .map(|shape| (Isometry2::identity(), shape))
+                        .collect()
+                }
+                // Parse an SVG path element, every subpath becomes a separate outline
+                "path" => {
+                    let mut path_shapes = vec![];
+                    for (points, closed) in flatten_path_data(element)? {
+                        path_shapes.extend(
+                            outline_shapes(&points, closed)
+                                .map_err(|err| {
+                                    invalid(&format!("triangulating failed: {:?}", err))
+                                })?
+                                .into_iter()
+                                .map(|shape| (Isometry2::identity(), shape)),
+                        );
                     }
-                    other => panic!("Unrecognized metadata collider element \"{}\".", other),
-                };
 
-                (Isometry2::new(offset, nalgebra::zero()), shape_handle)
-            })
-            .collect::<Vec<_>>();
+                    path_shapes
+                }
+                other => return Err(SvgError::UnknownCollider(other.to_string())),
+            };
+
+            shapes.extend(element_shapes.into_iter().map(|(position, shape_handle)| {
+                (
+                    Isometry2::new(
+                        position.translation.vector - origin,
+                        position.rotation.angle(),
+                    ),
+                    shape_handle,
+                )
+            }));
+        }
 
         if !shapes.is_empty() {
-            Some(Compound::new(shapes))
+            Ok(Compound::new(shapes))
         } else {
-            None
+            Err(SvgError::NoColliders(collider_element.name.clone()))
         }
     }
 
@@ -165,6 +786,215 @@ impl Svg {
     }
 }
 
+/// Parse an optional floating point attribute of a metadata element.
+fn parse_attribute(element: &Element, name: &str) -> Result<Option<f32>, SvgError> {
+    element
+        .attributes
+        .get(name)
+        .map(|value| {
+            value
+                .parse::<f32>()
+                .map_err(|err| SvgError::InvalidAttribute {
+                    element: element.name.clone(),
+                    attribute: name.to_string(),
+                    reason: err.to_string(),
+                })
+        })
+        .transpose()
+}
+
+/// Parse an attribute of a metadata element that must be set.
+fn required_attribute<T>(element: &Element, name: &str) -> Result<T, SvgError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    element
+        .attributes
+        .get(name)
+        .ok_or_else(|| SvgError::MissingAttribute {
+            element: element.name.clone(),
+            attribute: name.to_string(),
+        })?
+        .parse::<T>()
+        .map_err(|err| SvgError::InvalidAttribute {
+            element: element.name.clone(),
+            attribute: name.to_string(),
+            reason: err.to_string(),
+        })
+}
+
+/// Parse the coordinates in the points attribute of a polygon or polyline element.
+fn parse_points(element: &Element) -> Result<Vec<Point>, SvgError> {
+    let points = required_attribute::<String>(element, "points")?;
+    let coordinates = points
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|coordinate| !coordinate.is_empty())
+        .map(|coordinate| {
+            coordinate
+                .parse::<f32>()
+                .map_err(|err| SvgError::InvalidAttribute {
+                    element: element.name.clone(),
+                    attribute: "points".to_string(),
+                    reason: format!("\"{}\" {}", coordinate, err),
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(coordinates
+        .chunks_exact(2)
+        .map(|xy| Point::new(xy[0], xy[1]))
+        .collect())
+}
+
+/// Flatten the data of a path element into the points of its subpaths, with whether they're
+/// closed.
+fn flatten_path_data(element: &Element) -> Result<Vec<(Vec<Point>, bool)>, SvgError> {
+    // Let usvg resolve the relative, shorthand & arc commands, like in the drawn paths
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1" viewBox="0 0 1 1"><path d="{}"/></svg>"#,
+        required_attribute::<String>(element, "d")?
+    );
+    let rtree =
+        Tree::from_str(&svg, &Options::default()).map_err(|err| SvgError::InvalidAttribute {
+            element: element.name.clone(),
+            attribute: "d".to_string(),
+            reason: err.to_string(),
+        })?;
+
+    let mut outlines = vec![];
+    for node in rtree.root().descendants() {
+        if let NodeKind::Path(ref path) = *node.borrow() {
+            let mut points = vec![];
+            for event in convert_path(path).flattened(PATH_TOLERANCE) {
+                match event {
+                    PathEvent::Begin { at } => points.push(at),
+                    PathEvent::Line { to, .. } => points.push(to),
+                    PathEvent::End { close, .. } => outlines.push((mem::take(&mut points), close)),
+                    // The curves are flattened into lines
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    Ok(outlines)
+}
+
+/// The collision shape of an ellipse around the origin, a convex polygon unless it's a circle.
+///
+/// Returns `None` when the polygon is too small to have an area.
+fn ellipse_shape<N>(radius_x: f64, radius_y: f64) -> Option<ShapeHandle<N>>
+where
+    N: RealField,
+{
+    if (radius_x - radius_y).abs() < f64::EPSILON {
+        return Some(ShapeHandle::new(Ball::<N>::new(f(radius_x))));
+    }
+
+    let points = (0..ELLIPSE_SEGMENTS)
+        .map(|index| {
+            let angle = index as f64 / ELLIPSE_SEGMENTS as f64 * std::f64::consts::PI * 2.0;
+
+            Point2::new(f(angle.cos() * radius_x), f(angle.sin() * radius_y))
+        })
+        .collect::<Vec<_>>();
+
+    ConvexPolygon::try_from_points(&points).map(ShapeHandle::new)
+}
+
+/// The collision shapes of an outline, concave polygons are split into triangles.
+fn outline_shapes<N>(
+    points: &[Point],
+    closed: bool,
+) -> Result<Vec<ShapeHandle<N>>, TessellationError>
+where
+    N: RealField,
+{
+    let convert = |point: &Point| Point2::new(f::<f64, N>(point.x as f64), f(point.y as f64));
+
+    if !closed {
+        return Ok(vec![ShapeHandle::new(Polyline::new(
+            points.iter().map(convert).collect(),
+            None,
+        ))]);
+    }
+
+    // The last point of a closed path can be the same as the first
+    let points = match (points.first(), points.last()) {
+        (Some(first), Some(last)) if points.len() > 1 && first == last => {
+            &points[..points.len() - 1]
+        }
+        _ => points,
+    };
+    if points.len() < 3 {
+        return Ok(vec![]);
+    }
+
+    if is_convex(points) {
+        return Ok(
+            ConvexPolygon::try_from_points(&points.iter().map(convert).collect::<Vec<_>>())
+                .map(ShapeHandle::new)
+                .into_iter()
+                .collect(),
+        );
+    }
+
+    // Triangulate the concave polygon, every triangle is convex
+    let mut events = vec![PathEvent::Begin { at: points[0] }];
+    events.extend(points.windows(2).map(|line| PathEvent::Line {
+        from: line[0],
+        to: line[1],
+    }));
+    events.push(PathEvent::End {
+        last: points[points.len() - 1],
+        first: points[0],
+        close: true,
+    });
+    let mut triangles: VertexBuffers<Point, u16> = VertexBuffers::new();
+    FillTessellator::new().tessellate(
+        events,
+        &FillOptions::tolerance(PATH_TOLERANCE),
+        &mut BuffersBuilder::new(&mut triangles, |pos: Point, _: FillAttributes| pos),
+    )?;
+
+    Ok(triangles
+        .indices
+        .chunks_exact(3)
+        .filter_map(|triangle| {
+            let corners = triangle
+                .iter()
+                .map(|index| convert(&triangles.vertices[*index as usize]))
+                .collect::<Vec<_>>();
+
+            ConvexPolygon::try_from_points(&corners).map(ShapeHandle::new)
+        })
+        .collect())
+}
+
+/// Whether all corners of a polygon turn in the same direction.
+fn is_convex(points: &[Point]) -> bool {
+    let mut clockwise = None;
+    for index in 0..points.len() {
+        let a = points[index];
+        let b = points[(index + 1) % points.len()];
+        let c = points[(index + 2) % points.len()];
+
+        let cross = (b - a).cross(c - b);
+        // Ignore straight corners
+        if cross.abs() <= std::f32::EPSILON {
+            continue;
+        }
+        match clockwise {
+            None => clockwise = Some(cross > 0.0),
+            Some(clockwise) if clockwise != (cross > 0.0) => return false,
+            Some(_) => (),
+        }
+    }
+
+    true
+}
+
 struct PathConvIter<'a> {
     iter: std::slice::Iter<'a, PathSegment>,
     prev: Point,
@@ -254,52 +1084,280 @@ impl<'l> Iterator for PathConvIter<'l> {
     }
 }
 
-fn parse_node(rtree: Tree) -> Result<VertexBuffers<Vertex, u16>> {
-    // Tessalate the path, converting it to vertices & indices
-    let mut geometry: VertexBuffers<Vertex, u16> = VertexBuffers::new();
+/// The geometry of the named groups by their IDs.
+type Parts = HashMap<String, Geometry>;
+
+/// Tessellate all paths, returns the geometry of the whole tree & of every named group, with the
+/// embedded bitmaps.
+///
+/// Only PNG files embedded as data URLs are supported, they're stretched to the rectangle of the
+/// image element. Paths in a group with a clip path or a mask are clipped by the shapes of the
+/// closest one, bitmaps aren't clipped. The paths with one of the glowing IDs, or in a group with
+/// one, are drawn into the bloom.
+fn parse_node(rtree: Tree, glowing: &HashSet<String>) -> Result<(Geometry, Parts, Vec<SvgImage>)> {
+    let mut geometry = Geometry::default();
+    let mut parts: Parts = HashMap::new();
+    let mut images = vec![];
 
     let mut fill_tess = FillTessellator::new();
     let mut stroke_tess = StrokeTessellator::new();
 
-    // Loop over all nodes in the SVG tree
-    for node in rtree.root().descendants() {
+    // The group clipping the last path, its number & its mask
+    let mut clip: Option<(Node, usize, VertexBuffers<Vertex, u32>)> = None;
+    let mut clipped_groups = 0;
+
+    // Loop over all nodes in the SVG tree, the clip paths & masks are only drawn as masks
+    for node in rtree
+        .root()
+        .descendants()
+        .filter(|node| !rtree.is_in_defs(node))
+    {
+        if let NodeKind::Image(ref image) = *node.borrow() {
+            let data = match (&image.data, image.format) {
+                (ImageData::Raw(data), ImageFormat::PNG) => data.clone(),
+                (ImageData::Path(path), _) => {
+                    return Err(anyhow!(
+                        "Image \"{}\" links to \"{}\", only embedded images are supported",
+                        image.id,
+                        path.display()
+                    ))
+                }
+                (_, format) => {
+                    return Err(anyhow!(
+                        "Image \"{}\" is a {:?} file, only PNG files are supported",
+                        image.id,
+                        format
+                    ))
+                }
+            };
+
+            // Only the translation & the scale of the transform are applied to bitmaps
+            let transform = node_transform(&node, &image.transform);
+            let rect = image.view_box.rect;
+            let (x, y) = transform.apply(rect.x(), rect.y());
+            let (scale_x, scale_y) = transform.get_scale();
+            let part = node_part(&node);
+            if let Some(part) = part.as_ref() {
+                // A part can consist of only bitmaps
+                parts.entry(part.clone()).or_default();
+            }
+            images.push(SvgImage {
+                data,
+                pos: (x as f32, y as f32),
+                size: (
+                    (rect.width() * scale_x) as f32,
+                    (rect.height() * scale_y) as f32,
+                ),
+                part,
+            });
+        }
+
         if let NodeKind::Path(ref path) = *node.borrow() {
+            // Tessalate the path, converting it to vertices & indices
+            let mut path_geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+
             if let Some(ref fill) = path.fill {
-                // Get the fill color
-                let color = match fill.paint {
-                    Paint::Color(color) => color,
-                    _ => todo!("Color not defined"),
-                };
+                // Get the fill color or gradient
+                let ctor = vertex_ctor(&rtree, path, &fill.paint, fill.opacity.value())?;
 
                 // Tessellate the fill
                 fill_tess
                     .tessellate(
                         convert_path(path),
                         &FillOptions::tolerance(PATH_TOLERANCE),
-                        &mut BuffersBuilder::new(
-                            &mut geometry,
-                            VertexCtor::new(color, fill.opacity.value() as f32),
-                        ),
+                        &mut BuffersBuilder::new(&mut path_geometry, ctor),
                     )
                     .map_err(|err| anyhow!("tesselation failed: {:?}", err))?;
             }
 
             if let Some(ref stroke) = path.stroke {
-                let (color, stroke_opts) = convert_stroke(stroke);
+                let ctor = vertex_ctor(&rtree, path, &stroke.paint, stroke.opacity.value())?;
+                let stroke_opts = convert_stroke(stroke);
+                // Split dashed strokes into the dashes before tessellating them as separate lines
+                let events = match stroke.dasharray {
+                    Some(ref dasharray) => {
+                        dash_path(convert_path(path), dasharray, stroke.dashoffset)
+                    }
+                    None => convert_path(path).collect(),
+                };
                 // Tessellate the stroke
                 let _ = stroke_tess.tessellate(
-                    convert_path(path),
+                    events,
                     &stroke_opts.with_tolerance(PATH_TOLERANCE),
+                    &mut BuffersBuilder::new(&mut path_geometry, ctor),
+                );
+            }
+
+            // Tessellated in the coordinates of the path so the gradients & the strokes are
+            // transformed with it
+            let transform = node_transform(&node, &path.transform);
+            if !transform.is_default() {
+                for vertex in path_geometry.vertices.iter_mut() {
+                    vertex.transform(&transform);
+                }
+            }
+
+            if is_glowing(&node, &path.id, glowing) {
+                for vertex in path_geometry.vertices.iter_mut() {
+                    vertex.set_glow(1.0);
+                }
+            }
+
+            // Paths after each other in the same group share its mask
+            clip = match clipping_group(&node) {
+                Some((group, _)) if clip.as_ref().map(|(last, ..)| last) == Some(&group) => clip,
+                Some((group, id)) => {
+                    let mask = tessellate_mask(&rtree, &group, &id, &mut fill_tess)?;
+                    clipped_groups += 1;
+
+                    Some((group, clipped_groups, mask))
+                }
+                None => None,
+            };
+            let path_clip = clip.as_ref().map(|(_, number, mask)| (*number, mask));
+
+            if let Some(part) = node_part(&node) {
+                parts
+                    .entry(part)
+                    .or_default()
+                    .append(&path_geometry, path_clip);
+            }
+
+            geometry.append(&path_geometry, path_clip);
+        }
+    }
+
+    Ok((geometry, parts, images))
+}
+
+/// The transform of an element combined with the transforms of all groups it's in.
+fn node_transform(node: &Node, own: &Transform) -> Transform {
+    let mut transform = node.abs_transform();
+    transform.append(own);
+
+    transform
+}
+
+/// The closest group a node is in that's clipped by a clip path or a mask, with the ID of it.
+fn clipping_group(node: &Node) -> Option<(Node, String)> {
+    node.ancestors().skip(1).find_map(|ancestor| {
+        let id = match *ancestor.borrow() {
+            NodeKind::Group(ref group) => group.clip_path.clone().or_else(|| group.mask.clone()),
+            _ => None,
+        };
+
+        id.map(|id| (ancestor, id))
+    })
+}
+
+/// Tessellate the shapes of the clip path or the mask of a group in the coordinates of the SVG.
+///
+/// The shapes are filled completely, so the transparency of a mask is ignored.
+fn tessellate_mask(
+    rtree: &Tree,
+    group: &Node,
+    id: &str,
+    fill_tess: &mut FillTessellator,
+) -> Result<VertexBuffers<Vertex, u32>> {
+    let element = rtree
+        .defs_by_id(id)
+        .ok_or_else(|| anyhow!("Clip path or mask \"{}\" doesn't exist", id))?;
+    let units = match *element.borrow() {
+        NodeKind::ClipPath(ref clip_path) => clip_path.units,
+        NodeKind::Mask(ref mask) => mask.content_units,
+        _ => return Err(anyhow!("\"{}\" is not a clip path or a mask", id)),
+    };
+    if units != Units::UserSpaceOnUse {
+        return Err(anyhow!(
+            "Clip path or mask \"{}\" is relative to the bounding box, which isn't supported",
+            id
+        ));
+    }
+
+    // The shapes are in the coordinates of the clipped group
+    let group_transform = node_transform(group, &group.transform());
+
+    let mut mask = VertexBuffers::new();
+    for node in element.descendants() {
+        if let NodeKind::Path(ref path) = *node.borrow() {
+            let mut path_geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+            fill_tess
+                .tessellate(
+                    convert_path(path),
+                    &FillOptions::tolerance(PATH_TOLERANCE),
                     &mut BuffersBuilder::new(
-                        &mut geometry,
-                        VertexCtor::new(color, stroke.opacity.value() as f32),
+                        &mut path_geometry,
+                        VertexCtor::new(Color::new(0xff, 0xff, 0xff), 1.0),
                     ),
-                );
+                )
+                .map_err(|err| anyhow!("tesselation failed: {:?}", err))?;
+
+            // The transform of the clip path is one of the ancestors
+            let mut transform = group_transform;
+            transform.append(&node_transform(&node, &path.transform));
+            for vertex in path_geometry.vertices.iter_mut() {
+                vertex.transform(&transform);
             }
+
+            append_geometry(&mut mask, &path_geometry);
         }
     }
 
-    Ok(geometry)
+    Ok(mask)
+}
+
+/// Collect the IDs of the elements marked with `glow="true"`, usvg only keeps the IDs.
+fn glowing_ids(element: &Element, ids: &mut HashSet<String>) -> Result<(), SvgError> {
+    if element.attributes.get("glow").map(String::as_str) == Some("true") {
+        match element.attributes.get("id") {
+            Some(id) => {
+                ids.insert(id.clone());
+            }
+            None => {
+                return Err(SvgError::MissingAttribute {
+                    element: element.name.clone(),
+                    attribute: "id".to_string(),
+                })
+            }
+        }
+    }
+
+    for child in element.children.iter().filter_map(|node| node.as_element()) {
+        glowing_ids(child, ids)?;
+    }
+
+    Ok(())
+}
+
+/// Whether a path or one of the groups it's in is marked with `glow="true"`.
+fn is_glowing(node: &Node, id: &str, glowing: &HashSet<String>) -> bool {
+    glowing.contains(id)
+        || node
+            .ancestors()
+            .skip(1)
+            .any(|ancestor| match *ancestor.borrow() {
+                NodeKind::Group(ref group) => glowing.contains(&group.id),
+                _ => false,
+            })
+}
+
+/// The ID of the closest group a node belongs to.
+fn node_part(node: &Node) -> Option<String> {
+    node.ancestors()
+        .skip(1)
+        .find_map(|ancestor| match *ancestor.borrow() {
+            NodeKind::Group(ref group) if !group.id.is_empty() => Some(group.id.clone()),
+            _ => None,
+        })
+}
+
+/// Add the vertices & indices of a geometry to another one.
+fn append_geometry(to: &mut VertexBuffers<Vertex, u32>, from: &VertexBuffers<Vertex, u32>) {
+    let offset = to.vertices.len() as u32;
+
+    to.vertices.extend_from_slice(&from.vertices);
+    to.indices
+        .extend(from.indices.iter().map(|index| index + offset));
 }
 
 fn point(x: &f64, y: &f64) -> Point {
@@ -316,11 +1374,114 @@ fn convert_path(p: &Path) -> PathConvIter {
     }
 }
 
-fn convert_stroke(s: &Stroke) -> (Color, StrokeOptions) {
-    let color = match s.paint {
-        usvg::Paint::Color(c) => c,
-        _ => todo!("No fallback color"),
-    };
+/// Split the subpaths of a path into the dashes of a `stroke-dasharray`, starting `offset` into the
+/// pattern.
+///
+/// The curves are flattened first and every subpath starts the pattern again.
+fn dash_path(path: PathConvIter, dasharray: &[f64], offset: f32) -> Vec<PathEvent> {
+    let mut pattern = dasharray
+        .iter()
+        .map(|length| *length as f32)
+        .collect::<Vec<_>>();
+    // An odd number of lengths is repeated to get pairs of dashes & gaps
+    if pattern.len() % 2 == 1 {
+        pattern = pattern.repeat(2);
+    }
+    let total = pattern.iter().sum::<f32>();
+    if total <= 0.0 {
+        return path.collect();
+    }
+    // A negative offset starts before the pattern
+    let offset = offset.rem_euclid(total);
+
+    let mut events = vec![];
+    let mut points = vec![];
+    for event in path.flattened(PATH_TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => points.push(at),
+            PathEvent::Line { to, .. } => points.push(to),
+            PathEvent::End { first, close, .. } => {
+                if close {
+                    points.push(first);
+                }
+                dash_polyline(&mem::take(&mut points), &pattern, offset, &mut events);
+            }
+            // The curves are flattened into lines
+            _ => (),
+        }
+    }
+
+    events
+}
+
+/// Add the dashes along a line through points to the events of a path.
+fn dash_polyline(points: &[Point], pattern: &[f32], offset: f32, events: &mut Vec<PathEvent>) {
+    // Find the dash or gap the offset is in and how much of it is left
+    let mut index = 0;
+    let mut left = offset;
+    while left >= pattern[index] {
+        left -= pattern[index];
+        index = (index + 1) % pattern.len();
+    }
+    left = pattern[index] - left;
+
+    // The start of the dash being drawn
+    let mut start = None;
+    for segment in points.windows(2) {
+        let (mut from, to) = (segment[0], segment[1]);
+        let mut length = (to - from).length();
+        while length > 0.0 {
+            // The even lengths are dashes, the odd ones gaps
+            let dash = index % 2 == 0;
+            if dash && start.is_none() {
+                events.push(PathEvent::Begin { at: from });
+                start = Some(from);
+            }
+
+            let step = left.min(length);
+            let next = from.lerp(to, step / length);
+            if dash {
+                events.push(PathEvent::Line { from, to: next });
+            }
+            from = next;
+            length -= step;
+            left -= step;
+
+            if left <= 0.0 {
+                if let Some(first) = start.take() {
+                    events.push(PathEvent::End {
+                        last: from,
+                        first,
+                        close: false,
+                    });
+                }
+                index = (index + 1) % pattern.len();
+                left = pattern[index];
+            }
+        }
+    }
+
+    if let (Some(first), Some(last)) = (start, points.last()) {
+        events.push(PathEvent::End {
+            last: *last,
+            first,
+            close: false,
+        });
+    }
+}
+
+/// Create the vertex constructor for the paint of a fill or a stroke.
+fn vertex_ctor(rtree: &Tree, path: &Path, paint: &Paint, opacity: f64) -> Result<VertexCtor> {
+    match paint {
+        Paint::Color(color) => Ok(VertexCtor::new(*color, opacity as f32)),
+        Paint::Link(id) => Ok(VertexCtor::with_gradient(
+            Gradient::from_link(rtree, id, path.data.bbox())?,
+            opacity as f32,
+        )),
+    }
+}
+
+fn convert_stroke(s: &Stroke) -> StrokeOptions {
     let linecap = match s.linecap {
         usvg::LineCap::Butt => LineCap::Butt,
         usvg::LineCap::Square => LineCap::Square,
@@ -332,10 +1493,8 @@ fn convert_stroke(s: &Stroke) -> (Color, StrokeOptions) {
         usvg::LineJoin::Round => LineJoin::Round,
     };
 
-    let opt = StrokeOptions::tolerance(PATH_TOLERANCE)
+    StrokeOptions::tolerance(PATH_TOLERANCE)
         .with_line_width(s.width.value() as f32)
         .with_line_cap(linecap)
-        .with_line_join(linejoin);
-
-    (color, opt)
+        .with_line_join(linejoin)
 }
@@ -1,12 +1,16 @@
-use crate::render::{Instance, Mesh, Render};
+use crate::render::{tessellate_path, Instance, Mesh, Render, Vertex};
 use anyhow::{anyhow, Result};
-use lyon::{math::Point, path::PathEvent};
-use std::{collections::HashMap, str::Chars};
+use log::warn;
+use lyon::{math::Point, path::PathEvent, tessellation::VertexBuffers};
+use std::{collections::HashMap, mem};
 use ttf_parser::{Font as TtfFont, GlyphId, OutlineBuilder};
 use usvg::Color;
 
 const HEIGHT: f32 = 100.0;
 
+/// The glyph fonts show for characters they don't have.
+const NOTDEF: GlyphId = GlyphId(0);
+
 /// A parsed TTF font file containing information for displaying text.
 pub struct Font<'a> {
     /// Internal parsed font representation.
@@ -16,111 +20,631 @@ pub struct Font<'a> {
 impl<'a> Font<'a> {
     /// Parse a TTF string.
     pub fn from_bytes(font: &'a [u8]) -> Result<Self> {
-        let font = TtfFont::from_data(&font, 0).ok_or(anyhow!("Could not parse font"))?;
+        let font = TtfFont::from_data(&font, 0).ok_or_else(|| anyhow!("Could not parse font"))?;
 
         Ok(Self { font })
     }
 
-    /// Upload it and get a mesh.
-    pub fn upload(mut self, render: &mut Render, chars: Chars) -> Result<FontInstance> {
-        let mut meshes = HashMap::new();
-
-        // Scale the height to 100 high
-        let height = self.font.x_height().unwrap_or(self.font.height());
-        let scale = HEIGHT / height as f32;
+    /// All characters in the basic multilingual plane the font has an outline for.
+    pub fn outlined_chars(&self) -> String {
+        (0..=0xFFFF)
+            .filter_map(std::char::from_u32)
+            .filter(|ch| !ch.is_whitespace() && !ch.is_control())
+            .filter(|ch| {
+                self.font.glyph_index(*ch).map_or(false, |glyph_id| {
+                    self.font
+                        .outline_glyph(glyph_id, &mut GlyphBuilder::new(1.0))
+                        .is_some()
+                })
+            })
+            .collect()
+    }
 
-        // Upload the requested glyphs
+    /// Tessellate the requested glyphs without uploading them, so it can be done on any thread.
+    ///
+    /// Characters the font doesn't have get the `.notdef` glyph.
+    pub fn tessellate<I>(&self, chars: I) -> Result<FontGeometry>
+    where
+        I: IntoIterator<Item = char>,
+    {
+        let mut glyphs = HashMap::new();
         for ch in chars {
-            let glyph_id = self
-                .font
-                .glyph_index(ch)
-                .ok_or(anyhow!("Glyph not found"))?;
-            let mesh = self.upload_glyph(render, glyph_id, scale)?;
-
-            let advance =
-                self.font
-                    .glyph_hor_advance(glyph_id)
-                    .ok_or(anyhow!("Font is missing horizontal advance"))? as f32
-                    * scale;
-            let side_bearing =
-                self.font.glyph_hor_side_bearing(glyph_id).unwrap_or(0) as f32 * scale;
-
-            meshes.insert(
-                ch,
-                Glyph {
-                    mesh,
-                    advance,
-                    side_bearing,
-                },
-            );
+            if glyphs.contains_key(&ch) {
+                continue;
+            }
+
+            glyphs.insert(ch, self.tessellate_char(ch)?);
         }
 
-        Ok(FontInstance {
-            meshes,
-            space_width: 1.0 * HEIGHT,
+        Ok(FontGeometry { glyphs })
+    }
+
+    /// The scale from font units to the height of the glyphs.
+    fn scale(&self) -> f32 {
+        // Scale the height to 100 high
+        let height = self.font.x_height().unwrap_or_else(|| self.font.height());
+
+        HEIGHT / height as f32
+    }
+
+    /// The distance between the baselines of two lines, scaled to the height of the glyphs.
+    fn line_height(&self) -> f32 {
+        let font = &self.font;
+
+        (font.ascender() - font.descender() + font.line_gap()) as f32 * self.scale()
+    }
+
+    /// The horizontal adjustment between two glyphs from the kerning tables, scaled to the height
+    /// of the glyphs.
+    fn kerning(&self, left: GlyphId, right: GlyphId) -> f32 {
+        self.font
+            .kerning_subtables()
+            .filter(|subtable| subtable.is_horizontal() && !subtable.is_variable())
+            .find_map(|subtable| subtable.glyphs_kerning(left, right))
+            .map_or(0.0, |kerning| kerning as f32 * self.scale())
+    }
+
+    /// Tessellate the glyph of a character.
+    fn tessellate_char(&self, ch: char) -> Result<GlyphGeometry> {
+        let scale = self.scale();
+
+        let glyph_id = self.font.glyph_index(ch).unwrap_or(NOTDEF);
+        let geometry = match self.tessellate_glyph(glyph_id, scale)? {
+            Some(geometry) => Some(geometry),
+            // Draw a box when the font doesn't have an outline for missing characters
+            None if glyph_id == NOTDEF => Some(notdef_box()?),
+            // Whitespace
+            None => None,
+        };
+
+        let advance = self
+            .font
+            .glyph_hor_advance(glyph_id)
+            .map_or(HEIGHT, |advance| advance as f32 * scale);
+        let side_bearing = self.font.glyph_hor_side_bearing(glyph_id).unwrap_or(0) as f32 * scale;
+
+        Ok(GlyphGeometry {
+            id: glyph_id,
+            geometry,
+            advance,
+            side_bearing,
         })
     }
 
-    /// Upload a specific glyph.
-    fn upload_glyph(&mut self, render: &mut Render, glyph: GlyphId, scale: f32) -> Result<Mesh> {
+    /// Tessellate a specific glyph, nothing when it doesn't have an outline.
+    fn tessellate_glyph(
+        &self,
+        glyph: GlyphId,
+        scale: f32,
+    ) -> Result<Option<VertexBuffers<Vertex, u32>>> {
         let mut builder = GlyphBuilder::new(scale);
 
         // Convert the glyph to a lyon path
-        self.font
-            .outline_glyph(glyph, &mut builder)
-            .ok_or(anyhow!("Could not build outline of glyph"))?;
+        if self.font.outline_glyph(glyph, &mut builder).is_none() {
+            return Ok(None);
+        }
+
+        tessellate_path(builder.path().into_iter(), Color::white(), 1.0).map(Some)
+    }
+}
+
+impl Font<'static> {
+    /// Upload it and get a mesh.
+    pub fn upload<I>(self, render: &mut Render, chars: I) -> Result<FontInstance>
+    where
+        I: IntoIterator<Item = char>,
+    {
+        self.tessellate(chars)?.upload(self, render)
+    }
+}
+
+/// Tessellated glyphs that are not uploaded yet.
+pub struct FontGeometry {
+    /// The geometry of the glyphs by their character.
+    glyphs: HashMap<char, GlyphGeometry>,
+}
 
-        Ok(render.upload_path(builder.path().into_iter(), Color::white(), 1.0))
+impl FontGeometry {
+    /// Upload the glyphs and get a font instance, the other glyphs of the font are uploaded when
+    /// they're first used.
+    pub fn upload(self, font: Font<'static>, render: &mut Render) -> Result<FontInstance> {
+        let mut instance = FontInstance {
+            font,
+            meshes: HashMap::new(),
+        };
+        self.upload_into(&mut instance, render)?;
+
+        Ok(instance)
+    }
+
+    /// Upload the glyphs and add them to an existing font instance.
+    pub fn upload_into(self, instance: &mut FontInstance, render: &mut Render) -> Result<()> {
+        for (ch, glyph) in self.glyphs {
+            let mesh = match glyph.geometry {
+                Some(geometry) => {
+                    let mesh = render.upload_buffers(&geometry)?;
+                    render.set_label(mesh, "font");
+
+                    Some(mesh)
+                }
+                None => None,
+            };
+
+            instance.meshes.insert(
+                ch,
+                Glyph {
+                    id: glyph.id,
+                    mesh,
+                    advance: glyph.advance,
+                    side_bearing: glyph.side_bearing,
+                },
+            );
+        }
+
+        Ok(())
     }
 }
 
+/// The geometry of a glyph before it's uploaded.
+struct GlyphGeometry {
+    /// The glyph in the font, for kerning.
+    id: GlyphId,
+    /// The vertices & indices, nothing for whitespace.
+    geometry: Option<VertexBuffers<Vertex, u32>>,
+    /// The advance of the font.
+    advance: f32,
+    /// Horizontal side bearing.
+    side_bearing: f32,
+}
+
 /// Font with references to glyph meshes on the GPU.
+///
+/// Glyphs are tessellated & uploaded the first time a text needs them.
 pub struct FontInstance {
+    /// The parsed font the missing glyphs are tessellated from.
+    font: Font<'static>,
     /// List of meshes matching the characters.
     meshes: HashMap<char, Glyph>,
-    /// The horizontal font line gap.
-    space_width: f32,
 }
 
 impl FontInstance {
-    /// Get the mesh belonging to a letter.
-    pub fn letter_mesh(&self, letter: char) -> Option<Mesh> {
-        self.meshes.get(&letter).map(|glyph| glyph.mesh)
+    /// Upload the glyphs of the characters that are not part of the font instance yet.
+    pub fn upload_missing<I>(&mut self, render: &mut Render, chars: I) -> Result<()>
+    where
+        I: IntoIterator<Item = char>,
+    {
+        let missing = chars
+            .into_iter()
+            .filter(|ch| !self.meshes.contains_key(ch))
+            .collect::<Vec<_>>();
+
+        let start = miniquad::date::now();
+        let glyphs = self.font.tessellate(missing)?;
+        render.add_mesh_time(start);
+
+        glyphs.upload_into(self, render)
+    }
+
+    /// Get the glyph of a character, uploading it when it's used for the first time.
+    fn glyph(&mut self, render: &mut Render, ch: char) -> &Glyph {
+        if !self.meshes.contains_key(&ch) {
+            if let Err(err) = self.upload_missing(render, Some(ch)) {
+                warn!("uploading glyph {:?} failed: {}", ch, err);
+
+                // Don't try again every frame
+                self.meshes.insert(
+                    ch,
+                    Glyph {
+                        id: NOTDEF,
+                        mesh: None,
+                        advance: HEIGHT,
+                        side_bearing: 0.0,
+                    },
+                );
+            }
+        }
+
+        &self.meshes[&ch]
     }
 
     /// Form the mesh letters into the text.
-    pub fn text(&self, text: &str, x: f32, y: f32) -> Vec<(Instance, Mesh)> {
+    pub fn text(
+        &mut self,
+        render: &mut Render,
+        text: &str,
+        x: f32,
+        y: f32,
+    ) -> Vec<(Instance, Mesh)> {
+        self.layout(render, text, x, y, &TextLayout::new())
+    }
+
+    /// Form the mesh letters into the text with the letters scaled.
+    pub fn text_scaled(
+        &mut self,
+        render: &mut Render,
+        text: &str,
+        x: f32,
+        y: f32,
+        scale: f32,
+    ) -> Vec<(Instance, Mesh)> {
+        self.layout(render, text, x, y, &TextLayout::new().scale(scale))
+    }
+
+    /// Form the mesh letters into lines of text, the baseline of the first line is at the
+    /// position.
+    pub fn layout(
+        &mut self,
+        render: &mut Render,
+        text: &str,
+        x: f32,
+        y: f32,
+        layout: &TextLayout,
+    ) -> Vec<(Instance, Mesh)> {
+        let lines = self.lines(render, text, layout);
+        let widths = lines
+            .iter()
+            .map(|line| self.line_width(render, line, layout.scale))
+            .collect::<Vec<_>>();
+        let block_width = block_width(&widths, layout);
+        let line_height = self.scaled_line_height(layout);
+
         let mut result = Vec::new();
+        for (index, (line, width)) in lines.iter().zip(widths).enumerate() {
+            let mut letter_x = x + match layout.alignment {
+                Alignment::Left => 0.0,
+                Alignment::Center => (block_width - width) / 2.0,
+                Alignment::Right => block_width - width,
+            };
+            let line_y = y + index as f32 * line_height;
+
+            let mut previous = None;
+            for ch in line.chars() {
+                let glyph = self.glyph(render, ch);
+                let (id, mesh, advance, side_bearing) =
+                    (glyph.id, glyph.mesh, glyph.advance, glyph.side_bearing);
 
-        let mut letter_x = x;
+                if let Some(previous) = previous {
+                    letter_x += self.font.kerning(previous, id) * layout.scale;
+                }
+                previous = Some(id);
 
-        for ch in text.chars() {
-            // Find the character
-            if let Some(glyph) = self.meshes.get(&ch) {
-                result.push((Instance::new(letter_x + glyph.side_bearing, y), glyph.mesh));
+                // Whitespace only moves the next letter
+                if let Some(mesh) = mesh {
+                    let mut instance =
+                        Instance::new(letter_x + side_bearing * layout.scale, line_y);
+                    instance.set_scale(layout.scale);
+                    result.push((instance, mesh));
+                }
 
-                letter_x += glyph.advance;
-            } else {
-                // Used for not defined letters and whitespace
-                letter_x += self.space_width;
+                letter_x += advance * layout.scale;
             }
         }
 
         result
     }
+
+    /// The width & height of the lines of text, from the top of the first line to the baseline of
+    /// the last.
+    pub fn measure(&mut self, render: &mut Render, text: &str, layout: &TextLayout) -> (f32, f32) {
+        let lines = self.lines(render, text, layout);
+        let widths = lines
+            .iter()
+            .map(|line| self.line_width(render, line, layout.scale))
+            .collect::<Vec<_>>();
+
+        let height = HEIGHT * layout.scale
+            + lines.len().saturating_sub(1) as f32 * self.scaled_line_height(layout);
+
+        (block_width(&widths, layout), height)
+    }
+
+    /// Split the text into lines at the newlines, wrapping the words that don't fit.
+    fn lines(&mut self, render: &mut Render, text: &str, layout: &TextLayout) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in text.lines() {
+            let max_width = match layout.max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(paragraph.to_string());
+                    continue;
+                }
+            };
+
+            // Words that are too long on their own get a line for themselves
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{} {}", line, word)
+                };
+
+                if !line.is_empty() && self.line_width(render, &candidate, layout.scale) > max_width
+                {
+                    lines.push(mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    /// The width of a single line with the letters scaled.
+    fn line_width(&mut self, render: &mut Render, line: &str, scale: f32) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for ch in line.chars() {
+            let glyph = self.glyph(render, ch);
+            let (id, advance) = (glyph.id, glyph.advance);
+
+            if let Some(previous) = previous {
+                width += self.font.kerning(previous, id);
+            }
+            previous = Some(id);
+
+            width += advance;
+        }
+
+        width * scale
+    }
+
+    /// The distance between the baselines of the lines.
+    fn scaled_line_height(&self, layout: &TextLayout) -> f32 {
+        layout
+            .line_height
+            .unwrap_or_else(|| self.font.line_height() * layout.scale)
+    }
+}
+
+/// How the lines of a text are positioned relative to each other.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Alignment {
+    /// All lines start at the same position.
+    Left,
+    /// The lines are centered around the middle of the text.
+    Center,
+    /// All lines end at the same position.
+    Right,
+}
+
+/// Options for forming letters into lines of text.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextLayout {
+    /// The scale of the letters.
+    scale: f32,
+    /// The distance between the baselines, the line height of the font when not set.
+    line_height: Option<f32>,
+    /// Words are wrapped to the next line when the line becomes wider.
+    max_width: Option<f32>,
+    /// How the lines are aligned.
+    alignment: Alignment,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextLayout {
+    /// Unscaled left aligned lines that are only broken at newlines.
+    pub fn new() -> Self {
+        Self {
+            scale: 1.0,
+            line_height: None,
+            max_width: None,
+            alignment: Alignment::Left,
+        }
+    }
+
+    /// Scale the letters.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+
+        self
+    }
+
+    /// Set the distance between the baselines of the lines, after scaling.
+    pub fn line_height(mut self, line_height: f32) -> Self {
+        self.line_height = Some(line_height);
+
+        self
+    }
+
+    /// Wrap the words that don't fit in the width, after scaling.
+    pub fn max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+
+        self
+    }
+
+    /// Align the lines.
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+
+        self
+    }
+}
+
+/// The width the lines are aligned in, the maximum width when words are wrapped.
+fn block_width(widths: &[f32], layout: &TextLayout) -> f32 {
+    layout
+        .max_width
+        .unwrap_or_else(|| widths.iter().cloned().fold(0.0, f32::max))
+}
+
+/// The point of a text its position is at.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anchor {
+    /// The top left corner.
+    TopLeft,
+    /// The middle of the top.
+    Top,
+    /// The top right corner.
+    TopRight,
+    /// The middle of the left side.
+    Left,
+    /// The center.
+    Center,
+    /// The middle of the right side.
+    Right,
+    /// The bottom left corner, on the baseline.
+    BottomLeft,
+    /// The middle of the baseline.
+    Bottom,
+    /// The bottom right corner, on the baseline.
+    BottomRight,
+}
+
+impl Anchor {
+    /// The fraction of the width & height of the text from the top left to the anchor.
+    fn fraction(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+
+    /// The alignment of the lines on the side of the anchor.
+    fn alignment(self) -> Alignment {
+        match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => Alignment::Left,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => Alignment::Center,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => Alignment::Right,
+        }
+    }
+}
+
+/// The coordinates the position of a text is in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Space {
+    /// World units, the text moves with the camera.
+    World,
+    /// UI pixels with the origin at the top left of the screen.
+    Screen,
+}
+
+/// A string drawn with the glyphs of a font.
+///
+/// The glyphs are only formed again when the string changes, moving or resizing the text is free.
+#[derive(Debug, Clone)]
+pub struct Text {
+    /// The position of the anchor.
+    pub pos: (f32, f32),
+    /// The height of the lowercase letters.
+    pub size: f32,
+    /// The point of the text at the position.
+    pub anchor: Anchor,
+    /// The coordinates of the position.
+    pub space: Space,
+    /// The characters shown.
+    string: String,
+    /// The unscaled glyphs with the baseline at the origin, empty until the text is updated.
+    glyphs: Vec<(Instance, Mesh)>,
+    /// The unscaled width & height of the lines.
+    bounds: (f32, f32),
+    /// Whether the string changed since the glyphs were formed.
+    dirty: bool,
+}
+
+impl Text {
+    /// Create a text, the glyphs are formed with the next update.
+    pub fn new(string: &str, pos: (f32, f32), size: f32, anchor: Anchor, space: Space) -> Self {
+        Self {
+            pos,
+            size,
+            anchor,
+            space,
+            string: string.to_string(),
+            glyphs: vec![],
+            bounds: (0.0, 0.0),
+            dirty: true,
+        }
+    }
+
+    /// Change the characters shown, nothing happens when they're the same.
+    pub fn set_string(&mut self, string: &str) {
+        if self.string != string {
+            self.string = string.to_string();
+            self.dirty = true;
+        }
+    }
+
+    /// Form the glyphs when the string changed.
+    pub fn update(&mut self, font: &mut FontInstance, render: &mut Render) {
+        if self.dirty {
+            // Align the lines to the side of the anchor
+            let layout = TextLayout::new().alignment(self.anchor.alignment());
+            self.glyphs = font.layout(render, &self.string, 0.0, 0.0, &layout);
+            self.bounds = font.measure(render, &self.string, &layout);
+            self.dirty = false;
+        }
+    }
+
+    /// The glyph instances at the position of the text.
+    pub fn instances(&self) -> impl Iterator<Item = (Instance, Mesh)> + '_ {
+        let scale = self.size / HEIGHT;
+        let (anchor_x, anchor_y) = self.anchor.fraction();
+        // The glyphs are drawn above their baseline
+        let x = self.pos.0 - anchor_x * self.bounds.0 * scale;
+        let y = self.pos.1 + (HEIGHT - anchor_y * self.bounds.1) * scale;
+
+        self.glyphs.iter().map(move |(glyph, mesh)| {
+            let mut instance = *glyph;
+            instance.set_x(x + glyph.x() * scale);
+            instance.set_y(y + glyph.y() * scale);
+            instance.set_scale(scale);
+
+            (instance, *mesh)
+        })
+    }
 }
 
 /// A glyph for a character.
 #[derive(Debug)]
 struct Glyph {
-    /// The reference to the GPU mesh.
-    mesh: Mesh,
+    /// The glyph in the font, for kerning.
+    id: GlyphId,
+    /// The reference to the GPU mesh, nothing for whitespace.
+    mesh: Option<Mesh>,
     /// The advance of the font.
     advance: f32,
     /// Horizontal side bearing.
     side_bearing: f32,
 }
 
+/// A hollow box as high as the lowercase letters, for characters the font doesn't have.
+fn notdef_box() -> Result<VertexBuffers<Vertex, u32>> {
+    let (width, height, border) = (HEIGHT * 0.6, HEIGHT, HEIGHT * 0.1);
+
+    // The inner rectangle is a hole because of the even-odd fill rule
+    let mut builder = GlyphBuilder::new(1.0);
+    for &(left, bottom, right, top) in [
+        (0.0, 0.0, width, height),
+        (border, border, width - border, height - border),
+    ]
+    .iter()
+    {
+        builder.move_to(left, bottom);
+        builder.line_to(right, bottom);
+        builder.line_to(right, top);
+        builder.line_to(left, top);
+        builder.close();
+    }
+
+    tessellate_path(builder.path().into_iter(), Color::white(), 1.0)
+}
+
 /// Builder struct for creating lyon paths from a font glyph.
 struct GlyphBuilder {
     path: Vec<PathEvent>,
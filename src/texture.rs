@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Result};
+use png::{ColorType, Decoder, Transformations};
+
+/// A decoded bitmap that can be uploaded as a texture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    /// Width in pixels.
+    pub width: u16,
+    /// Height in pixels.
+    pub height: u16,
+    /// RGBA values of the pixels, starting with the top row.
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Decode a PNG file, every color type is converted to 8 bit RGBA.
+    pub fn from_png(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+        // Expand palettes & low bit depths and strip 16 bit channels, so only the color type
+        // has to be converted
+        decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+
+        let mut buffer = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buffer)?;
+
+        let pixels = match info.color_type {
+            ColorType::RGBA => buffer,
+            ColorType::RGB => buffer
+                .chunks_exact(3)
+                .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 0xff])
+                .collect(),
+            ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|ga| vec![ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            ColorType::Grayscale => buffer
+                .iter()
+                .flat_map(|&gray| vec![gray, gray, gray, 0xff])
+                .collect(),
+            other => return Err(anyhow!("Unsupported PNG color type {:?}", other)),
+        };
+
+        if info.width > u16::MAX as u32 || info.height > u16::MAX as u32 {
+            return Err(anyhow!(
+                "PNG of {}x{} pixels is too large",
+                info.width,
+                info.height
+            ));
+        }
+
+        Ok(Self {
+            width: info.width as u16,
+            height: info.height as u16,
+            pixels,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{render::Instance, replication::EntityId};
+
+/// The unit another unit is attached to, its transform is composed from the one of the parent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Parent(pub EntityId);
+
+/// The transform of an attached unit relative to its parent, like a sword in the hand of a
+/// character.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LocalTransform {
+    /// Horizontal offset from the parent before it's rotated & scaled.
+    pub x: f32,
+    /// Vertical offset from the parent before it's rotated & scaled.
+    pub y: f32,
+    /// Rotation in radians added to the one of the parent.
+    pub rotation: f32,
+    /// Scale multiplied with the one of the parent.
+    pub scale: f32,
+}
+
+impl LocalTransform {
+    /// Offset from the parent without rotating or scaling.
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            x,
+            y,
+            rotation: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    /// Set the rotation in radians relative to the parent.
+    pub fn rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+
+        self
+    }
+
+    /// Set the scale relative to the parent.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+
+        self
+    }
+
+    /// Move, rotate & scale the instance of the child to where the parent holds it.
+    ///
+    /// The offset is scaled & rotated with the parent, so a flipped parent holds the child on the
+    /// other side and mirrored. The z index of the child is kept.
+    pub fn apply(&self, parent: &Instance, child: &mut Instance) {
+        let (scale_x, scale_y) = parent.scale();
+        let (sin, cos) = parent.rotation().sin_cos();
+        let (x, y) = (self.x * scale_x, self.y * scale_y);
+        let mirror = (scale_x * scale_y).signum();
+
+        child.set_x(parent.x() + x * cos - y * sin);
+        child.set_y(parent.y() + x * sin + y * cos);
+        child.set_rotation(parent.rotation() + self.rotation * mirror);
+        child.set_scale_xy(scale_x * self.scale, scale_y * self.scale);
+    }
+}
+
+impl Default for LocalTransform {
+    fn default() -> Self {
+        Self::new(0.0, 0.0)
+    }
+}
@@ -0,0 +1,567 @@
+use crate::replication::EntityId;
+use glsp::{bail, lib, rfn, FromVal, GFn, GResult, Root, Runtime, Sym, Val};
+use std::{collections::VecDeque, f32::consts::PI};
+
+/// The values to set on the properties of targets.
+pub type PropertyValues = Vec<(Target, Property, Value)>;
+
+/// How the progress of a tween is shaped over its duration.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Easing {
+    /// A constant speed.
+    Linear,
+    /// Start slow, speeding up quadratically.
+    QuadIn,
+    /// Start fast, slowing down quadratically.
+    QuadOut,
+    /// Start & end slow.
+    QuadInOut,
+    /// Start slow, speeding up cubically.
+    CubicIn,
+    /// Start fast, slowing down cubically.
+    CubicOut,
+    /// Start & end slower than the quadratic one.
+    CubicInOut,
+    /// Wind up like a spring before shooting to the end.
+    ElasticIn,
+    /// Overshoot the end and wobble back like a spring.
+    ElasticOut,
+    /// Bounce up from the start a few times before moving to the end.
+    BounceIn,
+    /// Bounce on the end a few times like a dropped ball.
+    BounceOut,
+}
+
+impl Easing {
+    /// The easing the scripts refer to by name, like `quad-in-out`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "linear" => Easing::Linear,
+            "quad-in" => Easing::QuadIn,
+            "quad-out" => Easing::QuadOut,
+            "quad-in-out" => Easing::QuadInOut,
+            "cubic-in" => Easing::CubicIn,
+            "cubic-out" => Easing::CubicOut,
+            "cubic-in-out" => Easing::CubicInOut,
+            "elastic-in" => Easing::ElasticIn,
+            "elastic-out" => Easing::ElasticOut,
+            "bounce-in" => Easing::BounceIn,
+            "bounce-out" => Easing::BounceOut,
+            _ => return None,
+        })
+    }
+
+    /// Shape the linear progress from 0.0 to 1.0, the result can be outside of it for the elastic
+    /// easings.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::ElasticIn => 1.0 - elastic_out(1.0 - t),
+            Easing::ElasticOut => elastic_out(t),
+            Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+            Easing::BounceOut => bounce_out(t),
+        }
+    }
+}
+
+/// A value that can be interpolated between two of them.
+pub trait Lerp: Copy {
+    /// The value at a progress from 0.0 at itself to 1.0 at the other one.
+    fn lerp(self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for (f32, f32) {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        (self.0.lerp(to.0, t), self.1.lerp(to.1, t))
+    }
+}
+
+/// A single movement of a tween towards a value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Step<T> {
+    /// The value at the end.
+    to: T,
+    /// How long the movement takes in seconds.
+    duration: f32,
+    /// The shape of the movement.
+    easing: Easing,
+}
+
+/// Animates a value through a chain of steps, one after another.
+///
+/// It starts from whatever the value is when it's first advanced, so it continues smoothly from
+/// any other animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tween<T> {
+    /// The value at the start of the current step, `None` before it's advanced.
+    from: Option<T>,
+    /// The current step followed by the chained ones.
+    steps: VecDeque<Step<T>>,
+    /// The time since the start of the current step in seconds.
+    elapsed: f32,
+}
+
+impl<T: Lerp> Tween<T> {
+    /// Move towards a value in a duration in seconds.
+    pub fn new(to: T, duration: f32, easing: Easing) -> Self {
+        let mut tween = Self {
+            from: None,
+            steps: VecDeque::new(),
+            elapsed: 0.0,
+        };
+        tween.then(to, duration, easing);
+
+        tween
+    }
+
+    /// Chain another movement after the last one.
+    pub fn then(&mut self, to: T, duration: f32, easing: Easing) {
+        self.steps.push_back(Step {
+            to,
+            duration: duration.max(0.0),
+            easing,
+        });
+    }
+
+    /// Advance with the time since the previous frame in seconds, returns the new value.
+    ///
+    /// The current value is only used when it wasn't advanced before.
+    pub fn advance(&mut self, delta: f32, current: T) -> T {
+        let mut from = self.from.unwrap_or(current);
+        self.elapsed += delta;
+
+        // Finish the steps that fit in the elapsed time, the remainder goes to the next one
+        while let Some(step) = self.steps.front() {
+            if self.elapsed < step.duration {
+                break;
+            }
+
+            self.elapsed -= step.duration;
+            from = step.to;
+            self.steps.pop_front();
+        }
+        self.from = Some(from);
+
+        match self.steps.front() {
+            Some(step) => from.lerp(step.to, step.easing.apply(self.elapsed / step.duration)),
+            None => from,
+        }
+    }
+
+    /// Whether all steps are finished.
+    pub fn is_done(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// What a tween animates, of the camera or of a unit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Property {
+    /// The world position.
+    Position,
+    /// The rotation in radians.
+    Rotation,
+    /// The scale of the instance of a unit, only what's drawn.
+    Scale,
+    /// The screen pixels per world unit of the camera.
+    Zoom,
+}
+
+impl Property {
+    /// The property the scripts refer to by name.
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "position" => Property::Position,
+            "rotation" => Property::Rotation,
+            "scale" => Property::Scale,
+            "zoom" => Property::Zoom,
+            _ => return None,
+        })
+    }
+}
+
+/// What owns the animated property.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Target {
+    /// The view of the world.
+    Camera,
+    /// A unit.
+    Entity(EntityId),
+}
+
+/// The value of a property.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Value {
+    /// A single number, like a rotation.
+    Scalar(f32),
+    /// A world position.
+    Point(f32, f32),
+}
+
+/// A tween of either kind of value.
+#[derive(Debug, Clone, PartialEq)]
+enum Animated {
+    /// Animates a single number.
+    Scalar(Tween<f32>),
+    /// Animates a world position.
+    Point(Tween<(f32, f32)>),
+}
+
+impl Animated {
+    /// Start a tween of the kind of the value.
+    fn new(to: Value, duration: f32, easing: Easing) -> Self {
+        match to {
+            Value::Scalar(to) => Animated::Scalar(Tween::new(to, duration, easing)),
+            Value::Point(x, y) => Animated::Point(Tween::new((x, y), duration, easing)),
+        }
+    }
+
+    /// Chain another movement, returns whether the value is of the same kind.
+    fn then(&mut self, to: Value, duration: f32, easing: Easing) -> bool {
+        match (self, to) {
+            (Animated::Scalar(tween), Value::Scalar(to)) => tween.then(to, duration, easing),
+            (Animated::Point(tween), Value::Point(x, y)) => tween.then((x, y), duration, easing),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Advance from the current value, `None` when the current value is of the other kind.
+    fn advance(&mut self, delta: f32, current: Value) -> Option<Value> {
+        match (self, current) {
+            (Animated::Scalar(tween), Value::Scalar(current)) => {
+                Some(Value::Scalar(tween.advance(delta, current)))
+            }
+            (Animated::Point(tween), Value::Point(x, y)) => {
+                let (x, y) = tween.advance(delta, (x, y));
+
+                Some(Value::Point(x, y))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether all steps are finished.
+    fn is_done(&self) -> bool {
+        match self {
+            Animated::Scalar(tween) => tween.is_done(),
+            Animated::Point(tween) => tween.is_done(),
+        }
+    }
+}
+
+/// A tween the scripts started.
+#[derive(Clone)]
+struct Running {
+    /// The number the scripts refer to it by.
+    id: i32,
+    /// What owns the property.
+    target: Target,
+    /// What's animated.
+    property: Property,
+    /// The steps of the animation.
+    animated: Animated,
+    /// The function called when all steps are finished.
+    on_done: Option<Root<GFn>>,
+}
+
+/// The running tweens, saved for seeking in replays.
+///
+/// The functions called when they're finished are script values, so it should be kept in the
+/// runtime.
+#[derive(Clone)]
+pub struct TweensState {
+    /// The tweens that aren't finished.
+    running: Vec<Running>,
+    /// The id of the next tween.
+    next_id: i32,
+}
+
+lib! {
+/// The tweens the scripts started, advanced every frame with the new values applied to the camera
+/// & the units.
+pub struct Tweens {
+    /// The tweens that aren't finished.
+    running: Vec<Running>,
+    /// The id of the next tween.
+    next_id: i32,
+}
+}
+
+impl Default for Tweens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tweens {
+    /// Create without any tweens.
+    pub fn new() -> Self {
+        Self {
+            running: vec![],
+            next_id: 0,
+        }
+    }
+
+    /// Get the running tweens, should be called inside the runtime.
+    pub fn state(&self) -> TweensState {
+        TweensState {
+            running: self.running.clone(),
+            next_id: self.next_id,
+        }
+    }
+
+    /// Continue the previously saved tweens, should be called inside the runtime.
+    pub fn restore(&mut self, state: &TweensState) {
+        self.running = state.running.clone();
+        self.next_id = state.next_id;
+    }
+
+    /// Advance every tween with the time since the previous frame in seconds.
+    ///
+    /// The current value of a property is only asked for when a tween starts, a tween is stopped
+    /// when its target doesn't exist anymore. Returns the values to apply & the functions of the
+    /// finished tweens to call.
+    pub fn update<F>(&mut self, delta: f32, current: F) -> (PropertyValues, Vec<Root<GFn>>)
+    where
+        F: Fn(Target, Property) -> Option<Value>,
+    {
+        let mut values = vec![];
+        let mut stopped = vec![];
+        for tween in self.running.iter_mut() {
+            match current(tween.target, tween.property)
+                .and_then(|value| tween.animated.advance(delta, value))
+            {
+                Some(value) => values.push((tween.target, tween.property, value)),
+                None => stopped.push(tween.id),
+            }
+        }
+
+        let mut finished = vec![];
+        self.running.retain(|tween| {
+            if stopped.contains(&tween.id) {
+                return false;
+            }
+            if !tween.animated.is_done() {
+                return true;
+            }
+
+            if let Some(on_done) = &tween.on_done {
+                finished.push(on_done.clone());
+            }
+
+            false
+        });
+
+        (values, finished)
+    }
+
+    /// Animate a property of the camera, returns the id of the tween.
+    fn tween_camera(
+        &mut self,
+        property: Sym,
+        to: Val,
+        duration: f32,
+        easing: Option<Sym>,
+    ) -> GResult<i32> {
+        let property = parse_property(property)?;
+        if property == Property::Scale {
+            bail!("the camera can't be scaled, tween its zoom instead");
+        }
+
+        self.start(Target::Camera, property, to, duration, easing)
+    }
+
+    /// Animate a property of a unit, returns the id of the tween.
+    fn tween_entity(
+        &mut self,
+        entity: (i32, i32),
+        property: Sym,
+        to: Val,
+        duration: f32,
+        easing: Option<Sym>,
+    ) -> GResult<i32> {
+        let property = parse_property(property)?;
+        if property == Property::Zoom {
+            bail!("units can't be zoomed, tween their scale instead");
+        }
+
+        self.start(
+            Target::Entity(EntityId::from_script(entity)),
+            property,
+            to,
+            duration,
+            easing,
+        )
+    }
+
+    /// Start a tween, replacing the running one of the same property.
+    fn start(
+        &mut self,
+        target: Target,
+        property: Property,
+        to: Val,
+        duration: f32,
+        easing: Option<Sym>,
+    ) -> GResult<i32> {
+        let animated = Animated::new(parse_value(property, &to)?, duration, parse_easing(easing)?);
+
+        self.running
+            .retain(|tween| tween.target != target || tween.property != property);
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.running.push(Running {
+            id,
+            target,
+            property,
+            animated,
+            on_done: None,
+        });
+
+        Ok(id)
+    }
+
+    /// Chain another movement after the last one of a tween, returns the id for chaining further.
+    fn then(&mut self, id: i32, to: Val, duration: f32, easing: Option<Sym>) -> GResult<i32> {
+        let easing = parse_easing(easing)?;
+        let tween = match self.running.iter_mut().find(|tween| tween.id == id) {
+            Some(tween) => tween,
+            None => bail!("tween {} is finished or doesn't exist", id),
+        };
+
+        let to = parse_value(tween.property, &to)?;
+        if !tween.animated.then(to, duration, easing) {
+            bail!("can't chain {:?} to tween {}", to, id);
+        }
+
+        Ok(id)
+    }
+
+    /// Call a function without arguments when all movements of a tween are finished.
+    fn on_done(&mut self, id: i32, callback: Root<GFn>) -> GResult<i32> {
+        match self.running.iter_mut().find(|tween| tween.id == id) {
+            Some(tween) => tween.on_done = Some(callback),
+            None => bail!("tween {} is finished or doesn't exist", id),
+        }
+
+        Ok(id)
+    }
+
+    /// Stop a tween where it is without calling its function, returns whether it was running.
+    fn stop(&mut self, id: i32) -> bool {
+        let count = self.running.len();
+        self.running.retain(|tween| tween.id != id);
+
+        self.running.len() != count
+    }
+
+    /// Whether a tween is still running.
+    fn is_running(&self, id: i32) -> bool {
+        self.running.iter().any(|tween| tween.id == id)
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("tween-camera", rfn!(Self::tween_camera))?;
+            glsp::bind_rfn("tween-entity", rfn!(Self::tween_entity))?;
+            glsp::bind_rfn("tween-then", rfn!(Self::then))?;
+            glsp::bind_rfn("tween-on-done", rfn!(Self::on_done))?;
+            glsp::bind_rfn("stop-tween", rfn!(Self::stop))?;
+            glsp::bind_rfn("tweening?", rfn!(Self::is_running))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// The property of a name, failing when it's unknown.
+fn parse_property(name: Sym) -> GResult<Property> {
+    match Property::from_name(&name.name()) {
+        Some(property) => Ok(property),
+        None => bail!("unknown tween property {}", name),
+    }
+}
+
+/// The easing of an optional name, linear when it's missing.
+fn parse_easing(name: Option<Sym>) -> GResult<Easing> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(Easing::Linear),
+    };
+
+    match Easing::from_name(&name.name()) {
+        Some(easing) => Ok(easing),
+        None => bail!("unknown easing {}", name),
+    }
+}
+
+/// The value a script animates a property to, an array of two numbers for positions.
+fn parse_value(property: Property, to: &Val) -> GResult<Value> {
+    Ok(match property {
+        Property::Position => {
+            let (x, y) = <(f32, f32)>::from_val(to)?;
+
+            Value::Point(x, y)
+        }
+        _ => Value::Scalar(f32::from_val(to)?),
+    })
+}
+
+/// Overshoot & wobble back with a decaying sine.
+fn elastic_out(t: f32) -> f32 {
+    if t <= 0.0 {
+        0.0
+    } else if t >= 1.0 {
+        1.0
+    } else {
+        2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+    }
+}
+
+/// Bounces with a decreasing height, made out of parabolas.
+fn bounce_out(t: f32) -> f32 {
+    const STRENGTH: f32 = 7.5625;
+    const WIDTH: f32 = 2.75;
+
+    if t < 1.0 / WIDTH {
+        STRENGTH * t * t
+    } else if t < 2.0 / WIDTH {
+        let t = t - 1.5 / WIDTH;
+        STRENGTH * t * t + 0.75
+    } else if t < 2.5 / WIDTH {
+        let t = t - 2.25 / WIDTH;
+        STRENGTH * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / WIDTH;
+        STRENGTH * t * t + 0.984_375
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    render::{Instance, Mesh, Render},
+    replay::{Button, InputEvent},
+    text::{Alignment, FontInstance, TextLayout},
+};
+use glsp::{lib, rfn, Runtime};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    mem,
+    ops::RangeInclusive,
+};
+
+/// Height of every widget in UI pixels.
+const WIDGET_HEIGHT: f32 = 36.0;
+/// Space between the widgets in UI pixels.
+const SPACING: f32 = 8.0;
+/// Width of the widgets when no column is started.
+const DEFAULT_WIDTH: f32 = 200.0;
+/// Position of the first widget when no column is started.
+const DEFAULT_POS: (f32, f32) = (20.0, 20.0);
+/// Scale of the glyphs of the text on the widgets.
+const TEXT_SCALE: f32 = 0.16;
+
+/// Color of the widgets.
+const COLOR: [f32; 4] = [0.2, 0.2, 0.2, 0.9];
+/// Color of the widget under the mouse.
+const HOVER_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 0.9];
+/// Color of the widget the mouse button is held on.
+const ACTIVE_COLOR: [f32; 4] = [0.12, 0.12, 0.12, 0.9];
+/// Color of the filled part of the sliders.
+const FILL_COLOR: [f32; 4] = [0.25, 0.5, 0.8, 0.9];
+
+/// The z index of the rectangles, above the panels of the menus.
+const BACKGROUND_Z: u8 = 4;
+/// The z index of the text on the rectangles.
+const FOREGROUND_Z: u8 = 5;
+
+/// A colored rectangle of a widget.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Quad {
+    /// The top left corner in UI pixels.
+    pos: (f32, f32),
+    /// The width & height in UI pixels.
+    size: (f32, f32),
+    /// The tint of the white rectangle.
+    color: [f32; 4],
+}
+
+/// The text of a widget, vertically centered on it.
+#[derive(Debug, Clone, PartialEq)]
+struct Caption {
+    /// The characters shown.
+    text: String,
+    /// The top left corner of the widget in UI pixels.
+    pos: (f32, f32),
+    /// The width of the widget in UI pixels.
+    width: f32,
+    /// How the text is aligned in the width.
+    alignment: Alignment,
+}
+
+/// How the mouse interacts with a widget this frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Interaction {
+    /// The mouse is above it.
+    hovered: bool,
+    /// The mouse button was pressed on it and isn't released yet.
+    active: bool,
+    /// The mouse button was pressed & released on it.
+    clicked: bool,
+}
+
+lib! {
+/// Immediate mode widgets in screen space, declared every frame by the scenes & the scripts.
+///
+/// The widgets are placed below each other in a column and told apart by their text. Calling a
+/// widget returns how the live mouse interacts with it and queues it for drawing.
+pub struct Ui {
+    /// The size of the screen in UI pixels at the last draw.
+    size: (f32, f32),
+    /// The mouse position in UI pixels.
+    mouse: (f32, f32),
+    /// Whether the left mouse button was pressed since the last frame.
+    pressed: bool,
+    /// Whether the left mouse button was released since the last frame.
+    released: bool,
+    /// The widget the mouse button is held on, it gets the release & keeps dragging a slider.
+    active: Option<u64>,
+    /// The top left corner of the next widget in UI pixels.
+    cursor: (f32, f32),
+    /// The width of the widgets in the column in UI pixels.
+    width: f32,
+    /// The rectangles of the widgets declared this frame.
+    quads: Vec<Quad>,
+    /// The texts of the widgets declared this frame.
+    captions: Vec<Caption>,
+    /// The rectangles of the widgets that can be clicked this frame.
+    hit_rects: Vec<((f32, f32), (f32, f32))>,
+    /// The rectangles of the widgets that could be clicked in the last drawn frame, clicks on them
+    /// aren't passed to the gameplay.
+    last_hit_rects: Vec<((f32, f32), (f32, f32))>,
+    /// The white rectangle stretched to the size of every quad, uploaded by the first draw.
+    mesh: Option<Mesh>,
+}
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ui {
+    /// Start without widgets.
+    pub fn new() -> Self {
+        Self {
+            size: (0.0, 0.0),
+            mouse: (0.0, 0.0),
+            pressed: false,
+            released: false,
+            active: None,
+            cursor: DEFAULT_POS,
+            width: DEFAULT_WIDTH,
+            quads: vec![],
+            captions: vec![],
+            hit_rects: vec![],
+            last_hit_rects: vec![],
+            mesh: None,
+        }
+    }
+
+    /// The size of the screen in UI pixels, for placing the widgets.
+    pub fn size(&self) -> (f32, f32) {
+        self.size
+    }
+
+    /// Start a column of widgets at a position in UI pixels.
+    pub fn begin(&mut self, x: f32, y: f32, width: f32) {
+        self.cursor = (x, y);
+        self.width = width;
+    }
+
+    /// A line of text.
+    pub fn label(&mut self, text: &str) {
+        let (pos, size) = self.next_rect();
+
+        self.captions.push(Caption {
+            text: text.to_string(),
+            pos,
+            width: size.0,
+            alignment: Alignment::Left,
+        });
+    }
+
+    /// A button with a text.
+    ///
+    /// Returns whether it's clicked this frame.
+    pub fn button(&mut self, text: &str) -> bool {
+        let (pos, size) = self.next_rect();
+        let interaction = self.interact(widget_id(text), pos, size);
+
+        self.quads.push(Quad {
+            pos,
+            size,
+            color: widget_color(interaction),
+        });
+        self.captions.push(Caption {
+            text: text.to_string(),
+            pos,
+            width: size.0,
+            alignment: Alignment::Center,
+        });
+
+        interaction.clicked
+    }
+
+    /// A horizontal slider with a text, dragging it changes the value inside the range.
+    ///
+    /// Returns whether the value changed this frame.
+    pub fn slider(&mut self, text: &str, value: &mut f32, range: RangeInclusive<f32>) -> bool {
+        let (pos, size) = self.next_rect();
+        let interaction = self.interact(widget_id(text), pos, size);
+        let (start, end) = (*range.start(), *range.end());
+
+        let old = *value;
+        if interaction.active {
+            let fraction = ((self.mouse.0 - pos.0) / size.0).max(0.0).min(1.0);
+            *value = start + fraction * (end - start);
+        }
+        *value = value.max(start.min(end)).min(start.max(end));
+
+        let fraction = if (end - start).abs() > std::f32::EPSILON {
+            (*value - start) / (end - start)
+        } else {
+            0.0
+        };
+        self.quads.push(Quad {
+            pos,
+            size,
+            color: widget_color(interaction),
+        });
+        self.quads.push(Quad {
+            pos,
+            size: (size.0 * fraction, size.1),
+            color: FILL_COLOR,
+        });
+
+        // Small ranges need decimals
+        let precision = if (end - start).abs() >= 10.0 { 0 } else { 2 };
+        self.captions.push(Caption {
+            text: format!("{} {:.*}", text, precision, value),
+            pos,
+            width: size.0,
+            alignment: Alignment::Center,
+        });
+
+        (*value - old).abs() > std::f32::EPSILON
+    }
+
+    /// Track the live mouse, the positions are in screen pixels.
+    ///
+    /// Returns whether the input is consumed, which are clicks on the widgets.
+    pub fn input_event(&mut self, event: &InputEvent, ui_scale: f32) -> bool {
+        let to_ui = |x: f64, y: f64| (x as f32 / ui_scale, y as f32 / ui_scale);
+
+        match *event {
+            InputEvent::MouseMotion { x, y } => {
+                self.mouse = to_ui(x, y);
+
+                false
+            }
+            InputEvent::MouseButtonDown {
+                button: Button::Left,
+                x,
+                y,
+            } => {
+                self.mouse = to_ui(x, y);
+                self.pressed = true;
+
+                let mouse = self.mouse;
+                self.last_hit_rects
+                    .iter()
+                    .any(|&(pos, size)| contains(pos, size, mouse))
+            }
+            InputEvent::MouseButtonUp {
+                button: Button::Left,
+                x,
+                y,
+            } => {
+                self.mouse = to_ui(x, y);
+                self.released = true;
+
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Queue the widgets declared since the last draw and start a new frame.
+    pub fn draw(&mut self, ui_size: (f32, f32), font: &mut FontInstance, render: &mut Render) {
+        let mesh = *self
+            .mesh
+            .get_or_insert_with(|| render.upload_stretchable_rectangle());
+
+        for quad in self.quads.iter() {
+            let mut instance = Instance::new(quad.pos.0, quad.pos.1);
+            instance.set_z(BACKGROUND_Z);
+            instance.set_size(quad.size.0, quad.size.1);
+            instance.set_color(quad.color);
+            render.draw_screen(mesh, instance);
+        }
+
+        for caption in self.captions.iter() {
+            let layout = TextLayout::new()
+                .scale(TEXT_SCALE)
+                .max_width(caption.width)
+                .alignment(caption.alignment);
+
+            // The position of the text is its baseline
+            let (_, height) = font.measure(render, &caption.text, &layout);
+            let baseline = caption.pos.1 + (WIDGET_HEIGHT + height) / 2.0;
+            for (mut instance, mesh) in
+                font.layout(render, &caption.text, caption.pos.0, baseline, &layout)
+            {
+                instance.set_z(FOREGROUND_Z);
+                render.draw_screen(mesh, instance);
+            }
+        }
+
+        self.size = ui_size;
+        self.end_frame();
+    }
+
+    /// Forget the widgets declared since the last frame without drawing them.
+    pub fn end_frame(&mut self) {
+        self.quads.clear();
+        self.captions.clear();
+        self.last_hit_rects = mem::take(&mut self.hit_rects);
+
+        if self.released {
+            self.active = None;
+        }
+        self.pressed = false;
+        self.released = false;
+
+        self.cursor = DEFAULT_POS;
+        self.width = DEFAULT_WIDTH;
+    }
+
+    /// The rectangle of the next widget in the column, moving the column down.
+    fn next_rect(&mut self) -> ((f32, f32), (f32, f32)) {
+        let pos = self.cursor;
+        self.cursor.1 += WIDGET_HEIGHT + SPACING;
+
+        (pos, (self.width, WIDGET_HEIGHT))
+    }
+
+    /// Hit test a widget against the mouse.
+    fn interact(&mut self, id: u64, pos: (f32, f32), size: (f32, f32)) -> Interaction {
+        self.hit_rects.push((pos, size));
+
+        let hovered = contains(pos, size, self.mouse);
+        if hovered && self.pressed {
+            self.active = Some(id);
+        }
+        let active = self.active == Some(id);
+
+        Interaction {
+            hovered,
+            active,
+            clicked: active && hovered && self.released,
+        }
+    }
+
+    /// A column of widgets from the scripts, the width is optional.
+    fn begin_script(&mut self, x: f32, y: f32, width: Option<f32>) {
+        self.begin(x, y, width.unwrap_or(DEFAULT_WIDTH));
+    }
+
+    /// A slider from the scripts, returns the new value.
+    fn slider_script(&mut self, text: &str, value: f32, min: f32, max: f32) -> f32 {
+        let mut value = value;
+        self.slider(text, &mut value, min..=max);
+
+        value
+    }
+
+    /// Bind the GameLisp functions.
+    pub fn bind_functions(runtime: &Runtime) {
+        runtime.run(|| {
+            glsp::bind_rfn("ui-begin", rfn!(Self::begin_script))?;
+            glsp::bind_rfn("ui-label", rfn!(Self::label))?;
+            glsp::bind_rfn("ui-button", rfn!(Self::button))?;
+            glsp::bind_rfn("ui-slider", rfn!(Self::slider_script))?;
+            glsp::bind_rfn("ui-size", rfn!(Self::size))?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Identify a widget by its text.
+fn widget_id(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// The color of a widget for how the mouse interacts with it.
+fn widget_color(interaction: Interaction) -> [f32; 4] {
+    if interaction.active {
+        ACTIVE_COLOR
+    } else if interaction.hovered {
+        HOVER_COLOR
+    } else {
+        COLOR
+    }
+}
+
+/// Whether a point is inside a rectangle.
+fn contains(pos: (f32, f32), size: (f32, f32), (x, y): (f32, f32)) -> bool {
+    x >= pos.0 && x < pos.0 + size.0 && y >= pos.1 && y < pos.1 + size.1
+}
@@ -1,36 +1,123 @@
 use crate::{
+    animation::Animator,
+    controller::CharacterController,
     object::ObjectDef,
-    physics::{Physics, RigidBody},
-    render::Instance,
+    physics::{BodyKind, CollisionGroup, Physics, RigidBody},
+    render::{Instance, Mesh},
+    transform::{LocalTransform, Parent},
     Float, Vec2,
 };
+use glsp::{GResult, ToVal, Val};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Allegiance {
     Enemy,
     Ally,
 }
 
+impl Allegiance {
+    /// The RGBA tint of the units of this side, so they can share the same mesh.
+    pub fn tint(self) -> [f32; 4] {
+        match self {
+            Self::Enemy => [1.0, 0.7, 0.7, 1.0],
+            Self::Ally => [0.7, 0.8, 1.0, 1.0],
+        }
+    }
+}
+
+impl ToVal for Allegiance {
+    fn to_val(&self) -> GResult<Val> {
+        let name = match self {
+            Self::Enemy => "enemy",
+            Self::Ally => "ally",
+        };
+
+        Ok(Val::Sym(glsp::sym(name)?))
+    }
+}
+
 impl Default for Allegiance {
     fn default() -> Self {
         Self::Enemy
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Health(Float);
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    /// The lifepoints left.
+    current: Float,
+    /// The lifepoints the unit is spawned with.
+    max: Float,
+}
+
+impl Health {
+    /// Start with all lifepoints.
+    pub fn new(max: Float) -> Self {
+        Self { current: max, max }
+    }
+
+    /// Remove lifepoints, a negative amount heals.
+    pub fn damage(&mut self, amount: Float) {
+        self.current -= amount;
+    }
+
+    /// Whether all lifepoints are gone.
+    pub fn is_dead(self) -> bool {
+        self.current <= 0.0
+    }
+
+    /// The lifepoints the unit is spawned with.
+    pub fn max(self) -> Float {
+        self.max
+    }
+
+    /// The part of the lifepoints left, between zero & one.
+    pub fn fraction(self) -> Float {
+        if self.max > 0.0 {
+            (self.current / self.max).max(0.0).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
 
 impl Default for Health {
     fn default() -> Self {
-        Self(100.0)
+        Self::new(100.0)
     }
 }
 
+/// A spawned unit.
+#[derive(Debug, Clone)]
+pub struct Unit {
+    /// The mesh the instance belongs to.
+    pub mesh: Mesh,
+    /// The rendered mesh instance.
+    pub instance: Instance,
+    /// The physics body.
+    pub rigid_body: RigidBody,
+    /// The side the unit fights for.
+    pub allegiance: Allegiance,
+    /// The lifepoints of the unit.
+    pub health: Health,
+    /// Moves the parts of the unit when its definition has a skeleton.
+    pub animator: Animator,
+    /// Walks & jumps the unit, created when a script first moves it like that.
+    pub controller: Option<CharacterController>,
+    /// The unit it's attached to, its body follows the parent instead of being simulated.
+    pub parent: Option<Parent>,
+    /// Where the parent holds it when it's attached.
+    pub local_transform: LocalTransform,
+}
+
 pub struct UnitBuilder<'a> {
     allegiance: Allegiance,
     health: Health,
     pos: Vec2,
-    z: u8,
+    tint: [f32; 4],
+    kind: BodyKind,
     def: &'a mut ObjectDef,
 }
 
@@ -39,9 +126,10 @@ impl<'a> UnitBuilder<'a> {
     pub fn ally(def: &'a mut ObjectDef) -> Self {
         Self {
             allegiance: Allegiance::Ally,
+            kind: def.kind,
             def,
             pos: Vec2::default(),
-            z: 0,
+            tint: Allegiance::Ally.tint(),
             health: Health::default(),
         }
     }
@@ -50,23 +138,36 @@ impl<'a> UnitBuilder<'a> {
     pub fn enemy(def: &'a mut ObjectDef) -> Self {
         Self {
             allegiance: Allegiance::Enemy,
+            kind: def.kind,
             def,
             pos: Vec2::default(),
-            z: 0,
+            tint: Allegiance::Enemy.tint(),
             health: Health::default(),
         }
     }
 
     /// Spawn the unit in the world.
-    pub fn spawn(self, physics: &mut Physics<Float>) -> (Instance, RigidBody) {
-        self.def.spawn(physics, self.pos, self.z)
-    }
+    pub fn spawn(self, physics: &mut Physics<Float>) -> Unit {
+        let (mut instance, rigid_body) = self.def.spawn_as(
+            physics,
+            self.pos,
+            0,
+            CollisionGroup::Unit(self.allegiance),
+            self.kind,
+        );
+        instance.set_color(self.tint);
 
-    /// Set the lifepoints of the unit.
-    pub fn health(mut self, health: Float) -> Self {
-        self.health = Health(health);
-
-        self
+        Unit {
+            mesh: self.def.mesh(),
+            instance,
+            rigid_body,
+            allegiance: self.allegiance,
+            health: self.health,
+            animator: Animator::default(),
+            controller: None,
+            parent: None,
+            local_transform: LocalTransform::default(),
+        }
     }
 
     /// Set the position of the unit.
@@ -76,9 +177,16 @@ impl<'a> UnitBuilder<'a> {
         self
     }
 
-    /// Set the z index of the unit.
-    pub fn z(mut self, z: u8) -> Self {
-        self.z = z;
+    /// Set how the body of the unit is moved, instead of how the definition says.
+    pub fn kind(mut self, kind: BodyKind) -> Self {
+        self.kind = kind;
+
+        self
+    }
+
+    /// Set the RGBA color the mesh is multiplied with, instead of the color of the side.
+    pub fn tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
 
         self
     }
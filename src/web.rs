@@ -0,0 +1,60 @@
+use log::Level;
+use sapp_jsutils::JsObject;
+use std::{ffi::CString, os::raw::c_char};
+
+extern "C" {
+    // Provided by gl.js of miniquad
+    fn console_debug(message: *const c_char);
+    fn console_info(message: *const c_char);
+    fn console_warn(message: *const c_char);
+    fn console_error(message: *const c_char);
+
+    // Provided by web.js
+    fn web_url_query() -> JsObject;
+}
+
+/// The version of web.js the functions are written for, as `0.1.0`.
+#[no_mangle]
+pub extern "C" fn web_crate_version() -> u32 {
+    1 << 16
+}
+
+/// Write a message to the console of the browser, standard error isn't shown anywhere.
+pub fn console(level: Level, message: &str) {
+    let log = match level {
+        Level::Error => console_error,
+        Level::Warn => console_warn,
+        Level::Info => console_info,
+        Level::Debug | Level::Trace => console_debug,
+    };
+
+    // Messages with a null byte can't be passed to JavaScript
+    if let Ok(message) = CString::new(message) {
+        // Safe because the string is null terminated and copied by JavaScript before it's freed
+        unsafe { log(message.as_ptr()) };
+    }
+}
+
+/// The query of the page URL as command line arguments.
+///
+/// Every `key=value` pair becomes a `--key=value` flag, a key without a value a `--key` flag and
+/// keys with a dot are configuration overrides: `?scene=arena&mute&display.vsync=false`.
+pub fn args() -> Vec<String> {
+    let mut query = String::new();
+    // Safe because the function is registered by web.js before the game starts
+    unsafe { web_url_query() }.to_string(&mut query);
+
+    query
+        .trim_start_matches('?')
+        .split('&')
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| {
+            let key = arg.split('=').next().unwrap_or_default();
+            if key.contains('.') {
+                arg.to_string()
+            } else {
+                format!("--{}", arg)
+            }
+        })
+        .collect()
+}
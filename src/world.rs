@@ -0,0 +1,1456 @@
+use crate::{
+    combat_log::CombatLog,
+    controller::CharacterController,
+    inventory::{Inventory, InventoryGrid, ItemDef, ItemRegistry},
+    level::Level,
+    motion::{Explosion, MotionCommand, UnitVelocity},
+    nine_slice::NineSlice,
+    object::{Object, ObjectRegistry},
+    physics::{
+        BodyKind, BodyState, CollisionEvent, CollisionGroup, Force, Joint, Physics, RigidBody,
+    },
+    projectile::Projectile,
+    render::{Instance, Mesh, Render},
+    replication::{EntityId, ReplicatedState, Snapshot},
+    spawner::{
+        ForceKind, ForceRequest, JointKind, JointRequest, SpawnKind, SpawnRequest, SpawnRequests,
+    },
+    transform::{LocalTransform, Parent},
+    tween::{Property, Value},
+    unit::{Allegiance, Health, Unit, UnitBuilder},
+    Float, Vec2,
+};
+use anyhow::{anyhow, Result};
+use generational_arena::{Arena, Index};
+use glsp::{GResult, GSend, ToVal, Val};
+use log::{error, warn};
+use nphysics2d::object::DefaultBodyHandle;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Downwards acceleration of the physics bodies.
+pub const GRAVITY: Float = 9.81 * 100.0;
+
+/// Amount of messages kept in the combat log.
+const COMBAT_LOG_HISTORY: usize = 100;
+/// Size of the combat log panel in UI pixels.
+const COMBAT_LOG_SIZE: (f32, f32) = (320.0, 160.0);
+/// Space between the UI panels and the border of the screen in UI pixels.
+const UI_MARGIN: f32 = 16.0;
+
+/// Assets that are loaded incrementally while the loading screen is shown.
+#[derive(Default)]
+pub struct Assets {
+    /// The object definitions loaded so far.
+    pub defs: ObjectRegistry,
+    /// The stretchable mesh for UI panels.
+    pub panel: Option<NineSlice>,
+    /// The levels by the name the scripts spawn them by.
+    pub levels: HashMap<String, Level>,
+}
+
+// The definitions hold no script values, the assets are moved into the world while the scripts run
+impl GSend for Assets {}
+
+/// The simulated part of the world, saved in save slots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldState {
+    /// The inventory of the player.
+    inventory: Inventory,
+    /// The slot of the stack that's being dragged, dropping it moves the items.
+    dragging: Option<usize>,
+    /// The motion of the physics bodies.
+    bodies: Vec<BodyState<Float>>,
+}
+
+/// Everything simulated in the world, saved before frames for seeking in replays.
+///
+/// Unlike the saved state it keeps the entities with their numbers, so the scripts can keep
+/// referring to them. It's only valid in the same session.
+#[derive(Debug, Clone)]
+pub struct WorldCheckpoint {
+    /// The units, with the handles of their bodies before they were rebuilt.
+    units: Arena<Unit>,
+    /// The objects that aren't units.
+    objects: Arena<Object>,
+    /// The flying projectiles.
+    projectiles: Arena<Projectile>,
+    /// The positions & velocities of the bodies of the entities, by their handles.
+    bodies: HashMap<DefaultBodyHandle, BodyState<Float>>,
+    /// The joints by their numbers, with the units they connect.
+    joints: BTreeMap<i32, (Vec<EntityId>, Joint<Float>)>,
+    /// The winds & springs by their numbers, with the units they connect.
+    forces: BTreeMap<i32, (Vec<EntityId>, Force<Float>)>,
+    /// The inventory of the player.
+    inventory: Inventory,
+    /// The slot of the stack that's being dragged, dropping it moves the items.
+    dragging: Option<usize>,
+}
+
+/// A spawned unit or object as plain values, so a save can spawn it again in a new session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityState {
+    /// Name of the object definition it's spawned from, like "character".
+    def: String,
+    /// Whether it's a unit or an object, with what's specific to it.
+    kind: EntityKind,
+    /// Horizontal world position.
+    x: Float,
+    /// Vertical world position.
+    y: Float,
+    /// Rotation in radians.
+    rotation: Float,
+    /// Horizontal velocity in world units per second.
+    linear_x: Float,
+    /// Vertical velocity in world units per second.
+    linear_y: Float,
+    /// Angular velocity in radians per second.
+    angular: Float,
+}
+
+/// What kind of entity is saved.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum EntityKind {
+    /// A unit with the side it fights for, its lifepoints & its color.
+    Unit {
+        allegiance: Allegiance,
+        health: Health,
+        /// The color the mesh is multiplied with.
+        tint: [f32; 4],
+    },
+    /// An object that isn't a unit, like the ground.
+    Object,
+}
+
+/// Where a spawned entity is stored.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Spawned {
+    /// The index in the units.
+    Unit(Index),
+    /// The index in the objects.
+    Object(Index),
+    /// Projectiles aren't referenced after firing them.
+    Projectile,
+}
+
+/// What a physics body belongs to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BodyOwner {
+    /// A unit, passed to the scripts as its entity.
+    Unit(EntityId),
+    /// An object or projectile, passed to the scripts as the name of its definition.
+    Object(&'static str),
+}
+
+impl ToVal for BodyOwner {
+    fn to_val(&self) -> GResult<Val> {
+        match self {
+            BodyOwner::Unit(entity) => entity.to_script().to_val(),
+            BodyOwner::Object(name) => Ok(Val::Sym(glsp::sym(name)?)),
+        }
+    }
+}
+
+/// Bodies that touched each other during the fixed steps, passed to the scripts afterwards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Touch {
+    /// Two bodies started touching.
+    Collision(BodyOwner, BodyOwner),
+    /// A body entered the area of a sensor, the sensor is first.
+    SensorEnter(BodyOwner, BodyOwner),
+    /// A body left the area of a sensor, the sensor is first.
+    SensorExit(BodyOwner, BodyOwner),
+}
+
+/// The gameplay state, available when all assets are loaded.
+pub struct World {
+    /// The object definitions by the name the scripts spawn them by.
+    pub defs: ObjectRegistry,
+    /// The levels by the name the scripts spawn them by.
+    pub levels: HashMap<String, Level>,
+    /// The physics system.
+    pub physics: Physics<Float>,
+    /// The duration of a single fixed step in seconds.
+    fixed_step: Float,
+    /// All spawned units.
+    pub units: Arena<Unit>,
+    /// All spawned objects that aren't units.
+    pub objects: Arena<Object>,
+    /// All flying projectiles.
+    pub projectiles: Arena<Projectile>,
+    /// What the physics bodies of the units, objects & projectiles belong to, for finding the
+    /// owners of the collisions without going through all entities.
+    body_owners: HashMap<DefaultBodyHandle, BodyOwner>,
+    /// The joints by the number the scripts know them by, with the units they connect.
+    ///
+    /// Ordered so they are removed in the same order every run, which keeps the simulation
+    /// deterministic.
+    joints: BTreeMap<i32, (Vec<EntityId>, Joint<Float>)>,
+    /// The winds & springs by the number the scripts know them by, with the units they connect.
+    forces: BTreeMap<i32, (Vec<EntityId>, Force<Float>)>,
+    /// The owners of the bodies that touched since the last drain.
+    collisions: Vec<Touch>,
+    /// The interpolated units of the server, when connected to one as a client.
+    pub remote_units: Snapshot,
+    /// All item definitions.
+    pub items: ItemRegistry,
+    /// The inventory of the player.
+    pub inventory: Inventory,
+    /// The UI for the inventory of the player.
+    pub inventory_grid: InventoryGrid,
+    /// The panel showing the gameplay messages.
+    pub combat_log: CombatLog,
+}
+
+// The physics hold no script values, the world is used while the scripts run
+impl GSend for World {}
+
+impl World {
+    /// Setup the gameplay state from the loaded assets.
+    pub fn new(assets: Assets, render: &mut Render, fixed_step: Float) -> Result<Self> {
+        let defs = assets.defs;
+        let levels = assets.levels;
+        let panel = assets
+            .panel
+            .ok_or_else(|| anyhow!("Panel asset not loaded"))?;
+
+        // Setup the inventory of the player
+        let mut items = ItemRegistry::default();
+        let arrow_item = items.register(ItemDef {
+            name: "item-arrows".to_string(),
+            mesh: defs.get("arrow")?.mesh(),
+            scale: 0.6,
+            max_stack: 50,
+        });
+        let mut inventory = Inventory::new(16);
+        inventory.add(arrow_item, 80, items.max_stack(arrow_item));
+        let inventory_grid = InventoryGrid::new(render, UI_MARGIN, UI_MARGIN, 8);
+
+        // Setup the log panel, it's moved to the bottom left corner by the layout
+        let combat_log = CombatLog::new(
+            panel.upload(render)?,
+            UI_MARGIN,
+            UI_MARGIN,
+            COMBAT_LOG_SIZE.0,
+            COMBAT_LOG_SIZE.1,
+            COMBAT_LOG_HISTORY,
+        );
+
+        // Instantiate the physics engine
+        let mut physics = Physics::new(GRAVITY);
+        physics.set_timestep(fixed_step);
+
+        Ok(Self {
+            defs,
+            levels,
+            physics,
+            fixed_step,
+            units: Arena::new(),
+            objects: Arena::new(),
+            projectiles: Arena::new(),
+            body_owners: HashMap::new(),
+            joints: BTreeMap::new(),
+            forces: BTreeMap::new(),
+            collisions: vec![],
+            remote_units: Snapshot::new(),
+            items,
+            inventory,
+            inventory_grid,
+            combat_log,
+        })
+    }
+
+    /// Simulate a single fixed step.
+    pub fn step(&mut self) {
+        // Walk & jump the units with a character controller
+        for (_, unit) in self.units.iter_mut() {
+            if let Some(controller) = unit.controller.as_mut() {
+                controller.update(&mut self.physics, &unit.rigid_body, self.fixed_step);
+            }
+        }
+
+        self.physics.step();
+        self.follow_parents();
+
+        // Find the owners before the projectiles that hit something are removed
+        for event in self.physics.drain_collisions() {
+            let touch = match event {
+                CollisionEvent::Started(a, b) => {
+                    self.body_owners(a, b).map(|(a, b)| Touch::Collision(a, b))
+                }
+                CollisionEvent::Entered(a, b) => self
+                    .body_owners(a, b)
+                    .map(|(a, b)| Touch::SensorEnter(a, b)),
+                CollisionEvent::Exited(a, b) => {
+                    self.body_owners(a, b).map(|(a, b)| Touch::SensorExit(a, b))
+                }
+                CollisionEvent::Stopped(..) => None,
+            };
+            self.collisions.extend(touch);
+        }
+
+        // Remove the projectiles that hit something they don't stick in or flew too long
+        let physics = &mut self.physics;
+        let mut removed = vec![];
+        for (index, projectile) in self.projectiles.iter_mut() {
+            if !projectile.update(physics, self.fixed_step) {
+                removed.push(index);
+            }
+        }
+        for index in removed {
+            if let Some(projectile) = self.projectiles.remove(index) {
+                self.body_owners.remove(&projectile.rigid_body.handle());
+                projectile.remove(physics);
+            }
+        }
+    }
+
+    /// Take the bodies that touched since the last time they were taken.
+    pub fn drain_collisions(&mut self) -> Vec<Touch> {
+        std::mem::take(&mut self.collisions)
+    }
+
+    /// Find what's at a position in the world, units are picked before the objects behind them.
+    pub fn pick(&self, x: Float, y: Float) -> Option<BodyOwner> {
+        let owners = self
+            .physics
+            .bodies_at((x, y))
+            .into_iter()
+            .filter_map(|handle| self.body_owner(handle))
+            .collect::<Vec<_>>();
+
+        owners
+            .iter()
+            .find(|owner| matches!(owner, BodyOwner::Unit(_)))
+            .or_else(|| owners.first())
+            .copied()
+    }
+
+    /// Find what both physics bodies belong to.
+    fn body_owners(
+        &self,
+        a: DefaultBodyHandle,
+        b: DefaultBodyHandle,
+    ) -> Option<(BodyOwner, BodyOwner)> {
+        self.body_owner(a).zip(self.body_owner(b))
+    }
+
+    /// Find what a physics body belongs to.
+    fn body_owner(&self, handle: DefaultBodyHandle) -> Option<BodyOwner> {
+        self.body_owners.get(&handle).copied()
+    }
+
+    /// Save the simulated state.
+    pub fn save_state(&self) -> WorldState {
+        WorldState {
+            inventory: self.inventory.clone(),
+            dragging: self.inventory_grid.dragging(),
+            bodies: self.physics.save_state(),
+        }
+    }
+
+    /// Go back to a saved state.
+    pub fn restore_state(&mut self, state: &WorldState) {
+        self.inventory = state.inventory.clone();
+        self.inventory_grid.set_dragging(state.dragging);
+        self.physics.restore_state(&state.bodies);
+    }
+
+    /// Save everything that's simulated, so the world can be rebuilt from it.
+    pub fn save_checkpoint(&self) -> WorldCheckpoint {
+        let units = self.units.iter().map(|(_, unit)| &unit.rigid_body);
+        let objects = self.objects.iter().map(|(_, object)| &object.rigid_body);
+        let projectiles = self
+            .projectiles
+            .iter()
+            .map(|(_, projectile)| &projectile.rigid_body);
+        let bodies = units
+            .chain(objects)
+            .chain(projectiles)
+            .filter_map(|rigid_body| self.physics.body_state(rigid_body))
+            .map(|state| (state.handle(), state))
+            .collect();
+
+        WorldCheckpoint {
+            units: self.units.clone(),
+            objects: self.objects.clone(),
+            projectiles: self.projectiles.clone(),
+            bodies,
+            joints: self.joints.clone(),
+            forces: self.forces.clone(),
+            inventory: self.inventory.clone(),
+            dragging: self.inventory_grid.dragging(),
+        }
+    }
+
+    /// Rebuild the world from a checkpoint with new physics.
+    ///
+    /// The entities keep their numbers and the bodies are created in the same order every time,
+    /// so the simulation continues the same way after every rebuild from the same checkpoint. The
+    /// contact caches of the old physics are gone, the contacts are found again without the
+    /// impulses of the previous steps.
+    pub fn restore_checkpoint(&mut self, checkpoint: &WorldCheckpoint) -> Result<()> {
+        let mut physics = Physics::new(GRAVITY);
+        physics.set_timestep(self.fixed_step);
+        self.physics = physics;
+        self.units = checkpoint.units.clone();
+        self.objects = checkpoint.objects.clone();
+        self.projectiles = checkpoint.projectiles.clone();
+        self.collisions.clear();
+        self.inventory = checkpoint.inventory.clone();
+        self.inventory_grid.set_dragging(checkpoint.dragging);
+
+        // The objects are in the collision group of their definition
+        let rigid_bodies = self
+            .units
+            .iter_mut()
+            .map(|(_, unit)| {
+                let group = CollisionGroup::Unit(unit.allegiance);
+
+                (unit.mesh, Some(group), &mut unit.rigid_body)
+            })
+            .chain(
+                self.objects
+                    .iter_mut()
+                    .map(|(_, object)| (object.mesh, None, &mut object.rigid_body)),
+            )
+            .chain(self.projectiles.iter_mut().map(|(_, projectile)| {
+                let group = CollisionGroup::Projectile(projectile.allegiance);
+
+                (projectile.mesh, Some(group), &mut projectile.rigid_body)
+            }));
+        let mut rebuilt_handles = HashMap::new();
+        for (mesh, group, rigid_body) in rigid_bodies {
+            let name = self
+                .defs
+                .name_of(mesh)
+                .ok_or_else(|| anyhow!("Definition of mesh {:?} doesn't exist", mesh))?;
+            let def = self.defs.get_mut(name)?;
+            let group = group.unwrap_or(def.group);
+            let state = checkpoint
+                .bodies
+                .get(&rigid_body.handle())
+                .ok_or_else(|| anyhow!("Body {:?} isn't saved", rigid_body.handle()))?;
+            let (x, y) = state.translation();
+
+            let (_, rebuilt) = def.spawn(&mut self.physics, Vec2::new(x, y), 0, group);
+            self.physics.set_body_state(&rebuilt, state);
+            rebuilt_handles.insert(rigid_body.handle(), rebuilt.handle());
+            *rigid_body = rebuilt;
+        }
+
+        // The stuck projectiles are welded to the rebuilt bodies again
+        for (_, projectile) in self.projectiles.iter_mut() {
+            projectile.rebuild_weld(&mut self.physics, &rebuilt_handles);
+        }
+
+        // The rebuilt bodies have new handles
+        let units = self.units.iter().map(|(index, unit)| {
+            let owner = BodyOwner::Unit(EntityId::from(index));
+
+            Some((unit.rigid_body.handle(), owner))
+        });
+        let objects = self
+            .objects
+            .iter()
+            .map(|(_, object)| (object.mesh, &object.rigid_body))
+            .chain(
+                self.projectiles
+                    .iter()
+                    .map(|(_, projectile)| (projectile.mesh, &projectile.rigid_body)),
+            )
+            .map(|(mesh, rigid_body)| {
+                let owner = BodyOwner::Object(self.defs.name_of(mesh)?);
+
+                Some((rigid_body.handle(), owner))
+            });
+        self.body_owners = units.chain(objects).flatten().collect();
+
+        // Connect the rebuilt bodies in the order the joints were added
+        let mut joints = checkpoint.joints.iter().collect::<Vec<_>>();
+        joints.sort_by_key(|(id, _)| **id);
+        self.joints.clear();
+        for (id, (connected, joint)) in joints {
+            let units = &self.units;
+            let rigid_body = |entity: &EntityId| {
+                units
+                    .get((*entity).into())
+                    .map(|unit| &unit.rigid_body)
+                    .ok_or_else(|| anyhow!("Unit {:?} doesn't exist", entity))
+            };
+            let a = rigid_body(&connected[0])?;
+            let b = connected.get(1).map(rigid_body).transpose()?;
+
+            let rebuilt = self.physics.rebuild_joint(a, b, joint);
+            self.joints.insert(*id, (connected.clone(), rebuilt));
+        }
+
+        // Apply the winds & springs in the order they were added
+        let mut forces = checkpoint.forces.iter().collect::<Vec<_>>();
+        forces.sort_by_key(|(id, _)| **id);
+        self.forces.clear();
+        for (id, (connected, force)) in forces {
+            let units = &self.units;
+            let rigid_body = |entity: &EntityId| {
+                units
+                    .get((*entity).into())
+                    .map(|unit| &unit.rigid_body)
+                    .ok_or_else(|| anyhow!("Unit {:?} doesn't exist", entity))
+            };
+            let a = connected.get(0).map(rigid_body).transpose()?;
+            let b = connected.get(1).map(rigid_body).transpose()?;
+
+            let rebuilt = self.physics.rebuild_force(a, b, force);
+            self.forces.insert(*id, (connected.clone(), rebuilt));
+        }
+
+        Ok(())
+    }
+
+    /// Save the spawned units & objects, so they can be spawned again in a new session.
+    ///
+    /// Projectiles are left out since they're gone in a few seconds, the joints since the
+    /// scripts keep track of them.
+    pub fn save_entities(&self) -> Vec<EntityState> {
+        let units = self.units.iter().map(|(_, unit)| {
+            (
+                unit.mesh,
+                EntityKind::Unit {
+                    allegiance: unit.allegiance,
+                    health: unit.health,
+                    tint: unit.instance.color(),
+                },
+                &unit.rigid_body,
+            )
+        });
+        let objects = self
+            .objects
+            .iter()
+            .map(|(_, object)| (object.mesh, EntityKind::Object, &object.rigid_body));
+
+        units
+            .chain(objects)
+            .filter_map(|(mesh, kind, rigid_body)| {
+                let def = self.defs.name_of(mesh)?;
+                let (x, y, rotation) = self.physics.position(rigid_body)?;
+                let (linear_x, linear_y) = self.physics.linear_velocity(rigid_body)?;
+                let angular = self.physics.angular_velocity(rigid_body)?;
+
+                Some(EntityState {
+                    def: def.to_string(),
+                    kind,
+                    x,
+                    y,
+                    rotation,
+                    linear_x,
+                    linear_y,
+                    angular,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace everything that's spawned with the saved units & objects.
+    ///
+    /// Returns the spawned units with the name of their definition, they're new entities.
+    pub fn restore_entities(&mut self, entities: &[EntityState]) -> Vec<(EntityId, String)> {
+        // Despawn the units first so their joints are removed with them
+        let units = self
+            .units
+            .iter()
+            .map(|(index, _)| EntityId::from(index))
+            .collect::<Vec<_>>();
+        for entity in units {
+            self.despawn(entity);
+        }
+        for (_, object) in self.objects.drain() {
+            self.body_owners.remove(&object.rigid_body.handle());
+            self.physics.remove(object.rigid_body);
+        }
+        for (_, projectile) in self.projectiles.drain() {
+            self.body_owners.remove(&projectile.rigid_body.handle());
+            projectile.remove(&mut self.physics);
+        }
+
+        let mut spawned = vec![];
+        for state in entities {
+            let kind = match state.kind {
+                EntityKind::Unit {
+                    allegiance, tint, ..
+                } => SpawnKind::Unit {
+                    allegiance,
+                    tint: Some(tint),
+                },
+                EntityKind::Object => SpawnKind::Object,
+            };
+            let request = SpawnRequest {
+                def: state.def.clone(),
+                kind,
+                x: state.x as f32,
+                y: state.y as f32,
+            };
+            let rigid_body = match (self.spawn_entity(&request), state.kind) {
+                (Ok(Spawned::Unit(index)), EntityKind::Unit { health, .. }) => {
+                    spawned.push((EntityId::from(index), state.def.clone()));
+
+                    self.units.get_mut(index).map(|unit| {
+                        unit.health = health;
+
+                        &unit.rigid_body
+                    })
+                }
+                (Ok(Spawned::Object(index)), _) => {
+                    self.objects.get(index).map(|object| &object.rigid_body)
+                }
+                (Ok(_), _) => None,
+                (Err(err), _) => {
+                    error!("restoring \"{}\" failed: {}", state.def, err);
+
+                    continue;
+                }
+            };
+            if let Some(rigid_body) = rigid_body {
+                self.physics.set_rotation(rigid_body, state.rotation);
+                self.physics
+                    .set_linear_velocity(rigid_body, (state.linear_x, state.linear_y));
+                self.physics.set_angular_velocity(rigid_body, state.angular);
+            }
+        }
+
+        spawned
+    }
+
+    /// Get the replicated components of all units, for sending them to the clients.
+    pub fn replicated_states(&self) -> Snapshot {
+        self.units
+            .iter()
+            .filter_map(|(index, unit)| {
+                // The physics body is leading for the transform
+                let (x, y, rotation) = self.physics.position(&unit.rigid_body).unwrap_or((
+                    unit.instance.x() as Float,
+                    unit.instance.y() as Float,
+                    unit.instance.rotation() as Float,
+                ));
+
+                Some((
+                    EntityId::from(index),
+                    ReplicatedState {
+                        def: self.defs.name_of(unit.mesh)?.to_string(),
+                        x: x as f32,
+                        y: y as f32,
+                        rotation: rotation as f32,
+                        health: unit.health,
+                        allegiance: unit.allegiance,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Apply what the scripts requested from the spawner, in the order the requests are listed in.
+    ///
+    /// Returns the spawned units with the name of their definition, so the scripts can be told.
+    pub fn apply_spawn_requests(&mut self, requests: SpawnRequests) -> Vec<(EntityId, String)> {
+        let mut spawned = vec![];
+        for request in requests.spawns {
+            match self.spawn(&request) {
+                Ok(Some(entity)) => spawned.push((entity, request.def)),
+                Ok(None) => (),
+                Err(err) => error!("spawning \"{}\" failed: {}", request.def, err),
+            }
+        }
+        for (name, x, y) in requests.levels {
+            match self.spawn_level(&name, x, y) {
+                Ok(units) => spawned.extend(units),
+                Err(err) => error!("spawning level \"{}\" failed: {}", name, err),
+            }
+        }
+        for entity in requests.despawns {
+            if !self.despawn(entity) {
+                warn!("can't despawn {:?}, it doesn't exist", entity);
+            }
+        }
+        for (entity, name) in requests.animations {
+            match self.play_animation(entity, &name) {
+                Ok(true) => (),
+                Ok(false) => warn!("can't animate {:?}, it doesn't exist", entity),
+                Err(err) => error!("playing animation of {:?} failed: {}", entity, err),
+            }
+        }
+        for (entity, flipped) in requests.flips {
+            if !self.flip_x(entity, flipped) {
+                warn!("can't flip {:?}, it doesn't exist", entity);
+            }
+        }
+        for joint in requests.joints {
+            if let Err(err) = self.add_joint(&joint) {
+                error!("adding joint {} failed: {}", joint.id, err);
+            }
+        }
+        for id in requests.removed_joints {
+            if !self.remove_joint(id) {
+                warn!("can't remove joint {}, it doesn't exist", id);
+            }
+        }
+        for force in requests.forces {
+            if let Err(err) = self.add_force(&force) {
+                error!("adding force {} failed: {}", force.id, err);
+            }
+        }
+        for id in requests.removed_forces {
+            if !self.remove_force(id) {
+                warn!("can't remove force {}, it doesn't exist", id);
+            }
+        }
+        for (entity, parent) in requests.attachments {
+            match parent {
+                Some((Parent(parent), local_transform)) => {
+                    if let Err(err) = self.attach(entity, parent, local_transform) {
+                        error!("attaching {:?} failed: {}", entity, err);
+                    }
+                }
+                None => {
+                    if !self.detach(entity) {
+                        warn!("can't detach {:?}, it doesn't exist", entity);
+                    }
+                }
+            }
+        }
+
+        spawned
+    }
+
+    /// Spawn a unit, object or projectile requested by the scripts, returns the entity of a unit.
+    pub fn spawn(&mut self, request: &SpawnRequest) -> Result<Option<EntityId>> {
+        Ok(match self.spawn_entity(request)? {
+            Spawned::Unit(index) => Some(EntityId::from(index)),
+            Spawned::Object(_) | Spawned::Projectile => None,
+        })
+    }
+
+    /// Spawn all objects & markers of a level with its origin at a world position.
+    ///
+    /// Returns the spawned units with the names of their definitions.
+    pub fn spawn_level(&mut self, name: &str, x: f32, y: f32) -> Result<Vec<(EntityId, String)>> {
+        let level = self
+            .levels
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Level \"{}\" doesn't exist", name))?;
+
+        Ok(level.spawn(self, x, y))
+    }
+
+    /// Spawn a unit, object or projectile, returns where it's stored.
+    fn spawn_entity(&mut self, request: &SpawnRequest) -> Result<Spawned> {
+        let def = self.defs.get_mut(&request.def)?;
+        let (x, y) = (request.x as Float, request.y as Float);
+
+        match request.kind {
+            SpawnKind::Unit { allegiance, tint } => {
+                // Units without a rigid body can't be moved or read, so static ones are kinematic
+                let kind = match def.kind {
+                    BodyKind::Static => BodyKind::Kinematic,
+                    kind => kind,
+                };
+                let mut builder = match allegiance {
+                    Allegiance::Ally => UnitBuilder::ally(def),
+                    Allegiance::Enemy => UnitBuilder::enemy(def),
+                };
+                if let Some(tint) = tint {
+                    builder = builder.tint(tint);
+                }
+                let unit = builder.pos(x, y).kind(kind).spawn(&mut self.physics);
+                let handle = unit.rigid_body.handle();
+                let index = self.units.insert(unit);
+                self.body_owners
+                    .insert(handle, BodyOwner::Unit(EntityId::from(index)));
+
+                Ok(Spawned::Unit(index))
+            }
+            SpawnKind::Object => {
+                let mesh = def.mesh();
+                let group = def.group;
+                let (instance, rigid_body) =
+                    def.spawn(&mut self.physics, Vec2::new(x, y), 0, group);
+                let handle = rigid_body.handle();
+                let index = self.objects.insert(Object {
+                    mesh,
+                    instance,
+                    rigid_body,
+                });
+                if let Some(name) = self.defs.name_of(mesh) {
+                    self.body_owners.insert(handle, BodyOwner::Object(name));
+                }
+
+                Ok(Spawned::Object(index))
+            }
+            SpawnKind::Projectile {
+                velocity,
+                allegiance,
+            } => {
+                let velocity = Vec2::new(velocity.0 as Float, velocity.1 as Float);
+                let projectile = Projectile::fire(
+                    def,
+                    &mut self.physics,
+                    Vec2::new(x, y),
+                    velocity,
+                    allegiance,
+                );
+                if let Some(name) = self.defs.name_of(projectile.mesh) {
+                    self.body_owners
+                        .insert(projectile.rigid_body.handle(), BodyOwner::Object(name));
+                }
+                self.projectiles.insert(projectile);
+
+                Ok(Spawned::Projectile)
+            }
+        }
+    }
+
+    /// Remove a unit with its physics body, returns whether it existed.
+    ///
+    /// Its instance isn't drawn anymore from the next frame on.
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        // Remove the joints to it, so the rods of distance joints don't linger
+        let attached = self
+            .joints
+            .iter()
+            .filter(|(_, (units, _))| units.contains(&entity))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in attached {
+            self.remove_joint(id);
+        }
+        let attached = self
+            .forces
+            .iter()
+            .filter(|(_, (units, _))| units.contains(&entity))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in attached {
+            self.remove_force(id);
+        }
+
+        // The attached units fall off
+        for (_, unit) in self.units.iter_mut() {
+            if unit.parent == Some(Parent(entity)) {
+                unit.parent = None;
+            }
+        }
+
+        match self.units.remove(entity.into()) {
+            Some(unit) => {
+                // The projectiles stuck in it are removed with it
+                let handle = unit.rigid_body.handle();
+                self.body_owners.remove(&handle);
+                let stuck = self
+                    .projectiles
+                    .iter()
+                    .filter(|(_, projectile)| projectile.stuck_to() == Some(handle))
+                    .map(|(index, _)| index)
+                    .collect::<Vec<_>>();
+                for index in stuck {
+                    if let Some(projectile) = self.projectiles.remove(index) {
+                        self.body_owners.remove(&projectile.rigid_body.handle());
+                        projectile.remove(&mut self.physics);
+                    }
+                }
+
+                self.physics.remove(unit.rigid_body);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mirror the mesh of a unit horizontally, returns whether the unit exists.
+    pub fn flip_x(&mut self, entity: EntityId, flipped: bool) -> bool {
+        match self.units.get_mut(entity.into()) {
+            Some(unit) => {
+                unit.instance.set_flip_x(flipped);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attach a unit to another one, so it's moved, rotated & scaled along with it.
+    ///
+    /// The body of the child stops being simulated and follows the parent after every step.
+    pub fn attach(
+        &mut self,
+        child: EntityId,
+        parent: EntityId,
+        local_transform: LocalTransform,
+    ) -> Result<()> {
+        if !self.units.contains(parent.into()) {
+            return Err(anyhow!("Parent {:?} doesn't exist", parent));
+        }
+
+        // The child can't be attached to itself through its own descendants
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            if entity == child {
+                return Err(anyhow!("{:?} is attached to {:?} already", parent, child));
+            }
+            ancestor = self
+                .units
+                .get(entity.into())
+                .and_then(|unit| unit.parent)
+                .map(|Parent(parent)| parent);
+        }
+
+        let unit = self
+            .units
+            .get_mut(child.into())
+            .ok_or_else(|| anyhow!("Unit {:?} doesn't exist", child))?;
+        unit.parent = Some(Parent(parent));
+        unit.local_transform = local_transform;
+        self.physics.set_kind(&unit.rigid_body, BodyKind::Kinematic);
+
+        Ok(())
+    }
+
+    /// Let an attached unit be simulated again where it's held, returns whether it exists.
+    pub fn detach(&mut self, child: EntityId) -> bool {
+        match self.units.get_mut(child.into()) {
+            Some(unit) => {
+                if unit.parent.take().is_some() {
+                    let kind = self
+                        .defs
+                        .by_mesh(unit.mesh)
+                        .map_or(BodyKind::Dynamic, |def| def.kind);
+                    self.physics.set_kind(&unit.rigid_body, kind);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move the bodies of the attached units to where their parents hold them.
+    fn follow_parents(&mut self) {
+        if self.units.iter().all(|(_, unit)| unit.parent.is_none()) {
+            return;
+        }
+
+        let physics = &self.physics;
+        let instances = self.propagate_transforms(|unit| {
+            let mut instance = unit.instance;
+            if let Some((x, y, rotation)) = physics.position(&unit.rigid_body) {
+                instance.set_x(x as f32);
+                instance.set_y(y as f32);
+                instance.set_rotation(rotation as f32);
+            }
+
+            instance
+        });
+
+        for (index, unit) in self.units.iter().filter(|(_, unit)| unit.parent.is_some()) {
+            let instance = instances[&index];
+            self.physics.set_translation(
+                &unit.rigid_body,
+                (instance.x() as Float, instance.y() as Float),
+            );
+            self.physics
+                .set_rotation(&unit.rigid_body, instance.rotation() as Float);
+        }
+    }
+
+    /// The instances of all units, the attached ones composed from the instances of their
+    /// parents.
+    ///
+    /// The instances of the units without a parent are taken from the function.
+    fn propagate_transforms<F>(&self, instance: F) -> HashMap<Index, Instance>
+    where
+        F: Fn(&Unit) -> Instance,
+    {
+        let mut instances = HashMap::with_capacity(self.units.len());
+        for (index, _) in self.units.iter() {
+            self.propagate_transform(index, &instance, &mut instances);
+        }
+
+        instances
+    }
+
+    /// The instance of a unit after the ones of its parents, cycles are prevented when attaching.
+    fn propagate_transform<F>(
+        &self,
+        index: Index,
+        instance: &F,
+        instances: &mut HashMap<Index, Instance>,
+    ) -> Instance
+    where
+        F: Fn(&Unit) -> Instance,
+    {
+        if let Some(composed) = instances.get(&index) {
+            return *composed;
+        }
+
+        let unit = &self.units[index];
+        let mut composed = instance(unit);
+        if let Some(Parent(parent)) = unit.parent {
+            if self.units.contains(parent.into()) {
+                let parent = self.propagate_transform(parent.into(), instance, instances);
+                unit.local_transform.apply(&parent, &mut composed);
+            }
+        }
+        instances.insert(index, composed);
+
+        composed
+    }
+
+    /// Remove the lifepoints the scripts requested from the units, the dead units aren't removed
+    /// yet.
+    ///
+    /// Returns where every damaged unit was hit with the amount it was dealt.
+    pub fn apply_damages(
+        &mut self,
+        damages: Vec<(EntityId, f32)>,
+    ) -> Vec<(Option<(f32, f32)>, f32)> {
+        let mut dealt = vec![];
+        for (entity, amount) in damages {
+            let pos = self.entity_position(entity);
+            if self.damage(entity, amount as Float) {
+                dealt.push((pos, amount));
+            } else {
+                warn!("can't damage {:?}, it doesn't exist", entity);
+            }
+        }
+
+        dealt
+    }
+
+    /// Remove lifepoints from a unit, returns whether it exists.
+    pub fn damage(&mut self, entity: EntityId, amount: Float) -> bool {
+        match self.units.get_mut(entity.into()) {
+            Some(unit) => {
+                unit.health.damage(amount);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Despawn the units without lifepoints, returns them with the side they fought for.
+    pub fn remove_dead(&mut self) -> Vec<(EntityId, Allegiance)> {
+        let dead = self
+            .units
+            .iter()
+            .filter(|(_, unit)| unit.health.is_dead())
+            .map(|(index, unit)| (EntityId::from(index), unit.allegiance))
+            .collect::<Vec<_>>();
+        for (entity, _) in dead.iter() {
+            self.despawn(*entity);
+        }
+
+        dead
+    }
+
+    /// Connect units with a joint requested by the scripts.
+    pub fn add_joint(&mut self, request: &JointRequest) -> Result<()> {
+        let units = &self.units;
+        let rigid_body = |entity: EntityId| {
+            units
+                .get(entity.into())
+                .map(|unit| &unit.rigid_body)
+                .ok_or_else(|| anyhow!("Unit {:?} doesn't exist", entity))
+        };
+        let a = rigid_body(request.a)?;
+        let b = request.b.map(rigid_body).transpose()?;
+
+        let point = |(x, y): (f32, f32)| (x as Float, y as Float);
+        let joint = match request.kind {
+            JointKind::Revolute { anchor } => self.physics.add_revolute_joint(a, b, point(anchor)),
+            JointKind::Prismatic {
+                anchor,
+                axis,
+                limits,
+            } => self.physics.add_prismatic_joint(
+                a,
+                b,
+                point(anchor),
+                point(axis),
+                limits.map(point),
+            ),
+            JointKind::Distance { anchor_a, anchor_b } => {
+                self.physics
+                    .add_distance_joint(a, b, point(anchor_a), point(anchor_b))
+            }
+        };
+
+        let mut connected = vec![request.a];
+        connected.extend(request.b);
+        self.joints.insert(request.id, (connected, joint));
+
+        Ok(())
+    }
+
+    /// Remove a joint by the number the scripts know it by, returns whether it existed.
+    pub fn remove_joint(&mut self, id: i32) -> bool {
+        match self.joints.remove(&id) {
+            Some((_, joint)) => {
+                self.physics.remove_joint(joint);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add a wind or a spring between units requested by the scripts.
+    pub fn add_force(&mut self, request: &ForceRequest) -> Result<()> {
+        let (connected, force) = match request.kind {
+            ForceKind::Wind { acceleration, zone } => {
+                let point = |(x, y): (f32, f32)| (x as Float, y as Float);
+
+                (
+                    vec![],
+                    self.physics.add_constant_force(
+                        point(acceleration),
+                        zone.map(|(min, max)| (point(min), point(max))),
+                    ),
+                )
+            }
+            ForceKind::Spring { a, b, stiffness } => {
+                let units = &self.units;
+                let rigid_body = |entity: EntityId| {
+                    units
+                        .get(entity.into())
+                        .map(|unit| &unit.rigid_body)
+                        .ok_or_else(|| anyhow!("Unit {:?} doesn't exist", entity))
+                };
+
+                (
+                    vec![a, b],
+                    self.physics
+                        .add_spring(rigid_body(a)?, rigid_body(b)?, stiffness as Float),
+                )
+            }
+        };
+        self.forces.insert(request.id, (connected, force));
+
+        Ok(())
+    }
+
+    /// Remove a wind or a spring by the number the scripts know it by, returns whether it
+    /// existed.
+    pub fn remove_force(&mut self, id: i32) -> bool {
+        match self.forces.remove(&id) {
+            Some((_, force)) => self.physics.remove_force(&force),
+            None => false,
+        }
+    }
+
+    /// Push the bodies around an explosion away from it, returns the units that are hit with the
+    /// part of the strength they got.
+    pub fn explode(&mut self, explosion: &Explosion) -> Vec<(EntityId, f32)> {
+        self.physics
+            .explode(
+                (explosion.x as Float, explosion.y as Float),
+                explosion.radius as Float,
+                explosion.strength as Float,
+            )
+            .into_iter()
+            .filter_map(|(handle, falloff)| match self.body_owner(handle)? {
+                BodyOwner::Unit(entity) => Some((entity, falloff as f32)),
+                BodyOwner::Object(_) => None,
+            })
+            .collect()
+    }
+
+    /// The velocities of all units, for the scripts to read.
+    pub fn unit_velocities(&self) -> HashMap<EntityId, UnitVelocity> {
+        self.units
+            .iter()
+            .map(|(index, unit)| {
+                let linear = self
+                    .physics
+                    .linear_velocity(&unit.rigid_body)
+                    .unwrap_or_default();
+                let angular = self
+                    .physics
+                    .angular_velocity(&unit.rigid_body)
+                    .unwrap_or_default();
+
+                (
+                    EntityId::from(index),
+                    UnitVelocity {
+                        linear: (linear.0 as f32, linear.1 as f32),
+                        angular: angular as f32,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// The world positions of all units, for the scripts to read.
+    pub fn unit_positions(&self) -> HashMap<EntityId, (f32, f32)> {
+        self.units
+            .iter()
+            .filter_map(|(index, _)| {
+                let entity = EntityId::from(index);
+
+                self.entity_position(entity)
+                    .map(|position| (entity, position))
+            })
+            .collect()
+    }
+
+    /// Change the motion of the units and let the explosions the scripts requested go off.
+    ///
+    /// Returns every explosion with the units it hit and the part of the strength they got.
+    pub fn apply_motion_requests(
+        &mut self,
+        commands: Vec<(EntityId, MotionCommand)>,
+        explosions: Vec<Explosion>,
+    ) -> Vec<(Explosion, Vec<(EntityId, f32)>)> {
+        for (entity, command) in commands {
+            if !self.apply_motion(entity, command) {
+                warn!("can't move {:?}, it doesn't exist", entity);
+            }
+        }
+
+        explosions
+            .into_iter()
+            .map(|explosion| {
+                let hits = self.explode(&explosion);
+
+                (explosion, hits)
+            })
+            .collect()
+    }
+
+    /// Change the motion of a unit requested by the scripts, returns whether the unit exists.
+    pub fn apply_motion(&mut self, entity: EntityId, command: MotionCommand) -> bool {
+        let unit = match self.units.get_mut(entity.into()) {
+            Some(unit) => unit,
+            None => return false,
+        };
+        let rigid_body = &unit.rigid_body;
+
+        let physics = &mut self.physics;
+        match command {
+            MotionCommand::Force(x, y) => physics.apply_force(rigid_body, (x as Float, y as Float)),
+            MotionCommand::Impulse(x, y) => {
+                physics.apply_impulse(rigid_body, (x as Float, y as Float))
+            }
+            MotionCommand::LinearVelocity(x, y) => {
+                physics.set_linear_velocity(rigid_body, (x as Float, y as Float))
+            }
+            MotionCommand::AngularVelocity(angular) => {
+                physics.set_angular_velocity(rigid_body, angular as Float)
+            }
+            MotionCommand::KinematicVelocity(x, y, angular) => {
+                if !physics.set_kinematic_velocity(
+                    rigid_body,
+                    (x as Float, y as Float),
+                    angular as Float,
+                ) {
+                    warn!("can't move {:?} kinematically, it isn't kinematic", entity);
+                }
+            }
+            MotionCommand::Walk(direction) => unit
+                .controller
+                .get_or_insert_with(CharacterController::new)
+                .set_direction(direction as Float),
+            MotionCommand::Jump => unit
+                .controller
+                .get_or_insert_with(CharacterController::new)
+                .jump(),
+        }
+
+        true
+    }
+
+    /// The value of a property of a unit a tween animates, `None` when the unit doesn't exist or
+    /// doesn't have it.
+    pub fn tween_value(&self, entity: EntityId, property: Property) -> Option<Value> {
+        let unit = self.units.get(entity.into())?;
+        let (x, y, rotation) = self.physics.position(&unit.rigid_body)?;
+
+        match property {
+            Property::Position => Some(Value::Point(x as f32, y as f32)),
+            Property::Rotation => Some(Value::Scalar(rotation as f32)),
+            Property::Scale => Some(Value::Scalar(unit.instance.scale().0.abs())),
+            Property::Zoom => None,
+        }
+    }
+
+    /// Set a property of a unit to the value of a tween, returns whether the unit exists.
+    ///
+    /// The body is moved & turned directly, so it doesn't keep the velocity it had.
+    pub fn apply_tween(&mut self, entity: EntityId, property: Property, value: Value) -> bool {
+        let unit = match self.units.get_mut(entity.into()) {
+            Some(unit) => unit,
+            None => return false,
+        };
+
+        match (property, value) {
+            (Property::Position, Value::Point(x, y)) => self
+                .physics
+                .set_translation(&unit.rigid_body, (x as Float, y as Float)),
+            (Property::Rotation, Value::Scalar(rotation)) => self
+                .physics
+                .set_rotation(&unit.rigid_body, rotation as Float),
+            (Property::Scale, Value::Scalar(scale)) => unit.instance.set_scale(scale),
+            _ => (),
+        }
+
+        true
+    }
+
+    /// Pass the instances of the units, objects & projectiles to the renderer, moved to their
+    /// bodies, and of the interpolated units of the server when this is a client.
+    ///
+    /// The alpha is how far the frame is between the last two fixed steps, the bodies are drawn in
+    /// between so the motion is smooth at any frame rate.
+    pub fn draw(&self, render: &mut Render, alpha: Float) {
+        let moved = |instance: &Instance, rigid_body: &RigidBody| {
+            self.interpolated_instance(instance, rigid_body, alpha)
+        };
+        let unit_instances = self.unit_instances(alpha);
+
+        // Every mesh of the definitions is set, so the ones without entities left aren't drawn
+        let mut instances: HashMap<Mesh, Vec<Instance>> = HashMap::new();
+        let mut skeletons = HashMap::new();
+        for def in self.defs.iter() {
+            instances.insert(def.mesh(), vec![]);
+            if let Some(skeleton) = &def.skeleton {
+                for part in skeleton.parts.iter() {
+                    instances.insert(part.mesh, vec![]);
+                }
+                skeletons.insert(def.mesh(), skeleton);
+            }
+        }
+        let mut push = |mesh: Mesh, instance: Instance| {
+            if let Some(instances) = instances.get_mut(&mesh) {
+                instances.push(instance);
+            }
+        };
+
+        for (index, unit) in self.units.iter() {
+            let instance = unit_instances[&index];
+            match skeletons.get(&unit.mesh) {
+                // Units with a skeleton are drawn with the meshes of their parts instead
+                Some(skeleton) => {
+                    for (part, pose) in skeleton
+                        .parts
+                        .iter()
+                        .zip(unit.animator.pose(skeleton, &instance))
+                    {
+                        push(part.mesh, pose);
+                    }
+                }
+                None => push(unit.mesh, instance),
+            }
+        }
+        for (_, object) in self.objects.iter() {
+            push(object.mesh, moved(&object.instance, &object.rigid_body));
+        }
+        for (_, projectile) in self.projectiles.iter() {
+            push(
+                projectile.mesh,
+                moved(&projectile.instance, &projectile.rigid_body),
+            );
+        }
+        for state in self.remote_units.values() {
+            if let Ok(def) = self.defs.get(&state.def) {
+                let mut instance = Instance::new(state.x, state.y);
+                instance.set_layer(def.layer, 0);
+                instance.set_rotation(state.rotation);
+                instance.set_color(state.allegiance.tint());
+
+                push(def.mesh(), instance);
+            }
+        }
+
+        for (mesh, instances) in instances.iter() {
+            render.set_instances(*mesh, instances);
+        }
+    }
+
+    /// The instances of the units as they're drawn, moved between the last two steps and the
+    /// attached ones along with their parents.
+    pub fn unit_instances(&self, alpha: Float) -> HashMap<Index, Instance> {
+        self.propagate_transforms(|unit| {
+            self.interpolated_instance(&unit.instance, &unit.rigid_body, alpha)
+        })
+    }
+
+    /// The instance with the interpolated transform of the physics body.
+    fn interpolated_instance(
+        &self,
+        instance: &Instance,
+        rigid_body: &RigidBody,
+        alpha: Float,
+    ) -> Instance {
+        let mut instance = *instance;
+        if let Some((x, y, rotation)) = self.physics.interpolated_position(rigid_body, alpha) {
+            instance.set_x(x as f32);
+            instance.set_y(y as f32);
+            instance.set_rotation(rotation as f32);
+        }
+
+        instance
+    }
+
+    /// Advance the animations of the units, should be called every frame.
+    pub fn animate(&mut self, delta: f32) {
+        for (_, unit) in self.units.iter_mut() {
+            unit.animator.update(delta);
+        }
+    }
+
+    /// Play an animation of a unit requested by the scripts, returns whether the unit exists.
+    pub fn play_animation(&mut self, entity: EntityId, name: &str) -> Result<bool> {
+        let mesh = match self.units.get(entity.into()) {
+            Some(unit) => unit.mesh,
+            None => return Ok(false),
+        };
+
+        let has_animation = self
+            .defs
+            .by_mesh(mesh)
+            .and_then(|def| def.skeleton.as_ref())
+            .map_or(false, |skeleton| skeleton.animations.contains_key(name));
+        if !has_animation {
+            return Err(anyhow!("Animation \"{}\" doesn't exist", name));
+        }
+
+        if let Some(unit) = self.units.get_mut(entity.into()) {
+            unit.animator.play(name);
+        }
+
+        Ok(true)
+    }
+
+    /// The position of a local or remote unit, `None` when it doesn't exist.
+    pub fn entity_position(&self, entity: EntityId) -> Option<(f32, f32)> {
+        match self.units.get(entity.into()) {
+            Some(unit) => Some(
+                self.physics
+                    .position(&unit.rigid_body)
+                    .map_or((unit.instance.x(), unit.instance.y()), |(x, y, _)| {
+                        (x as f32, y as f32)
+                    }),
+            ),
+            None => self
+                .remote_units
+                .get(&entity)
+                .map(|state| (state.x, state.y)),
+        }
+    }
+
+    /// Anchor the UI panels to the borders of the screen, the size is in UI pixels.
+    pub fn layout(&mut self, ui_size: (f32, f32)) {
+        self.combat_log
+            .set_pos(UI_MARGIN, ui_size.1 - COMBAT_LOG_SIZE.1 - UI_MARGIN);
+    }
+}
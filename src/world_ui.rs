@@ -0,0 +1,149 @@
+use crate::{
+    camera::Camera,
+    render::{Instance, Mesh, Render},
+    text::{Alignment, FontInstance, TextLayout},
+    world::World,
+    Float,
+};
+
+/// Size of the health bars in UI pixels.
+const BAR_SIZE: (f32, f32) = (40.0, 5.0);
+/// Space between the top of a unit and its health bar in UI pixels.
+const BAR_MARGIN: f32 = 6.0;
+/// Color of the empty part of the health bars.
+const BAR_BACKGROUND_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 0.8];
+/// Color of the lifepoints left in the health bars.
+const BAR_FOREGROUND_COLOR: [f32; 4] = [0.3, 0.85, 0.3, 0.9];
+
+/// Seconds a damage number is shown.
+const NUMBER_LIFETIME: f32 = 1.0;
+/// How fast the damage numbers rise in UI pixels per second.
+const NUMBER_SPEED: f32 = 40.0;
+/// Width the damage numbers are centered in, in UI pixels.
+const NUMBER_WIDTH: f32 = 100.0;
+/// Scale of the glyphs of the damage numbers.
+const NUMBER_SCALE: f32 = 0.14;
+/// Color of the damage numbers, the alpha fades out over their lifetime.
+const NUMBER_COLOR: [f32; 3] = [1.0, 0.9, 0.3];
+
+/// The z index of the empty health bars.
+const BAR_BACKGROUND_Z: u8 = 0;
+/// The z index of the lifepoints left in the health bars.
+const BAR_FOREGROUND_Z: u8 = 1;
+/// The z index of the damage numbers.
+const NUMBER_Z: u8 = 2;
+
+/// A short-lived text showing the damage a unit took.
+#[derive(Debug, Clone, PartialEq)]
+struct DamageNumber {
+    /// The amount as it's shown.
+    text: String,
+    /// The world position of the unit when it was damaged.
+    pos: (f32, f32),
+    /// Seconds since the damage.
+    age: f32,
+}
+
+/// Screen space overlay of the units in the world, with their health bars & the damage they take.
+#[derive(Default)]
+pub struct WorldUi {
+    /// The damage numbers that are still rising.
+    numbers: Vec<DamageNumber>,
+    /// The white rectangle stretched to the size of the bars, uploaded by the first draw.
+    mesh: Option<Mesh>,
+}
+
+impl WorldUi {
+    /// Start without damage numbers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show the damage a unit at a world position took.
+    pub fn damaged(&mut self, x: f32, y: f32, amount: Float) {
+        // Negative damage heals
+        let text = if amount < 0.0 {
+            format!("+{}", -amount.round())
+        } else {
+            format!("{}", amount.round())
+        };
+
+        self.numbers.push(DamageNumber {
+            text,
+            pos: (x, y),
+            age: 0.0,
+        });
+    }
+
+    /// Move the damage numbers up and remove the ones that are shown long enough.
+    pub fn update(&mut self, delta: f32) {
+        for number in self.numbers.iter_mut() {
+            number.age += delta;
+        }
+
+        self.numbers.retain(|number| number.age < NUMBER_LIFETIME);
+    }
+
+    /// Queue a health bar above every unit and the damage numbers, projected through the camera.
+    pub fn draw(
+        &mut self,
+        world: &World,
+        alpha: Float,
+        camera: &Camera,
+        font: &mut FontInstance,
+        render: &mut Render,
+    ) {
+        let mesh = *self
+            .mesh
+            .get_or_insert_with(|| render.upload_stretchable_rectangle());
+        let ui_scale = render.ui_scale();
+        let to_ui = |x: f32, y: f32| {
+            let (x, y) = camera.world_to_screen(x, y);
+
+            (x / ui_scale, y / ui_scale)
+        };
+
+        let instances = world.unit_instances(alpha);
+        for (index, unit) in world.units.iter() {
+            let instance = instances[&index];
+
+            // Place the bar above the top of the mesh, ignoring the rotation
+            let top = render
+                .mesh_bounds(unit.mesh)
+                .map_or(0.0, |((_, top), _)| top);
+            let (x, y) = to_ui(instance.x(), instance.y() + top * instance.scale().1.abs());
+            let pos = (x - BAR_SIZE.0 / 2.0, y - BAR_MARGIN - BAR_SIZE.1);
+
+            let mut background = Instance::new(pos.0, pos.1);
+            background.set_z(BAR_BACKGROUND_Z);
+            background.set_size(BAR_SIZE.0, BAR_SIZE.1);
+            background.set_color(BAR_BACKGROUND_COLOR);
+            render.draw_screen(mesh, background);
+
+            let mut foreground = Instance::new(pos.0, pos.1);
+            foreground.set_z(BAR_FOREGROUND_Z);
+            foreground.set_size(BAR_SIZE.0 * unit.health.fraction() as f32, BAR_SIZE.1);
+            foreground.set_color(BAR_FOREGROUND_COLOR);
+            render.draw_screen(mesh, foreground);
+        }
+
+        let layout = TextLayout::new()
+            .scale(NUMBER_SCALE)
+            .max_width(NUMBER_WIDTH)
+            .alignment(Alignment::Center);
+        for number in self.numbers.iter() {
+            let (x, y) = to_ui(number.pos.0, number.pos.1);
+            let y = y - number.age * NUMBER_SPEED;
+
+            let [red, green, blue] = NUMBER_COLOR;
+            let color = [red, green, blue, 1.0 - number.age / NUMBER_LIFETIME];
+            for (mut instance, mesh) in
+                font.layout(render, &number.text, x - NUMBER_WIDTH / 2.0, y, &layout)
+            {
+                instance.set_z(NUMBER_Z);
+                instance.set_color(color);
+                render.draw_screen(mesh, instance);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Simulate the same script twice without a window and compare the physics.
+
+use glsp::{Callable, GResult, Lib, Runtime};
+use replace_me::{
+    camera::Camera, checkpoint::Checkpoints, clock::Clock, config::Config, headless,
+    random::Random, render::Render, tween::Tweens, world::World, Float,
+};
+
+/// Lets the character walk & jump around with seeded random numbers.
+const SCRIPT: &str = "scripts/tests/determinism.glsp";
+
+#[test]
+fn same_input_gives_the_same_physics_state() {
+    let config = Config::default();
+
+    let first = headless::run(&config, 300, Some(SCRIPT)).unwrap();
+    let second = headless::run(&config, 300, Some(SCRIPT)).unwrap();
+
+    assert_eq!(
+        first, second,
+        "the physics state hashes {:016x} & {:016x} differ",
+        first, second
+    );
+}
+
+#[test]
+fn restored_checkpoint_gives_the_same_random_numbers() {
+    let config = Config::default();
+    let fixed_step = config.simulation.fixed_step();
+    let mut render = Render::offscreen();
+    let assets = headless::load_assets(&config, &mut render).unwrap();
+    let mut world = World::new(assets, &mut render, fixed_step as Float).unwrap();
+
+    let runtime = Runtime::new();
+    runtime.run(|| {
+        glsp::add_lib(Clock::new(fixed_step));
+        glsp::add_lib(Camera::new());
+        glsp::add_lib(Tweens::new());
+        glsp::add_lib(Random::new(config.simulation.seed));
+        glsp::add_lib(Checkpoints::new());
+
+        Ok(())
+    });
+    Random::bind_functions(&runtime);
+
+    let (saved, restored) = runtime
+        .run(|| {
+            let random: Callable = glsp::global("random")?;
+            let numbers =
+                || -> GResult<Vec<f64>> { (0..3).map(|_| glsp::call(&random, &())).collect() };
+
+            Checkpoints::borrow_mut().save(0, &world, "").unwrap();
+            let saved = numbers()?;
+            Checkpoints::borrow().restore(0, &mut world).unwrap();
+            let restored = numbers()?;
+
+            Ok((saved, restored))
+        })
+        .unwrap();
+
+    assert_eq!(saved, restored, "seeking changed the random numbers");
+}
@@ -0,0 +1,259 @@
+//! Apply requests to a world loaded like the headless simulation does and step it.
+
+use replace_me::{
+    config::Config,
+    headless,
+    motion::{Explosion, MotionCommand},
+    render::Render,
+    replication::EntityId,
+    spawner::{
+        ForceKind, ForceRequest, JointKind, JointRequest, SpawnKind, SpawnRequest, SpawnRequests,
+    },
+    unit::Allegiance,
+    world::{BodyOwner, Touch, World},
+    Float,
+};
+
+/// Load the world from the assets, without a window.
+fn world() -> World {
+    let config = Config::default();
+    let mut render = Render::offscreen();
+    let assets = headless::load_assets(&config, &mut render).unwrap();
+
+    World::new(assets, &mut render, config.simulation.fixed_step() as Float).unwrap()
+}
+
+/// Spawn the ground like the main scene does and a character at a position, returns the character.
+fn spawn_character(world: &mut World, x: f32, y: f32) -> EntityId {
+    let spawned = world.apply_spawn_requests(SpawnRequests {
+        spawns: vec![
+            SpawnRequest {
+                def: "ground".to_string(),
+                kind: SpawnKind::Object,
+                x: 0.0,
+                y: 300.0,
+            },
+            SpawnRequest {
+                def: "character".to_string(),
+                kind: SpawnKind::Unit {
+                    allegiance: Allegiance::Ally,
+                    tint: None,
+                },
+                x,
+                y,
+            },
+        ],
+        ..Default::default()
+    });
+    assert_eq!(spawned.len(), 1, "only the character is a unit");
+    assert_eq!(spawned[0].1, "character");
+
+    spawned[0].0
+}
+
+/// Simulate an amount of fixed steps.
+fn step(world: &mut World, steps: usize) {
+    for _ in 0..steps {
+        world.step();
+    }
+}
+
+#[test]
+fn spawned_unit_lands_on_the_ground() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+    // The scripts can control a unit in the frame it's spawned
+    world.apply_motion_requests(vec![(character, MotionCommand::Walk(0.0))], vec![]);
+
+    step(&mut world, 300);
+
+    let (_, y) = world.entity_position(character).unwrap();
+    assert!(y > 100.0, "the character didn't fall: {}", y);
+    assert!(y < 400.0, "the character fell through the ground: {}", y);
+}
+
+#[test]
+fn joint_to_the_world_holds_the_unit() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+    world.apply_spawn_requests(SpawnRequests {
+        joints: vec![JointRequest {
+            id: 0,
+            kind: JointKind::Revolute {
+                anchor: (200.0, 0.0),
+            },
+            a: character,
+            b: None,
+        }],
+        ..Default::default()
+    });
+
+    step(&mut world, 120);
+
+    let (x, y) = world.entity_position(character).unwrap();
+    assert!(
+        (x - 200.0).abs() < 1.0 && y.abs() < 1.0,
+        "the character moved away from the hinge: {} {}",
+        x,
+        y
+    );
+
+    // The joint is removed with the unit
+    world.apply_spawn_requests(SpawnRequests {
+        despawns: vec![character],
+        ..Default::default()
+    });
+    assert!(world.entity_position(character).is_none());
+    assert!(!world.remove_joint(0));
+}
+
+#[test]
+fn wind_pushes_the_unit() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+    world.apply_spawn_requests(SpawnRequests {
+        forces: vec![ForceRequest {
+            id: 0,
+            kind: ForceKind::Wind {
+                acceleration: (200.0, 0.0),
+                zone: None,
+            },
+        }],
+        ..Default::default()
+    });
+
+    step(&mut world, 60);
+
+    let (x, _) = world.entity_position(character).unwrap();
+    assert!(x > 210.0, "the wind didn't push the character: {}", x);
+    assert!(world.remove_force(0));
+}
+
+#[test]
+fn damage_kills_the_unit() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+
+    let dealt = world.apply_damages(vec![(character, 1.0)]);
+    assert_eq!(dealt, vec![(Some((200.0, 0.0)), 1.0)]);
+    assert!(world.remove_dead().is_empty());
+
+    world.apply_damages(vec![(character, 1000.0)]);
+    assert_eq!(
+        world.remove_dead(),
+        vec![(character, Allegiance::Ally)],
+        "the character survived"
+    );
+
+    // The dead unit can't be damaged anymore
+    assert!(world.apply_damages(vec![(character, 1.0)]).is_empty());
+}
+
+#[test]
+fn motion_moves_the_unit() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+
+    let hits = world.apply_motion_requests(
+        vec![(character, MotionCommand::LinearVelocity(100.0, 0.0))],
+        vec![],
+    );
+    assert!(hits.is_empty());
+    step(&mut world, 30);
+    let (x, y) = world.entity_position(character).unwrap();
+    assert!(x > 210.0, "the character didn't move: {}", x);
+
+    // The origin of the character is at its feet
+    let explosion = Explosion {
+        x: x - 10.0,
+        y: y - 50.0,
+        radius: 100.0,
+        strength: 100.0,
+    };
+    let hits = world.apply_motion_requests(vec![], vec![explosion]);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].1.len(), 1, "the explosion didn't hit the character");
+    assert_eq!(hits[0].1[0].0, character);
+}
+
+#[test]
+fn arrow_hits_the_unit() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+    step(&mut world, 120);
+    let (x, y) = world.entity_position(character).unwrap();
+
+    // Shot down from above by the other side, so it doesn't pass through the character
+    world.apply_spawn_requests(SpawnRequests {
+        spawns: vec![SpawnRequest {
+            def: "arrow".to_string(),
+            kind: SpawnKind::Projectile {
+                velocity: (0.0, 600.0),
+                allegiance: Some(Allegiance::Enemy),
+            },
+            x,
+            y: y - 200.0,
+        }],
+        ..Default::default()
+    });
+    world.drain_collisions();
+
+    let mut touches = vec![];
+    for _ in 0..60 {
+        world.step();
+        touches.extend(world.drain_collisions());
+    }
+
+    let arrow = BodyOwner::Object("arrow");
+    let unit = BodyOwner::Unit(character);
+    assert!(
+        touches
+            .iter()
+            .any(|touch| *touch == Touch::Collision(arrow, unit)
+                || *touch == Touch::Collision(unit, arrow)),
+        "the arrow didn't hit the character: {:?}",
+        touches
+    );
+}
+
+#[test]
+fn restored_checkpoint_continues_where_it_was_saved() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+    world.apply_motion_requests(
+        vec![(character, MotionCommand::LinearVelocity(100.0, 0.0))],
+        vec![],
+    );
+    step(&mut world, 30);
+
+    let checkpoint = world.save_checkpoint();
+    let saved = world.entity_position(character).unwrap();
+    step(&mut world, 60);
+    assert_ne!(world.entity_position(character).unwrap(), saved);
+
+    // The entities keep their numbers
+    world.restore_checkpoint(&checkpoint).unwrap();
+    assert_eq!(world.entity_position(character).unwrap(), saved);
+}
+
+#[test]
+fn restoring_a_checkpoint_twice_simulates_the_same() {
+    let mut world = world();
+    let character = spawn_character(&mut world, 200.0, 0.0);
+    world.apply_motion_requests(
+        vec![(character, MotionCommand::LinearVelocity(100.0, 0.0))],
+        vec![],
+    );
+    step(&mut world, 30);
+    let checkpoint = world.save_checkpoint();
+
+    let mut simulate = || {
+        world.restore_checkpoint(&checkpoint).unwrap();
+        step(&mut world, 60);
+
+        world.entity_position(character).unwrap()
+    };
+    let first = simulate();
+    let second = simulate();
+    assert_eq!(first, second);
+}
@@ -0,0 +1,11 @@
+// Functions of the game that need the browser, loaded after sapp_jsutils.js
+miniquad_add_plugin({
+	register_plugin: function (importObject) {
+		// Passed to the game as command line arguments
+		importObject.env.web_url_query = function () {
+			return js_object(decodeURIComponent(window.location.search));
+		};
+	},
+	name: "web",
+	version: "0.1.0",
+});